@@ -0,0 +1,48 @@
+/*
+ * The C interface of nordselect, available when it is built with the `capi` feature:
+ *
+ *     cargo rustc --release --lib --features capi --crate-type cdylib
+ *
+ * Filters are passed as a JSON array of the filters of the command line, e.g. ["nl", "p2p"].
+ * Servers are returned as JSON. Strings returned by these functions must be freed with
+ * nordselect_string_free, server lists with nordselect_servers_free. When a function fails, it
+ * returns NULL and nordselect_last_error tells why, also when nordselect panicked.
+ *
+ * Keep this file in sync with src/ffi.rs.
+ */
+#ifndef NORDSELECT_H
+#define NORDSELECT_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A server list. */
+typedef struct NordselectServers NordselectServers;
+
+/* Downloads the server list and returns the best server passing the filters. */
+char *nordselect_best_server(const char *filters_json);
+
+/* Downloads the server list from the NordVPN API. */
+NordselectServers *nordselect_servers_from_api(void);
+
+/* Reads a server list from JSON, such as a saved response of the API. */
+NordselectServers *nordselect_servers_from_json(const char *json);
+
+/* Returns the best server of the list passing the filters. */
+char *nordselect_select(const NordselectServers *servers, const char *filters_json);
+
+/* Frees a server list. NULL is ignored. */
+void nordselect_servers_free(NordselectServers *servers);
+
+/* Returns why the last failing call on this thread failed, or NULL if none did. */
+char *nordselect_last_error(void);
+
+/* Frees a string returned by these functions. NULL is ignored. */
+void nordselect_string_free(char *text);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,1032 @@
+//! Downloading the server list from the NordVPN API.
+use crate::filters::Filter;
+use crate::servers::{ParseReport, Servers};
+use reqwest;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, USER_AGENT};
+use reqwest::StatusCode;
+use serde_derive::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The endpoint of the legacy API, which returns all servers at once.
+pub const LEGACY_ENDPOINT: &str = "https://nordvpn.com/api/server";
+
+/// The endpoint of the v1 API. The limit is set high enough to return all servers.
+pub const V1_ENDPOINT: &str = "https://api.nordvpn.com/v1/servers?limit=16384";
+
+/// The endpoint of the v1 API, without parameters.
+const V1_SERVERS: &str = "https://api.nordvpn.com/v1/servers";
+
+/// Returns the endpoint of the v1 API that lists `limit` servers, starting at the given offset.
+pub fn v1_page_endpoint(offset: usize, limit: usize) -> String {
+    page_endpoint(V1_SERVERS, offset, limit)
+}
+
+fn page_endpoint(base: &str, offset: usize, limit: usize) -> String {
+    format!("{}?limit={}&offset={}", base, limit, offset)
+}
+
+/// The origins of the NordVPN API, which are replaced by the base set with
+/// [ApiClient::api_base](struct.ApiClient.html#method.api_base).
+const API_ORIGINS: &[&str] = &["https://api.nordvpn.com", "https://nordvpn.com"];
+
+/// The amount of servers asked for per page by default. Small enough for a page to arrive in
+/// time on slow links.
+pub const DEFAULT_PAGE_SIZE: usize = 1000;
+
+/// How many pages are downloaded at the same time.
+const PARALLEL_PAGES: usize = 4;
+
+/// How many pages are downloaded at most, in case the API keeps returning full pages.
+const MAX_PAGES: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How far a paginated download has come, reported after every page.
+pub struct PageProgress {
+    /// The amount of pages that were downloaded.
+    pub pages: usize,
+    /// The amount of servers on those pages.
+    pub servers: usize,
+}
+
+/// Returns the endpoint of the v1 API that only lists the servers in the country with the given
+/// NordVPN identifier. See [countries::nord_id](../countries/fn.nord_id.html).
+pub fn v1_country_endpoint(country_id: u32) -> String {
+    format!("{}&filters[country_id]={}", V1_ENDPOINT, country_id)
+}
+
+/// Returns the endpoint of the v1 API that lists the `limit` servers NordVPN recommends in the
+/// country with the given id, best first. See
+/// [countries::nord_id](../countries/fn.nord_id.html).
+pub fn v1_recommendations_endpoint(country_id: u32, limit: usize) -> String {
+    format!(
+        "{}/recommendations?filters[country_id]={}&limit={}",
+        V1_SERVERS, country_id, limit
+    )
+}
+
+/// How long to wait for other endpoints after the first list has been received.
+const MERGE_GRACE: Duration = Duration::from_secs(2);
+
+/// The User-Agent sent by default.
+pub const DEFAULT_USER_AGENT: &str = concat!("nordselect/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// What identifies a version of a response, to ask the API whether it changed since: the `ETag`
+/// and `Last-Modified` headers it was sent with.
+pub struct Validators {
+    /// The `ETag` header of the response, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// The `Last-Modified` header of the response, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Returns the validators of the given response.
+    fn of(response: &Response) -> Validators {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+        Validators {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+
+    /// Returns whether there is nothing to validate with.
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Returns the reqwest client an [ApiClient](struct.ApiClient.html) sends its requests with.
+///
+/// When `use_proxy` is true, requests go through the proxy set in the `HTTPS_PROXY`,
+/// `HTTP_PROXY` or `ALL_PROXY` environment variables, except the ones to hosts listed in
+/// `NO_PROXY`. Otherwise, these variables are ignored.
+///
+/// # Panics
+///
+/// When the TLS backend cannot be set up, like `reqwest::blocking::Client::new`.
+pub fn http_client(use_proxy: bool) -> reqwest::blocking::Client {
+    let builder = reqwest::blocking::Client::builder();
+    let builder = if use_proxy {
+        builder
+    } else {
+        builder.no_proxy()
+    };
+    builder
+        .build()
+        .expect("the TLS backend could not be set up")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A request sent by an [ApiClient](struct.ApiClient.html), as handed to a
+/// [RequestLogger](trait.RequestLogger.html). Only what is needed to find out why a download
+/// failed is kept, not the body of the response.
+pub struct RequestRecord {
+    /// The URL the request was sent to, after applying the
+    /// [api_base](struct.ApiClient.html#method.api_base).
+    pub url: String,
+    /// The status code of the response, or `None` if no response was received.
+    pub status: Option<u16>,
+    /// The time until the body of the response was read, or until the request failed.
+    pub duration: Duration,
+    /// The amount of bytes of the body that were read, after decompressing it, or `None` if no
+    /// response was received.
+    pub size: Option<u64>,
+    /// Why no response was received, if so.
+    pub error: Option<String>,
+}
+
+impl RequestRecord {
+    /// Records a request to the given URL that was sent at `start` and failed.
+    fn failed(url: &str, start: Instant, err: &reqwest::Error) -> RequestRecord {
+        RequestRecord {
+            url: url.to_string(),
+            status: err.status().map(|status| status.as_u16()),
+            duration: start.elapsed(),
+            size: None,
+            error: Some(err.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for RequestRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GET {}", self.url)?;
+        if let Some(error) = &self.error {
+            return write!(
+                f,
+                " failed after {} ms: {}",
+                self.duration.as_millis(),
+                error
+            );
+        }
+        if let Some(status) = self.status {
+            write!(f, " -> {}", status)?;
+        }
+        write!(f, " in {} ms", self.duration.as_millis())?;
+        match self.size {
+            Some(size) => write!(f, ", {} bytes", size),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Keeps track of the requests an [ApiClient](struct.ApiClient.html) sends, e.g. to find out why
+/// downloading the server list fails now and then. See
+/// [ApiClient::logger](struct.ApiClient.html#method.logger).
+pub trait RequestLogger: Send + Sync {
+    /// Records a request, once its response was read or it failed.
+    fn log(&self, record: &RequestRecord);
+}
+
+/// Logs every request to standard error, on a line of its own.
+///
+/// # Example
+///
+/// ```no_run
+/// use nordselect::api::{ApiClient, StderrLogger};
+/// use nordselect::Servers;
+///
+/// // Prints e.g. "GET https://nordvpn.com/api/server -> 200 in 812 ms, 4561432 bytes".
+/// let client = ApiClient::new().logger(StderrLogger);
+/// let data = Servers::from_api_with_client(&client);
+/// ```
+pub struct StderrLogger;
+
+impl RequestLogger for StderrLogger {
+    fn log(&self, record: &RequestRecord) {
+        eprintln!("{}", record);
+    }
+}
+
+/// A response to a request of an [ApiClient](struct.ApiClient.html). Its body is read through
+/// [Read](https://doc.rust-lang.org/std/io/trait.Read.html), or with [text](#method.text).
+///
+/// When the client logs its requests, the request is logged once the response is dropped, with
+/// the size of the body as far as it was read. That size is counted, as the `Content-Length`
+/// header is gone once a compressed body is decompressed.
+pub struct Response {
+    /// The response as received by reqwest.
+    inner: reqwest::blocking::Response,
+    /// The logger and the request to log, if the request is logged.
+    log: Option<(Arc<dyn RequestLogger>, RequestRecord)>,
+    /// The moment the request was sent.
+    start: Instant,
+    /// The amount of bytes of the body that were read.
+    read: u64,
+}
+
+impl Response {
+    /// Returns the status code of the response.
+    pub fn status(&self) -> StatusCode {
+        self.inner.status()
+    }
+
+    /// Returns the headers of the response.
+    pub fn headers(&self) -> &reqwest::header::HeaderMap {
+        self.inner.headers()
+    }
+
+    /// Turns a client or server error status code into an error, like
+    /// `reqwest::blocking::Response::error_for_status`.
+    pub fn error_for_status(self) -> reqwest::Result<Response> {
+        self.inner.error_for_status_ref()?;
+        Ok(self)
+    }
+
+    /// Reads the whole body as text.
+    pub fn text(mut self) -> std::io::Result<String> {
+        let mut text = String::new();
+        self.read_to_string(&mut text)?;
+        Ok(text)
+    }
+}
+
+impl Read for Response {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read += read as u64;
+        Ok(read)
+    }
+}
+
+impl Drop for Response {
+    fn drop(&mut self) {
+        if let Some((logger, mut record)) = self.log.take() {
+            record.duration = self.start.elapsed();
+            record.size = Some(self.read);
+            logger.log(&record);
+        }
+    }
+}
+
+/// The state of a token bucket: requests can be sent as long as there are tokens left, and a
+/// token is added every interval.
+struct TokenBucket {
+    /// The amount of requests that can be sent right away.
+    tokens: f64,
+    /// The last moment tokens were added.
+    refilled: Instant,
+}
+
+#[derive(Clone)]
+/// The HTTP client used to talk to the API.
+///
+/// Requests are sent with a User-Agent identifying nordselect. Programs that download the server
+/// list regularly, like daemons, should limit how often they do so using
+/// [min_interval](#method.min_interval), to avoid getting blocked by the API. Clones share the
+/// same limit.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::api::ApiClient;
+/// use std::time::Duration;
+///
+/// let client = ApiClient::new()
+///     .user_agent("my-daemon/1.0")
+///     .min_interval(Duration::from_secs(60))
+///     .burst(3);
+/// ```
+///
+/// Requests go through the proxy set in the environment, see [http_client](fn.http_client.html),
+/// unless the client is created with [without_proxy](#method.without_proxy). An existing reqwest
+/// client, e.g. one set up with another proxy, can be used as well:
+///
+/// ```
+/// use nordselect::api::ApiClient;
+///
+/// let client = ApiClient::from_client(reqwest::blocking::Client::new());
+/// ```
+pub struct ApiClient {
+    /// The client doing the actual requests.
+    client: reqwest::blocking::Client,
+    /// The User-Agent sent with every request.
+    user_agent: String,
+    /// The minimal time between requests, once the burst is used up.
+    min_interval: Duration,
+    /// The amount of requests that can be sent at once.
+    burst: u32,
+    /// The bucket shared between all clones.
+    bucket: Arc<Mutex<TokenBucket>>,
+    /// The URL requests to the NordVPN API are sent to instead, if any.
+    api_base: Option<String>,
+    /// Where the requests are logged, if anywhere.
+    logger: Option<Arc<dyn RequestLogger>>,
+}
+
+impl Default for ApiClient {
+    fn default() -> ApiClient {
+        ApiClient::new()
+    }
+}
+
+impl ApiClient {
+    /// Creates a client sending the [default User-Agent](constant.DEFAULT_USER_AGENT.html),
+    /// without a limit on the amount of requests.
+    pub fn new() -> ApiClient {
+        ApiClient::from_client(http_client(true))
+    }
+
+    /// Same as [new](#method.new), but connects directly, ignoring the proxy set in the
+    /// environment.
+    pub fn without_proxy() -> ApiClient {
+        ApiClient::from_client(http_client(false))
+    }
+
+    /// Creates a client sending its requests through the given reqwest client, keeping its
+    /// connection pool, proxies and TLS settings. The User-Agent is still set on every request,
+    /// see [user_agent](#method.user_agent).
+    pub fn from_client(client: reqwest::blocking::Client) -> ApiClient {
+        ApiClient {
+            client,
+            user_agent: String::from(DEFAULT_USER_AGENT),
+            min_interval: Duration::from_secs(0),
+            burst: 4,
+            bucket: Arc::new(Mutex::new(TokenBucket {
+                tokens: 4f64,
+                refilled: Instant::now(),
+            })),
+            api_base: None,
+            logger: None,
+        }
+    }
+
+    /// Changes the User-Agent sent with every request.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> ApiClient {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Waits at least `interval` between requests, once the burst is used up. A zero interval
+    /// disables the limit.
+    pub fn min_interval(mut self, interval: Duration) -> ApiClient {
+        self.min_interval = interval;
+        self
+    }
+
+    /// Changes how many requests can be sent at once, which is 4 by default. Downloading the
+    /// server list can take a request per endpoint. At least one request is always allowed.
+    pub fn burst(mut self, burst: u32) -> ApiClient {
+        self.burst = burst.max(1);
+        if let Ok(mut bucket) = self.bucket.lock() {
+            bucket.tokens = bucket.tokens.min(self.burst as f64);
+        }
+        self
+    }
+
+    /// Sends the requests meant for the NordVPN API to the given base URL instead, keeping their
+    /// path and query, e.g. to a proxy caching the API or to a local server in tests. Requests
+    /// to other URLs, like mirrors, are sent as they are.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use nordselect::api::ApiClient;
+    /// use nordselect::Servers;
+    ///
+    /// // Asks http://localhost:8080/api/server and http://localhost:8080/v1/servers?limit=16384.
+    /// let client = ApiClient::new().api_base("http://localhost:8080");
+    /// let data = Servers::from_api_with_client(&client);
+    /// ```
+    pub fn api_base(mut self, base: impl Into<String>) -> ApiClient {
+        self.api_base = Some(base.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Hands every request that is sent to the given logger, with its status code, duration and
+    /// response size, once its [Response](struct.Response.html) is dropped. Clones log to the
+    /// same logger.
+    pub fn logger(mut self, logger: impl RequestLogger + 'static) -> ApiClient {
+        self.logger = Some(Arc::new(logger));
+        self
+    }
+
+    /// Returns the URL a request for the given URL is sent to.
+    fn resolve<'a>(&self, url: &'a str) -> Cow<'a, str> {
+        if let Some(base) = &self.api_base {
+            for origin in API_ORIGINS {
+                let path = url
+                    .strip_prefix(origin)
+                    .filter(|path| path.is_empty() || path.starts_with(['/', '?'].as_ref()));
+                if let Some(path) = path {
+                    return Cow::Owned(format!("{}{}", base, path));
+                }
+            }
+        }
+        Cow::Borrowed(url)
+    }
+
+    /// Blocks until a request can be sent.
+    fn wait_for_turn(&self) {
+        if self.min_interval == Duration::from_secs(0) {
+            return;
+        }
+
+        loop {
+            let wait = {
+                // A poisoned lock only means another thread panicked while holding it.
+                let mut bucket = match self.bucket.lock() {
+                    Ok(bucket) => bucket,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+                let now = Instant::now();
+                let refill = now.duration_since(bucket.refilled).as_secs_f64()
+                    / self.min_interval.as_secs_f64();
+                bucket.tokens = (bucket.tokens + refill).min(self.burst as f64);
+                bucket.refilled = now;
+
+                if bucket.tokens >= 1f64 {
+                    bucket.tokens -= 1f64;
+                    return;
+                }
+                self.min_interval.mul_f64(1f64 - bucket.tokens)
+            };
+            thread::sleep(wait);
+        }
+    }
+
+    /// Sends a GET request to the given URL, once the rate limit allows it.
+    pub fn get(&self, url: &str) -> reqwest::Result<Response> {
+        self.wait_for_turn();
+        let url = self.resolve(url);
+        let request = self
+            .client
+            .get(url.as_ref())
+            .header(USER_AGENT, self.user_agent.as_str());
+        self.send(&url, request)
+    }
+
+    /// Sends a GET request to the given URL asking to only send the response if it does not match
+    /// the validators anymore, once the rate limit allows it. The API answers with
+    /// `304 Not Modified` when it still matches.
+    pub fn get_if_modified(&self, url: &str, validators: &Validators) -> reqwest::Result<Response> {
+        self.wait_for_turn();
+        let url = self.resolve(url);
+        let mut request = self
+            .client
+            .get(url.as_ref())
+            .header(USER_AGENT, self.user_agent.as_str());
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag.as_str());
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified.as_str());
+        }
+        self.send(&url, request)
+    }
+
+    /// Sends the request to the given URL, logging it when asked to.
+    fn send(
+        &self,
+        url: &str,
+        request: reqwest::blocking::RequestBuilder,
+    ) -> reqwest::Result<Response> {
+        let start = Instant::now();
+        match request.send() {
+            Ok(inner) => {
+                let log = self.logger.as_ref().map(|logger| {
+                    let record = RequestRecord {
+                        url: url.to_string(),
+                        status: Some(inner.status().as_u16()),
+                        duration: Duration::from_secs(0),
+                        size: None,
+                        error: None,
+                    };
+                    (Arc::clone(logger), record)
+                });
+                Ok(Response {
+                    inner,
+                    log,
+                    start,
+                    read: 0,
+                })
+            }
+            Err(err) => {
+                if let Some(logger) = &self.logger {
+                    logger.log(&RequestRecord::failed(url, start, &err));
+                }
+                Err(err)
+            }
+        }
+    }
+}
+
+impl From<reqwest::blocking::Client> for ApiClient {
+    fn from(client: reqwest::blocking::Client) -> ApiClient {
+        ApiClient::from_client(client)
+    }
+}
+
+type DownloadResult = Result<Servers, Box<dyn std::error::Error + Send + Sync>>;
+
+fn download(
+    client: &ApiClient,
+    endpoint: &str,
+    filter: Option<&(dyn Filter + Send + Sync)>,
+) -> DownloadResult {
+    download_reporting(client, endpoint, filter).map(|(servers, _)| servers)
+}
+
+/// Same as `download`, but also reports how many entries the list had.
+fn download_reporting(
+    client: &ApiClient,
+    endpoint: &str,
+    filter: Option<&(dyn Filter + Send + Sync)>,
+) -> Result<(Servers, ParseReport), Box<dyn std::error::Error + Send + Sync>> {
+    // The response is parsed while it comes in, instead of reading it into memory first.
+    let response = client.get(endpoint)?.error_for_status()?;
+
+    Servers::from_reader_reporting(response, filter.map(|filter| filter as &dyn Filter))
+        .map_err(|err| err.to_string().into())
+}
+
+/// A list with its validators, or `None` if it did not change.
+type ConditionalResult =
+    Result<Option<(Servers, Validators)>, Box<dyn std::error::Error + Send + Sync>>;
+
+/// The merged lists of all endpoints with their validators, or `None` if none changed.
+type ModifiedLists = Option<(Servers, BTreeMap<String, Validators>)>;
+
+/// Downloads the server list from the endpoint, unless it still matches the validators. Returns
+/// `None` if it did, or the list with its new validators otherwise.
+fn download_if_modified(
+    client: &ApiClient,
+    endpoint: &str,
+    validators: Option<&Validators>,
+) -> ConditionalResult {
+    let response = match validators {
+        Some(validators) if !validators.is_empty() => {
+            client.get_if_modified(endpoint, validators)?
+        }
+        _ => client.get(endpoint)?,
+    };
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+
+    let response = response.error_for_status()?;
+    let validators = Validators::of(&response);
+    let servers = Servers::from_reader(response).map_err(|err| err.to_string())?;
+    Ok(Some((servers, validators)))
+}
+
+/// Downloads the server lists from all endpoints at the same time and merges them, like
+/// [download_all](fn.download_all.html), unless none of them changed since they were sent with
+/// the given validators. Returns `None` in that case, or the list and the validators of every
+/// endpoint otherwise.
+///
+/// The merged list needs every endpoint, so endpoints that did not change are downloaded again
+/// when another one did.
+pub(crate) fn download_all_if_modified<S: AsRef<str>>(
+    client: &ApiClient,
+    endpoints: &[S],
+    validators: &BTreeMap<String, Validators>,
+) -> Result<ModifiedLists, Box<dyn std::error::Error>> {
+    let handles: Vec<_> = endpoints
+        .iter()
+        .map(|endpoint| {
+            let endpoint = endpoint.as_ref().to_string();
+            let known = validators.get(&endpoint).cloned();
+            let client = client.clone();
+            thread::spawn(move || {
+                let downloaded = download_if_modified(&client, &endpoint, known.as_ref())
+                    .map_err(|err| format!("{}: {}", endpoint, err));
+                (endpoint, downloaded)
+            })
+        })
+        .collect();
+
+    let mut responses = Vec::new();
+    for handle in handles {
+        let (endpoint, downloaded) = handle.join().map_err(|_| "a download thread panicked")?;
+        responses.push((endpoint, downloaded?));
+    }
+    if responses.iter().all(|(_, downloaded)| downloaded.is_none()) {
+        return Ok(None);
+    }
+
+    let mut servers = Servers {
+        servers: Vec::new(),
+    };
+    let mut new_validators = BTreeMap::new();
+    for (endpoint, downloaded) in responses {
+        let (new_servers, validators) = match downloaded {
+            Some(downloaded) => downloaded,
+            None => download_if_modified(client, &endpoint, None)
+                .map_err(|err| format!("{}: {}", endpoint, err))?
+                .ok_or_else(|| format!("{}: not modified without validators", endpoint))?,
+        };
+        servers.merge(new_servers);
+        new_validators.insert(endpoint, validators);
+    }
+
+    Ok(Some((servers, new_validators)))
+}
+
+/// Downloads the server lists from all endpoints at the same time and merges them. Fails if any
+/// of the endpoints fails.
+///
+/// Only servers matching the filter are kept, if one is given.
+pub(crate) fn download_all<S: AsRef<str>>(
+    client: &ApiClient,
+    endpoints: &[S],
+    filter: Option<Arc<dyn Filter + Send + Sync>>,
+) -> Result<Servers, Box<dyn std::error::Error>> {
+    let handles: Vec<_> = endpoints
+        .iter()
+        .map(|endpoint| {
+            let endpoint = endpoint.as_ref().to_string();
+            let filter = filter.clone();
+            let client = client.clone();
+            thread::spawn(move || {
+                download(&client, &endpoint, filter.as_deref())
+                    .map_err(|err| format!("{}: {}", endpoint, err))
+            })
+        })
+        .collect();
+
+    let mut servers = Servers {
+        servers: Vec::new(),
+    };
+    for handle in handles {
+        let new_servers = handle
+            .join()
+            .map_err(|_| "a download thread panicked")??;
+        servers.merge(new_servers);
+    }
+
+    Ok(servers)
+}
+
+/// Downloads the server list from all endpoints at the same time, returning the first list that
+/// is received, merged with the lists that arrive shortly after it.
+///
+/// Only servers matching the filter are kept, if one is given.
+pub(crate) fn download_first<S: AsRef<str>>(
+    client: &ApiClient,
+    endpoints: &[S],
+    filter: Option<Arc<dyn Filter + Send + Sync>>,
+) -> Result<Servers, Box<dyn std::error::Error>> {
+    let (sender, receiver) = mpsc::channel();
+    for endpoint in endpoints {
+        let endpoint = endpoint.as_ref().to_string();
+        let sender = sender.clone();
+        let filter = filter.clone();
+        let client = client.clone();
+        thread::spawn(move || {
+            let result = download(&client, &endpoint, filter.as_deref());
+            // The receiver might have stopped listening, which is fine.
+            let _ = sender.send((endpoint, result));
+        });
+    }
+    drop(sender);
+
+    let mut servers: Option<Servers> = None;
+    let mut merge_until: Option<Instant> = None;
+    let mut errors = Vec::new();
+    loop {
+        let received = match merge_until {
+            None => receiver.recv().ok(),
+            Some(merge_until) => receiver
+                .recv_timeout(merge_until.saturating_duration_since(Instant::now()))
+                .ok(),
+        };
+
+        match received {
+            None => break,
+            Some((_, Ok(new_servers))) => match &mut servers {
+                Some(servers) => servers.merge(new_servers),
+                None => {
+                    servers = Some(new_servers);
+                    merge_until = Some(Instant::now() + MERGE_GRACE);
+                }
+            },
+            Some((endpoint, Err(err))) => errors.push(format!("{}: {}", endpoint, err)),
+        }
+    }
+
+    match servers {
+        Some(servers) => Ok(servers),
+        None if errors.is_empty() => Err("no endpoints given".into()),
+        None => Err(errors.join(", ").into()),
+    }
+}
+
+/// Downloads the servers of the v1 API page by page, a few pages at the same time, until a page
+/// is not full or only has servers of earlier pages. Fails if any of the pages fails, or if the
+/// list does not end within `MAX_PAGES` pages.
+pub(crate) fn download_paged(
+    client: &ApiClient,
+    page_size: usize,
+    progress: &mut dyn FnMut(PageProgress),
+) -> Result<Servers, Box<dyn std::error::Error>> {
+    download_pages(client, V1_SERVERS, page_size, progress)
+}
+
+fn download_pages(
+    client: &ApiClient,
+    base: &str,
+    page_size: usize,
+    progress: &mut dyn FnMut(PageProgress),
+) -> Result<Servers, Box<dyn std::error::Error>> {
+    let page_size = page_size.max(1);
+    let mut servers = Servers {
+        servers: Vec::new(),
+    };
+    let mut done = PageProgress {
+        pages: 0,
+        servers: 0,
+    };
+
+    loop {
+        if done.pages >= MAX_PAGES {
+            return Err(format!("the server list did not end within {} pages", MAX_PAGES).into());
+        }
+
+        let handles: Vec<_> = (0..PARALLEL_PAGES)
+            .map(|page| {
+                let endpoint = page_endpoint(base, (done.pages + page) * page_size, page_size);
+                let client = client.clone();
+                thread::spawn(move || {
+                    download_reporting(&client, &endpoint, None)
+                        .map_err(|err| format!("{}: {}", endpoint, err))
+                })
+            })
+            .collect();
+
+        let mut last_page_reached = false;
+        for handle in handles {
+            let (page, report) = handle
+                .join()
+                .map_err(|_| "a download thread panicked")??;
+            // Servers left out while parsing still take up room on the page.
+            last_page_reached |= report.servers + report.skipped < page_size;
+            done.pages += 1;
+            done.servers += page.servers.len();
+            let known = servers.servers.len();
+            servers.merge(page);
+            // An API that ignores the offset keeps returning the same page.
+            last_page_reached |= servers.servers.len() == known;
+            progress(done);
+        }
+        if last_page_reached {
+            return Ok(servers);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::CountryFilter;
+    use crate::mock_api::{MockApi, Response};
+
+    #[test]
+    fn rate_limit() {
+        let client = ApiClient::new()
+            .min_interval(Duration::from_millis(50))
+            .burst(2);
+        let start = Instant::now();
+        for _ in 0..4 {
+            client.clone().wait_for_turn();
+        }
+
+        // The first two requests are sent right away, the others wait for a new token.
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(start.elapsed() < Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn pages() {
+        let data = Servers::dummy_data();
+        let total = data.servers.len();
+        let api = MockApi::start(move |request| {
+            let parameter = |name| request.parameter(name).unwrap().parse::<usize>().unwrap();
+            let (limit, offset) = (parameter("limit"), parameter("offset"));
+            let page = &data.servers[offset.min(total)..(offset + limit).min(total)];
+            Response::ok(serde_json::to_string(page).unwrap())
+        });
+
+        let mut reported = Vec::new();
+        let page_size = total / 5 + 1;
+        let client = ApiClient::new().api_base(api.base());
+        let servers =
+            download_paged(&client, page_size, &mut |progress| reported.push(progress)).unwrap();
+
+        assert_eq!(servers.servers.len(), total);
+        assert_eq!(reported.len(), 8);
+        assert_eq!(
+            reported.last(),
+            Some(&PageProgress {
+                pages: 8,
+                servers: total,
+            })
+        );
+        assert!(api
+            .requests()
+            .iter()
+            .all(|request| request.path.starts_with("/v1/servers?limit=")));
+    }
+
+    #[test]
+    fn pages_with_skipped_servers() {
+        let mut data = serde_json::to_value(&Servers::dummy_data().servers).unwrap();
+        data[0]["flag"] = "ZZ".into();
+        data[0]["domain"] = "zz1.nordvpn.com".into();
+        let data = data.as_array().unwrap().clone();
+        let total = data.len();
+        let api = MockApi::start(move |request| {
+            let parameter = |name| request.parameter(name).unwrap().parse::<usize>().unwrap();
+            let (limit, offset) = (parameter("limit"), parameter("offset"));
+            let page = &data[offset.min(total)..(offset + limit).min(total)];
+            Response::ok(serde_json::to_string(page).unwrap())
+        });
+
+        // The first page is full, even though one of its servers is left out.
+        let client = ApiClient::new().api_base(api.base());
+        let servers = download_paged(&client, total / 5 + 1, &mut |_| {}).unwrap();
+        assert_eq!(servers.servers.len(), total - 1);
+    }
+
+    #[test]
+    fn pages_without_new_servers() {
+        let api = dummy_api();
+        let total = Servers::dummy_data().servers.len();
+
+        // The offset is ignored, so every page is the whole list.
+        let mut reported = Vec::new();
+        let client = ApiClient::new().api_base(api.base());
+        let servers = download_paged(&client, 2, &mut |progress| reported.push(progress)).unwrap();
+        assert_eq!(servers.servers.len(), total);
+        assert_eq!(reported.len(), PARALLEL_PAGES);
+    }
+
+    /// Serves the dummy data on both endpoints of the API.
+    fn dummy_api() -> MockApi {
+        let body = serde_json::to_string(&Servers::dummy_data().servers).unwrap();
+        MockApi::start(move |request| {
+            if request.path == "/api/server" || request.path.starts_with("/v1/servers?") {
+                Response::ok(body.clone())
+            } else {
+                Response::status(404)
+            }
+        })
+    }
+
+    #[test]
+    fn api_base() {
+        let api = dummy_api();
+        let client = ApiClient::new()
+            .user_agent("tester/1.0")
+            .api_base(api.base());
+        assert_eq!(client.resolve(LEGACY_ENDPOINT), api.url("/api/server"));
+        assert_eq!(
+            client.resolve(V1_ENDPOINT),
+            api.url("/v1/servers?limit=16384")
+        );
+        assert_eq!(
+            client.resolve("https://nordvpn.community/x"),
+            "https://nordvpn.community/x"
+        );
+        assert_eq!(
+            client.resolve("https://mirror.example/servers"),
+            "https://mirror.example/servers"
+        );
+
+        let servers = Servers::from_api_with_client(&client).unwrap();
+        assert_eq!(servers.servers, Servers::dummy_data().servers);
+        let mut paths: Vec<String> = api
+            .requests()
+            .into_iter()
+            .map(|request| request.path)
+            .collect();
+        paths.sort();
+        assert_eq!(paths, ["/api/server", "/v1/servers?limit=16384"]);
+        assert!(api
+            .requests()
+            .iter()
+            .all(|request| request.header("user-agent") == Some("tester/1.0")));
+    }
+
+    /// Keeps the requests it is handed.
+    #[derive(Default)]
+    struct MemoryLogger(Arc<Mutex<Vec<RequestRecord>>>);
+
+    impl RequestLogger for MemoryLogger {
+        fn log(&self, record: &RequestRecord) {
+            self.0.lock().unwrap().push(record.clone());
+        }
+    }
+
+    #[test]
+    fn log_requests() {
+        let api = dummy_api();
+        let logger = MemoryLogger::default();
+        let records = Arc::clone(&logger.0);
+        let client = ApiClient::new().api_base(api.base()).logger(logger);
+        let response = client.get(LEGACY_ENDPOINT).unwrap();
+        // Requests are logged once their response is dropped.
+        assert!(records.lock().unwrap().is_empty());
+        let body = response.text().unwrap();
+        client.clone().get(&api.url("/missing")).unwrap();
+
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].url, api.url("/api/server"));
+        assert_eq!(records[0].status, Some(200));
+        assert_eq!(records[0].size, Some(body.len() as u64));
+        assert_eq!(records[1].status, Some(404));
+        assert_eq!(records[1].size, Some(0));
+        assert!(records[1].to_string().contains("/missing -> 404 in "));
+        assert!(records.iter().all(|record| record.error.is_none()));
+    }
+
+    #[test]
+    fn log_compressed_requests() {
+        let body = serde_json::to_string(&Servers::dummy_data().servers).unwrap();
+        let size = body.len() as u64;
+        let api = MockApi::start(move |_| Response::ok(body.clone()).gzip());
+        let logger = MemoryLogger::default();
+        let records = Arc::clone(&logger.0);
+        let client = ApiClient::new().api_base(api.base()).logger(logger);
+
+        let servers = Servers::from_api_with_client(&client).unwrap();
+        assert_eq!(servers.servers.len(), Servers::dummy_data().servers.len());
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|record| record.size == Some(size)));
+    }
+
+    #[test]
+    fn direct_client() {
+        let api = dummy_api();
+        let client = ApiClient::without_proxy().api_base(api.base());
+        let servers = Servers::from_api_with_client(&client).unwrap();
+        assert_eq!(servers.servers.len(), Servers::dummy_data().servers.len());
+    }
+
+    #[test]
+    fn failing_endpoints() {
+        let api = dummy_api();
+        let client = ApiClient::new();
+        let working = api.url("/api/server");
+        let broken = api.url("/broken");
+
+        // The first list that arrives is enough, unless every list is needed.
+        let servers = download_first(&client, &[&working, &broken], None).unwrap();
+        assert_eq!(servers.servers.len(), Servers::dummy_data().servers.len());
+        let err = download_all(&client, &[&working, &broken], None).unwrap_err();
+        assert!(err.to_string().contains(&broken), "{}", err);
+        let err = download_first(&client, &[&broken], None).unwrap_err();
+        assert!(err.to_string().contains("404"), "{}", err);
+    }
+
+    #[test]
+    fn countries_fall_back() {
+        let data = Servers::dummy_data();
+        let belgian = data
+            .servers
+            .iter()
+            .filter(|server| server.flag == "BE")
+            .count();
+        let filter: Arc<dyn Filter + Send + Sync> = Arc::new(CountryFilter::from("BE"));
+
+        // Only the servers of the country are asked for.
+        let api = dummy_api();
+        let client = ApiClient::new().api_base(api.base());
+        let servers =
+            Servers::from_api_in_countries_with_client(&client, &["BE"], filter.clone()).unwrap();
+        assert_eq!(servers.servers.len(), belgian);
+        let requests = api.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].parameter("filters[country_id]"), Some("21"));
+
+        // When that fails, the full lists are downloaded.
+        let api = MockApi::start(move |request| {
+            if request.path == "/api/server" {
+                Response::ok(serde_json::to_string(&data.servers).unwrap())
+            } else {
+                Response::status(503)
+            }
+        });
+        let client = ApiClient::new().api_base(api.base());
+        let servers = Servers::from_api_in_countries_with_client(&client, &["BE"], filter).unwrap();
+        assert_eq!(servers.servers.len(), belgian);
+        assert!(api.requests().len() >= 2);
+    }
+}
@@ -0,0 +1,139 @@
+//! An audit trail of the servers that were selected, e.g. to see which servers a script rotating
+//! through servers used when.
+//!
+//! Selections are handed to a [SelectionLogger](trait.SelectionLogger.html) by
+//! [run::select](../run/fn.select.html), when one is set with
+//! [SelectOptions::logger](../run/struct.SelectOptions.html#method.logger). Only what was asked
+//! for and what was selected is recorded, nothing about the one asking, like their address or
+//! location.
+use crate::bench::Score;
+use crate::jsonl::{self, unix_now};
+use crate::servers::Server;
+use serde_derive::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// A selection, as stored in a selection log.
+pub struct SelectionRecord {
+    /// The moment of the selection, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The descriptions of the filters every server had to pass.
+    pub filters: Vec<String>,
+    /// The domain of the selected server, or `None` if no server passed the filters.
+    pub server: Option<String>,
+    /// The score of the selected server, written for people, e.g. `12.3 ms`, if it was scored.
+    pub score: Option<String>,
+}
+
+impl SelectionRecord {
+    /// Records a selection made now.
+    pub fn new(
+        filters: &[String],
+        server: Option<&Server>,
+        score: Option<Score>,
+    ) -> SelectionRecord {
+        SelectionRecord {
+            timestamp: unix_now(),
+            filters: filters.to_vec(),
+            server: server.map(|server| server.domain.to_string()),
+            score: score.map(|score| score.to_string()),
+        }
+    }
+}
+
+/// Keeps track of the selections that were made.
+pub trait SelectionLogger {
+    /// Records a selection. Errors are reported, but do not undo the selection.
+    fn log(&self, record: &SelectionRecord) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Logs selections to a file with one JSON record per line.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::audit::JsonlLogger;
+/// use nordselect::run::{self, SelectOptions};
+/// use nordselect::Servers;
+///
+/// let path = std::env::temp_dir().join("nordselect-doc-selections.jsonl");
+/// # let _ = std::fs::remove_file(&path);
+/// let logger = JsonlLogger::new(&path);
+/// let data = Servers::dummy_data();
+/// let options = SelectOptions::new(&data).filters(vec!["be"]).logger(&logger);
+/// let outcome = run::select(options).unwrap();
+///
+/// let records = logger.records().unwrap();
+/// assert_eq!(records.len(), 1);
+/// assert_eq!(records[0].server.as_ref().unwrap(), &outcome.server.unwrap().domain);
+/// ```
+pub struct JsonlLogger {
+    /// The file the selections are appended to.
+    path: PathBuf,
+}
+
+impl JsonlLogger {
+    /// Creates a logger appending to the file at the given path, which is created when the first
+    /// selection is logged.
+    pub fn new(path: impl Into<PathBuf>) -> JsonlLogger {
+        JsonlLogger { path: path.into() }
+    }
+
+    /// Returns the path of the file the selections are logged to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the logged selections, oldest first. A missing file holds no selections.
+    pub fn records(&self) -> Result<Vec<SelectionRecord>, Box<dyn std::error::Error>> {
+        jsonl::read(&self.path)
+    }
+}
+
+impl SelectionLogger for JsonlLogger {
+    fn log(&self, record: &SelectionRecord) -> Result<(), Box<dyn std::error::Error>> {
+        jsonl::append(&self.path, &[record])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::ScoreUnit;
+    use crate::servers::Servers;
+    use std::fs;
+
+    #[test]
+    fn append_records() {
+        let path = std::env::temp_dir().join("nordselect-test-selections.jsonl");
+        let _ = fs::remove_file(&path);
+        let logger = JsonlLogger::new(&path);
+        assert!(logger.records().unwrap().is_empty());
+
+        let data = Servers::dummy_data();
+        let filters = vec![String::from("country BE")];
+        let score = Score::new(12_345, ScoreUnit::Microseconds);
+        logger
+            .log(&SelectionRecord::new(
+                &filters,
+                Some(&data.servers[0]),
+                Some(score),
+            ))
+            .unwrap();
+        logger
+            .log(&SelectionRecord::new(&filters, None, None))
+            .unwrap();
+
+        let records = logger.records().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].filters, filters);
+        assert_eq!(
+            records[0].server.as_deref(),
+            Some(data.servers[0].domain.as_str())
+        );
+        assert_eq!(records[0].score.as_deref(), Some("12.3 ms"));
+        assert_eq!(records[1].server, None);
+        assert!(records[1].timestamp >= records[0].timestamp);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,564 @@
+//! Sending ping requests.
+//!
+//! liboping does not build on Windows, so the ICMP API of Windows is used there instead. It does
+//! not require any special privileges. Elsewhere, liboping requires the privilege to open raw
+//! sockets. Without it, unprivileged ICMP datagram sockets are used when the system offers them.
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A way to send ping requests.
+pub enum PingBackend {
+    /// liboping, which requires the privilege to open raw sockets, e.g. by running as root or
+    /// using `sudo setcap cap_net_raw+ep <binary>` on Linux.
+    Liboping,
+    /// ICMP datagram sockets, which require no privileges. macOS offers these, as does Linux when
+    /// the group of the user is in `net.ipv4.ping_group_range`.
+    DgramSocket,
+    /// The ICMP API of Windows.
+    Windows,
+}
+
+impl PingBackend {
+    /// Returns the best backend that can be used by the current process: liboping if raw sockets
+    /// can be opened, datagram sockets if those can be opened instead, and liboping otherwise,
+    /// which will fail with an error explaining the missing privilege.
+    ///
+    /// On Windows, this is always the ICMP API of Windows.
+    pub fn detect() -> PingBackend {
+        #[cfg(windows)]
+        return PingBackend::Windows;
+        #[cfg(not(windows))]
+        {
+            if dgram::can_open(libc::SOCK_RAW) || !dgram::can_open(libc::SOCK_DGRAM) {
+                PingBackend::Liboping
+            } else {
+                PingBackend::DgramSocket
+            }
+        }
+    }
+
+    /// Returns whether the current process can send pings using this backend.
+    pub fn is_available(self) -> bool {
+        match self {
+            #[cfg(not(windows))]
+            PingBackend::Liboping => dgram::can_open(libc::SOCK_RAW),
+            #[cfg(not(windows))]
+            PingBackend::DgramSocket => dgram::can_open(libc::SOCK_DGRAM),
+            #[cfg(windows)]
+            PingBackend::Windows => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for PingBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PingBackend::Liboping => "liboping",
+            PingBackend::DgramSocket => "ICMP datagram sockets",
+            PingBackend::Windows => "the ICMP API of Windows",
+        })
+    }
+}
+
+/// The answer to a single ping.
+pub(crate) struct Reply {
+    /// The host as it was given.
+    pub host: String,
+    /// The address the ping was sent to.
+    pub address: String,
+    /// How long it took to get an answer, or `None` if none came.
+    pub latency: Option<Duration>,
+}
+
+/// Pings all hosts once, at the same time, using the given backend. Fails if any of the hosts
+/// cannot be resolved, or if the backend is not available on this platform.
+pub(crate) fn ping_once(
+    backend: PingBackend,
+    hosts: &[&str],
+) -> Result<Vec<Reply>, Box<dyn std::error::Error>> {
+    match backend {
+        #[cfg(not(windows))]
+        PingBackend::Liboping => ping_liboping(hosts),
+        #[cfg(not(windows))]
+        PingBackend::DgramSocket => dgram::ping_once(hosts),
+        #[cfg(windows)]
+        PingBackend::Windows => ping_windows(hosts),
+        _ => Err(format!("{} cannot be used on this platform", backend).into()),
+    }
+}
+
+/// The smallest and the largest IPv4 packets tried when probing the path MTU, in bytes. Every
+/// host can receive the smallest one, the largest one is the MTU of Ethernet.
+pub(crate) const MTU_RANGE: (u16, u16) = (576, 1500);
+
+/// Returns the path MTU to the host: the size of the largest IPv4 packet that reaches it and is
+/// answered without being fragmented on the way. Returns `None` if even the smallest packet in
+/// [MTU_RANGE](constant.MTU_RANGE.html) is not answered.
+///
+/// Pings of different sizes with the Don't Fragment bit set are sent for this. That is only
+/// supported on Linux.
+pub(crate) fn path_mtu(host: &str) -> Result<Option<u16>, Box<dyn std::error::Error>> {
+    #[cfg(target_os = "linux")]
+    return dgram::path_mtu(host);
+    #[cfg(not(target_os = "linux"))]
+    Err(format!("Probing the MTU to {} is only supported on Linux", host).into())
+}
+
+/// Returns the largest size from `low` up to `high` that fits, assuming every smaller size fits
+/// as well, or `None` if not even `low` fits.
+#[cfg(any(test, target_os = "linux"))]
+fn largest_fitting<E>(
+    low: u16,
+    high: u16,
+    mut fits: impl FnMut(u16) -> Result<bool, E>,
+) -> Result<Option<u16>, E> {
+    if !fits(low)? {
+        return Ok(None);
+    }
+
+    // `low` always fits, everything above `high` never does.
+    let (mut low, mut high) = (low, high);
+    while low < high {
+        let middle = high - (high - low) / 2;
+        if fits(middle)? {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+
+    Ok(Some(low))
+}
+
+#[cfg(not(windows))]
+fn ping_liboping(hosts: &[&str]) -> Result<Vec<Reply>, Box<dyn std::error::Error>> {
+    let mut pingr = oping::Ping::new();
+    for host in hosts {
+        pingr.add_host(host)?;
+    }
+
+    Ok(pingr
+        .send()?
+        .map(|result| Reply {
+            // Unanswered pings are reported as dropped, with a negative latency.
+            latency: if result.dropped == 0 && result.latency_ms >= 0f64 {
+                Some(Duration::from_micros((result.latency_ms * 1000f64) as u64))
+            } else {
+                None
+            },
+            host: result.hostname,
+            address: result.address,
+        })
+        .collect())
+}
+
+#[cfg(windows)]
+fn ping_windows(hosts: &[&str]) -> Result<Vec<Reply>, Box<dyn std::error::Error>> {
+    use std::thread;
+
+    // Resolve all hosts first, to fail the same way liboping does.
+    let addresses = hosts
+        .iter()
+        .map(|host| windows::resolve(host).map(|address| (host.to_string(), address)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut replies = Vec::with_capacity(addresses.len());
+    for chunk in addresses.chunks(windows::PARALLEL_PINGS) {
+        let handles: Vec<_> = chunk
+            .iter()
+            .cloned()
+            .map(|(host, address)| {
+                thread::spawn(move || Reply {
+                    latency: windows::echo(address),
+                    address: address.to_string(),
+                    host,
+                })
+            })
+            .collect();
+        replies.extend(handles.into_iter().filter_map(|handle| handle.join().ok()));
+    }
+
+    Ok(replies)
+}
+
+#[cfg(not(windows))]
+mod dgram {
+    use super::Reply;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+    use std::os::unix::io::FromRawFd;
+    use std::time::{Duration, Instant};
+
+    /// How long to wait for answers, which is the default of liboping.
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// The type of ICMP echo requests and replies.
+    const ECHO_REQUEST: u8 = 8;
+    const ECHO_REPLY: u8 = 0;
+
+    /// Returns whether an ICMP socket of the given type can be opened.
+    pub(super) fn can_open(kind: libc::c_int) -> bool {
+        // Safe, as the socket is closed right away.
+        unsafe {
+            let fd = libc::socket(libc::AF_INET, kind, libc::IPPROTO_ICMP);
+            if fd < 0 {
+                return false;
+            }
+            libc::close(fd);
+        }
+        true
+    }
+
+    /// Opens an ICMP socket of the given type. Datagram sockets can be used through a UdpSocket,
+    /// as they work the same way. Raw sockets can as well, as long as the IP header of the
+    /// replies is skipped.
+    fn open(kind: libc::c_int) -> std::io::Result<UdpSocket> {
+        // Safe, as the socket is owned by the UdpSocket from here on.
+        unsafe {
+            let fd = libc::socket(libc::AF_INET, kind, libc::IPPROTO_ICMP);
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(UdpSocket::from_raw_fd(fd))
+        }
+    }
+
+    /// Builds an echo request with the given sequence number.
+    pub(super) fn echo_request(sequence: u16) -> [u8; 16] {
+        let mut packet = [0u8; 16];
+        packet[0] = ECHO_REQUEST;
+        packet[6..8].copy_from_slice(&sequence.to_be_bytes());
+        packet[8..].copy_from_slice(b"nordslct");
+        let checksum = checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+        packet
+    }
+
+    /// Builds an echo request with the given sequence number that is `length` bytes long,
+    /// including the ICMP header. It is never shorter than the one of
+    /// [echo_request](fn.echo_request.html).
+    pub(super) fn padded_echo_request(sequence: u16, length: usize) -> Vec<u8> {
+        let mut packet = echo_request(sequence).to_vec();
+        packet.resize(length.max(packet.len()), 0);
+        packet[2..4].copy_from_slice(&[0, 0]);
+        let checksum = checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+        packet
+    }
+
+    /// The internet checksum of the packet.
+    fn checksum(packet: &[u8]) -> u16 {
+        let mut sum: u32 = packet
+            .chunks(2)
+            .map(|word| u32::from(word[0]) << 8 | u32::from(*word.get(1).unwrap_or(&0)))
+            .sum();
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        !(sum as u16)
+    }
+
+    /// Returns the sequence number of the echo reply. Some systems, like macOS, include the IP
+    /// header, which is skipped.
+    pub(super) fn reply_sequence(packet: &[u8]) -> Option<u16> {
+        let packet = match packet.first() {
+            Some(first) if first >> 4 == 4 => packet.get(usize::from(first & 0x0f) * 4..)?,
+            _ => packet,
+        };
+        if *packet.first()? != ECHO_REPLY {
+            return None;
+        }
+        Some(u16::from_be_bytes([*packet.get(6)?, *packet.get(7)?]))
+    }
+
+    fn resolve(host: &str) -> Result<Ipv4Addr, Box<dyn std::error::Error>> {
+        (host, 0)
+            .to_socket_addrs()?
+            .find_map(|address| match address.ip() {
+                IpAddr::V4(address) => Some(address),
+                IpAddr::V6(_) => None,
+            })
+            .ok_or_else(|| format!("{} has no IPv4 address", host).into())
+    }
+
+    /// The size of the IPv4 header of the requests, which have no options.
+    #[cfg(target_os = "linux")]
+    const IP_HEADER: usize = 20;
+
+    /// How many pings of every size are sent before deciding it does not fit.
+    #[cfg(target_os = "linux")]
+    const MTU_TRIES: usize = 2;
+
+    /// Finds the path MTU to the host, see [path_mtu](../fn.path_mtu.html).
+    #[cfg(target_os = "linux")]
+    pub(super) fn path_mtu(host: &str) -> Result<Option<u16>, Box<dyn std::error::Error>> {
+        use std::os::unix::io::AsRawFd;
+
+        let address = resolve(host)?;
+        let socket = open(libc::SOCK_DGRAM).or_else(|_| open(libc::SOCK_RAW))?;
+        // Safe, as the option is an int that lives during the call.
+        let result = unsafe {
+            let option: libc::c_int = libc::IP_PMTUDISC_DO;
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::IPPROTO_IP,
+                libc::IP_MTU_DISCOVER,
+                &option as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        let destination = SocketAddr::new(IpAddr::V4(address), 0);
+        let mut sequence = 0u16;
+        let mut buffer = [0u8; 2048];
+        let found = super::largest_fitting(super::MTU_RANGE.0, super::MTU_RANGE.1, |size| {
+            for _ in 0..MTU_TRIES {
+                sequence = sequence.wrapping_add(1);
+                let request = padded_echo_request(sequence, usize::from(size) - IP_HEADER);
+                match socket.send_to(&request, destination) {
+                    Ok(_) => {}
+                    // Larger than the MTU the system already knows for this path.
+                    Err(err) if err.raw_os_error() == Some(libc::EMSGSIZE) => return Ok(false),
+                    Err(err) => return Err(err),
+                }
+
+                let deadline = Instant::now() + TIMEOUT;
+                loop {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining == Duration::from_secs(0) {
+                        break;
+                    }
+                    socket.set_read_timeout(Some(remaining))?;
+                    let (length, source) = match socket.recv_from(&mut buffer) {
+                        Ok(received) => received,
+                        Err(_) => break,
+                    };
+                    if source.ip() == destination.ip()
+                        && reply_sequence(&buffer[..length]) == Some(sequence)
+                    {
+                        return Ok(true);
+                    }
+                }
+            }
+
+            Ok(false)
+        })?;
+
+        Ok(found)
+    }
+
+    /// Pings all hosts once, at the same time. The sequence number tells the replies apart.
+    pub(super) fn ping_once(hosts: &[&str]) -> Result<Vec<Reply>, Box<dyn std::error::Error>> {
+        let addresses = hosts
+            .iter()
+            .map(|host| resolve(host))
+            .collect::<Result<Vec<_>, _>>()?;
+        let socket = open(libc::SOCK_DGRAM)?;
+
+        let mut sent = Vec::with_capacity(addresses.len());
+        for (sequence, address) in addresses.iter().enumerate() {
+            let destination = SocketAddr::new(IpAddr::V4(*address), 0);
+            socket.send_to(&echo_request(sequence as u16), destination)?;
+            sent.push(Instant::now());
+        }
+
+        let mut latencies = vec![None; addresses.len()];
+        let deadline = Instant::now() + TIMEOUT;
+        let mut buffer = [0u8; 1500];
+        let mut unanswered = addresses.len();
+        while unanswered > 0 {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                break;
+            }
+            socket.set_read_timeout(Some(remaining))?;
+            let (length, source) = match socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(_) => break,
+            };
+
+            let index = match reply_sequence(&buffer[..length]) {
+                Some(sequence) => usize::from(sequence),
+                None => continue,
+            };
+            let expected = addresses.get(index).map(|address| IpAddr::V4(*address));
+            if expected == Some(source.ip()) && latencies[index].is_none() {
+                latencies[index] = Some(sent[index].elapsed());
+                unanswered -= 1;
+            }
+        }
+
+        Ok(hosts
+            .iter()
+            .zip(addresses)
+            .zip(latencies)
+            .map(|((host, address), latency)| Reply {
+                host: host.to_string(),
+                address: address.to_string(),
+                latency,
+            })
+            .collect())
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::c_void;
+    use std::mem;
+    use std::net::{IpAddr, Ipv4Addr, ToSocketAddrs};
+    use std::ptr;
+    use std::time::Duration;
+
+    /// How many hosts are pinged at the same time.
+    pub(super) const PARALLEL_PINGS: usize = 64;
+
+    /// How long to wait for an answer, which is the default of liboping.
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// The data sent with every ping.
+    const REQUEST: &[u8] = b"nordselect";
+
+    /// The status of a ping that was answered.
+    const IP_SUCCESS: u32 = 0;
+
+    /// Returned by IcmpCreateFile when it fails.
+    const INVALID_HANDLE_VALUE: *mut c_void = -1isize as *mut c_void;
+
+    #[repr(C)]
+    struct IpOptionInformation {
+        ttl: u8,
+        tos: u8,
+        flags: u8,
+        options_size: u8,
+        options_data: *mut u8,
+    }
+
+    #[repr(C)]
+    struct IcmpEchoReply {
+        address: u32,
+        status: u32,
+        round_trip_time: u32,
+        data_size: u16,
+        reserved: u16,
+        data: *mut c_void,
+        options: IpOptionInformation,
+    }
+
+    #[link(name = "iphlpapi")]
+    extern "system" {
+        fn IcmpCreateFile() -> *mut c_void;
+        fn IcmpCloseHandle(handle: *mut c_void) -> i32;
+        fn IcmpSendEcho(
+            handle: *mut c_void,
+            destination: u32,
+            request_data: *const c_void,
+            request_size: u16,
+            request_options: *const IpOptionInformation,
+            reply_buffer: *mut c_void,
+            reply_size: u32,
+            timeout: u32,
+        ) -> u32;
+    }
+
+    /// Returns the first IPv4 address of the host, which is the only kind IcmpSendEcho supports.
+    pub(super) fn resolve(host: &str) -> Result<Ipv4Addr, Box<dyn std::error::Error>> {
+        (host, 0)
+            .to_socket_addrs()?
+            .find_map(|address| match address.ip() {
+                IpAddr::V4(address) => Some(address),
+                IpAddr::V6(_) => None,
+            })
+            .ok_or_else(|| format!("{} has no IPv4 address", host).into())
+    }
+
+    /// Pings the address once, returning how long it took to get an answer.
+    pub(super) fn echo(address: Ipv4Addr) -> Option<Duration> {
+        let mut buffer = vec![0u8; mem::size_of::<IcmpEchoReply>() + REQUEST.len() + 8];
+
+        // Safe, as the buffer is large enough for one reply and the handle is closed after use.
+        unsafe {
+            let handle = IcmpCreateFile();
+            if handle == INVALID_HANDLE_VALUE {
+                return None;
+            }
+            let replies = IcmpSendEcho(
+                handle,
+                // IPAddr is in network byte order.
+                u32::from_ne_bytes(address.octets()),
+                REQUEST.as_ptr() as *const c_void,
+                REQUEST.len() as u16,
+                ptr::null(),
+                buffer.as_mut_ptr() as *mut c_void,
+                buffer.len() as u32,
+                TIMEOUT.as_millis() as u32,
+            );
+            IcmpCloseHandle(handle);
+            if replies == 0 {
+                return None;
+            }
+
+            let reply = ptr::read_unaligned(buffer.as_ptr() as *const IcmpEchoReply);
+            if reply.status == IP_SUCCESS {
+                Some(Duration::from_millis(u64::from(reply.round_trip_time)))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn echo_packets() {
+        let request = dgram::echo_request(513);
+        // A correct checksum makes the checksum of the whole packet zero.
+        let sum: u32 = request
+            .chunks(2)
+            .map(|word| u32::from(word[0]) << 8 | u32::from(word[1]))
+            .sum();
+        assert_eq!((sum & 0xffff) + (sum >> 16), 0xffff);
+
+        let mut reply = request;
+        reply[0] = 0;
+        assert_eq!(dgram::reply_sequence(&reply), Some(513));
+        assert_eq!(dgram::reply_sequence(&request), None);
+
+        let mut with_header = vec![0x45];
+        with_header.extend_from_slice(&[0; 19]);
+        with_header.extend_from_slice(&reply);
+        assert_eq!(dgram::reply_sequence(&with_header), Some(513));
+
+        let padded = dgram::padded_echo_request(513, 1472);
+        assert_eq!(padded.len(), 1472);
+        assert_eq!(padded[..8], request[..8]);
+        let sum: u32 = padded
+            .chunks(2)
+            .map(|word| u32::from(word[0]) << 8 | u32::from(word[1]))
+            .sum();
+        assert_eq!((sum & 0xffff) + (sum >> 16), 0xffff);
+    }
+
+    #[test]
+    fn mtu_sweep() {
+        let mut tried = Vec::new();
+        let found = largest_fitting::<()>(576, 1500, |size| {
+            tried.push(size);
+            Ok(size <= 1420)
+        });
+        assert_eq!(found, Ok(Some(1420)));
+        assert!(tried.len() <= 12);
+
+        assert_eq!(
+            largest_fitting::<()>(576, 1500, |_| Ok(true)),
+            Ok(Some(1500))
+        );
+        assert_eq!(largest_fitting::<()>(576, 1500, |_| Ok(false)), Ok(None));
+    }
+}
@@ -0,0 +1,797 @@
+//! Benchmarkers give every server a score, after which the servers can be ordered on it.
+//!
+//! Where a [Sorter](../sorters/trait.Sorter.html) compares two servers at a time, a Benchmarker
+//! measures all servers at once, which is required for measurements such as ping tests or
+//! averages over time.
+#[cfg(feature = "native")]
+pub(crate) mod icmp;
+#[cfg(feature = "native")]
+pub mod ping;
+#[cfg(feature = "native")]
+pub mod recommend;
+pub mod resolve;
+mod score;
+#[cfg(feature = "native")]
+pub mod tcp;
+
+#[cfg(feature = "native")]
+pub use self::icmp::PingBackend;
+
+#[cfg(feature = "native")]
+pub use self::ping::{Aggregation, PingBenchmarker, PingSummary};
+#[cfg(feature = "native")]
+pub use self::recommend::NordRecommendationBenchmarker;
+pub use self::score::{Score, ScoreUnit};
+#[cfg(feature = "native")]
+pub use self::tcp::TcpBenchmarker;
+
+use crate::failures::FailureLog;
+use crate::history::History;
+#[cfg(feature = "geo-db")]
+use crate::locations;
+use crate::servers::{Domain, Server, Servers};
+use crate::sorters::Sorter;
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+#[cfg(feature = "geo-db")]
+use std::time::SystemTime;
+use std::time::{Duration, Instant};
+
+/// The scores of servers, mapped by their domain. Lower scores are better. The scores of one
+/// Benchmarker are all in the same unit.
+pub type Scores = HashMap<Domain, Score>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A server together with the score a Benchmarker gave it.
+pub struct ScoredServer {
+    /// The score of the server, or `None` if it could not be scored. Lower scores are better.
+    pub score: Option<Score>,
+    /// The server itself.
+    pub server: Server,
+}
+
+impl ScoredServer {
+    /// Attaches the given scores to the servers.
+    pub fn attach(servers: Vec<Server>, scores: &Scores) -> Vec<ScoredServer> {
+        servers
+            .into_iter()
+            .map(|server| ScoredServer {
+                score: scores.get(&server.domain).copied(),
+                server,
+            })
+            .collect()
+    }
+
+    /// Orders servers from best to worst: on their score first, with unscored servers last. Ties
+    /// are broken by the load, and then by the domain, so the order never depends on the order
+    /// the servers were listed in.
+    pub fn rank(&self, other: &ScoredServer) -> Ordering {
+        self.rank_before_ties(other)
+            .then_with(|| self.server.domain.cmp(&other.server.domain))
+    }
+
+    /// Same as [rank](#method.rank), but servers with the same score and load are ordered on a
+    /// hash of their domain and the seed instead. The same seed always gives the same order, while
+    /// different seeds spread the selection over all equally good servers.
+    pub fn rank_seeded(&self, other: &ScoredServer, seed: u64) -> Ordering {
+        self.rank_before_ties(other)
+            .then_with(|| {
+                tie_breaker(&self.server.domain, seed).cmp(&tie_breaker(&other.server.domain, seed))
+            })
+            .then_with(|| self.server.domain.cmp(&other.server.domain))
+    }
+
+    fn rank_before_ties(&self, other: &ScoredServer) -> Ordering {
+        let score = match (self.score, other.score) {
+            (Some(score), Some(other)) => score.partial_cmp(&other).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        score.then_with(|| self.server.load.cmp(&other.server.load))
+    }
+}
+
+/// Hashes the domain together with the seed, using FNV-1a. Unlike the hashers of the standard
+/// library, this gives the same result on every platform and in every version.
+pub(crate) fn tie_breaker(domain: &str, seed: u64) -> u64 {
+    seed.to_le_bytes()
+        .iter()
+        .chain(domain.as_bytes())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// Attaches the scores to the servers and orders them from best to worst. Unscored servers are
+/// handled according to the policy, ties are broken using the seed if one is given.
+pub(crate) fn rank(
+    servers: Vec<Server>,
+    scores: &Scores,
+    policy: ErrorPolicy,
+    seed: Option<u64>,
+) -> Result<Vec<ScoredServer>, Box<dyn std::error::Error>> {
+    let mut scored = ScoredServer::attach(servers, scores);
+    match policy {
+        ErrorPolicy::SkipServer => scored.retain(|scored| scored.score.is_some()),
+        ErrorPolicy::TreatAsWorst => {}
+        ErrorPolicy::Abort => {
+            if let Some(scored) = scored.iter().find(|scored| scored.score.is_none()) {
+                return Err(format!("{} could not be benchmarked", scored.server.domain).into());
+            }
+        }
+    }
+
+    match seed {
+        Some(seed) => scored.sort_by(|a, b| a.rank_seeded(b, seed)),
+        None => scored.sort_by(ScoredServer::rank),
+    }
+    Ok(scored)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// What happens to servers a Benchmarker could not score, e.g. because none of the pings to them
+/// were answered or because the deadline passed before they were measured.
+pub enum ErrorPolicy {
+    /// Leave the server out.
+    SkipServer,
+    /// Keep the server, after all servers that were scored. This is the default.
+    #[default]
+    TreatAsWorst,
+    /// Fail as a whole.
+    Abort,
+}
+
+/// A way to give servers a score. Lower scores are better.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::bench::{Benchmarker, Score, ScoreUnit, Scores};
+/// let mut data = Servers::dummy_data();
+///
+/// // Demo benchmarker: prefers short domains.
+/// struct DomainLengthBenchmarker;
+///
+/// impl Benchmarker for DomainLengthBenchmarker {
+///     fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+///         Ok(servers
+///             .servers
+///             .iter()
+///             .map(|server| {
+///                 let length = server.domain.len() as u32;
+///                 (server.domain.clone(), Score::new(length, ScoreUnit::Unitless))
+///             })
+///             .collect())
+///     }
+/// }
+///
+/// data.benchmark(&DomainLengthBenchmarker).unwrap();
+/// let shortest = data.servers.iter().map(|server| server.domain.len()).min();
+/// assert_eq!(shortest, Some(data.perfect_server().unwrap().domain.len()));
+/// ```
+///
+/// Benchmarkers only return scores, so different ones can be used as the same trait object,
+/// e.g. to choose one at runtime. Measurements beyond the score, such as the
+/// [PingSummary](struct.PingSummary.html) of every server, are available from the benchmarkers
+/// themselves.
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::bench::{self, Benchmarker, LoadBenchmarker};
+/// use nordselect::sorters::BandwidthSorter;
+///
+/// let prefer_bandwidth = true;
+/// let benchmarker: Box<dyn Benchmarker> = if prefer_bandwidth {
+///     Box::new(bench::from_sorter(&BandwidthSorter))
+/// } else {
+///     Box::new(LoadBenchmarker)
+/// };
+///
+/// let mut data = Servers::dummy_data();
+/// data.benchmark(&benchmarker).unwrap();
+/// assert!(data.perfect_server().is_some());
+/// ```
+pub trait Benchmarker {
+    /// Scores the given servers. Servers that could not be scored can be left out of the result.
+    fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>>;
+
+    /// Same as [benchmark](#tymethod.benchmark), but stops measuring once the given deadline has
+    /// passed, returning the scores of the servers measured so far.
+    ///
+    /// Benchmarkers that do not measure anything can ignore the deadline, which is what the
+    /// default implementation does.
+    fn benchmark_until(
+        &self,
+        servers: &Servers,
+        _deadline: Instant,
+    ) -> Result<Scores, Box<dyn std::error::Error>> {
+        self.benchmark(servers)
+    }
+}
+
+impl<B: Benchmarker + ?Sized> Benchmarker for &B {
+    fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+        (**self).benchmark(servers)
+    }
+
+    fn benchmark_until(
+        &self,
+        servers: &Servers,
+        deadline: Instant,
+    ) -> Result<Scores, Box<dyn std::error::Error>> {
+        (**self).benchmark_until(servers, deadline)
+    }
+}
+
+impl<B: Benchmarker + ?Sized> Benchmarker for Box<B> {
+    fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+        (**self).benchmark(servers)
+    }
+
+    fn benchmark_until(
+        &self,
+        servers: &Servers,
+        deadline: Instant,
+    ) -> Result<Scores, Box<dyn std::error::Error>> {
+        (**self).benchmark_until(servers, deadline)
+    }
+}
+
+/// Benchmarker that scores servers on their current load, in percent.
+pub struct LoadBenchmarker;
+
+impl Benchmarker for LoadBenchmarker {
+    fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+        Ok(servers
+            .servers
+            .iter()
+            .map(|server| {
+                let score = Score::new(u32::from(server.load), ScoreUnit::Percent);
+                (server.domain.clone(), score)
+            })
+            .collect())
+    }
+}
+
+/// Benchmarker that scores servers on their average load over a period of time, using a
+/// [History](../history/struct.History.html).
+///
+/// Servers without samples in that period are scored on their current load. Scores are the
+/// average load in [basis points](enum.ScoreUnit.html#variant.BasisPoints).
+pub struct HistoricLoadBenchmarker<'a> {
+    /// The recorded loads.
+    history: &'a History,
+    /// The period of time over which the load is averaged.
+    window: Duration,
+}
+
+impl<'a> HistoricLoadBenchmarker<'a> {
+    /// Creates a benchmarker averaging the load over the given period of time.
+    pub fn new(history: &'a History, window: Duration) -> HistoricLoadBenchmarker<'a> {
+        HistoricLoadBenchmarker { history, window }
+    }
+}
+
+impl<'a> Benchmarker for HistoricLoadBenchmarker<'a> {
+    fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+        Ok(servers
+            .servers
+            .iter()
+            .map(|server| {
+                let load = server
+                    .average_load(self.history, self.window)
+                    .unwrap_or_else(|| f64::from(server.load));
+                let score = Score::new((load * 100f64).round() as u32, ScoreUnit::BasisPoints);
+                (server.domain.clone(), score)
+            })
+            .collect())
+    }
+}
+
+/// Benchmarker that makes servers with recently reported failures less attractive, on top of the
+/// scores of another Benchmarker.
+///
+/// Every failure in the given period of time raises the score of a server by `weight` percent.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::bench::{LoadBenchmarker, PenaltyBenchmarker};
+/// use nordselect::failures::FailureLog;
+/// use std::time::Duration;
+///
+/// let mut data = Servers::dummy_data();
+/// data.benchmark(&LoadBenchmarker).unwrap();
+/// let best = data.perfect_server().unwrap();
+///
+/// let path = std::env::temp_dir().join("nordselect-doc-penalty.jsonl");
+/// # let _ = std::fs::remove_file(&path);
+/// let mut failures = FailureLog::open(&path).unwrap();
+/// failures.report(&best.domain).unwrap();
+/// failures.report(&best.domain).unwrap();
+///
+/// let benchmarker =
+///     PenaltyBenchmarker::new(&LoadBenchmarker, &failures, 10_000, Duration::from_secs(3600));
+/// data.benchmark(&benchmarker).unwrap();
+/// assert_ne!(data.perfect_server(), Some(best));
+/// ```
+pub struct PenaltyBenchmarker<'a> {
+    /// The Benchmarker giving the scores without penalties.
+    inner: &'a dyn Benchmarker,
+    /// The reported failures.
+    failures: &'a FailureLog,
+    /// The penalty per failure, in percent.
+    weight: u32,
+    /// The period of time in which failures are taken into account.
+    window: Duration,
+}
+
+impl<'a> PenaltyBenchmarker<'a> {
+    /// Creates a benchmarker adding `weight` percent to the score of `inner` for every failure
+    /// reported in the given period of time.
+    pub fn new(
+        inner: &'a dyn Benchmarker,
+        failures: &'a FailureLog,
+        weight: u32,
+        window: Duration,
+    ) -> PenaltyBenchmarker<'a> {
+        PenaltyBenchmarker {
+            inner,
+            failures,
+            weight,
+            window,
+        }
+    }
+}
+
+impl<'a> PenaltyBenchmarker<'a> {
+    fn penalize(&self, mut scores: Scores) -> Scores {
+        for (domain, score) in scores.iter_mut() {
+            let failures = self.failures.recent_failures(domain, self.window) as u64;
+            if failures > 0 {
+                // Add one, so servers with a perfect score can be penalized as well.
+                *score = score.map(|score| {
+                    let factor = u64::from(self.weight)
+                        .saturating_mul(failures)
+                        .saturating_add(100);
+                    let penalized = (u64::from(score) + 1).saturating_mul(factor) / 100;
+                    penalized.min(u64::from(u32::MAX)) as u32
+                });
+            }
+        }
+
+        scores
+    }
+}
+
+impl<'a> Benchmarker for PenaltyBenchmarker<'a> {
+    fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+        Ok(self.penalize(self.inner.benchmark(servers)?))
+    }
+
+    fn benchmark_until(
+        &self,
+        servers: &Servers,
+        deadline: Instant,
+    ) -> Result<Scores, Box<dyn std::error::Error>> {
+        Ok(self.penalize(self.inner.benchmark_until(servers, deadline)?))
+    }
+}
+
+/// Benchmarker that scores servers on their position when ordered by a
+/// [Sorter](../sorters/trait.Sorter.html), so existing Sorters can be used wherever a Benchmarker
+/// is expected. Servers the Sorter considers equal get the same score, which is
+/// [unitless](enum.ScoreUnit.html#variant.Unitless).
+///
+/// # Example
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::bench;
+/// use nordselect::sorters::LoadSorter;
+///
+/// let mut data = Servers::dummy_data();
+/// data.benchmark(&bench::from_sorter(&LoadSorter)).unwrap();
+/// let lowest = data.servers.iter().map(|server| server.load).min();
+/// assert_eq!(lowest, Some(data.perfect_server().unwrap().load));
+/// ```
+pub struct SorterBenchmarker<'a> {
+    /// The Sorter ordering the servers.
+    sorter: &'a dyn Sorter,
+}
+
+/// Turns a Sorter into a Benchmarker. See [SorterBenchmarker](struct.SorterBenchmarker.html).
+pub fn from_sorter(sorter: &dyn Sorter) -> SorterBenchmarker<'_> {
+    SorterBenchmarker { sorter }
+}
+
+impl<'a> Benchmarker for SorterBenchmarker<'a> {
+    fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+        let mut sorted: Vec<&Server> = servers.servers.iter().collect();
+        sorted.sort_by(|a, b| self.sorter.sort(a, b));
+
+        let mut scores = Scores::with_capacity(sorted.len());
+        let mut score = 0;
+        for (position, server) in sorted.iter().enumerate() {
+            if position > 0 && self.sorter.sort(sorted[position - 1], server) != Ordering::Equal {
+                score = position as u32;
+            }
+            scores.insert(
+                server.domain.clone(),
+                Score::new(score, ScoreUnit::Unitless),
+            );
+        }
+
+        Ok(scores)
+    }
+}
+
+/// Benchmarker that weighs the scores of several others, e.g. to take both the load and the
+/// latency of servers into account.
+///
+/// Scores in different units are made comparable first, see
+/// [Score::normalized](struct.Score.html#method.normalized): a full server weighs as much as
+/// 100 ms of latency. Unitless scores are spread between 0 and 1 over the servers that got one.
+/// The combined score is the weighted sum of those, in thousandths, and is unitless itself.
+/// Servers are only scored when every Benchmarker scored them.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::bench::{self, CombinedBenchmarker, LoadBenchmarker};
+/// use nordselect::sorters::BandwidthSorter;
+///
+/// let mut data = Servers::dummy_data();
+/// let benchmarker = CombinedBenchmarker::new()
+///     .add(LoadBenchmarker, 0.7)
+///     .add(bench::from_sorter(&BandwidthSorter), 0.3);
+/// data.benchmark(&benchmarker).unwrap();
+/// assert!(data.perfect_server().is_some());
+/// ```
+#[derive(Default)]
+pub struct CombinedBenchmarker<'a> {
+    /// The Benchmarkers, with the weight of their scores.
+    parts: Vec<(Box<dyn Benchmarker + 'a>, f64)>,
+}
+
+impl<'a> CombinedBenchmarker<'a> {
+    /// Creates a benchmarker without parts, which scores no server at all.
+    pub fn new() -> CombinedBenchmarker<'a> {
+        CombinedBenchmarker { parts: Vec::new() }
+    }
+
+    /// Adds the scores of the given Benchmarker, multiplied by the weight.
+    pub fn add(
+        mut self,
+        benchmarker: impl Benchmarker + 'a,
+        weight: f64,
+    ) -> CombinedBenchmarker<'a> {
+        self.parts.push((Box::new(benchmarker), weight));
+        self
+    }
+
+    /// Adds up the normalized scores of every part.
+    fn combine(&self, all_scores: Vec<Scores>) -> Scores {
+        let mut combined: HashMap<Domain, (f64, usize)> = HashMap::new();
+        for ((_, weight), scores) in self.parts.iter().zip(all_scores) {
+            let (min, max) = scores
+                .values()
+                .map(|score| score.value())
+                .fold((u32::MAX, 0), |(min, max), value| {
+                    (min.min(value), max.max(value))
+                });
+            for (domain, score) in scores {
+                let normalized = score.normalized().unwrap_or_else(|| {
+                    if max > min {
+                        f64::from(score.value() - min) / f64::from(max - min)
+                    } else {
+                        0f64
+                    }
+                });
+                let entry = combined.entry(domain).or_insert((0f64, 0));
+                entry.0 += weight * normalized;
+                entry.1 += 1;
+            }
+        }
+
+        combined
+            .into_iter()
+            .filter(|(_, (_, parts))| *parts == self.parts.len())
+            .map(|(domain, (total, _))| {
+                let value = (total * 1000f64).round().max(0f64).min(f64::from(u32::MAX));
+                (domain, Score::new(value as u32, ScoreUnit::Unitless))
+            })
+            .collect()
+    }
+}
+
+impl<'a> Benchmarker for CombinedBenchmarker<'a> {
+    fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+        let all_scores = self
+            .parts
+            .iter()
+            .map(|(benchmarker, _)| benchmarker.benchmark(servers))
+            .collect::<Result<Vec<Scores>, _>>()?;
+        Ok(self.combine(all_scores))
+    }
+
+    fn benchmark_until(
+        &self,
+        servers: &Servers,
+        deadline: Instant,
+    ) -> Result<Scores, Box<dyn std::error::Error>> {
+        let all_scores = self
+            .parts
+            .iter()
+            .map(|(benchmarker, _)| benchmarker.benchmark_until(servers, deadline))
+            .collect::<Result<Vec<Scores>, _>>()?;
+        Ok(self.combine(all_scores))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+/// How much the load, the latency and the distance of servers weigh in their score, e.g. as set
+/// in the `[scoring]` section of a [profile file](../profiles/index.html). Weights that are not
+/// given keep their default: only the load counts.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::bench::ScoringWeights;
+/// use nordselect::Servers;
+///
+/// let weights: ScoringWeights =
+///     serde_json::from_str(r#"{"load_weight": 0.6, "ping_weight": 0.4}"#).unwrap();
+/// assert_eq!(weights.distance_weight, 0f64);
+///
+/// // Without a ping test, the servers are only scored on their load.
+/// let mut data = Servers::dummy_data();
+/// data.benchmark(&weights.benchmarker(None, None)).unwrap();
+/// let lowest = data.servers.iter().map(|server| server.load).min();
+/// assert_eq!(lowest, Some(data.perfect_server().unwrap().load));
+/// ```
+pub struct ScoringWeights {
+    /// The weight of the load, as scored by [LoadBenchmarker](struct.LoadBenchmarker.html).
+    pub load_weight: f64,
+    /// The weight of the latency, e.g. as measured by a ping test.
+    pub ping_weight: f64,
+    /// The weight of the distance to the servers.
+    pub distance_weight: f64,
+}
+
+impl Default for ScoringWeights {
+    fn default() -> ScoringWeights {
+        ScoringWeights {
+            load_weight: 1f64,
+            ping_weight: 0f64,
+            distance_weight: 0f64,
+        }
+    }
+}
+
+impl ScoringWeights {
+    /// Returns a benchmarker weighing the load with the given benchmarkers of the latency and the
+    /// distance. Those are left out when they are not given or their weight is not positive. The
+    /// load is always a part, so servers are scored even when nothing else is.
+    pub fn benchmarker<'a>(
+        self,
+        ping: Option<Box<dyn Benchmarker + 'a>>,
+        distance: Option<Box<dyn Benchmarker + 'a>>,
+    ) -> CombinedBenchmarker<'a> {
+        let mut combined = CombinedBenchmarker::new().add(LoadBenchmarker, self.load_weight);
+        let parts = [(ping, self.ping_weight), (distance, self.distance_weight)];
+        for (benchmarker, weight) in parts {
+            if let Some(benchmarker) = benchmarker.filter(|_| weight > 0f64) {
+                combined = combined.add(benchmarker, weight);
+            }
+        }
+        combined
+    }
+}
+
+/// Benchmarker that prefers servers where it is night, as they tend to be less congested. The
+/// local time is estimated from the location of the server, see
+/// [locations::local_hour](../locations/fn.local_hour.html).
+///
+/// Servers are scored on how many whole hours the local time is away from the middle of the
+/// [off-peak hours](../locations/constant.OFF_PEAK_HOURS.html), times 100, plus their load. So
+/// servers where it is about as late are ordered on load. These scores are
+/// [unitless](enum.ScoreUnit.html#variant.Unitless). Servers of which the location is not known
+/// are not scored.
+///
+/// Only available with the `geo-db` feature.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::bench::OffPeakBenchmarker;
+/// use nordselect::{locations, Servers};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let mut data = Servers::dummy_data();
+/// // At 4:00 UTC, it is night in Europe and day in Asia.
+/// let four_am = UNIX_EPOCH + Duration::from_secs(4 * 3600);
+/// data.benchmark(&OffPeakBenchmarker::new().at(four_am)).unwrap();
+///
+/// let best = data.perfect_server().unwrap();
+/// let hour = locations::local_hour(locations::longitude(&best).unwrap(), four_am);
+/// assert!(3f64 < hour && hour < 5f64);
+/// ```
+#[cfg(feature = "geo-db")]
+pub struct OffPeakBenchmarker {
+    /// The moment at which the local time is looked at.
+    now: SystemTime,
+}
+
+#[cfg(feature = "geo-db")]
+impl Default for OffPeakBenchmarker {
+    fn default() -> OffPeakBenchmarker {
+        OffPeakBenchmarker::new()
+    }
+}
+
+#[cfg(feature = "geo-db")]
+impl OffPeakBenchmarker {
+    /// Creates a benchmarker looking at the local time now.
+    pub fn new() -> OffPeakBenchmarker {
+        OffPeakBenchmarker {
+            now: SystemTime::now(),
+        }
+    }
+
+    /// Looks at the local time at the given moment instead of now.
+    pub fn at(mut self, now: SystemTime) -> OffPeakBenchmarker {
+        self.now = now;
+        self
+    }
+}
+
+#[cfg(feature = "geo-db")]
+impl Benchmarker for OffPeakBenchmarker {
+    fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+        let (start, end) = locations::OFF_PEAK_HOURS;
+        let middle = f64::from(start) + f64::from((end + 24 - start) % 24) / 2f64;
+
+        Ok(servers
+            .servers
+            .iter()
+            .filter_map(|server| {
+                let hour = locations::local_hour(locations::longitude(server)?, self.now);
+                let distance = (hour - middle).rem_euclid(24f64);
+                let hours = distance.min(24f64 - distance) as u32;
+                let score = Score::new(hours * 100 + u32::from(server.load), ScoreUnit::Unitless);
+                Some((server.domain.clone(), score))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rank_ties() {
+        let mut servers = Servers::dummy_data().servers;
+        servers.truncate(3);
+        servers[0].load = 20;
+        servers[1].load = 10;
+        servers[2].load = 10;
+        let mut scores = Scores::new();
+        for server in &servers[..2] {
+            scores.insert(server.domain.clone(), Score::new(1, ScoreUnit::Unitless));
+        }
+
+        let ranked = rank(servers.clone(), &scores, ErrorPolicy::TreatAsWorst, None).unwrap();
+        assert_eq!(ranked[0].server, servers[1]);
+        assert_eq!(ranked[1].server, servers[0]);
+        assert_eq!(ranked[2].score, None);
+
+        let mut reversed = servers.clone();
+        reversed.reverse();
+        let tied = rank(reversed, &Scores::new(), ErrorPolicy::TreatAsWorst, None).unwrap();
+        let mut expected = servers[1..].to_vec();
+        expected.sort_by(|a, b| a.domain.cmp(&b.domain));
+        assert_eq!(tied[0].server, expected[0]);
+        assert_eq!(tied[2].server, servers[0]);
+    }
+
+    #[test]
+    fn seeded_ties() {
+        let mut servers = Servers::dummy_data().servers;
+        for server in servers.iter_mut() {
+            server.load = 10;
+        }
+        let first = |seed| {
+            let policy = ErrorPolicy::TreatAsWorst;
+            let ranked = rank(servers.clone(), &Scores::new(), policy, Some(seed)).unwrap();
+            ranked[0].server.domain.clone()
+        };
+
+        assert_eq!(first(1), first(1));
+        let firsts: std::collections::HashSet<Domain> = (0..20).map(first).collect();
+        assert!(firsts.len() > 1);
+    }
+
+    /// Scores servers on a fixed latency per server, in microseconds.
+    struct LatencyBenchmarker(Vec<u32>);
+
+    impl Benchmarker for LatencyBenchmarker {
+        fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+            Ok(servers
+                .servers
+                .iter()
+                .zip(&self.0)
+                .map(|(server, latency)| {
+                    let score = Score::new(*latency, ScoreUnit::Microseconds);
+                    (server.domain.clone(), score)
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn huge_penalties() {
+        let mut servers = Servers::dummy_data();
+        servers.servers.truncate(1);
+        let path = std::env::temp_dir().join("nordselect-test-huge-penalties.jsonl");
+        let _ = std::fs::remove_file(&path);
+        let mut failures = FailureLog::open(&path).unwrap();
+        failures.report(&servers.servers[0].domain).unwrap();
+        failures.report(&servers.servers[0].domain).unwrap();
+
+        let latency = LatencyBenchmarker(vec![u32::MAX]);
+        let window = Duration::from_secs(3600);
+        let scores = PenaltyBenchmarker::new(&latency, &failures, u32::MAX, window)
+            .benchmark(&servers)
+            .unwrap();
+        assert_eq!(scores[&servers.servers[0].domain].value(), u32::MAX);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn combined_units() {
+        let mut servers = Servers::dummy_data();
+        servers.servers.truncate(3);
+        servers.servers[0].load = 10;
+        servers.servers[1].load = 50;
+        servers.servers[2].load = 10;
+        // Without normalizing, 30 ms would outweigh any difference in load.
+        let latency = LatencyBenchmarker(vec![40_000, 10_000]);
+
+        let scores = CombinedBenchmarker::new()
+            .add(LoadBenchmarker, 1f64)
+            .add(&latency, 1f64)
+            .benchmark(&servers)
+            .unwrap();
+        let score = |server: usize| scores[&servers.servers[server].domain];
+        assert_eq!(score(0).unit(), ScoreUnit::Unitless);
+        assert_eq!(score(0).value(), 500);
+        assert_eq!(score(1).value(), 600);
+        assert!(score(0).is_better_than(score(1)));
+        // The third server has no latency.
+        assert_eq!(scores.len(), 2);
+    }
+
+    #[test]
+    fn sorter_scores() {
+        let mut servers = Servers::dummy_data();
+        servers.servers.truncate(3);
+        servers.servers[0].load = 20;
+        servers.servers[1].load = 10;
+        servers.servers[2].load = 10;
+
+        let scores = from_sorter(&crate::sorters::LoadSorter)
+            .benchmark(&servers)
+            .unwrap();
+        assert_eq!(scores[&servers.servers[0].domain].value(), 2);
+        assert_eq!(scores[&servers.servers[1].domain].value(), 0);
+        assert_eq!(scores[&servers.servers[2].domain].value(), 0);
+    }
+}
@@ -0,0 +1,461 @@
+//! Benchmarking servers on their ping.
+use super::icmp::{self, PingBackend};
+use super::resolve::Resolver;
+use super::{Benchmarker, Score, ScoreUnit, Scores};
+use crate::servers::{Domain, Servers};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How the latencies of the answered pings to a server are combined into one.
+pub enum Aggregation {
+    /// The middle latency, which a single slow ping hardly changes. This is the default.
+    #[default]
+    Median,
+    /// The average latency.
+    Mean,
+    /// The latency that 90% of the pings were at least as fast as.
+    P90,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The results of pinging one server a number of times.
+pub struct PingSummary {
+    /// The domain of the server.
+    pub domain: Domain,
+    /// The amount of pings sent.
+    pub sent: usize,
+    /// The latencies of the pings that were answered.
+    pub latencies: Vec<Duration>,
+    /// The path MTU to the server in bytes: the largest IPv4 packet that reaches it without
+    /// being fragmented. Only known when asked for with
+    /// [probe_mtu](struct.PingBenchmarker.html#method.probe_mtu) and the server answered.
+    pub mtu: Option<u16>,
+}
+
+impl PingSummary {
+    /// Creates an empty summary for the given domain.
+    pub fn new(domain: impl Into<Domain>) -> PingSummary {
+        PingSummary {
+            domain: domain.into(),
+            sent: 0,
+            latencies: Vec::new(),
+            mtu: None,
+        }
+    }
+
+    /// Returns the average latency of the answered pings, or `None` if none were answered.
+    pub fn average(&self) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+
+        Some(self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32)
+    }
+
+    /// Returns the median latency of the answered pings, or `None` if none were answered. With an
+    /// even amount of pings, this is the average of the two in the middle.
+    pub fn median(&self) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+
+        let mut latencies = self.latencies.clone();
+        latencies.sort_unstable();
+        let middle = latencies.len() / 2;
+        match latencies.len() % 2 {
+            0 => Some((latencies[middle - 1] + latencies[middle]) / 2),
+            _ => Some(latencies[middle]),
+        }
+    }
+
+    /// Returns the latency that the given percentage of the answered pings were at least as fast
+    /// as, or `None` if none were answered.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::bench::PingSummary;
+    /// use std::time::Duration;
+    ///
+    /// let mut summary = PingSummary::new(String::from("be1.nordvpn.com"));
+    /// summary.latencies = (1..=10).map(Duration::from_millis).collect();
+    /// assert_eq!(summary.percentile(90), Some(Duration::from_millis(9)));
+    /// assert_eq!(summary.percentile(100), Some(Duration::from_millis(10)));
+    /// ```
+    pub fn percentile(&self, percentage: u8) -> Option<Duration> {
+        let mut latencies = self.latencies.clone();
+        latencies.sort_unstable();
+        // The nearest rank: the smallest latency at least this share of the pings are below.
+        let scaled = latencies.len() * usize::from(percentage.min(100));
+        let rank = match (scaled / 100, scaled % 100) {
+            (rank, 0) => rank,
+            (rank, _) => rank + 1,
+        };
+        latencies.get(rank.max(1) - 1).copied()
+    }
+
+    /// Combines the latencies of the answered pings into one, or returns `None` if none were
+    /// answered.
+    pub fn aggregate(&self, aggregation: Aggregation) -> Option<Duration> {
+        match aggregation {
+            Aggregation::Median => self.median(),
+            Aggregation::Mean => self.average(),
+            Aggregation::P90 => self.percentile(90),
+        }
+    }
+
+    /// Returns the share of pings that were not answered, in percent.
+    pub fn loss(&self) -> f64 {
+        if self.sent == 0 {
+            return 0f64;
+        }
+
+        100f64 * (self.sent - self.latencies.len()) as f64 / self.sent as f64
+    }
+}
+
+/// How many servers are probed for their path MTU at the same time.
+const PARALLEL_MTU_PROBES: usize = 32;
+
+/// By default, every percent of pings lost raises the score of a server by this many percent.
+pub const DEFAULT_LOSS_PENALTY: u32 = 5;
+
+/// Turns summaries into scores: the combined latency in microseconds, raised by `loss_penalty`
+/// percent for every percent of pings lost. Servers without answered pings are left out.
+pub(crate) fn summaries_to_scores(
+    summaries: &[PingSummary],
+    aggregation: Aggregation,
+    loss_penalty: u32,
+) -> Scores {
+    summaries
+        .iter()
+        .filter_map(|summary| {
+            let latency = summary.aggregate(aggregation)?.as_micros() as f64;
+            let factor = 1f64 + f64::from(loss_penalty) * summary.loss() / 100f64;
+            let score = (latency * factor).min(f64::from(u32::MAX)) as u32;
+            Some((
+                summary.domain.clone(),
+                Score::new(score, ScoreUnit::Microseconds),
+            ))
+        })
+        .collect()
+}
+
+/// Benchmarker that pings servers a given amount of times.
+///
+/// Like the [PingSorter](../../sorters/struct.PingSorter.html), this requires the privilege to
+/// send ping packets, unless the system allows unprivileged ICMP datagram sockets. See
+/// [PingBackend](../enum.PingBackend.html).
+pub struct PingBenchmarker {
+    /// How many times every server is pinged.
+    tries: usize,
+    /// Whether servers are pinged one by one instead of all at once.
+    one_by_one: bool,
+    /// Finds the addresses of the servers, instead of leaving that to the ping library.
+    resolver: Option<Arc<dyn Resolver + Send + Sync>>,
+    /// How the pings are sent.
+    backend: PingBackend,
+    /// How the latencies of every server are combined into its score.
+    aggregation: Aggregation,
+    /// How many percent the score rises for every percent of pings lost.
+    loss_penalty: u32,
+    /// Whether the path MTU to every server is probed as well.
+    probe_mtu: bool,
+}
+
+impl PingBenchmarker {
+    /// Creates a PingBenchmarker that pings all servers simultaneously, `tries` times. This is
+    /// less precise, but is faster to run.
+    pub fn new(tries: usize) -> PingBenchmarker {
+        PingBenchmarker {
+            tries,
+            one_by_one: false,
+            resolver: None,
+            backend: PingBackend::detect(),
+            aggregation: Aggregation::default(),
+            loss_penalty: DEFAULT_LOSS_PENALTY,
+            probe_mtu: false,
+        }
+    }
+
+    /// Pings servers after one another instead. This is more precise, but takes significantly
+    /// longer.
+    pub fn one_by_one(mut self) -> PingBenchmarker {
+        self.one_by_one = true;
+        self
+    }
+
+    /// Resolves the domains of the servers using the given resolver. Servers that cannot be
+    /// resolved are not pinged, instead of failing the whole test.
+    pub fn resolver(mut self, resolver: Arc<dyn Resolver + Send + Sync>) -> PingBenchmarker {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    /// Sends the pings using the given backend, instead of the one detected when creating the
+    /// benchmarker.
+    pub fn backend(mut self, backend: PingBackend) -> PingBenchmarker {
+        self.backend = backend;
+        self
+    }
+
+    /// Changes how the latencies of every server are combined into its score, which is the
+    /// median by default.
+    pub fn aggregation(mut self, aggregation: Aggregation) -> PingBenchmarker {
+        self.aggregation = aggregation;
+        self
+    }
+
+    /// Changes how many percent the score of a server rises for every percent of pings it lost,
+    /// which is [DEFAULT_LOSS_PENALTY](constant.DEFAULT_LOSS_PENALTY.html) by default. Use 0 to
+    /// only look at the latency.
+    pub fn loss_penalty(mut self, loss_penalty: u32) -> PingBenchmarker {
+        self.loss_penalty = loss_penalty;
+        self
+    }
+
+    /// Also probes the path MTU to every server that answered, see
+    /// [PingSummary::mtu](struct.PingSummary.html#structfield.mtu). This sends pings of
+    /// different sizes that may not be fragmented, which takes a few seconds per server and is
+    /// only supported on Linux.
+    ///
+    /// WireGuard adds 60 bytes to every packet over IPv4, so its MTU should be at most the path
+    /// MTU minus 60.
+    pub fn probe_mtu(mut self) -> PingBenchmarker {
+        self.probe_mtu = true;
+        self
+    }
+
+    /// Returns the backend the pings are sent with.
+    pub fn used_backend(&self) -> PingBackend {
+        self.backend
+    }
+
+    /// Pings all servers and returns the results for every server, in the order of `servers`.
+    pub fn measure(
+        &self,
+        servers: &Servers,
+    ) -> Result<Vec<PingSummary>, Box<dyn std::error::Error>> {
+        self.measure_impl(servers, None)
+    }
+
+    /// Same as [measure](#method.measure), but stops sending pings once the given deadline has
+    /// passed. Servers that were not pinged in time have no pings sent.
+    pub fn measure_until(
+        &self,
+        servers: &Servers,
+        deadline: Instant,
+    ) -> Result<Vec<PingSummary>, Box<dyn std::error::Error>> {
+        self.measure_impl(servers, Some(deadline))
+    }
+
+    fn measure_impl(
+        &self,
+        servers: &Servers,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<PingSummary>, Box<dyn std::error::Error>> {
+        let mut summaries: HashMap<Domain, PingSummary> = servers
+            .servers
+            .iter()
+            .map(|server| {
+                (
+                    server.domain.clone(),
+                    PingSummary::new(server.domain.clone()),
+                )
+            })
+            .collect();
+        let deadline_passed = || match deadline {
+            Some(deadline) => Instant::now() >= deadline,
+            None => false,
+        };
+
+        // The hosts to ping, together with the domains they belong to.
+        let mut resolved: Vec<(String, &str)> = Vec::with_capacity(servers.servers.len());
+        for server in &servers.servers {
+            let host = match &self.resolver {
+                None => server.domain.to_string(),
+                Some(resolver) => match resolver.resolve(&server.domain) {
+                    Ok(address) => address.to_string(),
+                    Err(_) => {
+                        if let Some(summary) = summaries.get_mut(&server.domain) {
+                            summary.sent = self.tries;
+                        }
+                        continue;
+                    }
+                },
+            };
+            resolved.push((host, &server.domain));
+        }
+        let domains: HashMap<&str, &str> = resolved
+            .iter()
+            .map(|(host, domain)| (host.as_str(), *domain))
+            .collect();
+
+        let all_hosts = resolved.iter().map(|(host, _)| host.as_str());
+        let hosts: Vec<Vec<&str>> = if self.one_by_one {
+            all_hosts.map(|host| vec![host]).collect()
+        } else {
+            vec![all_hosts.collect()]
+        };
+
+        'hosts: for hosts in hosts {
+            for _ in 0..self.tries {
+                if deadline_passed() {
+                    break 'hosts;
+                }
+
+                for reply in icmp::ping_once(self.backend, &hosts)? {
+                    let domain = domains
+                        .get(reply.host.as_str())
+                        .or_else(|| domains.get(reply.address.as_str()));
+                    if let Some(summary) = domain.and_then(|domain| summaries.get_mut(*domain)) {
+                        summary.sent += 1;
+                        summary.latencies.extend(reply.latency);
+                    }
+                }
+            }
+        }
+
+        if self.probe_mtu {
+            // Only servers that answered can tell which packets reach them.
+            let answered: Vec<(String, String)> = resolved
+                .iter()
+                .filter(|(_, domain)| match summaries.get(*domain) {
+                    Some(summary) => !summary.latencies.is_empty(),
+                    None => false,
+                })
+                .map(|(host, domain)| (host.clone(), domain.to_string()))
+                .collect();
+            for chunk in answered.chunks(PARALLEL_MTU_PROBES) {
+                let handles: Vec<_> = chunk
+                    .iter()
+                    .cloned()
+                    .map(|(host, domain)| {
+                        thread::spawn(move || {
+                            let mtu = icmp::path_mtu(&host).map_err(|err| err.to_string());
+                            (domain, mtu)
+                        })
+                    })
+                    .collect();
+                for handle in handles {
+                    let (domain, mtu) = match handle.join() {
+                        Ok(probed) => probed,
+                        Err(_) => continue,
+                    };
+                    if let Some(summary) = summaries.get_mut(domain.as_str()) {
+                        summary.mtu = mtu?;
+                    }
+                }
+            }
+        }
+
+        Ok(servers
+            .servers
+            .iter()
+            .filter_map(|server| summaries.remove(&server.domain))
+            .collect())
+    }
+}
+
+impl Benchmarker for PingBenchmarker {
+    /// Scores servers on their combined ping in microseconds, raised for lost pings.
+    fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+        Ok(summaries_to_scores(
+            &self.measure(servers)?,
+            self.aggregation,
+            self.loss_penalty,
+        ))
+    }
+
+    fn benchmark_until(
+        &self,
+        servers: &Servers,
+        deadline: Instant,
+    ) -> Result<Scores, Box<dyn std::error::Error>> {
+        Ok(summaries_to_scores(
+            &self.measure_until(servers, deadline)?,
+            self.aggregation,
+            self.loss_penalty,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_statistics() {
+        let mut summary = PingSummary::new(String::from("be1.nordvpn.com"));
+        assert_eq!(summary.average(), None);
+        assert_eq!(summary.loss(), 0f64);
+
+        summary.sent = 4;
+        summary.latencies = vec![Duration::from_millis(10), Duration::from_millis(20)];
+        assert_eq!(summary.average(), Some(Duration::from_millis(15)));
+        assert_eq!(summary.loss(), 50f64);
+
+        let scores = summaries_to_scores(
+            &[summary, PingSummary::new(String::from("be2"))],
+            Aggregation::Mean,
+            0,
+        );
+        assert_eq!(scores.len(), 1);
+        assert_eq!(scores["be1.nordvpn.com"].value(), 15000);
+    }
+
+    #[test]
+    fn aggregations() {
+        let mut summary = PingSummary::new(String::from("be1.nordvpn.com"));
+        assert_eq!(summary.aggregate(Aggregation::Median), None);
+        assert_eq!(summary.aggregate(Aggregation::P90), None);
+
+        // One slow ping ruins the mean, but not the median.
+        summary.latencies = [12, 10, 11, 1000, 13]
+            .iter()
+            .map(|ms| Duration::from_millis(*ms))
+            .collect();
+        summary.sent = 5;
+        assert_eq!(
+            summary.aggregate(Aggregation::Mean),
+            Some(Duration::from_micros(209_200))
+        );
+        assert_eq!(
+            summary.aggregate(Aggregation::Median),
+            Some(Duration::from_millis(12))
+        );
+        assert_eq!(
+            summary.aggregate(Aggregation::P90),
+            Some(Duration::from_millis(1000))
+        );
+
+        summary.latencies.pop();
+        assert_eq!(summary.median(), Some(Duration::from_micros(11_500)));
+        assert_eq!(summary.percentile(50), Some(Duration::from_millis(11)));
+
+        let scores = summaries_to_scores(&[summary], Aggregation::default(), 0);
+        assert_eq!(scores["be1.nordvpn.com"].value(), 11500);
+    }
+
+    #[test]
+    fn loss_penalty() {
+        let mut lossy = PingSummary::new(String::from("be1.nordvpn.com"));
+        lossy.sent = 5;
+        lossy.latencies = vec![Duration::from_millis(20); 4];
+        let mut lossless = PingSummary::new(String::from("be2.nordvpn.com"));
+        lossless.sent = 5;
+        lossless.latencies = vec![Duration::from_millis(25); 5];
+        let summaries = [lossy, lossless];
+
+        // Losing 20% of the pings doubles the score.
+        let scores = summaries_to_scores(&summaries, Aggregation::Median, DEFAULT_LOSS_PENALTY);
+        assert_eq!(scores["be1.nordvpn.com"].value(), 40000);
+        assert_eq!(scores["be2.nordvpn.com"].value(), 25000);
+
+        let scores = summaries_to_scores(&summaries, Aggregation::Median, 0);
+        assert_eq!(scores["be1.nordvpn.com"].value(), 20000);
+    }
+}
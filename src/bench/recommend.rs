@@ -0,0 +1,216 @@
+//! Benchmarking servers on the ranking of the servers NordVPN recommends itself.
+use super::{Benchmarker, CombinedBenchmarker, Score, ScoreUnit, Scores};
+use crate::api::{self, ApiClient};
+use crate::countries;
+use crate::servers::Servers;
+use serde_derive::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+
+/// How many servers are asked for in every country by default.
+pub const DEFAULT_RECOMMENDATIONS: usize = 50;
+
+/// A server as listed by the recommendation endpoint, of which only the domain is needed.
+#[derive(Deserialize)]
+struct Recommendation {
+    hostname: String,
+}
+
+/// Benchmarker that scores servers on their position in the recommendations of the NordVPN
+/// API, which is how the official apps pick a server.
+///
+/// The recommendations of every country of the servers are asked for separately, so servers are
+/// ranked against the other servers in their country. The best recommended server of every
+/// country scores 0, the next one 1, and so on. Servers that are not recommended score the
+/// [limit](#method.limit), after all recommended ones. Servers in countries unknown to the API
+/// are not scored. Scores are [unitless](enum.ScoreUnit.html#variant.Unitless).
+///
+/// The recommendations take the location of the one asking into account, so they differ from
+/// place to place.
+///
+/// # Example
+///
+/// ```no_run
+/// use nordselect::bench::{LoadBenchmarker, NordRecommendationBenchmarker};
+/// use nordselect::filters::CountryFilter;
+/// use nordselect::Servers;
+///
+/// let mut data = Servers::from_api().unwrap();
+/// data.filter(&CountryFilter::from("NL"));
+///
+/// // Follow NordVPN, but keep an eye on the load.
+/// let benchmarker = NordRecommendationBenchmarker::new().blend(LoadBenchmarker, 0.3);
+/// data.benchmark(&benchmarker).unwrap();
+/// println!("{}", data.perfect_server().unwrap().domain);
+/// ```
+pub struct NordRecommendationBenchmarker {
+    /// The client asking the API.
+    client: ApiClient,
+    /// How many servers are asked for in every country.
+    limit: usize,
+}
+
+impl Default for NordRecommendationBenchmarker {
+    fn default() -> NordRecommendationBenchmarker {
+        NordRecommendationBenchmarker::new()
+    }
+}
+
+impl NordRecommendationBenchmarker {
+    /// Creates a benchmarker asking for the
+    /// [DEFAULT_RECOMMENDATIONS](constant.DEFAULT_RECOMMENDATIONS.html) best servers of every
+    /// country, using a default client.
+    pub fn new() -> NordRecommendationBenchmarker {
+        NordRecommendationBenchmarker {
+            client: ApiClient::default(),
+            limit: DEFAULT_RECOMMENDATIONS,
+        }
+    }
+
+    /// Sends the requests using the given client instead, e.g. to limit how often the API is
+    /// asked.
+    pub fn client(mut self, client: ApiClient) -> NordRecommendationBenchmarker {
+        self.client = client;
+        self
+    }
+
+    /// Changes how many servers are asked for in every country.
+    pub fn limit(mut self, limit: usize) -> NordRecommendationBenchmarker {
+        self.limit = limit;
+        self
+    }
+
+    /// Returns the domains of the servers the API recommends in the countries of the given
+    /// servers, best first, by country.
+    pub fn recommendations(
+        &self,
+        servers: &Servers,
+    ) -> Result<HashMap<String, Vec<String>>, Box<dyn std::error::Error>> {
+        let flags: BTreeSet<&str> = servers
+            .servers
+            .iter()
+            .map(|server| server.flag.as_str())
+            .collect();
+
+        let mut recommendations = HashMap::new();
+        for country in flags {
+            let id = match countries::nord_id(country) {
+                Some(id) => id,
+                None => continue,
+            };
+            let endpoint = api::v1_recommendations_endpoint(id, self.limit);
+            let text = self
+                .client
+                .get(&endpoint)
+                .and_then(|response| response.error_for_status())
+                .map_err(|err| err.to_string())
+                .and_then(|response| response.text().map_err(|err| err.to_string()))
+                .map_err(|err| format!("{}: {}", endpoint, err))?;
+            let servers: Vec<Recommendation> = serde_json::from_str(&text)?;
+            recommendations.insert(
+                country.to_string(),
+                servers.into_iter().map(|server| server.hostname).collect(),
+            );
+        }
+
+        Ok(recommendations)
+    }
+
+    /// Returns a benchmarker weighing the recommendations with the scores of another
+    /// Benchmarker, which weigh `weight` and the recommendations `1 - weight`. See
+    /// [CombinedBenchmarker](struct.CombinedBenchmarker.html).
+    pub fn blend<'a>(self, local: impl Benchmarker + 'a, weight: f64) -> CombinedBenchmarker<'a> {
+        CombinedBenchmarker::new()
+            .add(self, 1f64 - weight)
+            .add(local, weight)
+    }
+}
+
+impl Benchmarker for NordRecommendationBenchmarker {
+    fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+        let recommendations = self.recommendations(servers)?;
+        let positions: HashMap<&str, usize> = recommendations
+            .values()
+            .flat_map(|domains| domains.iter().enumerate())
+            .map(|(position, domain)| (domain.as_str(), position))
+            .collect();
+
+        Ok(servers
+            .servers
+            .iter()
+            .filter(|server| recommendations.contains_key(server.flag.as_str()))
+            .map(|server| {
+                let position = positions
+                    .get(server.domain.as_str())
+                    .copied()
+                    .unwrap_or(self.limit);
+                let score = Score::new(position as u32, ScoreUnit::Unitless);
+                (server.domain.clone(), score)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::LoadBenchmarker;
+    use crate::mock_api::{MockApi, Response};
+    use crate::servers::Domain;
+
+    #[test]
+    fn scores_follow_recommendations() {
+        let mut data = Servers::dummy_data();
+        data.servers
+            .retain(|server| server.flag == "BE" || server.flag == "NL");
+        let belgian: Vec<Domain> = data
+            .servers
+            .iter()
+            .filter(|server| server.flag == "BE")
+            .map(|server| server.domain.clone())
+            .collect();
+        assert!(belgian.len() >= 3);
+
+        // The API recommends two of the Belgian servers, the last one first, and no Dutch one.
+        let recommended = [belgian[belgian.len() - 1].clone(), belgian[1].clone()];
+        let body = serde_json::to_string(
+            &recommended
+                .iter()
+                .map(|domain| serde_json::json!({ "hostname": domain, "load": 90 }))
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+        let api = MockApi::start(move |request| {
+            if request.parameter("filters[country_id]") == Some("21") {
+                Response::ok(body.clone())
+            } else {
+                Response::ok("[]")
+            }
+        });
+
+        let benchmarker = NordRecommendationBenchmarker::new()
+            .client(ApiClient::new().api_base(api.base()))
+            .limit(10);
+        let scores = benchmarker.benchmark(&data).unwrap();
+        assert_eq!(scores.len(), data.servers.len());
+        assert_eq!(scores[&recommended[0]].value(), 0);
+        assert_eq!(scores[&recommended[1]].value(), 1);
+        assert_eq!(scores[&belgian[0]].value(), 10);
+
+        let requests = api.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|request| request
+            .path
+            .starts_with("/v1/servers/recommendations?")
+            && request.parameter("limit") == Some("10")));
+
+        // Blended with the load, the recommendations still win when they weigh the most.
+        data.benchmark(&benchmarker.blend(LoadBenchmarker, 0.1))
+            .unwrap();
+        assert_eq!(data.perfect_server().unwrap().domain, recommended[0]);
+
+        let api = MockApi::start(|_| Response::status(500));
+        let benchmarker =
+            NordRecommendationBenchmarker::new().client(ApiClient::new().api_base(api.base()));
+        assert!(benchmarker.benchmark(&Servers::dummy_data()).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+//! Resolving the domains of servers before they are benchmarked.
+//!
+//! By default, the resolver of the system is used. On networks where it does not work, e.g.
+//! behind a captive portal, another DNS server can be asked instead, or addresses can be given
+//! up front.
+use std::collections::HashMap;
+use std::iter::FromIterator;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A way to find the IP address of a server.
+pub trait Resolver {
+    /// Returns an IP address of the given domain.
+    fn resolve(&self, domain: &str) -> Result<IpAddr, Box<dyn std::error::Error>>;
+}
+
+/// Resolver using the resolver of the operating system.
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, domain: &str) -> Result<IpAddr, Box<dyn std::error::Error>> {
+        (domain, 0)
+            .to_socket_addrs()?
+            .next()
+            .map(|address| address.ip())
+            .ok_or_else(|| format!("{} has no addresses", domain).into())
+    }
+}
+
+/// The port DNS servers listen on.
+const DNS_PORT: u16 = 53;
+
+/// Resolver asking a given DNS server for the IPv4 address of a domain, over UDP.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::bench::resolve::DnsResolver;
+/// use std::time::Duration;
+///
+/// let resolver = DnsResolver::new("1.1.1.1".parse().unwrap()).timeout(Duration::from_secs(1));
+/// ```
+pub struct DnsResolver {
+    /// The DNS server to ask.
+    server: SocketAddr,
+    /// How long to wait for an answer.
+    timeout: Duration,
+}
+
+impl DnsResolver {
+    /// Creates a resolver asking the DNS server at the given address, waiting up to two seconds
+    /// for an answer.
+    pub fn new(server: IpAddr) -> DnsResolver {
+        DnsResolver {
+            server: SocketAddr::new(server, DNS_PORT),
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    /// Changes the port the DNS server listens on.
+    pub fn port(mut self, port: u16) -> DnsResolver {
+        self.server.set_port(port);
+        self
+    }
+
+    /// Changes how long to wait for an answer.
+    pub fn timeout(mut self, timeout: Duration) -> DnsResolver {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Resolver for DnsResolver {
+    fn resolve(&self, domain: &str) -> Result<IpAddr, Box<dyn std::error::Error>> {
+        let local: IpAddr = match self.server {
+            SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+            SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+        };
+        let socket = UdpSocket::bind(SocketAddr::new(local, 0))?;
+        socket.set_read_timeout(Some(self.timeout))?;
+        socket.connect(self.server)?;
+
+        // The id only has to tell answers to this query apart from stray packets.
+        let id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.subsec_nanos() as u16)
+            .unwrap_or(0);
+        socket.send(&query(id, domain)?)?;
+
+        let mut buffer = [0u8; 1500];
+        loop {
+            let length = socket.recv(&mut buffer)?;
+            if let Some(answer) = parse_answer(id, &buffer[..length]) {
+                return answer
+                    .map(IpAddr::V4)
+                    .map_err(|err| format!("{}: {}", domain, err).into());
+            }
+        }
+    }
+}
+
+/// Builds a DNS query for the A record of the domain.
+fn query(id: u16, domain: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut packet = Vec::with_capacity(18 + domain.len());
+    packet.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired, one question.
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in domain.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("{} is not a valid domain", domain).into());
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    // Type A, class IN.
+    packet.extend_from_slice(&[0, 0, 1, 0, 1]);
+
+    Ok(packet)
+}
+
+/// Returns the position right after the (possibly compressed) name starting at `position`.
+fn skip_name(packet: &[u8], mut position: usize) -> Option<usize> {
+    loop {
+        let length = *packet.get(position)?;
+        if length & 0xc0 == 0xc0 {
+            return Some(position + 2);
+        }
+        position += 1;
+        if length == 0 {
+            return Some(position);
+        }
+        position += usize::from(length);
+    }
+}
+
+/// Reads the first IPv4 address from an answer to the query with the given id. Returns `None`
+/// if the packet is not an answer to that query.
+fn parse_answer(id: u16, packet: &[u8]) -> Option<Result<Ipv4Addr, String>> {
+    let read_u16 = |position: usize| -> Option<u16> {
+        Some(u16::from_be_bytes([
+            *packet.get(position)?,
+            *packet.get(position + 1)?,
+        ]))
+    };
+
+    let is_answer = packet.get(2).map(|flags| flags & 0x80 != 0);
+    if read_u16(0) != Some(id) || is_answer != Some(true) {
+        return None;
+    }
+    match read_u16(2).map(|flags| flags & 0x000f) {
+        Some(0) => {}
+        Some(3) => return Some(Err(String::from("the domain does not exist"))),
+        Some(code) => return Some(Err(format!("the DNS server answered with error {}", code))),
+        None => return None,
+    }
+
+    let mut position = 12;
+    for _ in 0..read_u16(4)? {
+        position = skip_name(packet, position)? + 4;
+    }
+    for _ in 0..read_u16(6)? {
+        position = skip_name(packet, position)?;
+        let record_type = read_u16(position)?;
+        let length = usize::from(read_u16(position + 8)?);
+        let data = packet.get(position + 10..position + 10 + length)?;
+        if record_type == 1 && length == 4 {
+            return Some(Ok(Ipv4Addr::new(data[0], data[1], data[2], data[3])));
+        }
+        position += 10 + length;
+    }
+
+    Some(Err(String::from("no IPv4 address found")))
+}
+
+#[derive(Default)]
+/// Resolver that uses addresses given up front, e.g. from an earlier lookup. Other domains are
+/// passed on to the fallback resolver, if there is one.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::bench::resolve::{Resolver, StaticResolver};
+/// use std::net::IpAddr;
+///
+/// let address: IpAddr = "192.0.2.1".parse().unwrap();
+/// let resolver: StaticResolver =
+///     vec![(String::from("be1.nordvpn.com"), address)].into_iter().collect();
+/// assert_eq!(resolver.resolve("be1.nordvpn.com").unwrap(), address);
+/// assert!(resolver.resolve("be2.nordvpn.com").is_err());
+/// ```
+pub struct StaticResolver {
+    /// The addresses of the known domains.
+    addresses: HashMap<String, IpAddr>,
+    /// The resolver used for other domains.
+    fallback: Option<Box<dyn Resolver + Send + Sync>>,
+}
+
+impl StaticResolver {
+    /// Creates a resolver without any addresses.
+    pub fn new() -> StaticResolver {
+        StaticResolver::default()
+    }
+
+    /// Adds the address of a domain, replacing the previous one.
+    pub fn insert(&mut self, domain: impl Into<String>, address: IpAddr) {
+        self.addresses.insert(domain.into(), address);
+    }
+
+    /// Passes domains without a known address on to the given resolver.
+    pub fn fallback(mut self, resolver: impl Resolver + Send + Sync + 'static) -> StaticResolver {
+        self.fallback = Some(Box::new(resolver));
+        self
+    }
+}
+
+impl FromIterator<(String, IpAddr)> for StaticResolver {
+    fn from_iter<I: IntoIterator<Item = (String, IpAddr)>>(iter: I) -> StaticResolver {
+        StaticResolver {
+            addresses: iter.into_iter().collect(),
+            fallback: None,
+        }
+    }
+}
+
+impl Resolver for StaticResolver {
+    fn resolve(&self, domain: &str) -> Result<IpAddr, Box<dyn std::error::Error>> {
+        match (self.addresses.get(domain), &self.fallback) {
+            (Some(address), _) => Ok(*address),
+            (None, Some(fallback)) => fallback.resolve(domain),
+            (None, None) => Err(format!("no address known for {}", domain).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn dns_lookup() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let mut buffer = [0u8; 512];
+            let (length, client) = server.recv_from(&mut buffer).unwrap();
+            let mut answer = buffer[..length].to_vec();
+            // An answer without errors, to the one question, with one record.
+            answer[2] |= 0x80;
+            answer[7] = 1;
+            answer.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 7]);
+            server.send_to(&answer, client).unwrap();
+        });
+
+        let resolver = DnsResolver::new(Ipv4Addr::LOCALHOST.into()).port(port);
+        let address = resolver.resolve("be1.nordvpn.com").unwrap();
+        assert_eq!(address, IpAddr::from([192, 0, 2, 7]));
+        handle.join().unwrap();
+
+        assert!(query(1, "be1..nordvpn.com").is_err());
+        let mut not_found = query(1, "be1.nordvpn.com").unwrap();
+        not_found[2] |= 0x80;
+        not_found[3] |= 3;
+        assert!(parse_answer(2, &not_found).is_none());
+        assert!(parse_answer(1, &not_found).unwrap().is_err());
+    }
+}
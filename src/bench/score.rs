@@ -0,0 +1,153 @@
+//! The scores Benchmarkers give, together with what they count.
+use std::cmp::Ordering;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// What a [Score](struct.Score.html) counts.
+pub enum ScoreUnit {
+    /// A load, in percent.
+    Percent,
+    /// A load, in hundredths of a percent.
+    BasisPoints,
+    /// A duration, in microseconds, e.g. a latency.
+    Microseconds,
+    /// A distance, in meters.
+    Meters,
+    /// A number that only means something compared to the other scores of the same Benchmarker,
+    /// e.g. a position in an ordered list.
+    Unitless,
+}
+
+impl ScoreUnit {
+    /// Returns the amount of this unit that is considered as bad as the same amount of any other
+    /// unit: a full server, 100 ms of latency or 1000 km of distance. Returns `None` for
+    /// [Unitless](#variant.Unitless) scores, which cannot be compared to others.
+    pub fn scale(self) -> Option<f64> {
+        match self {
+            ScoreUnit::Percent => Some(100f64),
+            ScoreUnit::BasisPoints => Some(10_000f64),
+            ScoreUnit::Microseconds => Some(100_000f64),
+            ScoreUnit::Meters => Some(1_000_000f64),
+            ScoreUnit::Unitless => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The score a [Benchmarker](trait.Benchmarker.html) gave a server. Lower scores are better.
+///
+/// Scores only compare to scores in the same unit: a score of 30 percent load is not better or
+/// worse than one of 20 microseconds. To weigh scores in different units against each other,
+/// compare their [normalized](#method.normalized) values.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::bench::{Score, ScoreUnit};
+///
+/// let low_load = Score::new(20, ScoreUnit::Percent);
+/// let high_load = Score::new(80, ScoreUnit::Percent);
+/// let fast = Score::new(15_000, ScoreUnit::Microseconds);
+///
+/// assert!(low_load.is_better_than(high_load));
+/// assert!(!low_load.is_better_than(fast) && !fast.is_better_than(low_load));
+/// assert_eq!(low_load.partial_cmp(&fast), None);
+///
+/// // 15 ms is less bad than a server that is 20% full.
+/// assert!(fast.normalized().unwrap() < low_load.normalized().unwrap());
+/// ```
+pub struct Score {
+    /// The amount of the unit.
+    value: u32,
+    /// What the value counts.
+    unit: ScoreUnit,
+}
+
+impl Score {
+    /// Creates a score of `value` times the unit.
+    pub fn new(value: u32, unit: ScoreUnit) -> Score {
+        Score { value, unit }
+    }
+
+    /// Returns the amount of the unit.
+    pub fn value(self) -> u32 {
+        self.value
+    }
+
+    /// Returns what the score counts.
+    pub fn unit(self) -> ScoreUnit {
+        self.unit
+    }
+
+    /// Returns whether this score is better, so lower, than the other one. Scores in different
+    /// units are never better than each other.
+    pub fn is_better_than(self, other: Score) -> bool {
+        self < other
+    }
+
+    /// Returns the score divided by the [scale](enum.ScoreUnit.html#method.scale) of its unit, so
+    /// scores in different units can be weighed against each other. Returns `None` for unitless
+    /// scores.
+    pub fn normalized(self) -> Option<f64> {
+        self.unit.scale().map(|scale| f64::from(self.value) / scale)
+    }
+
+    /// Returns the score with its value changed by `change`, in the same unit.
+    pub(crate) fn map(self, change: impl FnOnce(u32) -> u32) -> Score {
+        Score {
+            value: change(self.value),
+            unit: self.unit,
+        }
+    }
+}
+
+/// Scores in the same unit are ordered on their value, lower being better. Scores in different
+/// units are not ordered.
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Score) -> Option<Ordering> {
+        if self.unit == other.unit {
+            Some(self.value.cmp(&other.value))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    /// Writes the score in a unit fit for people, e.g. `12.3 ms`.
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let value = f64::from(self.value);
+        match self.unit {
+            ScoreUnit::Percent => write!(formatter, "{}%", self.value),
+            ScoreUnit::BasisPoints => write!(formatter, "{:.2}%", value / 100f64),
+            ScoreUnit::Microseconds => write!(formatter, "{:.1} ms", value / 1000f64),
+            ScoreUnit::Meters => write!(formatter, "{:.1} km", value / 1000f64),
+            ScoreUnit::Unitless => write!(formatter, "{}", self.value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_units() {
+        assert_eq!(Score::new(42, ScoreUnit::Percent).to_string(), "42%");
+        assert_eq!(
+            Score::new(4250, ScoreUnit::BasisPoints).to_string(),
+            "42.50%"
+        );
+        assert_eq!(
+            Score::new(12_345, ScoreUnit::Microseconds).to_string(),
+            "12.3 ms"
+        );
+        assert_eq!(Score::new(1_500, ScoreUnit::Meters).to_string(), "1.5 km");
+        assert_eq!(Score::new(3, ScoreUnit::Unitless).to_string(), "3");
+        assert_eq!(Score::new(3, ScoreUnit::Unitless).normalized(), None);
+        assert_eq!(
+            Score::new(5_000, ScoreUnit::BasisPoints).normalized(),
+            Score::new(50, ScoreUnit::Percent).normalized()
+        );
+    }
+}
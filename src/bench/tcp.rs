@@ -0,0 +1,179 @@
+//! Benchmarking servers on the time it takes to open a TCP connection.
+use super::ping::{summaries_to_scores, Aggregation, PingSummary, DEFAULT_LOSS_PENALTY};
+use super::resolve::{Resolver, SystemResolver};
+use super::{Benchmarker, Scores};
+use crate::servers::{Server, Servers};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How many servers are probed at the same time.
+const PARALLEL_PROBES: usize = 32;
+
+#[derive(Clone)]
+/// Benchmarker that measures how long it takes to open a TCP connection to every server.
+///
+/// Unlike ping tests, this does not require any special privileges.
+pub struct TcpBenchmarker {
+    /// How many times every server is probed.
+    tries: usize,
+    /// The port to connect to.
+    port: u16,
+    /// How long to wait for a connection.
+    timeout: Duration,
+    /// Finds the addresses of the servers.
+    resolver: Arc<dyn Resolver + Send + Sync>,
+    /// How the connection times of every server are combined into its score.
+    aggregation: Aggregation,
+    /// How many percent the score rises for every percent of failed connections.
+    loss_penalty: u32,
+}
+
+impl TcpBenchmarker {
+    /// Creates a TcpBenchmarker that connects to port 443 of every server `tries` times, waiting
+    /// up to a second for every connection.
+    pub fn new(tries: usize) -> TcpBenchmarker {
+        TcpBenchmarker {
+            tries,
+            port: 443,
+            timeout: Duration::from_secs(1),
+            resolver: Arc::new(SystemResolver),
+            aggregation: Aggregation::default(),
+            loss_penalty: DEFAULT_LOSS_PENALTY,
+        }
+    }
+
+    /// Changes the port to connect to.
+    pub fn port(mut self, port: u16) -> TcpBenchmarker {
+        self.port = port;
+        self
+    }
+
+    /// Changes how long to wait for a connection.
+    pub fn timeout(mut self, timeout: Duration) -> TcpBenchmarker {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Changes how the addresses of the servers are found, which is the resolver of the system
+    /// by default.
+    pub fn resolver(mut self, resolver: Arc<dyn Resolver + Send + Sync>) -> TcpBenchmarker {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Changes how the connection times of every server are combined into its score, which is
+    /// the median by default.
+    pub fn aggregation(mut self, aggregation: Aggregation) -> TcpBenchmarker {
+        self.aggregation = aggregation;
+        self
+    }
+
+    /// Changes how many percent the score of a server rises for every percent of connections
+    /// that failed, which is [DEFAULT_LOSS_PENALTY](../ping/constant.DEFAULT_LOSS_PENALTY.html) by
+    /// default.
+    pub fn loss_penalty(mut self, loss_penalty: u32) -> TcpBenchmarker {
+        self.loss_penalty = loss_penalty;
+        self
+    }
+
+    fn probe(&self, server: &Server) -> PingSummary {
+        let (tries, port, timeout) = (self.tries, self.port, self.timeout);
+        let mut summary = PingSummary::new(server.domain.clone());
+        let address = match resolve(server, port, &*self.resolver) {
+            Some(address) => address,
+            None => {
+                summary.sent = tries;
+                return summary;
+            }
+        };
+
+        for _ in 0..tries {
+            let start = Instant::now();
+            summary.sent += 1;
+            if TcpStream::connect_timeout(&address, timeout).is_ok() {
+                summary.latencies.push(start.elapsed());
+            }
+        }
+
+        summary
+    }
+
+    /// Probes all servers and returns the results for every server, in the order of `servers`.
+    pub fn measure(&self, servers: &Servers) -> Vec<PingSummary> {
+        let mut summaries = Vec::with_capacity(servers.servers.len());
+        for chunk in servers.servers.chunks(PARALLEL_PROBES) {
+            let handles: Vec<_> = chunk
+                .iter()
+                .cloned()
+                .map(|server| {
+                    let benchmarker = self.clone();
+                    thread::spawn(move || benchmarker.probe(&server))
+                })
+                .collect();
+            summaries.extend(handles.into_iter().filter_map(|handle| handle.join().ok()));
+        }
+
+        summaries
+    }
+}
+
+fn resolve(server: &Server, port: u16, resolver: &dyn Resolver) -> Option<SocketAddr> {
+    resolver
+        .resolve(&server.domain)
+        .ok()
+        .map(|address| SocketAddr::new(address, port))
+}
+
+/// Returns whether a TCP connection to the given port of the server can be opened within the
+/// timeout. This is a cheap way to check whether a server can be reached at all, e.g. before
+/// connecting to it using OpenVPN over TCP, which uses port 443.
+pub fn is_reachable(server: &Server, port: u16, timeout: Duration) -> bool {
+    is_reachable_via(server, port, timeout, &SystemResolver)
+}
+
+/// Same as [is_reachable](fn.is_reachable.html), but uses the given resolver to find the address
+/// of the server.
+pub fn is_reachable_via(
+    server: &Server,
+    port: u16,
+    timeout: Duration,
+    resolver: &dyn Resolver,
+) -> bool {
+    match resolve(server, port, resolver) {
+        Some(address) => TcpStream::connect_timeout(&address, timeout).is_ok(),
+        None => false,
+    }
+}
+
+impl Benchmarker for TcpBenchmarker {
+    /// Scores servers on their combined connection time in microseconds, raised for failures.
+    fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+        Ok(summaries_to_scores(
+            &self.measure(servers),
+            self.aggregation,
+            self.loss_penalty,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::Domain;
+    use std::net::TcpListener;
+
+    #[test]
+    fn reachability() {
+        let mut server = Servers::dummy_data().servers.remove(0);
+        server.domain = Domain::from("127.0.0.1");
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(is_reachable(&server, port, Duration::from_secs(1)));
+
+        drop(listener);
+        assert!(!is_reachable(&server, port, Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,142 @@
+//! A blacklist of servers that could not be reached, learned automatically.
+//!
+//! Some internet providers block part of the NordVPN servers. Servers that fail a reachability
+//! check are put on the blacklist for a while, so they are not selected again on the next runs.
+use crate::filters::BlackListFilter;
+use crate::jsonl::{self, unix_now};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// A blacklisted server, as stored in the blacklist.
+pub struct BlacklistEntry {
+    /// The domain of the server.
+    pub domain: String,
+    /// The moment the server is no longer blacklisted, in seconds since the Unix epoch.
+    pub expires: u64,
+}
+
+/// Servers that could not be reached, backed by a file with one JSON entry per line.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::blacklist::AutoBlacklist;
+/// use std::time::Duration;
+///
+/// let path = std::env::temp_dir().join("nordselect-doc-blacklist.jsonl");
+/// # let _ = std::fs::remove_file(&path);
+/// let mut blacklist = AutoBlacklist::open(&path).unwrap();
+/// let mut data = Servers::dummy_data();
+/// let unreachable = data.servers[0].domain.clone();
+/// blacklist.add(&unreachable, Duration::from_secs(3600)).unwrap();
+///
+/// data.filter(&blacklist.filter());
+/// assert!(data.servers.iter().all(|server| server.domain != unreachable));
+/// ```
+pub struct AutoBlacklist {
+    /// The file the entries are appended to.
+    path: PathBuf,
+    /// The moment every blacklisted domain expires.
+    entries: HashMap<String, u64>,
+}
+
+/// Ways to open an AutoBlacklist.
+impl AutoBlacklist {
+    /// Opens the blacklist stored at the given path. A missing file is treated as an empty
+    /// blacklist.
+    pub fn open(path: impl Into<PathBuf>) -> Result<AutoBlacklist, Box<dyn std::error::Error>> {
+        let mut blacklist = AutoBlacklist {
+            path: path.into(),
+            entries: HashMap::new(),
+        };
+
+        for entry in jsonl::read(&blacklist.path)? {
+            blacklist.insert(entry);
+        }
+
+        Ok(blacklist)
+    }
+
+    /// Returns the location of the blacklist in the data directory of the current user (e.g.
+    /// `~/.local/share/nordselect/auto_blacklist.jsonl` on Linux).
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("nordselect").join("auto_blacklist.jsonl"))
+    }
+}
+
+impl AutoBlacklist {
+    /// Returns the path of the file backing this AutoBlacklist.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn insert(&mut self, entry: BlacklistEntry) {
+        let expires = self.entries.entry(entry.domain).or_insert(0);
+        *expires = entry.expires.max(*expires);
+    }
+
+    /// Blacklists the server with the given domain for the given period of time.
+    pub fn add(
+        &mut self,
+        domain: &str,
+        duration: Duration,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let entry = BlacklistEntry {
+            domain: domain.to_string(),
+            expires: unix_now() + duration.as_secs(),
+        };
+
+        jsonl::append(&self.path, &[&entry])?;
+        self.insert(entry);
+
+        Ok(())
+    }
+
+    /// Removes all expired entries, and rewrites the blacklist accordingly.
+    pub fn prune(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let now = unix_now();
+        self.entries.retain(|_, expires| *expires > now);
+
+        let entries: Vec<BlacklistEntry> = self
+            .entries
+            .iter()
+            .map(|(domain, expires)| BlacklistEntry {
+                domain: domain.clone(),
+                expires: *expires,
+            })
+            .collect();
+        jsonl::rewrite(&self.path, &entries)?;
+
+        Ok(())
+    }
+
+    /// Returns whether the server with the given domain is blacklisted at the moment.
+    pub fn contains(&self, domain: &str) -> bool {
+        match self.entries.get(domain) {
+            Some(expires) => *expires > unix_now(),
+            None => false,
+        }
+    }
+
+    /// Returns whether no server is blacklisted at the moment.
+    pub fn is_empty(&self) -> bool {
+        let now = unix_now();
+        self.entries.values().all(|expires| *expires <= now)
+    }
+
+    /// Returns a filter removing all servers that are blacklisted at the moment.
+    pub fn filter(&self) -> BlackListFilter {
+        let now = unix_now();
+        BlackListFilter::from(
+            self.entries
+                .iter()
+                .filter(|(_, expires)| **expires > now)
+                .map(|(domain, _)| domain.clone())
+                .collect::<HashSet<String>>(),
+        )
+    }
+}
@@ -0,0 +1,436 @@
+//! A local snapshot of the server list, stored between runs.
+//!
+//! The CLI stores every list it downloads, so that later runs can compare the current state of
+//! the API against it.
+//!
+//! Snapshots are stored together with the [version](constant.CACHE_VERSION.html) of their format.
+//! Snapshots written by older versions of this library are migrated when they are read. Ones that
+//! cannot be migrated, e.g. because they were written by a newer version, are reported as an
+//! [IncompatibleCache](struct.IncompatibleCache.html) error.
+//!
+//! [Cache::refresh](struct.Cache.html#method.refresh) keeps the `ETag` and `Last-Modified`
+//! headers of the lists next to the snapshot, so that a refresh of an unchanged list only costs a
+//! `304 Not Modified` response.
+use crate::api::{self, ApiClient, Validators};
+use crate::servers::{Server, Servers};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{self, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// The version of the format snapshots are stored in. Raise it whenever the serialized form of a
+/// [Server](../servers/struct.Server.html) changes, and add a migration from the previous version.
+pub const CACHE_VERSION: u32 = 1;
+
+/// The migrations from every version to the next one: the first one migrates the servers of
+/// version 0 to version 1, and so on.
+///
+/// Version 0 stored the servers without a version, in the format of version 1.
+const MIGRATIONS: &[fn(&mut Vec<Value>)] = &[|_| {}];
+
+#[derive(Serialize)]
+/// The way a snapshot is written.
+struct StoredSnapshot<'a> {
+    version: u32,
+    servers: &'a [Server],
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+/// The ways a snapshot can be read, before it is migrated.
+enum AnySnapshot {
+    Versioned { version: u32, servers: Vec<Value> },
+    Unversioned(Vec<Value>),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+/// What is stored next to a snapshot about the conditional requests that refreshed it.
+struct StoredValidators {
+    /// The validators of the lists the snapshot was made of, by endpoint.
+    #[serde(default)]
+    endpoints: BTreeMap<String, Validators>,
+    /// How often a refresh found that the lists did not change.
+    #[serde(default)]
+    hits: u64,
+    /// How often a refresh had to download new lists.
+    #[serde(default)]
+    misses: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// How often [Cache::refresh](struct.Cache.html#method.refresh) found the stored lists unchanged
+/// and how often it had to download new ones, as returned by
+/// [Cache::refresh_stats](struct.Cache.html#method.refresh_stats).
+pub struct RefreshStats {
+    /// The amount of refreshes that found the lists unchanged.
+    pub hits: u64,
+    /// The amount of refreshes that downloaded new lists.
+    pub misses: u64,
+}
+
+#[derive(Debug)]
+/// The result of [Cache::refresh](struct.Cache.html#method.refresh).
+pub struct Refreshed {
+    /// The current server list.
+    pub servers: Servers,
+    /// Whether the list changed since the last refresh and was downloaded again.
+    pub modified: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The error returned when a snapshot was stored in a format that cannot be read, e.g. by a newer
+/// version of this library. The snapshot should be replaced by a fresh one.
+pub struct IncompatibleCache {
+    /// The version of the format the snapshot was stored in, if it could be read.
+    pub version: Option<u32>,
+}
+
+impl fmt::Display for IncompatibleCache {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.version {
+            Some(version) if version > CACHE_VERSION => write!(
+                formatter,
+                "the stored server list was written by a newer version of nordselect (format {})",
+                version
+            ),
+            _ => formatter.write_str("the stored server list is in an unknown format"),
+        }
+    }
+}
+
+impl std::error::Error for IncompatibleCache {}
+
+/// A snapshot of the server list, stored as a file.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::cache::Cache;
+///
+/// let cache = Cache::new(std::env::temp_dir().join("nordselect-doc-cache.json"));
+/// cache.store(&Servers::dummy_data()).unwrap();
+///
+/// assert_eq!(
+///     cache.load().unwrap().servers.len(),
+///     Servers::dummy_data().servers.len()
+/// );
+/// ```
+pub struct Cache {
+    /// The file in which the snapshot is stored.
+    path: PathBuf,
+}
+
+/// Ways to construct a Cache.
+impl Cache {
+    /// Creates a Cache that stores its snapshot at the given path.
+    pub fn new(path: impl Into<PathBuf>) -> Cache {
+        Cache { path: path.into() }
+    }
+
+    /// Returns the Cache in the cache directory of the current user (e.g.
+    /// `~/.cache/nordselect/servers.json` on Linux).
+    ///
+    /// Returns `None` if the platform has no such directory.
+    pub fn default_location() -> Option<Cache> {
+        dirs::cache_dir().map(|dir| Cache::new(dir.join("nordselect").join("servers.json")))
+    }
+}
+
+impl Cache {
+    /// Returns the path of the file used by this Cache.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path of the file keeping the validators of the snapshot, next to it.
+    fn validators_path(&self) -> PathBuf {
+        self.path.with_extension("validators.json")
+    }
+
+    /// Reads the validators of the snapshot. Missing or unreadable ones are treated as none.
+    fn load_validators(&self) -> StoredValidators {
+        fs::read_to_string(self.validators_path())
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// Replaces the stored validators.
+    fn store_validators(
+        &self,
+        validators: &StoredValidators,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(self.validators_path(), serde_json::to_string(validators)?)?;
+        Ok(())
+    }
+
+    /// Returns whether a snapshot has been stored.
+    pub fn exists(&self) -> bool {
+        self.path.is_file()
+    }
+
+    /// Reads the stored snapshot, migrating it if it was stored by an older version. Returns an
+    /// error if there is none or if it could not be read. Snapshots in a format that cannot be
+    /// read give an [IncompatibleCache](struct.IncompatibleCache.html) error.
+    pub fn load(&self) -> Result<Servers, Box<dyn std::error::Error>> {
+        let text = fs::read_to_string(&self.path)?;
+        let (version, mut servers) = match serde_json::from_str(&text) {
+            Ok(AnySnapshot::Versioned { version, servers }) => (version, servers),
+            Ok(AnySnapshot::Unversioned(servers)) => (0, servers),
+            Err(_) => return Err(IncompatibleCache { version: None }.into()),
+        };
+
+        let incompatible = IncompatibleCache {
+            version: Some(version),
+        };
+        let migrations = MIGRATIONS
+            .get(version as usize..)
+            .ok_or_else(|| incompatible.clone())?;
+        for migration in migrations {
+            migration(&mut servers);
+        }
+        let servers = servers
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<Result<Vec<Server>, _>>()
+            .map_err(|_| incompatible)?;
+
+        Ok(Servers { servers })
+    }
+
+    /// Same as [load](#method.load), but removes a snapshot in a format that cannot be read,
+    /// returning `None` as if there was none. A fresh snapshot can then be stored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::cache::Cache;
+    ///
+    /// let path = std::env::temp_dir().join("nordselect-doc-cache-incompatible.json");
+    /// std::fs::write(&path, r#"{"version": 1000, "servers": []}"#).unwrap();
+    ///
+    /// let cache = Cache::new(&path);
+    /// assert!(cache.load().is_err());
+    /// assert!(cache.load_or_clear().unwrap().is_none());
+    /// assert!(!cache.exists());
+    /// ```
+    pub fn load_or_clear(&self) -> Result<Option<Servers>, Box<dyn std::error::Error>> {
+        if !self.exists() {
+            return Ok(None);
+        }
+
+        match self.load() {
+            Ok(servers) => Ok(Some(servers)),
+            Err(err) if err.is::<IncompatibleCache>() => {
+                self.clear()?;
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Replaces the stored snapshot by the given servers, creating the directory if required.
+    ///
+    /// The validators of the previous snapshot no longer apply, so the next
+    /// [refresh](#method.refresh) downloads the lists again.
+    pub fn store(&self, servers: &Servers) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_snapshot(servers)?;
+
+        let mut validators = self.load_validators();
+        if !validators.endpoints.is_empty() {
+            validators.endpoints.clear();
+            self.store_validators(&validators)?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the stored snapshot, leaving the validators alone.
+    fn write_snapshot(&self, servers: &Servers) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let snapshot = StoredSnapshot {
+            version: CACHE_VERSION,
+            servers: &servers.servers,
+        };
+        fs::write(&self.path, serde_json::to_string(&snapshot)?)?;
+
+        Ok(())
+    }
+
+    /// Removes the stored snapshot and its validators. Succeeds if there was none.
+    pub fn clear(&self) -> Result<(), Box<dyn std::error::Error>> {
+        for path in &[self.path.clone(), self.validators_path()] {
+            match fs::remove_file(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Downloads the server lists from all endpoints and stores them, like
+    /// [Servers::from_endpoints](../struct.Servers.html#method.from_endpoints) followed by
+    /// [store](#method.store), but asks the API to only send lists that changed since the last
+    /// refresh. When none did, the stored snapshot is returned without downloading or parsing
+    /// anything. Fails if any of the endpoints fails.
+    ///
+    /// Programs refreshing the list often, like daemons, should use this to go easy on the API.
+    pub fn refresh<S: AsRef<str>>(
+        &self,
+        client: &ApiClient,
+        endpoints: &[S],
+    ) -> Result<Refreshed, Box<dyn std::error::Error>> {
+        let mut validators = self.load_validators();
+        // Validators only help when the lists they belong to are still there.
+        let stored = self.load_or_clear().unwrap_or(None);
+        if stored.is_none() {
+            validators.endpoints.clear();
+        }
+
+        match api::download_all_if_modified(client, endpoints, &validators.endpoints)? {
+            None => {
+                validators.hits += 1;
+                self.store_validators(&validators)?;
+                Ok(Refreshed {
+                    // There are only no changes when there were validators, and so a snapshot.
+                    servers: stored.ok_or("the stored server list disappeared")?,
+                    modified: false,
+                })
+            }
+            Some((servers, endpoints)) => {
+                self.write_snapshot(&servers)?;
+                validators.endpoints = endpoints;
+                validators.misses += 1;
+                self.store_validators(&validators)?;
+                Ok(Refreshed {
+                    servers,
+                    modified: true,
+                })
+            }
+        }
+    }
+
+    /// Returns how often [refresh](#method.refresh) found the lists unchanged and how often it
+    /// downloaded them, since the snapshot was first stored or last cleared.
+    pub fn refresh_stats(&self) -> RefreshStats {
+        let validators = self.load_validators();
+        RefreshStats {
+            hits: validators.hits,
+            misses: validators.misses,
+        }
+    }
+
+    /// Returns when the stored snapshot was written, how large it is and how many servers it
+    /// contains. Returns an error if there is no snapshot or if it could not be read.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::Servers;
+    /// use nordselect::cache::Cache;
+    ///
+    /// let cache = Cache::new(std::env::temp_dir().join("nordselect-doc-cache-metadata.json"));
+    /// cache.store(&Servers::dummy_data()).unwrap();
+    ///
+    /// let metadata = cache.metadata().unwrap();
+    /// assert_eq!(metadata.servers, Servers::dummy_data().servers.len());
+    /// assert!(metadata.age() < std::time::Duration::from_secs(60));
+    ///
+    /// cache.clear().unwrap();
+    /// assert!(cache.metadata().is_err());
+    /// ```
+    pub fn metadata(&self) -> Result<CacheMetadata, Box<dyn std::error::Error>> {
+        let metadata = fs::metadata(&self.path)?;
+
+        Ok(CacheMetadata {
+            stored: metadata.modified()?,
+            size: metadata.len(),
+            servers: self.load()?.servers.len(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Information about a stored snapshot, as returned by
+/// [Cache::metadata](struct.Cache.html#method.metadata).
+pub struct CacheMetadata {
+    /// When the snapshot was stored.
+    pub stored: SystemTime,
+    /// The size of the file, in bytes.
+    pub size: u64,
+    /// The amount of servers in the snapshot.
+    pub servers: usize,
+}
+
+impl CacheMetadata {
+    /// Returns how long ago the snapshot was stored. Snapshots from the future are zero seconds
+    /// old.
+    pub fn age(&self) -> Duration {
+        self.stored.elapsed().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_api::{MockApi, Response};
+
+    #[test]
+    fn conditional_refresh() {
+        let data = Servers::dummy_data();
+        let body = serde_json::to_string(&data.servers).unwrap();
+        let api = MockApi::start(move |request| {
+            if request.header("if-none-match") == Some("\"1\"") {
+                Response::status(304)
+            } else {
+                Response::ok(body.clone()).header("ETag", "\"1\"")
+            }
+        });
+        let endpoint = api::V1_ENDPOINT;
+
+        let cache = Cache::new(std::env::temp_dir().join("nordselect-test-cache-refresh.json"));
+        cache.clear().unwrap();
+        let client = ApiClient::new().api_base(api.base());
+        let first = cache.refresh(&client, &[&endpoint]).unwrap();
+        assert!(first.modified);
+        let second = cache.refresh(&client, &[&endpoint]).unwrap();
+        assert!(!second.modified);
+        assert_eq!(second.servers.servers, first.servers.servers);
+        assert_eq!(cache.refresh_stats(), RefreshStats { hits: 1, misses: 1 });
+
+        // A snapshot stored otherwise has no validators.
+        cache.store(&data).unwrap();
+        assert!(cache.refresh(&client, &[&endpoint]).unwrap().modified);
+        cache.clear().unwrap();
+        assert_eq!(cache.refresh_stats(), RefreshStats::default());
+    }
+
+    #[test]
+    fn migrate_unversioned() {
+        let path = std::env::temp_dir().join("nordselect-test-cache-unversioned.json");
+        let data = Servers::dummy_data();
+        fs::write(&path, serde_json::to_string(&data.servers).unwrap()).unwrap();
+
+        let cache = Cache::new(&path);
+        assert_eq!(cache.load().unwrap().servers, data.servers);
+        cache.store(&data).unwrap();
+        let stored: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(stored["version"], CACHE_VERSION);
+
+        fs::write(&path, r#"[{"domain": 5}]"#).unwrap();
+        let err = cache.load().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<IncompatibleCache>(),
+            Some(&IncompatibleCache { version: Some(0) })
+        );
+        cache.clear().unwrap();
+    }
+}
@@ -0,0 +1,900 @@
+//! Turning the filters given on the command line into [Filters](../filters/trait.Filter.html), the
+//! way the nordselect CLI does it.
+//!
+//! Filters are normalized before they are interpreted: case, accents and full-width characters
+//! are ignored, so `BÉNÉLUX`, `benelux` and `ｂｅｎｅｌｕｘ` are the same filter. On top of that,
+//! [Aliases](struct.Aliases.html) translate other names for a filter, such as the Cyrillic `ЕЮ`
+//! for the European Union.
+use crate::filters::{self, Filter};
+use crate::servers::{Country, Protocol, ServerCategory, Servers};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::iter::FromIterator;
+use std::time::Duration;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Normalizes a filter: full-width and other compatibility characters are replaced by their
+/// common form, accents are removed and everything is put in lowercase.
+///
+/// # Examples
+///
+/// ```
+/// use nordselect::cli_help::normalize;
+///
+/// assert_eq!(normalize("Bénélux"), "benelux");
+/// assert_eq!(normalize("ＵＳ"), "us");
+/// assert_eq!(normalize(" ЕЮ "), "ею");
+/// ```
+pub fn normalize(filter: &str) -> String {
+    filter
+        .trim()
+        .nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Other names for filters, mapped on the filter they stand for, and custom regions.
+///
+/// The default table contains common names of regions in other languages and scripts. More can be
+/// added using [add](#method.add), custom regions using [add_region](#method.add_region).
+///
+/// # Examples
+///
+/// ```
+/// use nordselect::cli_help::Aliases;
+///
+/// let mut aliases = Aliases::default();
+/// assert_eq!(aliases.resolve("ею"), "eu");
+/// assert_eq!(aliases.resolve("be"), "be");
+///
+/// aliases.add("Belgique", "BE");
+/// assert_eq!(aliases.resolve("belgique"), "be");
+/// ```
+pub struct Aliases {
+    /// The normalized aliases, mapped on the normalized filter they stand for.
+    aliases: HashMap<String, String>,
+    /// The custom regions, by their normalized name.
+    regions: HashMap<String, filters::Region>,
+}
+
+impl Aliases {
+    /// Creates an empty alias table.
+    pub fn new() -> Aliases {
+        Aliases {
+            aliases: HashMap::new(),
+            regions: HashMap::new(),
+        }
+    }
+
+    /// Makes `alias` stand for `filter`. Both are normalized first.
+    pub fn add(&mut self, alias: &str, filter: &str) {
+        self.aliases.insert(normalize(alias), normalize(filter));
+    }
+
+    /// Returns the filter the given normalized filter stands for, or the filter itself if it is
+    /// not an alias.
+    pub fn resolve<'a>(&'a self, filter: &'a str) -> &'a str {
+        self.aliases
+            .get(filter)
+            .map(String::as_str)
+            .unwrap_or(filter)
+    }
+
+    /// Makes a custom region a filter, named after its short notation. Countries and built-in
+    /// regions with the same name go first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nordselect::cli_help::Aliases;
+    /// use nordselect::filters::Region;
+    ///
+    /// let mut aliases = Aliases::default();
+    /// aliases.add_region(Region::custom("Nordics", vec!["DK".parse().unwrap()]));
+    /// assert_eq!(aliases.region("nordics").unwrap().countries(), ["DK"]);
+    /// assert_eq!(aliases.region("benelux"), Some(Region::Benelux));
+    /// ```
+    pub fn add_region(&mut self, region: filters::Region) {
+        self.regions.insert(normalize(region.short()), region);
+    }
+
+    /// Returns the built-in or custom region the given normalized filter names, if any.
+    pub fn region(&self, filter: &str) -> Option<filters::Region> {
+        filters::Region::from_str(&filter.to_uppercase())
+            .or_else(|| self.regions.get(filter).cloned())
+    }
+
+    /// Returns the custom regions, in no particular order.
+    pub fn regions(&self) -> impl Iterator<Item = &filters::Region> {
+        self.regions.values()
+    }
+}
+
+impl Default for Aliases {
+    fn default() -> Aliases {
+        let mut aliases = Aliases::new();
+        // The European Union in Cyrillic (Bulgarian and Russian) and in Romance languages.
+        aliases.add("ЕЮ", "eu");
+        aliases.add("ЕС", "eu");
+        aliases.add("UE", "eu");
+        // The European Economic Area in German.
+        aliases.add("EWR", "eea");
+        aliases
+    }
+}
+
+/// The reasons filters given on the command line can not be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterError {
+    /// The filter is not known. Known filters that look like it are suggested, closest first.
+    Unknown(String, Vec<String>),
+    /// The filter is a country without any servers.
+    NoServers(String, Country),
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterError::Unknown(filter, suggestions) => {
+                write!(formatter, "unknown filter: \"{}\"", filter)?;
+                if !suggestions.is_empty() {
+                    let suggestions: Vec<String> = suggestions
+                        .iter()
+                        .map(|suggestion| format!("\"{}\"", suggestion))
+                        .collect();
+                    write!(formatter, " (did you mean {}?)", suggestions.join(" or "))?;
+                }
+                Ok(())
+            }
+            FilterError::NoServers(filter, country) => write!(
+                formatter,
+                "there are no servers in {} (\"{}\")",
+                country.name(),
+                filter
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+/// All protocol filters.
+pub const PROTOCOLS: &[&str] = &[
+    "tcp",
+    "udp",
+    "pptp",
+    "l2tp",
+    "tcp_xor",
+    "udp_xor",
+    "socks",
+    "cybersecproxy",
+    "sslproxy",
+    "cybersecsslproxy",
+    "proxy",
+    "wg_udp",
+    "nordlynx",
+];
+
+/// All filters on other features.
+pub const FEATURES: &[&str] = &["portforward"];
+
+/// All category filters.
+pub const CATEGORIES: &[&str] = &[
+    "standard",
+    "dedicated",
+    "double",
+    "obfuscated",
+    "p2p",
+    "tor",
+];
+
+/// Prepended to a region to select all countries outside of it, e.g. `non-14e`.
+pub const REGION_COMPLEMENT_PREFIX: &str = "non-";
+
+/// Prepended to the name of a city to select the servers in it, e.g. `city:brussels`.
+pub const CITY_PREFIX: &str = "city:";
+
+/// Prepended to a country to select the servers in it and the countries next to it, e.g.
+/// `near:be`. A radius can be appended to also go to their neighbours, e.g. `near:be:2`.
+pub const NEARBY_PREFIX: &str = "near:";
+
+/// Prepended to a load to select the servers with at most that load, e.g. `maxload:40`.
+pub const MAX_LOAD_PREFIX: &str = "maxload:";
+
+/// Prepended to an amount of days to select the servers added at least that long ago, e.g.
+/// `minage:30`.
+pub const MIN_AGE_PREFIX: &str = "minage:";
+
+/// Prepended to countries separated by commas to select from the first of them that has servers
+/// passing the other filters, e.g. `prefer:nl,de,fr`. Servers in other countries are not selected.
+pub const PREFER_PREFIX: &str = "prefer:";
+
+/// Returns the category a (normalized) filter stands for, if any.
+pub fn parse_category(filter: &str) -> Option<ServerCategory> {
+    match filter {
+        "p2p" => Some(ServerCategory::P2P),
+        "standard" => Some(ServerCategory::Standard),
+        "double" => Some(ServerCategory::Double),
+        "dedicated" => Some(ServerCategory::Dedicated),
+        "tor" => Some(ServerCategory::Tor),
+        "obfuscated" => Some(ServerCategory::Obfuscated),
+        _ => None,
+    }
+}
+
+/// Returns the filter standing for the category, the reverse of
+/// [parse_category](fn.parse_category.html). Unknown categories have no filter and are called
+/// `unknown`.
+pub fn category_name(category: &ServerCategory) -> &'static str {
+    match category {
+        ServerCategory::Standard => "standard",
+        ServerCategory::P2P => "p2p",
+        ServerCategory::Obfuscated => "obfuscated",
+        ServerCategory::Dedicated => "dedicated",
+        ServerCategory::Tor => "tor",
+        ServerCategory::Double => "double",
+        ServerCategory::UnknownServer => "unknown",
+    }
+}
+
+/// Returns the filter a (normalized) filter such as `near:be:2` stands for, if it is one.
+pub fn parse_nearby(filter: &str) -> Option<filters::NearbyCountriesFilter> {
+    let mut parts = filter.strip_prefix(NEARBY_PREFIX)?.splitn(2, ':');
+    let country: Country = parts.next()?.parse().ok()?;
+    let radius = match parts.next() {
+        Some(radius) => radius.parse().ok()?,
+        None => 1,
+    };
+    let filter = filters::NearbyCountriesFilter::new(country.as_str(), radius);
+    Some(filter)
+}
+
+/// Returns the countries a (normalized) preference such as `prefer:nl,de,fr` lists, most
+/// preferred first, if it is one. The countries may be aliases.
+pub fn parse_preference(filter: &str, aliases: &Aliases) -> Option<Vec<Country>> {
+    filter
+        .strip_prefix(PREFER_PREFIX)?
+        .split(',')
+        .map(|country| aliases.resolve(country.trim()).parse().ok())
+        .collect()
+}
+
+/// Returns the countries listed by the preferences among the given filters, most preferred first.
+/// Preferences are not filters: servers are only selected from the first of these countries that
+/// has servers passing the filters. See [PREFER_PREFIX](constant.PREFER_PREFIX.html).
+///
+/// # Examples
+///
+/// ```
+/// use nordselect::cli_help::{self, Aliases};
+///
+/// let countries = cli_help::preferred_countries(vec!["p2p", "prefer:NL,de"], &Aliases::default());
+/// assert_eq!(countries, ["NL", "DE"]);
+/// ```
+pub fn preferred_countries<'a, I: IntoIterator<Item = &'a str>>(
+    cli_filters: I,
+    aliases: &Aliases,
+) -> Vec<Country> {
+    cli_filters
+        .into_iter()
+        .filter_map(|filter| parse_preference(&normalize(filter), aliases))
+        .flatten()
+        .collect()
+}
+
+/// Parses a (normalized) filter that does not depend on the server list: a category, a protocol,
+/// a minimal bandwidth, a maximal load, a city, nearby countries or another feature. The returned boolean tells whether it is a category
+/// filter.
+pub fn parse_static_filter(filter: &str) -> Option<(Box<dyn Filter>, bool)> {
+    if let Some(category) = parse_category(filter) {
+        return Some((Box::new(filters::CategoryFilter::from(category)), true));
+    }
+
+    // A minimal bandwidth, e.g. 10gbps.
+    let bandwidth = filter
+        .strip_suffix("gbps")
+        .map(|amount| (amount, 1000))
+        .or_else(|| filter.strip_suffix("mbps").map(|amount| (amount, 1)));
+    if let Some((amount, factor)) = bandwidth {
+        let bandwidth = amount
+            .parse::<u32>()
+            .ok()
+            .and_then(|amount| amount.checked_mul(factor));
+        if let Some(bandwidth) = bandwidth {
+            return Some((Box::new(filters::BandwidthFilter::from(bandwidth)), false));
+        }
+    }
+
+    if let Some(city) = filter.strip_prefix(CITY_PREFIX) {
+        return Some((Box::new(filters::CityFilter::from(city)), false));
+    }
+
+    if let Some(load) = filter.strip_prefix(MAX_LOAD_PREFIX) {
+        let load: u8 = load.parse().ok()?;
+        return Some((Box::new(filters::LoadFilter::from(load)), false));
+    }
+
+    if let Some(days) = filter.strip_prefix(MIN_AGE_PREFIX) {
+        let days: u64 = days.parse().ok()?;
+        let min_age = Duration::from_secs(days.checked_mul(24 * 3600)?);
+        return Some((Box::new(filters::MinAgeFilter::from(min_age)), false));
+    }
+
+    if filter.starts_with(NEARBY_PREFIX) {
+        return Some((Box::new(parse_nearby(filter)?), false));
+    }
+
+    if filter == "portforward" {
+        let filter = filters::RawFeatureFilter::from_key("port_forwarding");
+        return Some((Box::new(filter), false));
+    }
+
+    let protocol = match filter {
+        "tcp" => Protocol::Tcp,
+        "udp" => Protocol::Udp,
+        "pptp" => Protocol::Pptp,
+        "l2tp" => Protocol::L2tp,
+        "tcp_xor" => Protocol::OpenVPNXTcp,
+        "udp_xor" => Protocol::OpenVPNXUdp,
+        "socks" => Protocol::Socks,
+        "cybersecproxy" => Protocol::CyberSecProxy,
+        "sslproxy" => Protocol::SslProxy,
+        "cybersecsslproxy" => Protocol::CyberSecSslProxy,
+        "proxy" => Protocol::Proxy,
+        "wg_udp" | "nordlynx" => Protocol::WireGuardUdp,
+        _ => return None,
+    };
+    Some((Box::new(filters::ProtocolFilter::from(protocol)), false))
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// How many servers match every filter that takes no argument, as returned by
+/// [filter_counts](fn.filter_counts.html).
+pub struct FilterCounts {
+    /// The amount of servers by protocol, feature and category filter.
+    pub filters: BTreeMap<&'static str, usize>,
+    /// The amount of servers by country.
+    pub countries: BTreeMap<Country, usize>,
+}
+
+/// Counts how many servers match every protocol, feature and category filter and every country,
+/// going over the servers only once. Filters no server matches are counted as 0.
+///
+/// # Examples
+///
+/// ```
+/// use nordselect::cli_help;
+/// use nordselect::Servers;
+///
+/// let data = Servers::dummy_data();
+/// let counts = cli_help::filter_counts(&data);
+///
+/// let belgian = data.servers.iter().filter(|server| server.flag == "BE").count();
+/// assert_eq!(counts.countries[&"BE".parse().unwrap()], belgian);
+/// assert!(counts.filters["tcp"] <= data.servers.len());
+/// assert_eq!(counts.filters.len(), cli_help::PROTOCOLS.len() + 7);
+/// ```
+pub fn filter_counts(data: &Servers) -> FilterCounts {
+    let keywords: Vec<(&'static str, Box<dyn Filter>)> = PROTOCOLS
+        .iter()
+        .chain(FEATURES)
+        .chain(CATEGORIES)
+        .filter_map(|keyword| Some((*keyword, parse_static_filter(keyword)?.0)))
+        .collect();
+
+    let mut counts = FilterCounts {
+        filters: keywords.iter().map(|(keyword, _)| (*keyword, 0)).collect(),
+        countries: BTreeMap::new(),
+    };
+    for server in &data.servers {
+        *counts.countries.entry(server.flag).or_default() += 1;
+        for (keyword, filter) in &keywords {
+            if filter.filter(server) {
+                *counts.filters.get_mut(keyword).unwrap() += 1;
+            }
+        }
+    }
+
+    counts
+}
+
+/// Up to this many suggestions are given for an unknown filter.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// The amount of single character insertions, deletions and substitutions needed to turn one
+/// string into the other.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Returns the known filters that look like the given (normalized) unknown filter, closest
+/// first: protocols, features, categories, regions and the countries with servers, which are
+/// matched on their name but suggested as their code.
+///
+/// # Examples
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::cli_help::suggest;
+///
+/// let data = Servers::dummy_data();
+/// assert_eq!(suggest("bnelux", &data), vec!["benelux"]);
+/// assert_eq!(suggest("!belgum", &data), vec!["!be"]);
+/// assert!(suggest("qwertyuiop", &data).is_empty());
+/// ```
+pub fn suggest(filter: &str, data: &Servers) -> Vec<String> {
+    let (filter, is_negating) = split_negation(filter);
+
+    let keywords = PROTOCOLS.iter().chain(FEATURES).chain(CATEGORIES);
+    let mut candidates: Vec<(String, String)> = keywords
+        .map(|keyword| (keyword.to_string(), keyword.to_string()))
+        .collect();
+    let regions = filters::Region::from_str_options();
+    let languages = filters::Region::language_options();
+    for (region, _) in regions.iter().chain(&languages) {
+        let region = normalize(region);
+        let complement = format!("{}{}", REGION_COMPLEMENT_PREFIX, region);
+        candidates.push((complement.clone(), complement));
+        candidates.push((region.clone(), region));
+    }
+    for flag in data.flags() {
+        if let Ok(country) = flag.parse::<Country>() {
+            candidates.push((normalize(country.name()), country.as_str().to_lowercase()));
+        }
+    }
+
+    // Short filters are a few typos away from nearly everything.
+    let max_distance = match filter.chars().count() {
+        0..=3 => 1,
+        4..=7 => 2,
+        _ => 3,
+    };
+    let mut suggestions: Vec<(usize, String)> = candidates
+        .into_iter()
+        .map(|(name, suggestion)| (levenshtein(filter, &name), suggestion))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    suggestions.sort();
+    let mut seen = HashSet::new();
+    suggestions.retain(|(_, suggestion)| seen.insert(suggestion.clone()));
+
+    suggestions
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, suggestion)| {
+            if is_negating {
+                format!("!{}", suggestion)
+            } else {
+                suggestion
+            }
+        })
+        .collect()
+}
+
+/// Splits the `!` off a filter. The returned boolean tells whether the filter should be negated.
+pub fn split_negation(filter: &str) -> (&str, bool) {
+    match filter.strip_prefix('!') {
+        Some(filter) => (filter, true),
+        None => (filter, false),
+    }
+}
+
+/// Checks the filters given on the command line without turning them into Filters, e.g. to fail
+/// before the server list is downloaded.
+///
+/// Without a server list, this only checks whether every filter is known: country codes should
+/// be [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2) codes. With a server
+/// list, every country, including the excluded ones, should also have servers in it. This is
+/// stricter than [parse_filters](fn.parse_filters.html), which allows excluding a country without
+/// servers.
+///
+/// # Examples
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::cli_help::{self, Aliases};
+///
+/// let aliases = Aliases::default();
+/// assert!(cli_help::check_filters(vec!["be", "!xk", "tcp"], None, &aliases).is_ok());
+/// assert!(cli_help::check_filters(vec!["nofilter"], None, &aliases).is_err());
+///
+/// // There are no servers in Kosovo.
+/// let data = Servers::dummy_data();
+/// assert!(cli_help::check_filters(vec!["be", "!xk"], Some(&data), &aliases).is_err());
+/// ```
+pub fn check_filters<'a, I: IntoIterator<Item = &'a str>>(
+    cli_filters: I,
+    data: Option<&Servers>,
+    aliases: &Aliases,
+) -> Result<(), FilterError> {
+    let flags = data.map(Servers::flags);
+    for original_filter in cli_filters {
+        let normalized = normalize(original_filter);
+        let (filter, is_negating) = split_negation(&normalized);
+        let filter = aliases.resolve(filter);
+
+        if !is_negating && parse_preference(filter, aliases).is_some() {
+            continue;
+        }
+
+        if let Ok(country) = filter.parse::<Country>() {
+            match &flags {
+                Some(flags) if !flags.contains(country.as_str()) => {
+                    return Err(FilterError::NoServers(original_filter.to_string(), country));
+                }
+                _ => continue,
+            }
+        }
+
+        let region = filter
+            .strip_prefix(REGION_COMPLEMENT_PREFIX)
+            .unwrap_or(filter);
+        if parse_static_filter(filter).is_none() && aliases.region(region).is_none() {
+            let no_servers = Servers {
+                servers: Vec::new(),
+            };
+            let suggestions = suggest(&normalized, data.unwrap_or(&no_servers));
+            return Err(FilterError::Unknown(original_filter.to_string(), suggestions));
+        }
+    }
+
+    Ok(())
+}
+
+/// Turns the filters given on the command line into Filters.
+///
+/// All countries and regions that are selected end up in a single filter, so servers in any of
+/// them are kept, e.g. `us ca mx`. Servers have to pass all other filters. Countries are checked
+/// against the given servers, so selecting a country without servers gives an error instead of an
+/// empty selection. When `default_to_standard` is set and no category is asked for, only standard
+/// servers are kept.
+///
+/// # Examples
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::cli_help::{self, Aliases};
+///
+/// let mut data = Servers::dummy_data();
+/// let filters = cli_help::parse_filters(vec!["Ｐ２Ｐ", "!ЕЮ"], &data, &Aliases::default(), true)
+///     .unwrap();
+/// for filter in filters {
+///     data.filter(filter.as_ref());
+/// }
+/// assert!(data.perfect_server().is_some());
+///
+/// assert!(cli_help::parse_filters(vec!["nowhere"], &data, &Aliases::default(), true).is_err());
+/// ```
+pub fn parse_filters<'a, I: IntoIterator<Item = &'a str>>(
+    cli_filters: I,
+    data: &Servers,
+    aliases: &Aliases,
+    default_to_standard: bool,
+) -> Result<Vec<Box<dyn Filter>>, FilterError> {
+    Ok(
+        parse_labelled_filters(cli_filters, data, aliases, default_to_standard)?
+            .into_iter()
+            .map(|(_, filter)| filter)
+            .collect(),
+    )
+}
+
+/// A Filter, together with the filters given on the command line it stands for.
+pub type LabelledFilter = (String, Box<dyn Filter>);
+
+/// Same as [parse_filters](fn.parse_filters.html), but also returns which of the given filters
+/// every Filter stands for, e.g. to tell the user which filter removed all servers.
+///
+/// # Examples
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::cli_help::{self, Aliases};
+///
+/// let data = Servers::dummy_data();
+/// let filters =
+///     cli_help::parse_labelled_filters(vec!["tcp", "be", "nl"], &data, &Aliases::default(), true)
+///         .unwrap();
+/// let labels: Vec<&str> = filters.iter().map(|(label, _)| label.as_str()).collect();
+/// assert_eq!(labels, vec!["tcp", "standard (default)", "be, nl"]);
+/// ```
+pub fn parse_labelled_filters<'a, I: IntoIterator<Item = &'a str>>(
+    cli_filters: I,
+    data: &Servers,
+    aliases: &Aliases,
+    default_to_standard: bool,
+) -> Result<Vec<LabelledFilter>, FilterError> {
+    // Parse which countries are in the data
+    let flags = data.flags();
+
+    let mut lib_filters: Vec<LabelledFilter> = Vec::new();
+    let mut category_filter_added = false;
+    let mut included_countries = HashSet::new();
+    let mut excluded_countries = HashSet::new();
+    let mut included_labels = Vec::new();
+    let mut excluded_labels = Vec::new();
+
+    for original_filter in cli_filters {
+        let normalized = normalize(original_filter);
+        let (filter, is_negating) = split_negation(&normalized);
+        let filter = aliases.resolve(filter);
+
+        // Preferences are applied after the filters, see preferred_countries.
+        if !is_negating && parse_preference(filter, aliases).is_some() {
+            continue;
+        }
+
+        if let Some((lib_filter, is_category_filter)) = parse_static_filter(filter) {
+            lib_filters.push((
+                original_filter.to_string(),
+                if is_negating {
+                    Box::new(filters::NegatingFilter::from(lib_filter))
+                } else {
+                    lib_filter
+                },
+            ));
+            if is_category_filter {
+                category_filter_added = true;
+            }
+            continue;
+        }
+
+        let (contries_to_modify, labels) = if is_negating {
+            (&mut excluded_countries, &mut excluded_labels)
+        } else {
+            (&mut included_countries, &mut included_labels)
+        };
+
+        if let Ok(country) = filter.parse::<Country>() {
+            // Excluding a country without servers does no harm, only selecting from one does.
+            if !is_negating && !flags.contains(country.as_str()) {
+                return Err(FilterError::NoServers(original_filter.to_string(), country));
+            }
+            contries_to_modify.insert(country);
+            labels.push(original_filter);
+            continue;
+        }
+
+        if let Some(region_countries) = aliases.region(filter) {
+            contries_to_modify.extend(region_countries.countries());
+            labels.push(original_filter);
+            continue;
+        }
+
+        // All countries outside of a region, e.g. non-14e.
+        let outside_region = filter
+            .strip_prefix(REGION_COMPLEMENT_PREFIX)
+            .and_then(|region| aliases.region(region));
+        if let Some(region) = outside_region {
+            contries_to_modify.extend(region.complement(data));
+            labels.push(original_filter);
+            continue;
+        }
+
+        return Err(FilterError::Unknown(
+            original_filter.to_string(),
+            suggest(&normalized, data),
+        ));
+    }
+
+    // Use a Standard server if no special server is requested.
+    if default_to_standard && !category_filter_added {
+        lib_filters.push((
+            String::from("standard (default)"),
+            Box::new(filters::CategoryFilter::from(ServerCategory::Standard)),
+        ));
+    }
+
+    // Add countries filters.
+    if !included_countries.is_empty() {
+        lib_filters.push((
+            included_labels.join(", "),
+            Box::new(filters::CountriesFilter::from_iter(included_countries)),
+        ));
+    }
+    if !excluded_countries.is_empty() {
+        lib_filters.push((
+            excluded_labels.join(", "),
+            Box::new(filters::NegatingFilter::new(
+                filters::CountriesFilter::from_iter(excluded_countries),
+            )),
+        ));
+    }
+
+    Ok(lib_filters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_negation_test() {
+        assert_eq!(split_negation("qwe"), ("qwe", false));
+        assert_eq!(split_negation("!qwe"), ("qwe", true));
+        assert_eq!(split_negation(""), ("", false));
+    }
+
+    #[test]
+    fn listed_filters_exist() {
+        for filter in PROTOCOLS {
+            assert_eq!(
+                parse_static_filter(filter).map(|(_, category)| category),
+                Some(false)
+            );
+        }
+        for filter in FEATURES {
+            assert_eq!(
+                parse_static_filter(filter).map(|(_, category)| category),
+                Some(false)
+            );
+        }
+        for filter in CATEGORIES {
+            assert!(parse_category(filter).is_some());
+        }
+    }
+
+    #[test]
+    fn normalized_filters() {
+        let data = Servers::dummy_data();
+        let aliases = Aliases::default();
+        for filter in &[
+            "TCP",
+            "ｔｃｐ",
+            "Benelux",
+            "BÉNÉLUX",
+            "!ЕЮ",
+            "！eu",
+            "Be",
+            "ＢＥ",
+            "uk",
+            "non-14e",
+            "NON-EU",
+            "10Gbps",
+            "!500mbps",
+            "City:São-Paulo",
+            "near:BE:2",
+            "Lang:ES",
+            "maxload:40",
+            "minage:30",
+            "Prefer:NL,be",
+            "non-lang:de",
+        ] {
+            assert!(
+                parse_filters(vec![*filter], &data, &aliases, true).is_ok(),
+                "{} was not recognised",
+                filter
+            );
+        }
+    }
+
+    #[test]
+    fn nearby() {
+        let nearby = parse_nearby("near:be").unwrap();
+        assert!(nearby.may_pass_country("NL".parse().unwrap()));
+        assert!(!nearby.may_pass_country("ES".parse().unwrap()));
+        assert!(parse_nearby("near:xx").is_none());
+        assert!(parse_nearby("near:be:far").is_none());
+    }
+
+    #[test]
+    fn preferences() {
+        let mut aliases = Aliases::default();
+        aliases.add("Belgique", "BE");
+        assert_eq!(
+            parse_preference("prefer:nl, belgique", &aliases).unwrap(),
+            ["NL", "BE"]
+        );
+        assert_eq!(parse_preference("prefer:nl,xx", &aliases), None);
+        assert_eq!(parse_preference("nl", &aliases), None);
+
+        // Preferences are not filters, and cannot be negated.
+        let data = Servers::dummy_data();
+        assert!(parse_filters(vec!["prefer:nl"], &data, &aliases, false)
+            .unwrap()
+            .is_empty());
+        assert!(parse_filters(vec!["!prefer:nl"], &data, &aliases, false).is_err());
+        assert!(check_filters(vec!["!prefer:nl"], None, &aliases).is_err());
+        assert!(check_filters(vec!["prefer:"], None, &aliases).is_err());
+    }
+
+    #[test]
+    fn region_complements() {
+        let data = Servers::dummy_data();
+        let aliases = Aliases::default();
+        let mut outside = Servers::dummy_data();
+        for filter in parse_filters(vec!["non-14e"], &data, &aliases, false).unwrap() {
+            outside.filter(&*filter);
+        }
+
+        assert!(!outside.servers.is_empty());
+        let fourteen_eyes = filters::Region::FourteenEyes.countries();
+        assert!(outside
+            .servers
+            .iter()
+            .all(|server| !fourteen_eyes.contains(&server.flag)));
+    }
+
+    #[test]
+    fn negated_regions() {
+        let data = Servers::dummy_data();
+        let aliases = Aliases::default();
+        for (filter, region) in &[
+            ("!eu", filters::Region::EuropeanUnion),
+            ("!14e", filters::Region::FourteenEyes),
+        ] {
+            let mut outside = Servers::dummy_data();
+            for filter in parse_filters(vec![*filter], &data, &aliases, false).unwrap() {
+                outside.filter(&*filter);
+            }
+
+            assert!(!outside.servers.is_empty());
+            let countries = region.countries();
+            assert!(outside
+                .servers
+                .iter()
+                .all(|server| !countries.contains(&server.flag)));
+        }
+
+        // Excluded regions and countries end up in a single filter.
+        let filters = parse_labelled_filters(vec!["!eu", "!ch"], &data, &aliases, false).unwrap();
+        assert_eq!(filters.len(), 1);
+        assert_eq!(filters[0].0, "!eu, !ch");
+    }
+
+    #[test]
+    fn countries_are_combined() {
+        let mut data = Servers::dummy_data();
+        let aliases = Aliases::default();
+        let filters =
+            parse_labelled_filters(vec!["us", "tcp", "ca", "benelux"], &data, &aliases, false)
+                .unwrap();
+        let labels: Vec<&str> = filters.iter().map(|(label, _)| label.as_str()).collect();
+        assert_eq!(labels, vec!["tcp", "us, ca, benelux"]);
+
+        for (_, filter) in filters {
+            data.filter(&*filter);
+        }
+        for country in &["US", "CA", "BE"] {
+            assert!(data.servers.iter().any(|server| server.flag == *country));
+        }
+        assert!(data
+            .servers
+            .iter()
+            .all(|server| server.features.openvpn_tcp));
+    }
+    #[test]
+    fn unknown_filter_suggestions() {
+        let data = Servers::dummy_data();
+        assert_eq!(levenshtein("benelux", "bnelux"), 1);
+        assert_eq!(levenshtein("", "tcp"), 3);
+        assert_eq!(levenshtein("udp", "tcp"), 2);
+
+        let error = parse_filters(vec!["tcp", "!Bnelux"], &data, &Aliases::default(), true)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error,
+            FilterError::Unknown(String::from("!Bnelux"), vec![String::from("!benelux")])
+        );
+        assert_eq!(
+            error.to_string(),
+            "unknown filter: \"!Bnelux\" (did you mean \"!benelux\"?)"
+        );
+        assert_eq!(suggest("non-14", &data)[0], "non-14e");
+        assert_eq!(suggest("p2", &data)[0], "p2p");
+    }
+}
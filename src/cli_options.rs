@@ -0,0 +1,588 @@
+//! Reading the options of the command line tool that decide how servers are selected. Other
+//! frontends can take the same options as text and get the same
+//! [SelectOptions](../run/struct.SelectOptions.html) as the tool, or the same errors.
+//!
+//! # Example
+//!
+//! ```
+//! use nordselect::cli_options::CliOptions;
+//! use nordselect::run;
+//! use nordselect::Servers;
+//!
+//! let data = Servers::dummy_data();
+//! let mut cli = CliOptions::default();
+//! cli.alternates = Some(String::from("2"));
+//! let options = cli
+//!     .select_options(&data, vec!["be", "udp"], Vec::new(), None, None, None)
+//!     .unwrap();
+//! let outcome = run::select(options).unwrap();
+//! assert_eq!(outcome.alternates.len(), 2);
+//!
+//! cli.margin = String::from("150");
+//! assert!(cli.margin().is_err());
+//! ```
+use crate::bench::resolve::{DnsResolver, Resolver, StaticResolver, SystemResolver};
+use crate::bench::{
+    self, Aggregation, Benchmarker, ErrorPolicy, HistoricLoadBenchmarker, PingBenchmarker,
+    ScoringWeights, TcpBenchmarker,
+};
+use crate::cli_help::LabelledFilter;
+use crate::failures::FailureLog;
+use crate::history::History;
+use crate::run::SelectOptions;
+use crate::servers::{SampleStrategy, Servers};
+use crate::sorters::{AgeSorter, BandwidthSorter, HubScoreSorter};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How many times one server of every country is connected to over TCP to find the nearest
+/// country, when no ping test is asked for.
+pub const NEAREST_COUNTRY_TRIES: usize = 2;
+
+/// How long failures are taken into account, when they are reported.
+pub const FAILURE_WINDOW: Duration = Duration::from_secs(24 * 3600);
+
+/// The reasons options given to the command line tool can not be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionError {
+    /// The value of the option can not be used, e.g. a deadline that is not a number.
+    Invalid {
+        /// The option as named in messages, e.g. `deadline`.
+        option: &'static str,
+        /// Why the value can not be used.
+        reason: String,
+    },
+    /// The option needs a feature this library was built without, e.g. `geo-db` for
+    /// `off-peak`.
+    MissingFeature {
+        /// The option, e.g. `off-peak`.
+        option: &'static str,
+        /// The feature it needs.
+        feature: &'static str,
+    },
+}
+
+impl OptionError {
+    fn invalid(option: &'static str, reason: impl fmt::Display) -> OptionError {
+        OptionError::Invalid {
+            option,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for OptionError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptionError::Invalid { option, reason } => {
+                write!(formatter, "could not read {}: {}", option, reason)
+            }
+            OptionError::MissingFeature { option, feature } => write!(
+                formatter,
+                "{} needs the {} feature, which this version was built without",
+                option, feature
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OptionError {}
+
+/// The options of the command line tool that decide how servers are selected, as the text they
+/// were given as. The defaults are the ones of the tool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CliOptions {
+    /// `--ping`: whether the servers are pinged, all at the same time.
+    pub multi_ping: bool,
+    /// `--sping`: whether the servers are pinged, one after the other.
+    pub single_ping: bool,
+    /// `--tries`: how many times every server is pinged.
+    pub tries: String,
+    /// `--ping-agg`: how the latencies of the tries are combined, `median`, `mean` or `p90`.
+    pub ping_agg: String,
+    /// `--ping-errors`: what happens to servers that do not answer, `skip`, `worst` or `abort`.
+    pub ping_errors: String,
+    /// `--amount`: how many of the least loaded servers are pinged.
+    pub amount: String,
+    /// `--seed`: the seed to choose between equally good servers with, a number or `random`.
+    pub seed: Option<String>,
+    /// `--sample`: how many of the servers passing the filters are considered.
+    pub sample: Option<String>,
+    /// `--sample-by`: how the sample is taken, `country` or `random`.
+    pub sample_by: String,
+    /// `--deadline`: after how many seconds benchmarking stops.
+    pub deadline: Option<String>,
+    /// `--history-window`: over how many hours the load is averaged.
+    pub history_window: String,
+    /// `--prefer-bandwidth`: whether servers with the highest bandwidth are preferred.
+    pub prefer_bandwidth: bool,
+    /// `--prefer-established`: whether the servers added longest ago are preferred.
+    pub prefer_established: bool,
+    /// `--prefer-recommended`: whether the servers NordVPN recommends are preferred.
+    pub prefer_recommended: bool,
+    /// `--off-peak`: whether servers where it is night are preferred.
+    pub off_peak: bool,
+    /// `--failure-penalty`: how many percent worse servers get for every reported failure.
+    pub failure_penalty: String,
+    /// `--nearest-region`: whether only servers in the nearest country are selected.
+    pub nearest_region: bool,
+    /// `--dns`: the DNS server to resolve servers with.
+    pub dns: Option<String>,
+    /// `--resolve`: addresses of domains, as `DOMAIN=IP`.
+    pub resolve: Vec<String>,
+    /// `--verify`: whether the selected server is checked to be reachable.
+    pub verify: bool,
+    /// `--verify-attempts`: how many servers are tried when verifying.
+    pub verify_attempts: String,
+    /// `--alternates`: how many servers are listed next to the selected one.
+    pub alternates: Option<String>,
+    /// `--margin`: how many percent better another server should be than the kept one.
+    pub margin: String,
+    /// `--want`: whether servers are wanted one by one, which keeps all servers in order.
+    pub want: bool,
+    /// `--trace-filters`: whether the filters are applied one by one, counting servers.
+    pub trace_filters: bool,
+    /// The weights of the `[scoring]` section of the profiles file, if it has one.
+    pub scoring: Option<ScoringWeights>,
+}
+
+impl Default for CliOptions {
+    fn default() -> CliOptions {
+        CliOptions {
+            multi_ping: false,
+            single_ping: false,
+            tries: String::from("2"),
+            ping_agg: String::from("median"),
+            ping_errors: String::from("worst"),
+            amount: String::from("10"),
+            seed: None,
+            sample: None,
+            sample_by: String::from("country"),
+            deadline: None,
+            history_window: String::from("24"),
+            prefer_bandwidth: false,
+            prefer_established: false,
+            prefer_recommended: false,
+            off_peak: false,
+            failure_penalty: String::from("100"),
+            nearest_region: false,
+            dns: None,
+            resolve: Vec::new(),
+            verify: false,
+            verify_attempts: String::from("3"),
+            alternates: None,
+            margin: String::from("10"),
+            want: false,
+            trace_filters: false,
+            scoring: None,
+        }
+    }
+}
+
+/// Returns a seed that differs between runs.
+fn random_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+impl CliOptions {
+    /// Returns when benchmarking should stop, counting from `start`, if limited.
+    pub fn deadline(&self, start: Instant) -> Result<Option<Instant>, OptionError> {
+        let secs: f64 = match &self.deadline {
+            Some(secs) => secs
+                .parse()
+                .map_err(|err| OptionError::invalid("deadline", err))?,
+            None => return Ok(None),
+        };
+        if secs.is_nan() {
+            return Err(OptionError::invalid("deadline", "it should be a number"));
+        }
+        if secs < 0f64 {
+            return Err(OptionError::invalid(
+                "deadline",
+                "it should not be negative",
+            ));
+        }
+
+        // A Duration holds at most u64::MAX seconds, and an Instant may not go that far.
+        Some(secs)
+            .filter(|secs| *secs < u64::MAX as f64)
+            .and_then(|secs| start.checked_add(Duration::from_secs_f64(secs)))
+            .map(Some)
+            .ok_or_else(|| OptionError::invalid("deadline", "it is too far in the future"))
+    }
+
+    /// Returns the period over which the load is averaged.
+    pub fn history_window(&self) -> Result<Duration, OptionError> {
+        let hours: u64 = self
+            .history_window
+            .parse()
+            .map_err(|err| OptionError::invalid("history window", err))?;
+
+        hours
+            .checked_mul(3600)
+            .map(Duration::from_secs)
+            .ok_or_else(|| OptionError::invalid("history window", "it is too large"))
+    }
+
+    /// Returns the penalty per reported failure, in percent.
+    pub fn failure_penalty(&self) -> Result<u32, OptionError> {
+        self.failure_penalty
+            .parse()
+            .map_err(|err| OptionError::invalid("failure penalty", err))
+    }
+
+    /// Returns how many times every server is pinged.
+    pub fn tries(&self) -> Result<usize, OptionError> {
+        self.tries
+            .parse()
+            .map_err(|err| OptionError::invalid("tries of pings", err))
+    }
+
+    /// Returns how the latencies of the tries of every server are combined.
+    pub fn aggregation(&self) -> Result<Aggregation, OptionError> {
+        match self.ping_agg.as_str() {
+            "median" => Ok(Aggregation::Median),
+            "mean" => Ok(Aggregation::Mean),
+            "p90" => Ok(Aggregation::P90),
+            _ => Err(OptionError::invalid(
+                "ping aggregation",
+                "it should be median, mean or p90",
+            )),
+        }
+    }
+
+    /// Returns what happens to servers that do not answer pings.
+    pub fn error_policy(&self) -> Result<ErrorPolicy, OptionError> {
+        match self.ping_errors.as_str() {
+            "skip" => Ok(ErrorPolicy::SkipServer),
+            "worst" => Ok(ErrorPolicy::TreatAsWorst),
+            "abort" => Ok(ErrorPolicy::Abort),
+            _ => Err(OptionError::invalid(
+                "ping errors",
+                "it should be skip, worst or abort",
+            )),
+        }
+    }
+
+    /// Returns the seed to order equally good servers with, if one is given.
+    pub fn seed(&self) -> Result<Option<u64>, OptionError> {
+        match self.seed.as_deref() {
+            None => Ok(None),
+            Some("random") => Ok(Some(random_seed())),
+            Some(seed) => seed
+                .parse()
+                .map(Some)
+                .map_err(|err| OptionError::invalid("seed", err)),
+        }
+    }
+
+    /// Returns how many servers to sample, how, and using which seed, if sampling is asked for.
+    /// Without a seed, every run takes another sample.
+    pub fn sample(&self) -> Result<Option<(usize, SampleStrategy, u64)>, OptionError> {
+        let amount = match &self.sample {
+            Some(amount) => amount
+                .parse()
+                .map_err(|err| OptionError::invalid("sample size", err))?,
+            None => return Ok(None),
+        };
+        let strategy = match self.sample_by.as_str() {
+            "country" => SampleStrategy::PerCountry,
+            "random" => SampleStrategy::Random,
+            _ => {
+                return Err(OptionError::invalid(
+                    "sample strategy",
+                    "it should be country or random",
+                ))
+            }
+        };
+
+        let seed = self.seed()?.unwrap_or_else(random_seed);
+        Ok(Some((amount, strategy, seed)))
+    }
+
+    /// Returns how many alternates to list next to the selected server, if asked for.
+    pub fn alternates(&self) -> Result<Option<usize>, OptionError> {
+        match &self.alternates {
+            Some(amount) => amount
+                .parse()
+                .map(Some)
+                .map_err(|err| OptionError::invalid("alternates", err)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns how many servers should be tried when verifying, if they should be verified.
+    pub fn verify_attempts(&self) -> Result<Option<usize>, OptionError> {
+        if !self.verify {
+            return Ok(None);
+        }
+
+        match self.verify_attempts.parse() {
+            Ok(0) => Err(OptionError::invalid(
+                "verify attempts",
+                "at least one server should be tried",
+            )),
+            Ok(attempts) => Ok(Some(attempts)),
+            Err(err) => Err(OptionError::invalid("verify attempts", err)),
+        }
+    }
+
+    /// Returns the margin in percent another server should beat the kept server by.
+    pub fn margin(&self) -> Result<u8, OptionError> {
+        match self.margin.parse() {
+            Ok(margin) if margin <= 100 => Ok(margin),
+            _ => Err(OptionError::invalid(
+                "margin",
+                "it should be a percentage between 0 and 100",
+            )),
+        }
+    }
+
+    /// Returns the resolver given by `--dns` and `--resolve`, or `None` to use the one of the
+    /// system.
+    pub fn resolver(&self) -> Result<Option<Arc<dyn Resolver + Send + Sync>>, OptionError> {
+        let dns = match &self.dns {
+            Some(ip) => Some(DnsResolver::new(
+                ip.parse()
+                    .map_err(|err| OptionError::invalid("DNS server", err))?,
+            )),
+            None => None,
+        };
+        if self.resolve.is_empty() {
+            return Ok(dns.map(|dns| Arc::new(dns) as Arc<dyn Resolver + Send + Sync>));
+        }
+
+        let mut resolver = StaticResolver::new();
+        for address in &self.resolve {
+            let parsed = address
+                .split_once('=')
+                .and_then(|(domain, ip)| Some((domain, ip.parse().ok()?)));
+            match parsed {
+                Some((domain, ip)) => resolver.insert(domain, ip),
+                None => {
+                    return Err(OptionError::invalid(
+                        "resolved address",
+                        format!("\"{}\" should look like DOMAIN=IP", address),
+                    ))
+                }
+            }
+        }
+        Ok(Some(match dns {
+            Some(dns) => Arc::new(resolver.fallback(dns)),
+            None => Arc::new(resolver.fallback(SystemResolver)),
+        }))
+    }
+
+    /// Returns whether a ping test is asked for.
+    pub fn pings(&self) -> bool {
+        self.single_ping || self.multi_ping
+    }
+
+    /// Sets up the ping test. Returns the benchmarker and the amount of servers to ping, or
+    /// `None` when no ping test is asked for or when the deadline already passed.
+    pub fn ping_benchmarker(
+        &self,
+        deadline: Option<Instant>,
+    ) -> Result<Option<(PingBenchmarker, usize)>, OptionError> {
+        if !self.pings() {
+            return Ok(None);
+        }
+
+        let tries = self.tries()?;
+        let amount = self
+            .amount
+            .parse()
+            .map_err(|err| OptionError::invalid("amount of pings", err))?;
+        if let Some(deadline) = deadline {
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+        }
+
+        let mut benchmarker = PingBenchmarker::new(tries).aggregation(self.aggregation()?);
+        if let Some(resolver) = self.resolver()? {
+            benchmarker = benchmarker.resolver(resolver);
+        }
+        if self.single_ping {
+            Ok(Some((benchmarker, amount)))
+        } else {
+            Ok(Some((benchmarker.one_by_one(), amount)))
+        }
+    }
+
+    /// Returns the benchmarker to find the country with the lowest latency with. One server of
+    /// every country is pinged when a ping test is asked for, and connected to over TCP
+    /// otherwise.
+    pub fn nearest_benchmarker(
+        &self,
+        deadline: Option<Instant>,
+    ) -> Result<Box<dyn Benchmarker>, OptionError> {
+        if let Some((benchmarker, _)) = self.ping_benchmarker(deadline)? {
+            return Ok(Box::new(benchmarker));
+        }
+
+        let mut benchmarker =
+            TcpBenchmarker::new(NEAREST_COUNTRY_TRIES).aggregation(self.aggregation()?);
+        if let Some(resolver) = self.resolver()? {
+            benchmarker = benchmarker.resolver(resolver);
+        }
+        Ok(Box::new(benchmarker))
+    }
+
+    /// Returns the benchmarker ordering the servers when no ping test is done or when it fails,
+    /// if another one than their load is asked for.
+    fn fallback<'a>(
+        &self,
+        history: Option<&'a History>,
+    ) -> Result<Option<Box<dyn Benchmarker + 'a>>, OptionError> {
+        if let Some(history) = history {
+            let benchmarker = HistoricLoadBenchmarker::new(history, self.history_window()?);
+            return Ok(Some(Box::new(benchmarker)));
+        }
+
+        Ok(if self.prefer_bandwidth {
+            Some(Box::new(bench::from_sorter(&BandwidthSorter)))
+        } else if self.prefer_established {
+            Some(Box::new(bench::from_sorter(&AgeSorter)))
+        } else if self.prefer_recommended {
+            Some(Box::new(bench::from_sorter(&HubScoreSorter)))
+        } else if self.off_peak {
+            Some(off_peak_benchmarker()?)
+        } else {
+            None
+        })
+    }
+
+    /// Returns how the servers are selected from `data`, the way the command line tool does.
+    /// The filters are written like on the command line, the extra filters are applied on top of
+    /// them. With a history, servers are ordered on their average load. Servers of which
+    /// failures were reported are made worse.
+    ///
+    /// The aliases, the server to keep and the logger are left for the caller to set.
+    pub fn select_options<'a>(
+        &self,
+        data: &'a Servers,
+        filters: Vec<&str>,
+        extra_filters: Vec<LabelledFilter>,
+        deadline: Option<Instant>,
+        history: Option<&'a History>,
+        failures: Option<&'a FailureLog>,
+    ) -> Result<SelectOptions<'a>, OptionError> {
+        let mut options = SelectOptions::new(data)
+            .filters(filters)
+            .default_to_standard(!self.want)
+            .trace(self.trace_filters);
+        for (label, filter) in extra_filters {
+            options = options.filter(label, filter);
+        }
+        if let Some(fallback) = self.fallback(history)? {
+            options = options.fallback(fallback);
+        }
+        if let Some(failures) = failures.filter(|failures| !failures.is_empty()) {
+            options = options.failures(failures, self.failure_penalty()?, FAILURE_WINDOW);
+        }
+        if self.nearest_region {
+            options = options.nearest_country(self.nearest_benchmarker(deadline)?);
+        }
+
+        // All servers are kept for --want, which picks the servers it needs from them in order.
+        if self.want {
+            options = options.all_alternates();
+        } else {
+            let amount = self.verify_attempts()?.unwrap_or(1) - 1;
+            options = options.alternates(amount.max(self.alternates()?.unwrap_or(0)));
+        }
+        if let Some(seed) = self.seed()? {
+            options = options.seed(seed);
+        }
+        if let Some((amount, strategy, seed)) = self.sample()? {
+            options = options.sample(amount, strategy, seed);
+        }
+        if let Some(deadline) = deadline {
+            options = options.deadline(deadline);
+        }
+        if let Some((ping_benchmarker, amount)) = self.ping_benchmarker(deadline)? {
+            let policy = self.error_policy()?;
+            options = match self.scoring {
+                // The distance to servers is not known, so only the load and latency count.
+                Some(weights) => {
+                    let ping_benchmarker =
+                        weights.benchmarker(Some(Box::new(ping_benchmarker)), None);
+                    options.ping(ping_benchmarker, amount, policy)
+                }
+                None => options.ping(ping_benchmarker, amount, policy),
+            };
+        }
+
+        Ok(options)
+    }
+}
+
+/// Returns the benchmarker preferring servers where it is night.
+#[cfg(feature = "geo-db")]
+fn off_peak_benchmarker() -> Result<Box<dyn Benchmarker>, OptionError> {
+    Ok(Box::new(bench::OffPeakBenchmarker::new()))
+}
+
+/// Fails, as the location of servers is not known without the geo-db feature.
+#[cfg(not(feature = "geo-db"))]
+fn off_peak_benchmarker() -> Result<Box<dyn Benchmarker>, OptionError> {
+    Err(OptionError::MissingFeature {
+        option: "off-peak",
+        feature: "geo-db",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadlines() {
+        let start = Instant::now();
+        let deadline = |secs: &str| {
+            let cli = CliOptions {
+                deadline: Some(String::from(secs)),
+                ..CliOptions::default()
+            };
+            cli.deadline(start)
+        };
+
+        assert_eq!(CliOptions::default().deadline(start), Ok(None));
+        assert_eq!(
+            deadline("1.5"),
+            Ok(Some(start + Duration::from_millis(1500)))
+        );
+        for invalid in &["soon", "NaN", "-1", "1e300"] {
+            assert!(deadline(invalid).is_err(), "{} was accepted", invalid);
+        }
+    }
+
+    #[test]
+    fn invalid_options() {
+        let cli = CliOptions {
+            history_window: u64::MAX.to_string(),
+            verify: true,
+            verify_attempts: String::from("0"),
+            margin: String::from("101"),
+            ping_agg: String::from("mode"),
+            resolve: vec![String::from("nl1.nordvpn.com")],
+            ..CliOptions::default()
+        };
+
+        assert!(cli.history_window().is_err());
+        assert!(cli.verify_attempts().is_err());
+        assert!(cli.margin().is_err());
+        assert!(cli.aggregation().is_err());
+        assert_eq!(
+            cli.resolver().err().map(|err| err.to_string()),
+            Some(String::from(
+                "could not read resolved address: \"nl1.nordvpn.com\" should look like DOMAIN=IP"
+            ))
+        );
+    }
+}
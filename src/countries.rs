@@ -0,0 +1,595 @@
+//! Information about the countries NordVPN has servers in.
+
+/// The identifiers NordVPN uses for countries in the v1 API, mapped by their
+/// [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2) code.
+const NORD_IDS: &[(&str, u32)] = &[
+    ("AL", 2),
+    ("AR", 10),
+    ("AU", 13),
+    ("AT", 14),
+    ("BE", 21),
+    ("BA", 27),
+    ("BR", 30),
+    ("BG", 33),
+    ("CA", 38),
+    ("CL", 43),
+    ("CR", 52),
+    ("HR", 54),
+    ("CY", 56),
+    ("CZ", 57),
+    ("DK", 58),
+    ("EE", 68),
+    ("FI", 73),
+    ("FR", 74),
+    ("GE", 80),
+    ("DE", 81),
+    ("GR", 84),
+    ("HK", 97),
+    ("HU", 98),
+    ("IS", 99),
+    ("IN", 100),
+    ("ID", 101),
+    ("IE", 104),
+    ("IL", 105),
+    ("IT", 106),
+    ("JP", 108),
+    ("KR", 114),
+    ("LV", 119),
+    ("LT", 125),
+    ("LU", 126),
+    ("MK", 128),
+    ("MY", 131),
+    ("MX", 140),
+    ("MD", 142),
+    ("NL", 153),
+    ("NZ", 156),
+    ("NO", 163),
+    ("PL", 174),
+    ("PT", 175),
+    ("RO", 179),
+    ("RS", 192),
+    ("SG", 195),
+    ("SK", 196),
+    ("SI", 197),
+    ("ZA", 200),
+    ("ES", 202),
+    ("SE", 208),
+    ("CH", 209),
+    ("TW", 211),
+    ("TH", 214),
+    ("TR", 220),
+    ("UA", 225),
+    ("AE", 226),
+    ("GB", 227),
+    ("US", 228),
+    ("VN", 234),
+];
+
+/// Returns the identifier NordVPN uses for the given country in its v1 API. The country should be
+/// an uppercase [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2) code.
+///
+/// Returns `None` for countries without NordVPN servers, or that are not known to this library.
+///
+/// # Examples
+///
+/// ```
+/// use nordselect::countries;
+///
+/// assert_eq!(countries::nord_id("BE"), Some(21));
+/// assert_eq!(countries::nord_id("XK"), None);
+/// ```
+pub fn nord_id(alpha2: &str) -> Option<u32> {
+    NORD_IDS
+        .iter()
+        .find(|(code, _)| *code == alpha2)
+        .map(|(_, id)| *id)
+}
+
+/// Returns the [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2) code of
+/// the country NordVPN identifies with the given number, e.g. in the v1 API.
+///
+/// Returns `None` for identifiers that are not known to this library.
+///
+/// # Examples
+///
+/// ```
+/// use nordselect::countries;
+///
+/// assert_eq!(countries::from_nord_id(21), Some("BE"));
+/// assert_eq!(countries::from_nord_id(0), None);
+/// ```
+pub fn from_nord_id(id: u32) -> Option<&'static str> {
+    NORD_IDS
+        .iter()
+        .find(|(_, nord_id)| *nord_id == id)
+        .map(|(code, _)| *code)
+}
+
+/// Returns the codes of all countries NordVPN is known to have servers in.
+#[cfg(any(test, feature = "test-util"))]
+pub(crate) fn nord_countries() -> impl Iterator<Item = &'static str> {
+    NORD_IDS.iter().map(|(code, _)| *code)
+}
+
+/// The countries NordVPN has servers in that are next to each other, either sharing a border or
+/// separated by a short sea crossing. Every pair is listed once, sorted on its first code.
+const NEIGHBOURS: &[(&str, &str)] = &[
+    ("AL", "GR"),
+    ("AL", "IT"),
+    ("AL", "MK"),
+    ("AR", "BR"),
+    ("AR", "CL"),
+    ("AT", "CH"),
+    ("AT", "CZ"),
+    ("AT", "DE"),
+    ("AT", "HU"),
+    ("AT", "IT"),
+    ("AT", "SI"),
+    ("AT", "SK"),
+    ("BA", "HR"),
+    ("BA", "RS"),
+    ("BE", "DE"),
+    ("BE", "FR"),
+    ("BE", "LU"),
+    ("BE", "NL"),
+    ("BG", "GR"),
+    ("BG", "MK"),
+    ("BG", "RO"),
+    ("BG", "RS"),
+    ("BG", "TR"),
+    ("CA", "US"),
+    ("CH", "DE"),
+    ("CH", "FR"),
+    ("CH", "IT"),
+    ("CY", "TR"),
+    ("CZ", "DE"),
+    ("CZ", "PL"),
+    ("CZ", "SK"),
+    ("DE", "DK"),
+    ("DE", "FR"),
+    ("DE", "LU"),
+    ("DE", "NL"),
+    ("DE", "PL"),
+    ("DK", "NO"),
+    ("DK", "SE"),
+    ("EE", "FI"),
+    ("EE", "LV"),
+    ("ES", "FR"),
+    ("ES", "PT"),
+    ("FI", "NO"),
+    ("FI", "SE"),
+    ("FR", "GB"),
+    ("FR", "IT"),
+    ("FR", "LU"),
+    ("GB", "IE"),
+    ("GE", "TR"),
+    ("GR", "MK"),
+    ("GR", "TR"),
+    ("HR", "HU"),
+    ("HR", "RS"),
+    ("HR", "SI"),
+    ("HU", "RO"),
+    ("HU", "RS"),
+    ("HU", "SI"),
+    ("HU", "SK"),
+    ("HU", "UA"),
+    ("ID", "MY"),
+    ("ID", "SG"),
+    ("IT", "SI"),
+    ("JP", "KR"),
+    ("LT", "LV"),
+    ("LT", "PL"),
+    ("MD", "RO"),
+    ("MD", "UA"),
+    ("MK", "RS"),
+    ("MX", "US"),
+    ("MY", "SG"),
+    ("MY", "TH"),
+    ("NO", "SE"),
+    ("PL", "SK"),
+    ("PL", "UA"),
+    ("RO", "RS"),
+    ("RO", "UA"),
+    ("SK", "UA"),
+];
+
+/// Returns the countries with NordVPN servers that are next to the given one, either sharing a
+/// border or separated by a short sea crossing. The country should be an uppercase
+/// [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2) code.
+///
+/// # Examples
+///
+/// ```
+/// use nordselect::countries;
+///
+/// let neighbours: Vec<&str> = countries::neighbours("BE").collect();
+/// assert_eq!(neighbours, vec!["DE", "FR", "LU", "NL"]);
+/// assert_eq!(countries::neighbours("IS").count(), 0);
+/// ```
+pub fn neighbours<'a>(alpha2: &'a str) -> impl Iterator<Item = &'static str> + 'a {
+    NEIGHBOURS.iter().filter_map(move |(a, b)| {
+        if *a == alpha2 {
+            Some(*b)
+        } else if *b == alpha2 {
+            Some(*a)
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns the given country and the countries with NordVPN servers that can be reached from it
+/// by going to a [neighbour](fn.neighbours.html) at most `radius` times, sorted on code.
+///
+/// # Examples
+///
+/// ```
+/// use nordselect::countries;
+///
+/// assert_eq!(countries::nearby("LU", 0), vec!["LU"]);
+/// assert_eq!(countries::nearby("LU", 1), vec!["BE", "DE", "FR", "LU"]);
+/// assert!(countries::nearby("LU", 2).contains(&"NL"));
+/// ```
+pub fn nearby(alpha2: &str, radius: u8) -> Vec<&str> {
+    let mut found = vec![alpha2];
+    let mut frontier = vec![alpha2];
+    for _ in 0..radius {
+        let mut next = Vec::new();
+        for country in frontier {
+            for neighbour in neighbours(country) {
+                if !found.contains(&neighbour) {
+                    found.push(neighbour);
+                    next.push(neighbour);
+                }
+            }
+        }
+        frontier = next;
+    }
+    found.sort_unstable();
+    found
+}
+
+/// The names of all countries, mapped by their
+/// [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2) code. Kosovo, which has
+/// no official code yet, uses the commonly used `XK`. Sorted on code.
+const NAMES: &[(&str, &str)] = &[
+    ("AD", "Andorra"),
+    ("AE", "United Arab Emirates"),
+    ("AF", "Afghanistan"),
+    ("AG", "Antigua and Barbuda"),
+    ("AI", "Anguilla"),
+    ("AL", "Albania"),
+    ("AM", "Armenia"),
+    ("AO", "Angola"),
+    ("AQ", "Antarctica"),
+    ("AR", "Argentina"),
+    ("AS", "American Samoa"),
+    ("AT", "Austria"),
+    ("AU", "Australia"),
+    ("AW", "Aruba"),
+    ("AX", "Åland Islands"),
+    ("AZ", "Azerbaijan"),
+    ("BA", "Bosnia and Herzegovina"),
+    ("BB", "Barbados"),
+    ("BD", "Bangladesh"),
+    ("BE", "Belgium"),
+    ("BF", "Burkina Faso"),
+    ("BG", "Bulgaria"),
+    ("BH", "Bahrain"),
+    ("BI", "Burundi"),
+    ("BJ", "Benin"),
+    ("BL", "Saint Barthélemy"),
+    ("BM", "Bermuda"),
+    ("BN", "Brunei Darussalam"),
+    ("BO", "Bolivia"),
+    ("BQ", "Bonaire, Sint Eustatius and Saba"),
+    ("BR", "Brazil"),
+    ("BS", "Bahamas"),
+    ("BT", "Bhutan"),
+    ("BV", "Bouvet Island"),
+    ("BW", "Botswana"),
+    ("BY", "Belarus"),
+    ("BZ", "Belize"),
+    ("CA", "Canada"),
+    ("CC", "Cocos (Keeling) Islands"),
+    ("CD", "Congo, The Democratic Republic of the"),
+    ("CF", "Central African Republic"),
+    ("CG", "Congo"),
+    ("CH", "Switzerland"),
+    ("CI", "Côte d'Ivoire"),
+    ("CK", "Cook Islands"),
+    ("CL", "Chile"),
+    ("CM", "Cameroon"),
+    ("CN", "China"),
+    ("CO", "Colombia"),
+    ("CR", "Costa Rica"),
+    ("CU", "Cuba"),
+    ("CV", "Cabo Verde"),
+    ("CW", "Curaçao"),
+    ("CX", "Christmas Island"),
+    ("CY", "Cyprus"),
+    ("CZ", "Czechia"),
+    ("DE", "Germany"),
+    ("DJ", "Djibouti"),
+    ("DK", "Denmark"),
+    ("DM", "Dominica"),
+    ("DO", "Dominican Republic"),
+    ("DZ", "Algeria"),
+    ("EC", "Ecuador"),
+    ("EE", "Estonia"),
+    ("EG", "Egypt"),
+    ("EH", "Western Sahara"),
+    ("ER", "Eritrea"),
+    ("ES", "Spain"),
+    ("ET", "Ethiopia"),
+    ("FI", "Finland"),
+    ("FJ", "Fiji"),
+    ("FK", "Falkland Islands (Malvinas)"),
+    ("FM", "Micronesia, Federated States of"),
+    ("FO", "Faroe Islands"),
+    ("FR", "France"),
+    ("GA", "Gabon"),
+    ("GB", "United Kingdom"),
+    ("GD", "Grenada"),
+    ("GE", "Georgia"),
+    ("GF", "French Guiana"),
+    ("GG", "Guernsey"),
+    ("GH", "Ghana"),
+    ("GI", "Gibraltar"),
+    ("GL", "Greenland"),
+    ("GM", "Gambia"),
+    ("GN", "Guinea"),
+    ("GP", "Guadeloupe"),
+    ("GQ", "Equatorial Guinea"),
+    ("GR", "Greece"),
+    ("GS", "South Georgia and the South Sandwich Islands"),
+    ("GT", "Guatemala"),
+    ("GU", "Guam"),
+    ("GW", "Guinea-Bissau"),
+    ("GY", "Guyana"),
+    ("HK", "Hong Kong"),
+    ("HM", "Heard Island and McDonald Islands"),
+    ("HN", "Honduras"),
+    ("HR", "Croatia"),
+    ("HT", "Haiti"),
+    ("HU", "Hungary"),
+    ("ID", "Indonesia"),
+    ("IE", "Ireland"),
+    ("IL", "Israel"),
+    ("IM", "Isle of Man"),
+    ("IN", "India"),
+    ("IO", "British Indian Ocean Territory"),
+    ("IQ", "Iraq"),
+    ("IR", "Iran"),
+    ("IS", "Iceland"),
+    ("IT", "Italy"),
+    ("JE", "Jersey"),
+    ("JM", "Jamaica"),
+    ("JO", "Jordan"),
+    ("JP", "Japan"),
+    ("KE", "Kenya"),
+    ("KG", "Kyrgyzstan"),
+    ("KH", "Cambodia"),
+    ("KI", "Kiribati"),
+    ("KM", "Comoros"),
+    ("KN", "Saint Kitts and Nevis"),
+    ("KP", "North Korea"),
+    ("KR", "South Korea"),
+    ("KW", "Kuwait"),
+    ("KY", "Cayman Islands"),
+    ("KZ", "Kazakhstan"),
+    ("LA", "Laos"),
+    ("LB", "Lebanon"),
+    ("LC", "Saint Lucia"),
+    ("LI", "Liechtenstein"),
+    ("LK", "Sri Lanka"),
+    ("LR", "Liberia"),
+    ("LS", "Lesotho"),
+    ("LT", "Lithuania"),
+    ("LU", "Luxembourg"),
+    ("LV", "Latvia"),
+    ("LY", "Libya"),
+    ("MA", "Morocco"),
+    ("MC", "Monaco"),
+    ("MD", "Moldova"),
+    ("ME", "Montenegro"),
+    ("MF", "Saint Martin (French part)"),
+    ("MG", "Madagascar"),
+    ("MH", "Marshall Islands"),
+    ("MK", "North Macedonia"),
+    ("ML", "Mali"),
+    ("MM", "Myanmar"),
+    ("MN", "Mongolia"),
+    ("MO", "Macao"),
+    ("MP", "Northern Mariana Islands"),
+    ("MQ", "Martinique"),
+    ("MR", "Mauritania"),
+    ("MS", "Montserrat"),
+    ("MT", "Malta"),
+    ("MU", "Mauritius"),
+    ("MV", "Maldives"),
+    ("MW", "Malawi"),
+    ("MX", "Mexico"),
+    ("MY", "Malaysia"),
+    ("MZ", "Mozambique"),
+    ("NA", "Namibia"),
+    ("NC", "New Caledonia"),
+    ("NE", "Niger"),
+    ("NF", "Norfolk Island"),
+    ("NG", "Nigeria"),
+    ("NI", "Nicaragua"),
+    ("NL", "Netherlands"),
+    ("NO", "Norway"),
+    ("NP", "Nepal"),
+    ("NR", "Nauru"),
+    ("NU", "Niue"),
+    ("NZ", "New Zealand"),
+    ("OM", "Oman"),
+    ("PA", "Panama"),
+    ("PE", "Peru"),
+    ("PF", "French Polynesia"),
+    ("PG", "Papua New Guinea"),
+    ("PH", "Philippines"),
+    ("PK", "Pakistan"),
+    ("PL", "Poland"),
+    ("PM", "Saint Pierre and Miquelon"),
+    ("PN", "Pitcairn"),
+    ("PR", "Puerto Rico"),
+    ("PS", "Palestine, State of"),
+    ("PT", "Portugal"),
+    ("PW", "Palau"),
+    ("PY", "Paraguay"),
+    ("QA", "Qatar"),
+    ("RE", "Réunion"),
+    ("RO", "Romania"),
+    ("RS", "Serbia"),
+    ("RU", "Russian Federation"),
+    ("RW", "Rwanda"),
+    ("SA", "Saudi Arabia"),
+    ("SB", "Solomon Islands"),
+    ("SC", "Seychelles"),
+    ("SD", "Sudan"),
+    ("SE", "Sweden"),
+    ("SG", "Singapore"),
+    ("SH", "Saint Helena, Ascension and Tristan da Cunha"),
+    ("SI", "Slovenia"),
+    ("SJ", "Svalbard and Jan Mayen"),
+    ("SK", "Slovakia"),
+    ("SL", "Sierra Leone"),
+    ("SM", "San Marino"),
+    ("SN", "Senegal"),
+    ("SO", "Somalia"),
+    ("SR", "Suriname"),
+    ("SS", "South Sudan"),
+    ("ST", "Sao Tome and Principe"),
+    ("SV", "El Salvador"),
+    ("SX", "Sint Maarten (Dutch part)"),
+    ("SY", "Syria"),
+    ("SZ", "Eswatini"),
+    ("TC", "Turks and Caicos Islands"),
+    ("TD", "Chad"),
+    ("TF", "French Southern Territories"),
+    ("TG", "Togo"),
+    ("TH", "Thailand"),
+    ("TJ", "Tajikistan"),
+    ("TK", "Tokelau"),
+    ("TL", "Timor-Leste"),
+    ("TM", "Turkmenistan"),
+    ("TN", "Tunisia"),
+    ("TO", "Tonga"),
+    ("TR", "Türkiye"),
+    ("TT", "Trinidad and Tobago"),
+    ("TV", "Tuvalu"),
+    ("TW", "Taiwan"),
+    ("TZ", "Tanzania"),
+    ("UA", "Ukraine"),
+    ("UG", "Uganda"),
+    ("UM", "United States Minor Outlying Islands"),
+    ("US", "United States"),
+    ("UY", "Uruguay"),
+    ("UZ", "Uzbekistan"),
+    ("VA", "Holy See (Vatican City State)"),
+    ("VC", "Saint Vincent and the Grenadines"),
+    ("VE", "Venezuela"),
+    ("VG", "Virgin Islands, British"),
+    ("VI", "Virgin Islands, U.S."),
+    ("VN", "Vietnam"),
+    ("VU", "Vanuatu"),
+    ("WF", "Wallis and Futuna"),
+    ("WS", "Samoa"),
+    ("XK", "Kosovo"),
+    ("YE", "Yemen"),
+    ("YT", "Mayotte"),
+    ("ZA", "South Africa"),
+    ("ZM", "Zambia"),
+    ("ZW", "Zimbabwe"),
+];
+
+/// Returns the English name of the given country. The country should be an uppercase
+/// [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2) code.
+///
+/// Returns `None` for codes that are not assigned to any country.
+///
+/// # Examples
+///
+/// ```
+/// use nordselect::countries;
+///
+/// assert_eq!(countries::name("BE"), Some("Belgium"));
+/// assert_eq!(countries::name("XX"), None);
+/// ```
+pub fn name(alpha2: &str) -> Option<&'static str> {
+    NAMES
+        .binary_search_by(|(code, _)| (*code).cmp(alpha2))
+        .ok()
+        .map(|index| NAMES[index].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "native")]
+    use crate::api::ApiClient;
+    #[cfg(feature = "native")]
+    use serde_derive::Deserialize;
+    use std::collections::HashSet;
+
+    #[test]
+    fn nord_ids_are_unique() {
+        let ids: HashSet<u32> = NORD_IDS.iter().map(|(_, id)| *id).collect();
+        assert_eq!(ids.len(), NORD_IDS.len());
+
+        for (code, id) in NORD_IDS {
+            assert!(name(code).is_some(), "{} has no name", code);
+            assert_eq!(nord_id(code), Some(*id));
+            assert_eq!(from_nord_id(*id), Some(*code));
+        }
+    }
+
+    #[test]
+    fn neighbours_are_symmetric() {
+        for window in NEIGHBOURS.windows(2) {
+            assert!(window[0] < window[1], "{:?} is not sorted", window[1]);
+        }
+        for (a, b) in NEIGHBOURS {
+            assert!(a < b, "{} and {} are not sorted", a, b);
+            assert!(nord_id(a).is_some(), "{} has no servers", a);
+            assert!(nord_id(b).is_some(), "{} has no servers", b);
+            assert!(neighbours(b).any(|neighbour| neighbour == *a));
+        }
+
+        assert_eq!(nearby("PT", 1), vec!["ES", "PT"]);
+        assert_eq!(nearby("PT", 2), vec!["ES", "FR", "PT"]);
+        assert_eq!(nearby("AU", 3), vec!["AU"]);
+    }
+
+    /// A country as listed by the v1 API.
+    #[cfg(feature = "native")]
+    #[derive(Deserialize)]
+    struct ApiCountry {
+        id: u32,
+        code: String,
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    #[ignore] // Needs access to the API.
+    fn nord_ids_match_api() {
+        let text = ApiClient::new()
+            .get("https://api.nordvpn.com/v1/servers/countries")
+            .unwrap()
+            .text()
+            .unwrap();
+        let countries: Vec<ApiCountry> = serde_json::from_str(&text).unwrap();
+
+        for country in &countries {
+            assert_eq!(
+                nord_id(&country.code),
+                Some(country.id),
+                "{} is missing or wrong",
+                country.code
+            );
+        }
+        assert_eq!(countries.len(), NORD_IDS.len(), "countries were removed");
+    }
+}
@@ -0,0 +1,132 @@
+//! Announcing the best server whenever it changes, for programs running in the background.
+//!
+//! Desktop widgets and status bars can read the state file or listen for the D-Bus signal,
+//! instead of running nordselect over and over again. This is only available with the `daemon`
+//! feature.
+use crate::servers::Server;
+use serde_derive::Serialize;
+use serde_json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The object path the D-Bus signal is sent from.
+pub const DBUS_PATH: &str = "/be/editicalu/NordSelect";
+
+/// The interface of the D-Bus signal.
+pub const DBUS_INTERFACE: &str = "be.editicalu.NordSelect";
+
+/// The name of the D-Bus signal, which has the domain, the country and the load of the new best
+/// server as arguments. The domain is empty when no server matches anymore.
+pub const DBUS_SIGNAL: &str = "BestServerChanged";
+
+#[derive(Serialize)]
+/// The contents of the state file.
+struct State<'a> {
+    /// The best server, if any server matches.
+    server: Option<&'a Server>,
+    /// The moment the best server changed, in seconds since the Unix epoch.
+    changed: u64,
+}
+
+/// Keeps track of the best server, and announces it when it changes.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::daemon::Announcer;
+/// use nordselect::Servers;
+///
+/// let path = std::env::temp_dir().join("nordselect-doc-state.json");
+/// let mut announcer = Announcer::new().state_file(&path);
+/// let best = Servers::dummy_data().perfect_server();
+///
+/// assert!(announcer.update(best.clone()).unwrap());
+/// assert!(!announcer.update(best).unwrap());
+/// assert!(std::fs::read_to_string(&path).unwrap().contains("\"server\""));
+/// ```
+#[derive(Default)]
+pub struct Announcer {
+    /// The file the best server is written to, if any.
+    state_file: Option<PathBuf>,
+    /// The connection to the session bus, if the signal should be sent.
+    dbus: Option<zbus::blocking::Connection>,
+    /// The best server that was announced last.
+    best: Option<Server>,
+}
+
+impl Announcer {
+    /// Creates an Announcer that does not announce anything yet.
+    pub fn new() -> Announcer {
+        Announcer::default()
+    }
+
+    /// Returns the location of the state file in the data directory of the current user (e.g.
+    /// `~/.local/share/nordselect/best_server.json` on Linux).
+    pub fn default_state_file() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("nordselect").join("best_server.json"))
+    }
+
+    /// Writes the best server as JSON to the given file when it changes.
+    pub fn state_file(mut self, path: impl Into<PathBuf>) -> Announcer {
+        self.state_file = Some(path.into());
+        self
+    }
+
+    /// Sends the [BestServerChanged](constant.DBUS_SIGNAL.html) signal on the session bus when
+    /// the best server changes. Fails if the session bus can not be reached.
+    pub fn dbus(mut self) -> Result<Announcer, Box<dyn std::error::Error>> {
+        self.dbus = Some(zbus::blocking::Connection::session()?);
+        Ok(self)
+    }
+
+    /// Returns the best server that was announced last.
+    pub fn best(&self) -> Option<&Server> {
+        self.best.as_ref()
+    }
+
+    /// Remembers the new best server, and announces it if it is a different server than before.
+    /// Returns whether it was announced.
+    pub fn update(&mut self, best: Option<Server>) -> Result<bool, Box<dyn std::error::Error>> {
+        let domain = |server: Option<&Server>| server.map(|server| server.domain.clone());
+        if domain(best.as_ref()) == domain(self.best.as_ref()) {
+            // Only the load changed, which is not worth an announcement.
+            self.best = best;
+            return Ok(false);
+        }
+        self.best = best;
+
+        if let Some(path) = &self.state_file {
+            self.write_state(path)?;
+        }
+        if let Some(connection) = &self.dbus {
+            let body = match &self.best {
+                Some(server) => (server.domain.as_str(), server.flag.as_str(), server.load),
+                None => ("", "", 0),
+            };
+            connection.emit_signal(None::<&str>, DBUS_PATH, DBUS_INTERFACE, DBUS_SIGNAL, &body)?;
+        }
+
+        Ok(true)
+    }
+
+    fn write_state(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let state = State {
+            server: self.best.as_ref(),
+            changed: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Readers should never see a half written file.
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, serde_json::to_string(&state)?)?;
+        fs::rename(&temporary, path)?;
+
+        Ok(())
+    }
+}
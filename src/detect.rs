@@ -0,0 +1,227 @@
+//! Finding out which server you are connected to.
+//!
+//! NordVPN tells which public IP address your traffic comes from and whether it goes through
+//! one of its servers. The server is then found by looking up the addresses of the servers in
+//! the country of that address.
+use crate::api::ApiClient;
+use crate::bench::resolve::{Resolver, SystemResolver};
+use crate::servers::{Country, Server, Servers};
+use serde_derive::Deserialize;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::thread;
+
+/// The endpoint telling what NordVPN knows about the connection it is requested over.
+pub const STATUS_ENDPOINT: &str = "https://api.nordvpn.com/vpn/check/full";
+
+/// How many domains are looked up at the same time.
+const PARALLEL_LOOKUPS: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// What NordVPN knows about your connection, as returned by
+/// [ConnectedServerDetector::status](struct.ConnectedServerDetector.html#method.status).
+pub struct ConnectionStatus {
+    /// The public IP address your traffic comes from.
+    pub ip: IpAddr,
+    /// The country of that address, if known.
+    pub country: Option<Country>,
+    /// Whether your traffic goes through a NordVPN server.
+    pub protected: bool,
+}
+
+/// The response of the status endpoint.
+#[derive(Deserialize)]
+struct ApiStatus {
+    ip: String,
+    #[serde(default)]
+    country_code: Option<String>,
+    status: String,
+}
+
+impl ConnectionStatus {
+    /// Reads a response of the status endpoint.
+    fn from_json(text: &str) -> Result<ConnectionStatus, Box<dyn std::error::Error>> {
+        let status: ApiStatus = serde_json::from_str(text)?;
+
+        Ok(ConnectionStatus {
+            ip: status.ip.parse()?,
+            country: status.country_code.and_then(|code| code.parse().ok()),
+            protected: status.status.eq_ignore_ascii_case("protected"),
+        })
+    }
+}
+
+/// Finds the server you are connected to.
+///
+/// # Example
+///
+/// ```no_run
+/// use nordselect::detect::ConnectedServerDetector;
+/// use nordselect::Servers;
+///
+/// let data = Servers::from_api().unwrap();
+/// match ConnectedServerDetector::new().detect(&data).unwrap() {
+///     Some(server) => println!("Connected to {}", server.domain),
+///     None => println!("Not connected to NordVPN"),
+/// }
+/// ```
+pub struct ConnectedServerDetector {
+    /// Asks NordVPN for the status of the connection.
+    client: ApiClient,
+    /// Finds the addresses of the servers.
+    resolver: Arc<dyn Resolver + Send + Sync>,
+}
+
+impl Default for ConnectedServerDetector {
+    fn default() -> ConnectedServerDetector {
+        ConnectedServerDetector::new()
+    }
+}
+
+impl ConnectedServerDetector {
+    /// Creates a detector using the resolver of the system.
+    pub fn new() -> ConnectedServerDetector {
+        ConnectedServerDetector {
+            client: ApiClient::new(),
+            resolver: Arc::new(SystemResolver),
+        }
+    }
+
+    /// Changes the client used to ask NordVPN for the status of the connection.
+    pub fn client(mut self, client: ApiClient) -> ConnectedServerDetector {
+        self.client = client;
+        self
+    }
+
+    /// Changes how the addresses of the servers are found, which is the resolver of the system
+    /// by default.
+    pub fn resolver(
+        mut self,
+        resolver: Arc<dyn Resolver + Send + Sync>,
+    ) -> ConnectedServerDetector {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Asks NordVPN what it knows about the connection.
+    pub fn status(&self) -> Result<ConnectionStatus, Box<dyn std::error::Error>> {
+        let text = self
+            .client
+            .get(STATUS_ENDPOINT)?
+            .error_for_status()?
+            .text()?;
+        ConnectionStatus::from_json(&text)
+    }
+
+    /// Returns the server you are connected to, or `None` when you are not connected to NordVPN
+    /// or the server is not in the given list.
+    ///
+    /// Returns an error if the status of the connection could not be requested.
+    pub fn detect(&self, servers: &Servers) -> Result<Option<Server>, Box<dyn std::error::Error>> {
+        let status = self.status()?;
+        if !status.protected {
+            return Ok(None);
+        }
+
+        Ok(self.find(servers, &status))
+    }
+
+    /// Returns the server with the public IP address of the status. Only servers in the country
+    /// of that address are looked up, if it is known.
+    pub fn find(&self, servers: &Servers, status: &ConnectionStatus) -> Option<Server> {
+        let candidates: Vec<&Server> = servers
+            .servers
+            .iter()
+            .filter(|server| match status.country {
+                Some(country) => server.flag == country,
+                None => true,
+            })
+            .collect();
+
+        for chunk in candidates.chunks(PARALLEL_LOOKUPS) {
+            let handles: Vec<_> = chunk
+                .iter()
+                .map(|server| {
+                    let resolver = self.resolver.clone();
+                    let domain = server.domain.clone();
+                    thread::spawn(move || resolver.resolve(&domain).ok())
+                })
+                .collect();
+            let found = chunk
+                .iter()
+                .zip(handles)
+                .filter_map(|(server, handle)| {
+                    let address = handle.join().ok().flatten()?;
+                    if address == status.ip {
+                        Some(server)
+                    } else {
+                        None
+                    }
+                })
+                .next();
+            if let Some(server) = found {
+                return Some((*server).clone());
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::resolve::StaticResolver;
+    use crate::mock_api::{MockApi, Response};
+
+    #[test]
+    fn find_connected_server() {
+        let status = ConnectionStatus::from_json(
+            r#"{"ip": "192.0.2.1", "country": "Belgium", "country_code": "BE",
+                "isp": "Example", "status": "Protected"}"#,
+        )
+        .unwrap();
+        assert_eq!(status.country, Some("BE".parse().unwrap()));
+        assert!(status.protected);
+
+        let data = Servers::dummy_data();
+        let belgian: Vec<&Server> = data
+            .servers
+            .iter()
+            .filter(|server| server.flag == "BE")
+            .collect();
+        let mut resolver = StaticResolver::new();
+        for (number, server) in belgian.iter().enumerate() {
+            let last = (belgian.len() - number) as u8;
+            resolver.insert(server.domain.clone(), IpAddr::from([192, 0, 2, last]));
+        }
+        let detector = ConnectedServerDetector::new().resolver(Arc::new(resolver));
+
+        let found = detector.find(&data, &status).unwrap();
+        assert_eq!(found.domain, belgian[belgian.len() - 1].domain);
+
+        let elsewhere = ConnectionStatus {
+            ip: IpAddr::from([198, 51, 100, 1]),
+            ..status
+        };
+        assert_eq!(detector.find(&data, &elsewhere), None);
+
+        let unprotected =
+            ConnectionStatus::from_json(r#"{"ip": "198.51.100.1", "status": "Unprotected"}"#)
+                .unwrap();
+        assert!(!unprotected.protected);
+        assert_eq!(unprotected.country, None);
+    }
+
+    #[test]
+    fn detect_over_api() {
+        let api = MockApi::start(|request| match request.path.as_str() {
+            "/vpn/check/full" => Response::ok(r#"{"ip": "198.51.100.1", "status": "Unprotected"}"#),
+            _ => Response::status(404),
+        });
+        let detector = ConnectedServerDetector::new().client(ApiClient::new().api_base(api.base()));
+
+        assert!(!detector.status().unwrap().protected);
+        assert_eq!(detector.detect(&Servers::dummy_data()).unwrap(), None);
+    }
+}
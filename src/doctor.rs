@@ -0,0 +1,309 @@
+//! Checking whether everything nordselect depends on works, to explain what to do when it does
+//! not. This is what `nordselect doctor` runs.
+//!
+//! Every check returns a [Check](struct.Check.html), which tells how it went and, when it did
+//! not go well, how to fix it.
+use crate::api::{self, ApiClient};
+use crate::bench::resolve::Resolver;
+use crate::bench::PingBackend;
+use crate::cache::Cache;
+use crate::countries;
+use crate::profiles::Profiles;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+/// Stored server lists older than this are reported as outdated.
+pub const OUTDATED_CACHE: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// The country of which a recommended server is asked for to check the API.
+const SAMPLE_COUNTRY: &str = "NL";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+/// How a check went.
+pub enum Status {
+    /// Everything works.
+    Ok,
+    /// It works, but not as well as it could.
+    Warning,
+    /// It does not work.
+    Failed,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Failed => "failed",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// The outcome of a single check.
+pub struct Check {
+    /// What was checked, e.g. `api`.
+    pub name: &'static str,
+    /// How it went.
+    pub status: Status,
+    /// What was found, e.g. the error.
+    pub detail: String,
+    /// What to do about it, unless everything works.
+    pub fix: Option<String>,
+}
+
+impl Check {
+    /// Creates a check that went well.
+    fn ok(name: &'static str, detail: impl Into<String>) -> Check {
+        Check {
+            name,
+            status: Status::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    /// Creates a check that did not go well, with the way to fix it.
+    fn problem(
+        name: &'static str,
+        status: Status,
+        detail: impl Into<String>,
+        fix: impl Into<String>,
+    ) -> Check {
+        Check {
+            name,
+            status,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// A server as listed by the recommendation endpoint, of which only the domain is needed.
+#[derive(Deserialize)]
+struct Recommendation {
+    hostname: String,
+}
+
+/// Checks whether the API can be reached, by asking it for a recommended server. Returns the
+/// domain of that server as well, which can be used by [check_dns](fn.check_dns.html).
+pub fn check_api(client: &ApiClient) -> (Check, Option<String>) {
+    let id = countries::nord_id(SAMPLE_COUNTRY).expect("the sample country is known");
+    let endpoint = api::v1_recommendations_endpoint(id, 1);
+    let text = client
+        .get(&endpoint)
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| err.to_string())
+        .and_then(|response| response.text().map_err(|err| err.to_string()));
+    let text = match text {
+        Ok(text) => text,
+        Err(err) => return (
+            Check::problem(
+                "api",
+                Status::Failed,
+                format!("could not reach {}: {}", endpoint, err),
+                "Check your internet connection and firewall, or use a stored list with `--input`",
+            ),
+            None,
+        ),
+    };
+
+    match serde_json::from_str::<Vec<Recommendation>>(&text) {
+        Ok(servers) => {
+            let sample = servers.into_iter().next().map(|server| server.hostname);
+            (Check::ok("api", "the NordVPN API answers"), sample)
+        }
+        Err(err) => (
+            Check::problem(
+                "api",
+                Status::Failed,
+                format!("could not read the answer of {}: {}", endpoint, err),
+                "A proxy or captive portal may be answering instead; sign in or use `--mirror`",
+            ),
+            None,
+        ),
+    }
+}
+
+/// Checks whether the stored server list can be read, and whether it is recent.
+pub fn check_cache(cache: Option<&Cache>) -> Check {
+    let cache = match cache {
+        Some(cache) => cache,
+        None => {
+            return Check::problem(
+                "cache",
+                Status::Warning,
+                "no cache directory was found",
+                "Set XDG_CACHE_HOME or HOME, so that server lists can be stored",
+            )
+        }
+    };
+    if !cache.exists() {
+        return Check::problem(
+            "cache",
+            Status::Warning,
+            format!("no server list is stored in {}", cache.path().display()),
+            "Run `nordselect cache refresh` to store one",
+        );
+    }
+
+    match cache.metadata() {
+        Ok(metadata) if metadata.age() > OUTDATED_CACHE => Check::problem(
+            "cache",
+            Status::Warning,
+            format!(
+                "the {} servers stored in {} are {} days old",
+                metadata.servers,
+                cache.path().display(),
+                metadata.age().as_secs() / (24 * 3600)
+            ),
+            "Run `nordselect cache refresh` to store a recent list",
+        ),
+        Ok(metadata) => Check::ok(
+            "cache",
+            format!(
+                "{} servers are stored in {}",
+                metadata.servers,
+                cache.path().display()
+            ),
+        ),
+        Err(err) => Check::problem(
+            "cache",
+            Status::Failed,
+            format!("could not read {}: {}", cache.path().display(), err),
+            "Run `nordselect cache clear` to remove it",
+        ),
+    }
+}
+
+/// Checks whether the current process can send pings, and how.
+pub fn check_ping() -> Check {
+    let backend = PingBackend::detect();
+    if backend.is_available() {
+        return Check::ok("ping", format!("pings are sent using {}", backend));
+    }
+
+    let mut fixes = Vec::new();
+    if cfg!(unix) {
+        let exe = std::env::current_exe()
+            .map(|exe| exe.display().to_string())
+            .unwrap_or_else(|_| String::from("<binary>"));
+        fixes.push(format!("run `setcap cap_net_raw+ep {}` as root", exe));
+        if cfg!(target_os = "linux") {
+            fixes.push(String::from(
+                "allow unprivileged pings with `sysctl net.ipv4.ping_group_range=\"0 2147483647\"`",
+            ));
+        }
+    }
+    fixes.push(String::from("use `--tcp` to measure without pinging"));
+    let mut fix = fixes.join(", or ");
+    fix[..1].make_ascii_uppercase();
+    Check::problem(
+        "ping",
+        Status::Failed,
+        "neither raw nor datagram ICMP sockets can be opened",
+        fix,
+    )
+}
+
+/// Checks whether the domain of a server can be resolved.
+pub fn check_dns(resolver: &dyn Resolver, domain: Option<&str>) -> Check {
+    let domain = match domain {
+        Some(domain) => domain,
+        None => {
+            return Check::problem(
+                "dns",
+                Status::Warning,
+                "no server was found to resolve",
+                "Fix the API check, or store a list with `nordselect cache refresh`",
+            )
+        }
+    };
+
+    match resolver.resolve(domain) {
+        Ok(address) => Check::ok("dns", format!("{} resolves to {}", domain, address)),
+        Err(err) => Check::problem(
+            "dns",
+            Status::Failed,
+            format!("could not resolve {}: {}", domain, err),
+            "Ask another DNS server with `--dns 1.1.1.1`, or give addresses with `--resolve`",
+        ),
+    }
+}
+
+/// Checks whether the profiles file at the given path, if any, can be read.
+pub fn check_profiles(path: Option<&Path>) -> Check {
+    let path = match path {
+        Some(path) if path.exists() => path,
+        _ => return Check::ok("config", "no profiles file, the built-in profiles are used"),
+    };
+
+    match Profiles::new().load(path) {
+        Ok(()) => Check::ok("config", format!("{} can be read", path.display())),
+        Err(err) => Check::problem(
+            "config",
+            Status::Failed,
+            format!("could not read {}: {}", path.display(), err),
+            format!("Fix or remove {}", path.display()),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::resolve::StaticResolver;
+    use crate::mock_api::{MockApi, Response};
+    use crate::servers::Servers;
+
+    #[test]
+    fn api_and_dns() {
+        let api = MockApi::start(|request| {
+            if request.parameter("filters[country_id]") == Some("153") {
+                Response::ok(r#"[{"hostname": "nl1.nordvpn.com", "load": 10}]"#)
+            } else {
+                Response::status(404)
+            }
+        });
+        let (check, sample) = check_api(&ApiClient::new().api_base(api.base()));
+        assert_eq!(check.status, Status::Ok);
+        assert_eq!(sample.as_deref(), Some("nl1.nordvpn.com"));
+
+        let mut resolver = StaticResolver::new();
+        resolver.insert("nl1.nordvpn.com", "10.0.0.1".parse().unwrap());
+        assert_eq!(check_dns(&resolver, sample.as_deref()).status, Status::Ok);
+        assert_eq!(check_dns(&resolver, None).status, Status::Warning);
+
+        let api = MockApi::start(|_| Response::ok("<html>Sign in</html>"));
+        let (check, sample) = check_api(&ApiClient::new().api_base(api.base()));
+        assert_eq!(check.status, Status::Failed);
+        assert!(check.fix.is_some());
+        assert_eq!(sample, None);
+    }
+
+    #[test]
+    fn cache_and_profiles() {
+        assert_eq!(check_cache(None).status, Status::Warning);
+
+        let path = std::env::temp_dir().join("nordselect-test-doctor-cache.json");
+        let cache = Cache::new(&path);
+        let _ = cache.clear();
+        assert_eq!(check_cache(Some(&cache)).status, Status::Warning);
+        cache.store(&Servers::dummy_data()).unwrap();
+        assert_eq!(check_cache(Some(&cache)).status, Status::Ok);
+        std::fs::write(&path, "{").unwrap();
+        assert_eq!(check_cache(Some(&cache)).status, Status::Failed);
+        cache.clear().unwrap();
+
+        assert_eq!(check_profiles(None).status, Status::Ok);
+        let path = std::env::temp_dir().join("nordselect-test-doctor-profiles");
+        std::fs::write(&path, "work\n").unwrap();
+        let check = check_profiles(Some(&path));
+        assert_eq!(check.status, Status::Failed);
+        assert!(check.detail.contains("nordselect-test-doctor-profiles"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
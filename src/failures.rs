@@ -0,0 +1,127 @@
+//! A log of servers that could not be connected to.
+//!
+//! Scripts can report failed connections, after which the
+//! [PenaltyBenchmarker](../bench/struct.PenaltyBenchmarker.html) makes those servers less likely
+//! to be selected on the next runs.
+use crate::jsonl::{self, unix_now};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// A failed connection, as stored in the failure log.
+pub struct Failure {
+    /// The domain of the server that failed.
+    pub domain: String,
+    /// The moment of the failure, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// Failed connections, backed by a file with one JSON entry per line.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::failures::FailureLog;
+/// use std::time::Duration;
+///
+/// let path = std::env::temp_dir().join("nordselect-doc-failures.jsonl");
+/// # let _ = std::fs::remove_file(&path);
+/// let mut log = FailureLog::open(&path).unwrap();
+/// log.report("be1.nordvpn.com").unwrap();
+///
+/// assert_eq!(log.recent_failures("be1.nordvpn.com", Duration::from_secs(3600)), 1);
+/// assert_eq!(log.recent_failures("be2.nordvpn.com", Duration::from_secs(3600)), 0);
+/// ```
+pub struct FailureLog {
+    /// The file the failures are appended to.
+    path: PathBuf,
+    /// The timestamps of the failures per domain.
+    failures: HashMap<String, Vec<u64>>,
+}
+
+/// Ways to open a FailureLog.
+impl FailureLog {
+    /// Opens the failure log stored at the given path. A missing file is treated as an empty log.
+    pub fn open(path: impl Into<PathBuf>) -> Result<FailureLog, Box<dyn std::error::Error>> {
+        let mut log = FailureLog {
+            path: path.into(),
+            failures: HashMap::new(),
+        };
+
+        for failure in jsonl::read(&log.path)? {
+            log.insert(failure);
+        }
+
+        Ok(log)
+    }
+
+    /// Returns the location of the failure log in the data directory of the current user (e.g.
+    /// `~/.local/share/nordselect/failures.jsonl` on Linux).
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("nordselect").join("failures.jsonl"))
+    }
+}
+
+impl FailureLog {
+    /// Returns the path of the file backing this FailureLog.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether no failures have been reported.
+    pub fn is_empty(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    fn insert(&mut self, failure: Failure) {
+        self.failures
+            .entry(failure.domain)
+            .or_default()
+            .push(failure.timestamp);
+    }
+
+    /// Records a failed connection to the server with the given domain.
+    pub fn report(&mut self, domain: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let failure = Failure {
+            domain: domain.to_string(),
+            timestamp: unix_now(),
+        };
+
+        jsonl::append(&self.path, &[&failure])?;
+        self.insert(failure);
+
+        Ok(())
+    }
+
+    /// Removes all failures older than the given age, and rewrites the log accordingly.
+    pub fn prune(&mut self, max_age: Duration) -> Result<(), Box<dyn std::error::Error>> {
+        let oldest = unix_now().saturating_sub(max_age.as_secs());
+
+        let mut failures = Vec::new();
+        for (domain, timestamps) in self.failures.iter_mut() {
+            timestamps.retain(|timestamp| *timestamp >= oldest);
+            failures.extend(timestamps.iter().map(|timestamp| Failure {
+                domain: domain.clone(),
+                timestamp: *timestamp,
+            }));
+        }
+        self.failures.retain(|_, timestamps| !timestamps.is_empty());
+        jsonl::rewrite(&self.path, &failures)?;
+
+        Ok(())
+    }
+
+    /// Returns how many failures of the server with the given domain were reported in the given
+    /// period of time, counting back from now.
+    pub fn recent_failures(&self, domain: &str, window: Duration) -> usize {
+        let oldest = unix_now().saturating_sub(window.as_secs());
+        self.failures.get(domain).map_or(0, |timestamps| {
+            timestamps
+                .iter()
+                .filter(|timestamp| **timestamp >= oldest)
+                .count()
+        })
+    }
+}
@@ -0,0 +1,260 @@
+//! A C interface, so programs in other languages can select servers without running the command
+//! line tool. Only available with the `capi` feature.
+//!
+//! The functions are declared in `include/nordselect.h`. To build a shared library exporting them:
+//!
+//! ```text
+//! cargo rustc --release --lib --features capi --crate-type cdylib
+//! ```
+//!
+//! Filters are passed as a JSON array of the filters of the command line, e.g.
+//! `["nl", "p2p"]`, and servers are returned as JSON, in the format of stored server lists.
+//! Strings returned by this library must be freed with `nordselect_string_free`. When a function
+//! fails, it returns a null pointer and `nordselect_last_error` tells why. A panic inside the
+//! library counts as a failure as well, as unwinding into the caller is undefined behaviour.
+use crate::run::{self, SelectOptions};
+use crate::servers::Servers;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+thread_local! {
+    /// Why the last call on this thread failed, if it did.
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Remembers why the current call failed.
+fn set_error(error: impl ToString) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(error.to_string()));
+}
+
+/// Runs the body of an exported function, returning `failed` after remembering the error when it
+/// panics, so that the panic does not unwind into the caller.
+fn guard<T>(failed: T, body: impl FnOnce() -> T) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(value) => value,
+        Err(payload) => {
+            let reason = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown reason");
+            set_error(format!("nordselect panicked: {}", reason));
+            failed
+        }
+    }
+}
+
+/// Returns the result as a string owned by the caller, or a null pointer after remembering the
+/// error.
+fn into_c_string(result: Result<String, Box<dyn std::error::Error>>) -> *mut c_char {
+    match result.and_then(|text| Ok(CString::new(text)?)) {
+        Ok(text) => text.into_raw(),
+        Err(err) => {
+            set_error(err);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Reads a string given by the caller.
+///
+/// # Safety
+///
+/// `text` should be null or point to a string ending in a null byte.
+unsafe fn read_c_str<'a>(text: *const c_char) -> Result<&'a str, Box<dyn std::error::Error>> {
+    if text.is_null() {
+        return Err("a null pointer was given".into());
+    }
+    Ok(CStr::from_ptr(text).to_str()?)
+}
+
+/// Selects the best server out of the given ones, returning it as JSON.
+fn select(servers: &Servers, filters_json: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let filters: Vec<String> = serde_json::from_str(filters_json)?;
+    let outcome = run::select(SelectOptions::new(servers).filters(filters))?;
+    match outcome.server {
+        Some(server) => Ok(serde_json::to_string(&server)?),
+        None => Err("no server passes the filters".into()),
+    }
+}
+
+/// Downloads the server list from the NordVPN API and returns the best server passing the
+/// filters, as JSON. Returns null on failure.
+///
+/// # Safety
+///
+/// `filters_json` should point to a string ending in a null byte.
+#[no_mangle]
+pub unsafe extern "C" fn nordselect_best_server(filters_json: *const c_char) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        into_c_string(read_c_str(filters_json).and_then(|filters| {
+            let servers = Servers::from_api()?;
+            select(&servers, filters)
+        }))
+    })
+}
+
+/// Downloads the server list from the NordVPN API. Returns null on failure. The list must be
+/// freed with `nordselect_servers_free`.
+#[no_mangle]
+pub extern "C" fn nordselect_servers_from_api() -> *mut Servers {
+    guard(ptr::null_mut(), || match Servers::from_api() {
+        Ok(servers) => Box::into_raw(Box::new(servers)),
+        Err(err) => {
+            set_error(err);
+            ptr::null_mut()
+        }
+    })
+}
+
+/// Reads a server list from JSON, such as a saved response of the API. Returns null on failure.
+/// The list must be freed with `nordselect_servers_free`.
+///
+/// # Safety
+///
+/// `json` should point to a string ending in a null byte.
+#[no_mangle]
+pub unsafe extern "C" fn nordselect_servers_from_json(json: *const c_char) -> *mut Servers {
+    guard(ptr::null_mut(), || {
+        match read_c_str(json).and_then(|json| Servers::from_reader(json.as_bytes())) {
+            Ok(servers) => Box::into_raw(Box::new(servers)),
+            Err(err) => {
+                set_error(err);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Returns the best server of the list passing the filters, as JSON. Returns null on failure.
+///
+/// # Safety
+///
+/// `servers` should be a list returned by this library that was not freed yet, `filters_json`
+/// should point to a string ending in a null byte.
+#[no_mangle]
+pub unsafe extern "C" fn nordselect_select(
+    servers: *const Servers,
+    filters_json: *const c_char,
+) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        if servers.is_null() {
+            set_error("a null pointer was given");
+            return ptr::null_mut();
+        }
+        into_c_string(read_c_str(filters_json).and_then(|filters| select(&*servers, filters)))
+    })
+}
+
+/// Frees a server list returned by this library. Null is ignored.
+///
+/// # Safety
+///
+/// `servers` should be null or a list returned by this library that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn nordselect_servers_free(servers: *mut Servers) {
+    guard((), || {
+        if !servers.is_null() {
+            drop(Box::from_raw(servers));
+        }
+    })
+}
+
+/// Returns why the last failing call on this thread failed, or null if none did. The string must
+/// be freed with `nordselect_string_free`.
+#[no_mangle]
+pub extern "C" fn nordselect_last_error() -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let error = LAST_ERROR.with(|last| last.borrow().clone());
+        match error.and_then(|error| CString::new(error).ok()) {
+            Some(error) => error.into_raw(),
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+/// Frees a string returned by this library. Null is ignored.
+///
+/// # Safety
+///
+/// `text` should be null or a string returned by this library that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn nordselect_string_free(text: *mut c_char) {
+    guard((), || {
+        if !text.is_null() {
+            drop(CString::from_raw(text));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::Server;
+
+    /// Takes a string returned by the library.
+    unsafe fn take(text: *mut c_char) -> Option<String> {
+        if text.is_null() {
+            return None;
+        }
+        let owned = CStr::from_ptr(text).to_str().unwrap().to_string();
+        nordselect_string_free(text);
+        Some(owned)
+    }
+
+    #[test]
+    fn select_over_ffi() {
+        let json = std::fs::read_to_string("dummydata").unwrap();
+        let json = CString::new(json).unwrap();
+        unsafe {
+            let servers = nordselect_servers_from_json(json.as_ptr());
+            assert!(!servers.is_null());
+
+            let filters = CString::new(r#"["be", "tcp"]"#).unwrap();
+            let best = take(nordselect_select(servers, filters.as_ptr())).unwrap();
+            let best: Server = serde_json::from_str(&best).unwrap();
+            assert_eq!(best.flag, "BE");
+
+            let filters = CString::new(r#"["nowhere"]"#).unwrap();
+            assert_eq!(take(nordselect_select(servers, filters.as_ptr())), None);
+            let error = take(nordselect_last_error()).unwrap();
+            assert!(error.contains("nowhere"), "{}", error);
+
+            assert_eq!(take(nordselect_select(servers, ptr::null())), None);
+            nordselect_servers_free(servers);
+            nordselect_servers_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn panics_become_errors() {
+        let result: *mut c_char = guard(ptr::null_mut(), || panic!("the TLS backend is gone"));
+        assert!(result.is_null());
+        let error = unsafe { take(nordselect_last_error()) }.unwrap();
+        assert_eq!(error, "nordselect panicked: the TLS backend is gone");
+
+        let result = guard(ptr::null_mut::<Servers>(), || panic!("{} servers", 0));
+        assert!(result.is_null());
+        let error = unsafe { take(nordselect_last_error()) }.unwrap();
+        assert_eq!(error, "nordselect panicked: 0 servers");
+    }
+
+    #[test]
+    fn header_declares_functions() {
+        let header = include_str!("../include/nordselect.h");
+        for function in &[
+            "nordselect_best_server",
+            "nordselect_servers_from_api",
+            "nordselect_servers_from_json",
+            "nordselect_select",
+            "nordselect_servers_free",
+            "nordselect_last_error",
+            "nordselect_string_free",
+        ] {
+            assert!(header.contains(&format!("{}(", function)), "{}", function);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Describing filters as data, e.g. to store a set of filters in a configuration file or to share
+//! it with someone else.
+//!
+//! A [FilterSpec](enum.FilterSpec.html) names a built-in filter and its settings. It is turned into
+//! a [Filter](../filters/trait.Filter.html) with [to_filter](enum.FilterSpec.html#method.to_filter),
+//! while [Filter::spec](../filters/trait.Filter.html#method.spec) goes the other way.
+use crate::filters::{self, Filter, Region};
+use crate::servers::{Country, Domain, Protocol, ServerCategory};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// A built-in filter with its settings. It is (de)serialized as an object holding the kind of
+/// filter as `type`, next to its settings, e.g. `{"type": "country", "country": "BE"}`.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::filter_spec::FilterSpec;
+/// use nordselect::filters::Filter;
+/// use nordselect::Servers;
+///
+/// let json = r#"[
+///     {"type": "region", "region": "BENELUX"},
+///     {"type": "not", "filter": {"type": "protocol", "protocol": "pptp"}},
+///     {"type": "max_load", "load": 50}
+/// ]"#;
+/// let specs: Vec<FilterSpec> = serde_json::from_str(json).unwrap();
+///
+/// let mut data = Servers::dummy_data();
+/// for spec in &specs {
+///     data.filter(spec.to_filter().as_ref());
+/// }
+/// assert!(data.servers.iter().all(|server| server.load <= 50));
+///
+/// // Built-in filters can be turned back into their specification.
+/// let filter = specs[2].to_filter();
+/// assert_eq!(filter.spec().as_ref(), Some(&specs[2]));
+/// ```
+pub enum FilterSpec {
+    /// Keeps the servers in a country, see [CountryFilter](../filters/struct.CountryFilter.html).
+    Country {
+        /// The country to keep.
+        country: Country,
+    },
+    /// Keeps the servers in any of the countries, see
+    /// [CountriesFilter](../filters/struct.CountriesFilter.html).
+    Countries {
+        /// The countries to keep.
+        countries: Vec<Country>,
+    },
+    /// Keeps the servers in the countries of a region, see [Region](../filters/enum.Region.html).
+    /// Once built, the filter only knows the countries, so its specification is
+    /// [Countries](#variant.Countries).
+    Region {
+        /// The region to keep, by its short notation, e.g. `EU`.
+        region: Region,
+    },
+    /// Keeps the servers in a country and the countries near it, see
+    /// [NearbyCountriesFilter](../filters/struct.NearbyCountriesFilter.html).
+    Nearby {
+        /// The country the others are near to.
+        country: Country,
+        /// How many times a neighbour may be gone to.
+        radius: u8,
+    },
+    /// Keeps the servers supporting a protocol, see
+    /// [ProtocolFilter](../filters/struct.ProtocolFilter.html).
+    Protocol {
+        /// The protocol, by the name the API uses, e.g. `openvpn_udp`.
+        protocol: Protocol,
+    },
+    /// Keeps the servers in a category, see
+    /// [CategoryFilter](../filters/struct.CategoryFilter.html).
+    Category {
+        /// The category to keep.
+        category: ServerCategory,
+    },
+    /// Keeps the servers with at most the given load, see
+    /// [LoadFilter](../filters/struct.LoadFilter.html).
+    MaxLoad {
+        /// The maximal load, in percent.
+        load: u8,
+    },
+    /// Keeps the servers with at least the given bandwidth, see
+    /// [BandwidthFilter](../filters/struct.BandwidthFilter.html).
+    MinBandwidth {
+        /// The minimal bandwidth, in Mbit/s.
+        mbps: u32,
+    },
+    /// Keeps the servers that were added at least the given time ago, see
+    /// [MinAgeFilter](../filters/struct.MinAgeFilter.html).
+    MinAge {
+        /// The minimal age, in seconds.
+        seconds: u64,
+    },
+    /// Keeps the servers in a city, see [CityFilter](../filters/struct.CityFilter.html).
+    City {
+        /// The name of the city.
+        city: String,
+    },
+    /// Keeps the servers with a feature, see
+    /// [RawFeatureFilter](../filters/struct.RawFeatureFilter.html).
+    Feature {
+        /// The name of the feature in the API, e.g. `port_forwarding`.
+        key: String,
+    },
+    /// Keeps the servers where the local time is between two hours, see
+    /// [TimezoneFilter](../filters/struct.TimezoneFilter.html). The local time is looked at when
+    /// the filter is built.
+    #[cfg(feature = "geo-db")]
+    Timezone {
+        /// The first local hour at which servers are kept.
+        start: u8,
+        /// The local hour from which servers are no longer kept.
+        end: u8,
+    },
+    /// Removes the servers with the given domains, see
+    /// [BlackListFilter](../filters/struct.BlackListFilter.html).
+    Blacklist {
+        /// The domains of the servers to remove.
+        domains: Vec<Domain>,
+    },
+    /// Keeps the servers passing any of the filters, see
+    /// [CombinedFilter](../filters/struct.CombinedFilter.html).
+    Any {
+        /// The filters of which one should be passed.
+        filters: Vec<FilterSpec>,
+    },
+    /// Keeps the servers not passing the filter, see
+    /// [NegatingFilter](../filters/struct.NegatingFilter.html).
+    Not {
+        /// The filter that should not be passed.
+        filter: Box<FilterSpec>,
+    },
+}
+
+impl FilterSpec {
+    /// Builds the filter this describes.
+    pub fn to_filter(&self) -> Box<dyn Filter> {
+        match self {
+            FilterSpec::Country { country } => Box::new(filters::CountryFilter::from(*country)),
+            FilterSpec::Countries { countries } => Box::new(
+                countries
+                    .iter()
+                    .copied()
+                    .collect::<filters::CountriesFilter>(),
+            ),
+            FilterSpec::Region { region } => {
+                Box::new(filters::CountriesFilter::from(region.clone()))
+            }
+            FilterSpec::Nearby { country, radius } => Box::new(
+                filters::NearbyCountriesFilter::new(country.as_str(), *radius),
+            ),
+            FilterSpec::Protocol { protocol } => Box::new(filters::ProtocolFilter::from(*protocol)),
+            FilterSpec::Category { category } => Box::new(filters::CategoryFilter::from(*category)),
+            FilterSpec::MaxLoad { load } => Box::new(filters::LoadFilter::from(*load)),
+            FilterSpec::MinBandwidth { mbps } => Box::new(filters::BandwidthFilter::from(*mbps)),
+            FilterSpec::MinAge { seconds } => {
+                Box::new(filters::MinAgeFilter::from(Duration::from_secs(*seconds)))
+            }
+            FilterSpec::City { city } => Box::new(filters::CityFilter::from(city.as_str())),
+            FilterSpec::Feature { key } => Box::new(filters::RawFeatureFilter::from_key(key)),
+            #[cfg(feature = "geo-db")]
+            FilterSpec::Timezone { start, end } => {
+                Box::new(filters::TimezoneFilter::new(*start, *end))
+            }
+            FilterSpec::Blacklist { domains } => Box::new(filters::BlackListFilter::from(
+                domains.iter().cloned().collect::<HashSet<Domain>>(),
+            )),
+            FilterSpec::Any { filters: specs } => Box::new(filters::CombinedFilter::from(
+                specs.iter().map(FilterSpec::to_filter).collect::<Vec<_>>(),
+            )),
+            FilterSpec::Not { filter } => {
+                Box::new(filters::NegatingFilter::from(filter.to_filter()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specs() -> Vec<FilterSpec> {
+        let belgium: Country = "BE".parse().unwrap();
+        vec![
+            FilterSpec::Country { country: belgium },
+            FilterSpec::Countries {
+                countries: vec![belgium, "NL".parse().unwrap()],
+            },
+            FilterSpec::Nearby {
+                country: belgium,
+                radius: 1,
+            },
+            FilterSpec::Protocol {
+                protocol: Protocol::Tcp,
+            },
+            FilterSpec::Category {
+                category: ServerCategory::P2P,
+            },
+            FilterSpec::MaxLoad { load: 40 },
+            FilterSpec::MinBandwidth { mbps: 10_000 },
+            FilterSpec::MinAge { seconds: 3600 },
+            FilterSpec::City {
+                city: String::from("brussels"),
+            },
+            FilterSpec::Feature {
+                key: String::from("port_forwarding"),
+            },
+            FilterSpec::Blacklist {
+                domains: vec![Domain::from("be1.nordvpn.com")],
+            },
+            FilterSpec::Any {
+                filters: vec![
+                    FilterSpec::MaxLoad { load: 10 },
+                    FilterSpec::Not {
+                        filter: Box::new(FilterSpec::Country { country: belgium }),
+                    },
+                ],
+            },
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        for spec in specs() {
+            let json = serde_json::to_string(&spec).unwrap();
+            assert_eq!(serde_json::from_str::<FilterSpec>(&json).unwrap(), spec);
+            assert_eq!(spec.to_filter().spec(), Some(spec));
+        }
+
+        let region: FilterSpec =
+            serde_json::from_str(r#"{"type":"region","region":"benelux"}"#).unwrap();
+        let mut countries: Vec<Country> = Region::Benelux.countries();
+        countries.sort_unstable();
+        assert_eq!(
+            region.to_filter().spec(),
+            Some(FilterSpec::Countries { countries })
+        );
+        assert!(serde_json::from_str::<FilterSpec>(r#"{"type":"unknown"}"#).is_err());
+    }
+}
@@ -1,16 +1,170 @@
 //! The filters module consists of the Filter trait (used to implement filters) and several common inplementations of it.
 
-use super::{Protocol, Server, ServerCategory};
+use super::{Country, Domain, Protocol, Server, ServerCategory, Servers};
+#[cfg(feature = "native")]
+use crate::bench::PingSummary;
+use crate::filter_spec::FilterSpec;
+#[cfg(feature = "geo-db")]
+use crate::locations;
+use serde_derive::{Deserialize, Serialize};
+#[cfg(feature = "native")]
+use std::collections::HashMap;
 use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::iter::FromIterator;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Way to reduce the amount of available servers.
+///
+/// Filters can also be applied on a single server, e.g. to check whether the server you are
+/// connected to still fits your needs. See [matches_all](fn.matches_all.html).
 pub trait Filter {
     /// Returns whether this server fullfills the needs of the Filter. When false, the given server
     /// should be removed from the set.
     fn filter(&self, _: &Server) -> bool;
+
+    /// A rough estimate of how expensive this filter is to apply on a single server, used to
+    /// apply cheap filters first. Comparing a single field of a server costs 1.
+    ///
+    /// Defaults to 10, as filters defined outside this library could do anything.
+    fn cost(&self) -> u32 {
+        10
+    }
+
+    /// Describes what this filter keeps, e.g. `country BE`. Used to show which filters were
+    /// applied.
+    ///
+    /// Defaults to `custom filter`, for filters defined outside this library.
+    fn describe(&self) -> String {
+        String::from("custom filter")
+    }
+
+    /// Returns whether servers in the given country can pass this filter. While a server list is
+    /// read, servers in countries for which this is false are skipped before they are parsed in
+    /// full. The servers in other countries still have to pass [filter](#tymethod.filter).
+    ///
+    /// Defaults to `true`, for filters that do not only keep servers in some countries.
+    fn may_pass_country(&self, _: Country) -> bool {
+        true
+    }
+
+    /// Removes the servers that do not pass this filter from the list, keeping the order of the
+    /// others. Filters that can tell for the whole list at once, e.g. because none or all of the
+    /// servers pass, can override this to skip the check of every server.
+    ///
+    /// Defaults to [filter](#tymethod.filter) on every server.
+    fn retain(&self, servers: &mut Vec<Server>) {
+        servers.retain(|server| self.filter(server))
+    }
+
+    /// Returns the settings of this filter as a [FilterSpec](../filter_spec/enum.FilterSpec.html),
+    /// from which it can be built again, e.g. to store it in a configuration file.
+    ///
+    /// Defaults to `None`, for filters defined outside this library. Filters built from
+    /// measurements or files, like `MaxLossFilter`, have no specification either.
+    fn spec(&self) -> Option<FilterSpec> {
+        None
+    }
 }
 
+impl<F: Filter + ?Sized> Filter for &F {
+    fn filter(&self, server: &Server) -> bool {
+        (**self).filter(server)
+    }
+
+    fn cost(&self) -> u32 {
+        (**self).cost()
+    }
+
+    fn describe(&self) -> String {
+        (**self).describe()
+    }
+
+    fn may_pass_country(&self, country: Country) -> bool {
+        (**self).may_pass_country(country)
+    }
+
+    fn retain(&self, servers: &mut Vec<Server>) {
+        (**self).retain(servers)
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        (**self).spec()
+    }
+}
+
+impl<F: Filter + ?Sized> Filter for Box<F> {
+    fn filter(&self, server: &Server) -> bool {
+        (**self).filter(server)
+    }
+
+    fn cost(&self) -> u32 {
+        (**self).cost()
+    }
+
+    fn describe(&self) -> String {
+        (**self).describe()
+    }
+
+    fn may_pass_country(&self, country: Country) -> bool {
+        (**self).may_pass_country(country)
+    }
+
+    fn retain(&self, servers: &mut Vec<Server>) {
+        (**self).retain(servers)
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        (**self).spec()
+    }
+}
+
+/// Returns whether the server passes all filters, e.g. to check whether the server you are
+/// connected to still fits your needs and reconnect otherwise.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::filters::{CountryFilter, Filter, LoadFilter};
+/// use nordselect::{matches_all, Servers};
+///
+/// let data = Servers::dummy_data();
+/// let connected = data.servers.iter().find(|server| server.flag == "BE").unwrap();
+///
+/// let mut policy: Vec<Box<dyn Filter>> = vec![Box::new(CountryFilter::from("BE"))];
+/// assert!(matches_all(connected, &policy));
+///
+/// policy.push(Box::new(LoadFilter::from(connected.load.saturating_sub(1))));
+/// assert_eq!(matches_all(connected, &policy), connected.load == 0);
+/// ```
+pub fn matches_all(server: &Server, filters: &[Box<dyn Filter>]) -> bool {
+    filters.iter().all(|filter| filter.filter(server))
+}
+
+/// Returns copies of the servers passing all filters, which should be sorted on
+/// [cost](trait.Filter.html#method.cost). Only the servers passing the first filter are copied,
+/// the others are removed with [retain](trait.Filter.html#method.retain), until none are left.
+pub(crate) fn apply_all(servers: &[Server], filters: &[&dyn Filter]) -> Vec<Server> {
+    let (first, others) = match filters.split_first() {
+        Some(split) => split,
+        None => return servers.to_vec(),
+    };
+
+    let mut kept: Vec<Server> = servers
+        .iter()
+        .filter(|server| first.filter(server))
+        .cloned()
+        .collect();
+    for filter in others {
+        if kept.is_empty() {
+            break;
+        }
+        filter.retain(&mut kept);
+    }
+    kept
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 /// Filter to only use servers from one specific country.
 ///
 /// # Example
@@ -25,9 +179,8 @@ pub trait Filter {
 /// assert_eq!(data.perfect_server().unwrap().flag, "BE");
 /// ```
 pub struct CountryFilter {
-    /// The country on which to filter, noted according to
-    /// [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2).
-    country: String,
+    /// The country on which to filter, or `None` if an unknown country was given.
+    country: Option<Country>,
 }
 
 /// Ways to construct a CountryFilter.
@@ -39,27 +192,225 @@ impl CountryFilter {
         note = "Inefficient, use the From-trait implementation instead"
     )]
     pub fn from_code(countrycode: String) -> CountryFilter {
-        CountryFilter {
-            country: countrycode.to_ascii_uppercase(),
-        }
+        CountryFilter::from(countrycode.as_str())
     }
 }
 
 impl Filter for CountryFilter {
     fn filter(&self, server: &Server) -> bool {
-        self.country == server.flag
+        self.country == Some(server.flag)
+    }
+
+    fn cost(&self) -> u32 {
+        1
+    }
+
+    fn describe(&self) -> String {
+        match self.country {
+            Some(country) => format!("country {}", country),
+            None => String::from("unknown country"),
+        }
+    }
+
+    fn may_pass_country(&self, country: Country) -> bool {
+        self.country == Some(country)
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        self.country.map(|country| FilterSpec::Country { country })
     }
 }
 
+/// Builds a CountryFilter from an [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2)
+/// code. Unknown codes keep no servers at all, parse a [Country](../struct.Country.html) first to
+/// detect those.
 impl<'a> From<&'a str> for CountryFilter {
     fn from(countrycode: &str) -> CountryFilter {
         CountryFilter {
-            country: countrycode.to_ascii_uppercase(),
+            country: countrycode.parse().ok(),
+        }
+    }
+}
+
+impl From<Country> for CountryFilter {
+    fn from(country: Country) -> CountryFilter {
+        CountryFilter {
+            country: Some(country),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Filter to use servers from a country or the countries near it, e.g. Belgium and its
+/// neighbours. Which countries are near each other is embedded, see
+/// [countries::nearby](../countries/fn.nearby.html).
+///
+/// # Example
+///
+/// ```
+/// use nordselect::filters::{Filter, NearbyCountriesFilter};
+/// use nordselect::Servers;
+///
+/// let data = Servers::dummy_data();
+/// let filter = NearbyCountriesFilter::new("BE", 1);
+///
+/// assert!(data
+///     .servers
+///     .iter()
+///     .filter(|server| filter.filter(server))
+///     .all(|server| ["BE", "DE", "FR", "LU", "NL"].contains(&server.flag.as_str())));
+/// ```
+pub struct NearbyCountriesFilter {
+    /// The country the others are near to, or `None` if an unknown country was given.
+    center: Option<Country>,
+    /// How many times a neighbour may be gone to.
+    radius: u8,
+    /// The center and the countries near it.
+    countries: Vec<Country>,
+}
+
+impl NearbyCountriesFilter {
+    /// Creates a filter keeping the servers in the given country and the countries reached by
+    /// going to a neighbour at most `radius` times. The country should be an
+    /// [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2) code. Unknown codes
+    /// keep no servers at all.
+    pub fn new(countrycode: &str, radius: u8) -> NearbyCountriesFilter {
+        let center: Option<Country> = countrycode.parse().ok();
+        let countries = match center {
+            Some(center) => crate::countries::nearby(center.as_str(), radius)
+                .into_iter()
+                .filter_map(|code| code.parse().ok())
+                .collect(),
+            None => Vec::new(),
+        };
+        NearbyCountriesFilter {
+            center,
+            radius,
+            countries,
         }
     }
+
+    /// Returns the countries this filter keeps: the center and the countries near it.
+    pub fn countries(&self) -> &[Country] {
+        &self.countries
+    }
+}
+
+/// Builds a NearbyCountriesFilter keeping the country and its direct neighbours.
+impl From<&str> for NearbyCountriesFilter {
+    fn from(countrycode: &str) -> NearbyCountriesFilter {
+        NearbyCountriesFilter::new(countrycode, 1)
+    }
 }
 
-#[derive(Debug, PartialEq)]
+impl Filter for NearbyCountriesFilter {
+    fn filter(&self, server: &Server) -> bool {
+        self.countries.contains(&server.flag)
+    }
+
+    fn may_pass_country(&self, country: Country) -> bool {
+        self.countries.contains(&country)
+    }
+
+    fn cost(&self) -> u32 {
+        1
+    }
+
+    fn describe(&self) -> String {
+        match self.center {
+            Some(center) => {
+                let countries: Vec<&str> = self.countries.iter().map(Country::as_str).collect();
+                format!(
+                    "countries near {} (radius {}): {}",
+                    center,
+                    self.radius,
+                    countries.join(", ")
+                )
+            }
+            None => String::from("unknown country"),
+        }
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        self.center.map(|country| FilterSpec::Nearby {
+            country,
+            radius: self.radius,
+        })
+    }
+}
+
+/// The languages that can be selected as a region, with a description and the countries where
+/// they are an official language.
+const LANGUAGES: &[(&str, &str, &[&str])] = &[
+    (
+        "LANG:AR",
+        "Countries where Arabic is spoken.",
+        &[
+            "AE", "BH", "DZ", "EG", "IQ", "JO", "KW", "LB", "MA", "OM", "QA", "SA", "TN",
+        ],
+    ),
+    (
+        "LANG:DE",
+        "Countries where German is spoken.",
+        &["AT", "CH", "DE", "LI", "LU"],
+    ),
+    (
+        "LANG:EN",
+        "Countries where English is spoken.",
+        &["AU", "CA", "GB", "IE", "NZ", "US"],
+    ),
+    (
+        "LANG:ES",
+        "Countries where Spanish is spoken.",
+        &[
+            "AR", "BO", "CL", "CO", "CR", "CU", "DO", "EC", "ES", "GT", "HN", "MX", "NI", "PA",
+            "PE", "PR", "PY", "SV", "UY", "VE",
+        ],
+    ),
+    (
+        "LANG:FR",
+        "Countries where French is spoken.",
+        &["BE", "CA", "CH", "FR", "LU", "MC"],
+    ),
+    (
+        "LANG:IT",
+        "Countries where Italian is spoken.",
+        &["CH", "IT", "SM", "VA"],
+    ),
+    (
+        "LANG:NL",
+        "Countries where Dutch is spoken.",
+        &["BE", "NL", "SR"],
+    ),
+    (
+        "LANG:PT",
+        "Countries where Portuguese is spoken.",
+        &["AO", "BR", "MZ", "PT"],
+    ),
+    (
+        "LANG:RU",
+        "Countries where Russian is spoken.",
+        &["BY", "KG", "KZ", "RU"],
+    ),
+    (
+        "LANG:SV",
+        "Countries where Swedish is spoken.",
+        &["FI", "SE"],
+    ),
+    (
+        "LANG:ZH",
+        "Countries where Chinese is spoken.",
+        &["CN", "HK", "MO", "SG", "TW"],
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+/// A group of countries. It is (de)serialized as its [short notation](#method.short), or as its
+/// name and countries when it is a custom region.
+///
+/// Regions are added over time, so outside of this crate, matching on a Region needs a wildcard
+/// arm.
 pub enum Region {
     /// The [European Union](https://en.wikipedia.org/wiki/European_Union), consisting of 27 countries.
     ///
@@ -77,6 +428,17 @@ pub enum Region {
     NineEyes,
     /// [14 eyes programme countries.](https://en.wikipedia.org/wiki/Five_Eyes#Other_international_cooperatives)
     FourteenEyes,
+    /// The countries where a language is spoken, e.g. `LANG:DE` for German. Holds the short
+    /// notation, see [language_options](#method.language_options) for all of them.
+    Language(&'static str),
+    /// A region that is not built in, see [custom](#method.custom). Its short notation is its
+    /// name.
+    Custom {
+        /// The name of the region.
+        name: String,
+        /// The countries in the region, each only once.
+        countries: Vec<Country>,
+    },
 }
 
 impl Region {
@@ -93,7 +455,10 @@ impl Region {
             "6E" => Some(Region::SixEyes),
             "9E" => Some(Region::NineEyes),
             "14E" => Some(Region::FourteenEyes),
-            _ => None,
+            _ => LANGUAGES
+                .iter()
+                .find(|(short, _, _)| *short == region_short)
+                .map(|(short, _, _)| Region::Language(short)),
         }
     }
 
@@ -114,8 +479,26 @@ impl Region {
         ]
     }
 
+    /// Returns the short notations of all languages with their meanings in human readable form,
+    /// like [from_str_options](#method.from_str_options) does for the other regions.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::filters::Region;
+    ///
+    /// let (short, _) = Region::language_options()[0];
+    /// assert_eq!(Region::from_str(short).unwrap().short(), short);
+    /// ```
+    pub fn language_options() -> Vec<(&'static str, &'static str)> {
+        LANGUAGES
+            .iter()
+            .map(|(short, description, _)| (*short, *description))
+            .collect()
+    }
+
     /// Returns the main short notation for a given Region.
-    pub fn short(&self) -> &'static str {
+    pub fn short(&self) -> &str {
         match self {
             Region::EuropeanUnion => "EU",
             Region::EuropeanEconomicArea => "EEA",
@@ -124,31 +507,185 @@ impl Region {
             Region::SixEyes => "6E",
             Region::NineEyes => "9E",
             Region::FourteenEyes => "14E",
+            Region::Language(short) => short,
+            Region::Custom { name, .. } => name,
         }
     }
 
-    pub fn countries(&self) -> Vec<&str> {
-        match self {
-            Region::EuropeanEconomicArea => vec![
+    /// Returns the countries in this Region.
+    pub fn countries(&self) -> Vec<Country> {
+        let codes: &[&str] = match self {
+            Region::EuropeanEconomicArea => &[
                 "AT", "BE", "BG", "HR", "CY", "CZ", "DK", "EE", "FI", "FR", "DE", "GR", "HU", "IE",
                 "IT", "LV", "LT", "LU", "MT", "NL", "PL", "PT", "RO", "SK", "SI", "ES", "SE", "NO",
                 "LI", "IS",
             ],
-            Region::EuropeanUnion => vec![
+            Region::EuropeanUnion => &[
                 "AT", "BE", "BG", "HR", "CY", "CZ", "DK", "EE", "FI", "FR", "DE", "GR", "HU", "IE",
                 "IT", "LV", "LT", "LU", "MT", "NL", "PL", "PT", "RO", "SK", "SI", "ES", "SE",
             ],
-            Region::Benelux => vec!["BE", "LU", "NL"],
-            Region::FiveEyes => vec!["AU", "CA", "NZ", "GB", "US"],
-            Region::SixEyes => vec!["AU", "CA", "FR", "NZ", "GB", "US"],
-            Region::NineEyes => vec!["AU", "CA", "DK", "FR", "NL", "NO", "NZ", "GB", "US"],
-            Region::FourteenEyes => vec![
+            Region::Benelux => &["BE", "LU", "NL"],
+            Region::FiveEyes => &["AU", "CA", "NZ", "GB", "US"],
+            Region::SixEyes => &["AU", "CA", "FR", "NZ", "GB", "US"],
+            Region::NineEyes => &["AU", "CA", "DK", "FR", "NL", "NO", "NZ", "GB", "US"],
+            Region::FourteenEyes => &[
                 "AU", "BE", "CA", "DE", "DK", "ES", "FR", "IT", "NL", "NO", "NZ", "GB", "SE", "US",
             ],
+            Region::Language(short) => LANGUAGES
+                .iter()
+                .find(|(language, _, _)| language == short)
+                .map(|(_, _, countries)| *countries)
+                .unwrap_or(&[]),
+            Region::Custom { countries, .. } => return countries.clone(),
+        };
+
+        // All codes above are valid.
+        codes.iter().map(|code| code.parse().unwrap()).collect()
+    }
+
+    /// Returns the countries that have servers in the given list, but are not in this Region,
+    /// sorted on their code. This is e.g. every country outside the Fourteen Eyes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nordselect::Servers;
+    /// use nordselect::filters::{CountriesFilter, Region};
+    ///
+    /// let mut data = Servers::dummy_data();
+    /// let outside = Region::FourteenEyes.complement(&data);
+    /// assert!(!outside.is_empty());
+    /// assert!(outside.iter().all(|country| !Region::FourteenEyes.countries().contains(country)));
+    ///
+    /// data.filter(&outside.into_iter().collect::<CountriesFilter>());
+    /// assert_ne!(data.perfect_server().unwrap().flag, "US");
+    /// ```
+    pub fn complement(&self, servers: &Servers) -> Vec<Country> {
+        let countries = self.countries();
+        let mut complement: Vec<Country> = servers
+            .flags()
+            .iter()
+            .filter_map(|flag| flag.parse().ok())
+            .filter(|country| !countries.contains(country))
+            .collect();
+        complement.sort_unstable();
+
+        complement
+    }
+
+    /// Creates a region of the given countries, with the given name. Countries given more than
+    /// once are kept once.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::filters::Region;
+    ///
+    /// let codes = vec!["DK", "FI", "IS", "NO", "SE", "NO"];
+    /// let nordics = Region::custom("nordics", codes.iter().map(|code| code.parse().unwrap()));
+    /// assert_eq!(nordics.short(), "nordics");
+    /// assert_eq!(nordics.countries(), vec!["DK", "FI", "IS", "NO", "SE"]);
+    /// ```
+    pub fn custom<I: IntoIterator<Item = Country>>(
+        name: impl Into<String>,
+        countries: I,
+    ) -> Region {
+        let mut seen = HashSet::new();
+        Region::Custom {
+            name: name.into(),
+            countries: countries
+                .into_iter()
+                .filter(|country| seen.insert(*country))
+                .collect(),
+        }
+    }
+
+    /// Returns the region of the countries in any of the given regions. It is named after them,
+    /// e.g. `BENELUX+5E`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::filters::Region;
+    ///
+    /// let region = Region::union(&[Region::Benelux, Region::FiveEyes]);
+    /// assert_eq!(region.short(), "BENELUX+5E");
+    /// assert_eq!(region.countries().len(), 8);
+    /// ```
+    pub fn union(regions: &[Region]) -> Region {
+        let names: Vec<&str> = regions.iter().map(Region::short).collect();
+        Region::custom(names.join("+"), regions.iter().flat_map(Region::countries))
+    }
+
+    /// Returns the region of the countries in this region that are not in the other one. It is
+    /// named after both, e.g. `EEA-14E`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::filters::Region;
+    ///
+    /// let region = Region::EuropeanEconomicArea.minus(&Region::FourteenEyes);
+    /// assert_eq!(region.short(), "EEA-14E");
+    /// assert!(region.countries().contains(&"AT".parse().unwrap()));
+    /// assert!(!region.countries().contains(&"BE".parse().unwrap()));
+    /// ```
+    pub fn minus(&self, other: &Region) -> Region {
+        let excluded = other.countries();
+        Region::custom(
+            format!("{}-{}", self.short(), other.short()),
+            self.countries()
+                .into_iter()
+                .filter(|country| !excluded.contains(country)),
+        )
+    }
+}
+
+impl TryFrom<String> for Region {
+    type Error = String;
+
+    fn try_from(short: String) -> Result<Region, String> {
+        Region::from_str(&short.to_uppercase()).ok_or_else(|| format!("unknown region: {}", short))
+    }
+}
+
+/// How a Region is (de)serialized: by its short notation, or by its name and countries when it is
+/// a custom region.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RegionRepr {
+    Short(String),
+    Custom {
+        name: String,
+        countries: Vec<Country>,
+    },
+}
+
+impl serde::Serialize for Region {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let repr = match self {
+            Region::Custom { name, countries } => RegionRepr::Custom {
+                name: name.clone(),
+                countries: countries.clone(),
+            },
+            _ => RegionRepr::Short(self.short().to_string()),
+        };
+        serde::Serialize::serialize(&repr, serializer)
+    }
+}
+
+// Deserialized by hand, as the derived implementation would only borrow from `'static` input
+// because of the short notations of languages.
+impl<'de> serde::Deserialize<'de> for Region {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Region, D::Error> {
+        match <RegionRepr as serde::Deserialize>::deserialize(deserializer)? {
+            RegionRepr::Short(short) => Region::try_from(short).map_err(serde::de::Error::custom),
+            RegionRepr::Custom { name, countries } => Ok(Region::custom(name, countries)),
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 /// Filter that keeps servers from any of the provided countries.
 ///
 /// This struct can be build from your own list of countries, or it can be used with one of the
@@ -171,7 +708,7 @@ impl Region {
 /// ```
 pub struct CountriesFilter {
     /// Countries which are allowed.
-    countries: HashSet<String>,
+    countries: HashSet<Country>,
 }
 
 /// Region operations
@@ -194,7 +731,7 @@ impl CountriesFilter {
                     Self::region_countries("EU")
                         .unwrap()
                         .iter()
-                        .map(|s| String::from(*s)),
+                        .map(|s| s.parse().unwrap()),
                 ),
             }),
             _ => None,
@@ -233,19 +770,29 @@ impl CountriesFilter {
 impl From<Region> for CountriesFilter {
     fn from(region: Region) -> CountriesFilter {
         CountriesFilter {
-            countries: HashSet::from_iter(
-                region
-                    .countries()
-                    .into_iter()
-                    .map(String::from),
-            ),
+            countries: HashSet::from_iter(region.countries()),
         }
     }
 }
 
+/// Builds a CountriesFilter from [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2)
+/// codes. Unknown codes are left out.
 impl From<HashSet<String>> for CountriesFilter {
     fn from(countries: HashSet<String>) -> CountriesFilter {
-        CountriesFilter { countries }
+        CountriesFilter {
+            countries: countries
+                .iter()
+                .filter_map(|country| country.parse().ok())
+                .collect(),
+        }
+    }
+}
+
+impl FromIterator<Country> for CountriesFilter {
+    fn from_iter<I: IntoIterator<Item = Country>>(countries: I) -> CountriesFilter {
+        CountriesFilter {
+            countries: countries.into_iter().collect(),
+        }
     }
 }
 
@@ -253,8 +800,41 @@ impl Filter for CountriesFilter {
     fn filter(&self, server: &Server) -> bool {
         self.countries.contains(&server.flag)
     }
+
+    fn cost(&self) -> u32 {
+        2
+    }
+
+    fn describe(&self) -> String {
+        let mut countries: Vec<&str> = self
+            .countries
+            .iter()
+            .map(|country| country.as_str())
+            .collect();
+        countries.sort_unstable();
+        format!("countries {}", countries.join(", "))
+    }
+
+    fn may_pass_country(&self, country: Country) -> bool {
+        self.countries.contains(&country)
+    }
+
+    fn retain(&self, servers: &mut Vec<Server>) {
+        if self.countries.is_empty() {
+            servers.clear();
+        } else {
+            servers.retain(|server| self.countries.contains(&server.flag))
+        }
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        let mut countries: Vec<Country> = self.countries.iter().copied().collect();
+        countries.sort_unstable();
+        Some(FilterSpec::Countries { countries })
+    }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 /// Filter that keeps only servers that accept a specific protocol.
 ///
 /// # Example
@@ -283,23 +863,39 @@ impl From<Protocol> for ProtocolFilter {
 
 impl Filter for ProtocolFilter {
     fn filter(&self, server: &Server) -> bool {
-        match self.protocol {
-            Protocol::Tcp => server.features.openvpn_tcp,
-            Protocol::Udp => server.features.openvpn_udp,
-            Protocol::Pptp => server.features.pptp,
-            Protocol::L2tp => server.features.l2tp,
-            Protocol::OpenVPNXTcp => server.features.openvpn_tcp,
-            Protocol::OpenVPNXUdp => server.features.openvpn_udp,
-            Protocol::Socks => server.features.socks,
-            Protocol::CyberSecProxy => server.features.proxy_cybersec,
-            Protocol::SslProxy => server.features.proxy_ssl,
-            Protocol::CyberSecSslProxy => server.features.proxy_ssl_cybersec,
-            Protocol::Proxy => server.features.proxy,
-            Protocol::WireGuardUdp => server.features.wireguard_udp,
-        }
+        server.supports_any(std::slice::from_ref(&self.protocol))
+    }
+
+    fn cost(&self) -> u32 {
+        1
+    }
+
+    fn describe(&self) -> String {
+        let protocol = match self.protocol {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+            Protocol::Pptp => "pptp",
+            Protocol::L2tp => "l2tp",
+            Protocol::OpenVPNXTcp => "tcp_xor",
+            Protocol::OpenVPNXUdp => "udp_xor",
+            Protocol::Socks => "socks",
+            Protocol::CyberSecProxy => "cybersecproxy",
+            Protocol::SslProxy => "sslproxy",
+            Protocol::CyberSecSslProxy => "cybersecsslproxy",
+            Protocol::Proxy => "proxy",
+            Protocol::WireGuardUdp => "wg_udp",
+        };
+        format!("protocol {}", protocol)
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        Some(FilterSpec::Protocol {
+            protocol: self.protocol,
+        })
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 /// Filter that keeps servers with less or equal load compared to a provided value.
 ///
 /// # Example
@@ -329,6 +925,462 @@ impl Filter for LoadFilter {
     fn filter(&self, server: &Server) -> bool {
         server.load.cmp(&self.load) != std::cmp::Ordering::Greater
     }
+
+    fn cost(&self) -> u32 {
+        1
+    }
+
+    fn describe(&self) -> String {
+        format!("load <= {}%", self.load)
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        Some(FilterSpec::MaxLoad { load: self.load })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Filter that keeps servers with at least the given bandwidth, in Mbit/s. Servers of which the
+/// bandwidth is not known are removed.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::filters::{BandwidthFilter, Filter};
+/// use nordselect::Servers;
+///
+/// let mut server = Servers::dummy_data().servers.remove(0);
+/// server.bandwidth_tier = Some(10_000);
+///
+/// assert!(BandwidthFilter::from(10_000).filter(&server));
+/// server.bandwidth_tier = None;
+/// assert!(!BandwidthFilter::from(10_000).filter(&server));
+/// ```
+pub struct BandwidthFilter {
+    /// The minimal bandwidth, in Mbit/s.
+    bandwidth: u32,
+}
+
+impl From<u32> for BandwidthFilter {
+    fn from(bandwidth: u32) -> BandwidthFilter {
+        BandwidthFilter { bandwidth }
+    }
+}
+
+impl Filter for BandwidthFilter {
+    fn filter(&self, server: &Server) -> bool {
+        match server.bandwidth_tier {
+            Some(bandwidth) => bandwidth >= self.bandwidth,
+            None => false,
+        }
+    }
+
+    fn cost(&self) -> u32 {
+        1
+    }
+
+    fn describe(&self) -> String {
+        match (self.bandwidth / 1000, self.bandwidth % 1000) {
+            (gbps, 0) if gbps > 0 => format!("bandwidth >= {} Gbps", gbps),
+            _ => format!("bandwidth >= {} Mbps", self.bandwidth),
+        }
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        Some(FilterSpec::MinBandwidth {
+            mbps: self.bandwidth,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Filter that keeps servers that were added at least the given time ago, as brand-new servers
+/// sometimes have unstable routing. Servers of which it is not known when they were added are
+/// kept.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::filters::{Filter, MinAgeFilter};
+/// use nordselect::Servers;
+/// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+///
+/// let month = Duration::from_secs(30 * 24 * 3600);
+/// let filter = MinAgeFilter::from(month);
+/// let mut server = Servers::dummy_data().servers.remove(0);
+/// assert!(filter.filter(&server));
+///
+/// server.created_at = Some(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs());
+/// assert!(!filter.filter(&server));
+/// server.created_at = Some(server.created_at.unwrap() - month.as_secs());
+/// assert!(filter.filter(&server));
+/// ```
+pub struct MinAgeFilter {
+    /// The minimal age.
+    min_age: Duration,
+    /// The moment servers should have been added before, in seconds since the Unix epoch.
+    added_before: u64,
+}
+
+impl From<Duration> for MinAgeFilter {
+    fn from(min_age: Duration) -> MinAgeFilter {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or(0);
+        MinAgeFilter {
+            min_age,
+            added_before: now.saturating_sub(min_age.as_secs()),
+        }
+    }
+}
+
+impl Filter for MinAgeFilter {
+    fn filter(&self, server: &Server) -> bool {
+        match server.created_at {
+            Some(created_at) => created_at <= self.added_before,
+            None => true,
+        }
+    }
+
+    fn cost(&self) -> u32 {
+        1
+    }
+
+    fn describe(&self) -> String {
+        let days = self.min_age.as_secs() / (24 * 3600);
+        format!(
+            "added >= {} day{} ago",
+            days,
+            if days == 1 { "" } else { "s" }
+        )
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        Some(FilterSpec::MinAge {
+            seconds: self.min_age.as_secs(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Filter that keeps servers in the given city. Case, accents, spaces and punctuation are ignored,
+/// so `sao-paulo` matches `São Paulo`. Servers of which the city is not known are removed.
+///
+/// The cities with servers are listed by [Servers::locations](../struct.Servers.html#method.locations).
+///
+/// # Example
+///
+/// ```
+/// use nordselect::filters::{CityFilter, Filter};
+/// use nordselect::Servers;
+///
+/// let mut server = Servers::dummy_data().servers.remove(0);
+/// server.city = Some("New York".to_string());
+///
+/// assert!(CityFilter::from("new-york").filter(&server));
+/// assert!(!CityFilter::from("york").filter(&server));
+/// ```
+pub struct CityFilter {
+    /// The name of the city, as compared.
+    city: String,
+}
+
+/// Returns the name of a city as it is compared: normalized and without spaces or punctuation.
+fn city_key(city: &str) -> String {
+    crate::cli_help::normalize(city)
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+impl From<&str> for CityFilter {
+    fn from(city: &str) -> CityFilter {
+        CityFilter {
+            city: city_key(city),
+        }
+    }
+}
+
+impl Filter for CityFilter {
+    fn filter(&self, server: &Server) -> bool {
+        match &server.city {
+            Some(city) => city_key(city) == self.city,
+            None => false,
+        }
+    }
+
+    fn cost(&self) -> u32 {
+        1
+    }
+
+    fn describe(&self) -> String {
+        format!("city {}", self.city)
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        Some(FilterSpec::City {
+            city: self.city.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Filter that keeps servers where it is currently between the given local hours, e.g. the
+/// servers where it is night. The local time is estimated from the location of the server, see
+/// [locations::local_hour](../locations/fn.local_hour.html). Servers of which the location is
+/// not known are removed.
+///
+/// Only available with the `geo-db` feature.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::filters::{Filter, TimezoneFilter};
+/// use nordselect::Servers;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let mut server = Servers::dummy_data().servers.remove(0);
+/// server.flag = "GB".parse().unwrap();
+/// let three_am = UNIX_EPOCH + Duration::from_secs(3 * 3600);
+///
+/// assert!(TimezoneFilter::off_peak().at(three_am).filter(&server));
+/// assert!(!TimezoneFilter::new(8, 20).at(three_am).filter(&server));
+/// ```
+#[cfg(feature = "geo-db")]
+pub struct TimezoneFilter {
+    /// The local hours between which servers are kept.
+    hours: (u8, u8),
+    /// The moment at which the local time is looked at.
+    now: SystemTime,
+}
+
+#[cfg(feature = "geo-db")]
+impl TimezoneFilter {
+    /// Creates a filter keeping the servers where it is now between `start` and `end` o'clock.
+    /// The period can span midnight, e.g. from 22 until 6.
+    pub fn new(start: u8, end: u8) -> TimezoneFilter {
+        TimezoneFilter {
+            hours: (start, end),
+            now: SystemTime::now(),
+        }
+    }
+
+    /// Creates a filter keeping the servers where it is now off-peak, see
+    /// [OFF_PEAK_HOURS](../locations/constant.OFF_PEAK_HOURS.html).
+    pub fn off_peak() -> TimezoneFilter {
+        let (start, end) = locations::OFF_PEAK_HOURS;
+        TimezoneFilter::new(start, end)
+    }
+
+    /// Looks at the local time at the given moment instead of now.
+    pub fn at(mut self, now: SystemTime) -> TimezoneFilter {
+        self.now = now;
+        self
+    }
+}
+
+#[cfg(feature = "geo-db")]
+impl Filter for TimezoneFilter {
+    fn filter(&self, server: &Server) -> bool {
+        match locations::longitude(server) {
+            Some(longitude) => {
+                locations::hour_between(locations::local_hour(longitude, self.now), self.hours)
+            }
+            None => false,
+        }
+    }
+
+    fn cost(&self) -> u32 {
+        2
+    }
+
+    fn describe(&self) -> String {
+        format!("local time {}:00-{}:00", self.hours.0, self.hours.1)
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        Some(FilterSpec::Timezone {
+            start: self.hours.0,
+            end: self.hours.1,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Filter that keeps servers supporting a feature, given by the name the API uses for it.
+///
+/// This also works for features that are not known to this library yet, as long as the API
+/// reports them.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::filters::RawFeatureFilter;
+/// let mut data = Servers::dummy_data();
+///
+/// data.filter(&RawFeatureFilter::from_key("openvpn_tcp"));
+///
+/// assert!(data.perfect_server().unwrap().features.openvpn_tcp);
+/// ```
+pub struct RawFeatureFilter {
+    /// The name of the feature in the API.
+    key: String,
+}
+
+impl RawFeatureFilter {
+    /// Creates a filter on the feature with the given name, as used in the API.
+    pub fn from_key(key: &str) -> RawFeatureFilter {
+        RawFeatureFilter {
+            key: key.to_string(),
+        }
+    }
+}
+
+impl Filter for RawFeatureFilter {
+    fn filter(&self, server: &Server) -> bool {
+        server.features.get(&self.key).unwrap_or(false)
+    }
+
+    fn cost(&self) -> u32 {
+        2
+    }
+
+    fn describe(&self) -> String {
+        format!("feature {}", self.key)
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        Some(FilterSpec::Feature {
+            key: self.key.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Filter that removes the servers with the given domains.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::filters::BlackListFilter;
+/// use std::collections::HashSet;
+///
+/// let mut data = Servers::dummy_data();
+/// let mut blacklist = HashSet::new();
+/// blacklist.insert(data.servers[0].domain.clone());
+/// let amount = data.servers.len();
+///
+/// data.filter(&BlackListFilter::from(blacklist));
+/// assert_eq!(data.servers.len(), amount - 1);
+/// ```
+pub struct BlackListFilter {
+    /// The domains of the servers to remove.
+    domains: HashSet<Domain>,
+}
+
+impl From<HashSet<Domain>> for BlackListFilter {
+    fn from(domains: HashSet<Domain>) -> BlackListFilter {
+        BlackListFilter { domains }
+    }
+}
+
+impl From<HashSet<String>> for BlackListFilter {
+    fn from(domains: HashSet<String>) -> BlackListFilter {
+        BlackListFilter {
+            domains: domains.into_iter().map(Domain::from).collect(),
+        }
+    }
+}
+
+impl Filter for BlackListFilter {
+    fn filter(&self, server: &Server) -> bool {
+        !self.domains.contains(server.domain.as_str())
+    }
+
+    fn cost(&self) -> u32 {
+        2
+    }
+
+    fn describe(&self) -> String {
+        format!("not one of {} blacklisted servers", self.domains.len())
+    }
+
+    fn retain(&self, servers: &mut Vec<Server>) {
+        if !self.domains.is_empty() {
+            servers.retain(|server| !self.domains.contains(server.domain.as_str()))
+        }
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        let mut domains: Vec<Domain> = self.domains.iter().cloned().collect();
+        domains.sort_unstable();
+        Some(FilterSpec::Blacklist { domains })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Filter that removes the servers that lost more than the given share of pings, according to
+/// the results of a ping test. Servers that were not pinged are kept.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::bench::PingSummary;
+/// use nordselect::filters::{Filter, MaxLossFilter};
+/// use nordselect::Servers;
+/// use std::time::Duration;
+///
+/// let data = Servers::dummy_data();
+/// let mut summary = PingSummary::new(data.servers[0].domain.clone());
+/// summary.sent = 5;
+/// summary.latencies = vec![Duration::from_millis(20); 4];
+///
+/// let filter = MaxLossFilter::new(&[summary], 10f64);
+/// assert!(!filter.filter(&data.servers[0]));
+/// assert!(filter.filter(&data.servers[1]));
+/// ```
+#[cfg(feature = "native")]
+pub struct MaxLossFilter {
+    /// The share of pings every pinged server lost, in percent.
+    losses: HashMap<Domain, f64>,
+    /// The highest share of pings a server may lose, in percent.
+    max_loss: f64,
+}
+
+#[cfg(feature = "native")]
+impl MaxLossFilter {
+    /// Creates a filter removing the servers that lost more than `max_loss` percent of the pings
+    /// in the given results.
+    pub fn new(summaries: &[PingSummary], max_loss: f64) -> MaxLossFilter {
+        MaxLossFilter {
+            losses: summaries
+                .iter()
+                .map(|summary| (summary.domain.clone(), summary.loss()))
+                .collect(),
+            max_loss,
+        }
+    }
+}
+
+#[cfg(feature = "native")]
+impl Filter for MaxLossFilter {
+    fn filter(&self, server: &Server) -> bool {
+        match self.losses.get(&server.domain) {
+            Some(loss) => *loss <= self.max_loss,
+            None => true,
+        }
+    }
+
+    fn cost(&self) -> u32 {
+        2
+    }
+
+    fn describe(&self) -> String {
+        format!("loss <= {}%", self.max_loss)
+    }
 }
 
 /// Filter that contains multiple Filter instances. This could be more efficient, as only servers
@@ -378,8 +1430,37 @@ impl Filter for CombinedFilter {
             // Sorry for the confusing line of Rust code.
             .any(|filter| filter.filter(server))
     }
+
+    fn cost(&self) -> u32 {
+        self.filters.iter().map(|filter| filter.cost()).sum()
+    }
+
+    fn describe(&self) -> String {
+        let descriptions: Vec<String> = self
+            .filters
+            .iter()
+            .map(|filter| format!("({})", filter.describe()))
+            .collect();
+        descriptions.join(" or ")
+    }
+
+    fn may_pass_country(&self, country: Country) -> bool {
+        self.filters
+            .iter()
+            .any(|filter| filter.may_pass_country(country))
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        let filters = self
+            .filters
+            .iter()
+            .map(|filter| filter.spec())
+            .collect::<Option<_>>()?;
+        Some(FilterSpec::Any { filters })
+    }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 /// Filter the Servers using a given category.
 ///
 /// # Example
@@ -408,6 +1489,20 @@ impl Filter for CategoryFilter {
     fn filter(&self, server: &Server) -> bool {
         server.categories.contains(&self.category)
     }
+
+    fn cost(&self) -> u32 {
+        1
+    }
+
+    fn describe(&self) -> String {
+        format!("category {}", crate::cli_help::category_name(&self.category))
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        Some(FilterSpec::Category {
+            category: self.category,
+        })
+    }
 }
 
 /// Filter that negates the results of a given filter.
@@ -441,12 +1536,28 @@ impl Filter for NegatingFilter {
     fn filter(&self, server: &Server) -> bool {
         !self.0.filter(server)
     }
+
+    fn cost(&self) -> u32 {
+        self.0.cost()
+    }
+
+    fn describe(&self) -> String {
+        format!("not {}", self.0.describe())
+    }
+
+    fn spec(&self) -> Option<FilterSpec> {
+        Some(FilterSpec::Not {
+            filter: Box::new(self.0.spec()?),
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::super::Servers;
     use super::*;
+    use crate::test_util::arb_servers;
+    use proptest::prelude::*;
 
     #[test]
     #[allow(deprecated)]
@@ -507,6 +1618,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn language_regions() {
+        for (short, _) in Region::language_options() {
+            let region = Region::from_str(short).unwrap();
+            assert_eq!(region.short(), short);
+            assert!(!region.countries().is_empty(), "{} has no countries", short);
+        }
+
+        let german = Region::from_str("LANG:DE").unwrap().countries();
+        assert!(german.contains(&"AT".parse().unwrap()));
+        assert!(!german.contains(&"FR".parse().unwrap()));
+        assert_eq!(Region::from_str("LANG:XX"), None);
+    }
+
     #[test]
     fn countries_filter_empty() {
         let mut data = Servers::dummy_data();
@@ -533,6 +1658,100 @@ mod tests {
         assert!(vec.contains(&server_opt.unwrap().flag.as_str()));
     }
 
+    proptest! {
+        #[test]
+        fn negation_complements(data in arb_servers(0..50), load in 0u8..=100) {
+            let negated = NegatingFilter::new(LoadFilter::from(load));
+            for server in &data.servers {
+                prop_assert_ne!(LoadFilter::from(load).filter(server), negated.filter(server));
+            }
+        }
+
+        #[test]
+        fn matches_all_agrees_with_filter(data in arb_servers(0..50), load in 0u8..=100) {
+            let filters: Vec<Box<dyn Filter>> = vec![
+                Box::new(LoadFilter::from(load)),
+                Box::new(CategoryFilter::from(ServerCategory::Standard)),
+            ];
+            let mut filtered = Servers { servers: data.servers.clone() };
+            for filter in &filters {
+                filtered.filter(filter);
+            }
+
+            let matching: Vec<&Server> =
+                data.servers.iter().filter(|server| matches_all(server, &filters)).collect();
+            prop_assert_eq!(matching, filtered.servers.iter().collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn retain_agrees_with_filter(data in arb_servers(0..50), amount in 0usize..4) {
+            let countries: CountriesFilter =
+                data.servers.iter().take(amount).map(|server| server.flag).collect();
+            let blacklist = BlackListFilter::from(
+                data.servers
+                    .iter()
+                    .skip(1)
+                    .take(amount)
+                    .map(|server| server.domain.clone())
+                    .collect::<HashSet<Domain>>(),
+            );
+            let filters: Vec<&dyn Filter> = vec![&countries, &blacklist];
+            for filter in &filters {
+                let mut retained = data.servers.clone();
+                filter.retain(&mut retained);
+                let matching: Vec<&Server> =
+                    data.servers.iter().filter(|server| filter.filter(server)).collect();
+                prop_assert_eq!(matching, retained.iter().collect::<Vec<_>>());
+            }
+
+            let matching: Vec<&Server> = data
+                .servers
+                .iter()
+                .filter(|server| filters.iter().all(|filter| filter.filter(server)))
+                .collect();
+            let applied = apply_all(&data.servers, &filters);
+            prop_assert_eq!(matching, applied.iter().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn descriptions() {
+        assert_eq!(CountryFilter::from("be").describe(), "country BE");
+        assert_eq!(
+            CountriesFilter::from(Region::from_str("5E").unwrap()).describe(),
+            "countries AU, CA, GB, NZ, US"
+        );
+        assert_eq!(
+            NegatingFilter::new(ProtocolFilter::from(Protocol::OpenVPNXTcp)).describe(),
+            "not protocol tcp_xor"
+        );
+        assert_eq!(LoadFilter::from(30).describe(), "load <= 30%");
+    }
+
+    #[test]
+    fn xor_protocol_filters() {
+        let mut server = Servers::dummy_data().servers.remove(0);
+        server.features.openvpn_tcp = true;
+        server.features.openvpn_udp = true;
+        server.features.openvpn_xor_tcp = false;
+        server.features.openvpn_xor_udp = false;
+
+        assert!(ProtocolFilter::from(Protocol::Tcp).filter(&server));
+        assert!(ProtocolFilter::from(Protocol::Udp).filter(&server));
+        assert!(!ProtocolFilter::from(Protocol::OpenVPNXTcp).filter(&server));
+        assert!(!ProtocolFilter::from(Protocol::OpenVPNXUdp).filter(&server));
+
+        server.features.openvpn_tcp = false;
+        server.features.openvpn_udp = false;
+        server.features.openvpn_xor_tcp = true;
+        server.features.openvpn_xor_udp = true;
+
+        assert!(!ProtocolFilter::from(Protocol::Tcp).filter(&server));
+        assert!(!ProtocolFilter::from(Protocol::Udp).filter(&server));
+        assert!(ProtocolFilter::from(Protocol::OpenVPNXTcp).filter(&server));
+        assert!(ProtocolFilter::from(Protocol::OpenVPNXUdp).filter(&server));
+    }
+
     #[test]
     fn valid_regions() {
         assert_eq!(
@@ -583,4 +1802,46 @@ mod tests {
         assert_eq!(Region::from_str("12e"), None);
         assert_eq!(Region::from_str("15e"), None);
     }
+
+    #[test]
+    fn serde_round_trip() {
+        assert_eq!(
+            serde_json::to_string(&Protocol::Udp).unwrap(),
+            r#""openvpn_udp""#
+        );
+        let protocol: Protocol = serde_json::from_str(r#""wireguard_udp""#).unwrap();
+        assert_eq!(protocol, Protocol::WireGuardUdp);
+
+        assert_eq!(
+            serde_json::to_string(&Region::Benelux).unwrap(),
+            r#""BENELUX""#
+        );
+        let region: Region = serde_json::from_str(r#""lang:de""#).unwrap();
+        assert_eq!(region, Region::from_str("LANG:DE").unwrap());
+        assert!(serde_json::from_str::<Region>(r#""15E""#).is_err());
+        let custom = Region::Benelux.minus(&Region::FiveEyes);
+        let json = serde_json::to_string(&custom).unwrap();
+        assert_eq!(
+            json,
+            r#"{"name":"BENELUX-5E","countries":["BE","LU","NL"]}"#
+        );
+        assert_eq!(serde_json::from_str::<Region>(&json).unwrap(), custom);
+
+        let filter = CountriesFilter::from(Region::Benelux);
+        let json = serde_json::to_string(&filter).unwrap();
+        assert_eq!(
+            serde_json::from_str::<CountriesFilter>(&json).unwrap(),
+            filter
+        );
+        let filter = ProtocolFilter::from(Protocol::Tcp);
+        let json = serde_json::to_string(&filter).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ProtocolFilter>(&json).unwrap(),
+            filter
+        );
+
+        let data = Servers::dummy_data();
+        let json = serde_json::to_string(&data).unwrap();
+        assert_eq!(serde_json::from_str::<Servers>(&json).unwrap(), data);
+    }
 }
@@ -0,0 +1,196 @@
+//! Keeps track of the load of servers over time.
+//!
+//! The load reported by the API is a snapshot and changes quickly. By recording it on every run,
+//! servers can be compared on their average load instead, which gives more stable results. This
+//! is opt-in: nothing is recorded unless [History::record](struct.History.html#method.record) is
+//! called.
+use crate::jsonl::{self, unix_now};
+use crate::servers::{Domain, Server, Servers};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The load of a server at a certain moment, as stored in the history file.
+pub struct LoadSample {
+    /// The domain of the server.
+    pub domain: Domain,
+    /// The moment of the sample, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The load of the server at that moment.
+    pub load: u8,
+}
+
+/// Load samples of servers, backed by a file with one JSON sample per line.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::history::History;
+/// use std::time::Duration;
+///
+/// let path = std::env::temp_dir().join("nordselect-doc-history.jsonl");
+/// # let _ = std::fs::remove_file(&path);
+/// let mut history = History::open(&path).unwrap();
+/// let data = Servers::dummy_data();
+/// history.record(&data).unwrap();
+///
+/// let server = &data.servers[0];
+/// assert_eq!(
+///     server.average_load(&history, Duration::from_secs(3600)),
+///     Some(server.load as f64)
+/// );
+/// ```
+pub struct History {
+    /// The file the samples are appended to.
+    path: PathBuf,
+    /// The samples per domain, as `(timestamp, load)`.
+    samples: HashMap<Domain, Vec<(u64, u8)>>,
+}
+
+/// Ways to open a History.
+impl History {
+    /// Opens the history stored at the given path. A missing file is treated as an empty history.
+    pub fn open(path: impl Into<PathBuf>) -> Result<History, Box<dyn std::error::Error>> {
+        let path = path.into();
+        let mut history = History {
+            path,
+            samples: HashMap::new(),
+        };
+
+        for sample in jsonl::read(&history.path)? {
+            history.insert(sample);
+        }
+
+        Ok(history)
+    }
+
+    /// Returns the location of the history file in the data directory of the current user (e.g.
+    /// `~/.local/share/nordselect/history.jsonl` on Linux).
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("nordselect").join("history.jsonl"))
+    }
+}
+
+impl History {
+    /// Returns the path of the file backing this History.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn insert(&mut self, sample: LoadSample) {
+        self.samples
+            .entry(sample.domain)
+            .or_default()
+            .push((sample.timestamp, sample.load));
+    }
+
+    /// Stores the current load of all given servers, both in memory and in the history file.
+    pub fn record(&mut self, servers: &Servers) -> Result<(), Box<dyn std::error::Error>> {
+        let timestamp = unix_now();
+        let samples: Vec<LoadSample> = servers
+            .servers
+            .iter()
+            .map(|server| LoadSample {
+                domain: server.domain.clone(),
+                timestamp,
+                load: server.load,
+            })
+            .collect();
+
+        jsonl::append(&self.path, &samples)?;
+        for sample in samples {
+            self.insert(sample);
+        }
+
+        Ok(())
+    }
+
+    /// Removes all samples older than the given age, and rewrites the history file accordingly.
+    pub fn prune(&mut self, max_age: Duration) -> Result<(), Box<dyn std::error::Error>> {
+        let oldest = unix_now().saturating_sub(max_age.as_secs());
+
+        let mut kept = Vec::new();
+        for (domain, samples) in self.samples.iter_mut() {
+            samples.retain(|(timestamp, _)| *timestamp >= oldest);
+            kept.extend(samples.iter().map(|(timestamp, load)| LoadSample {
+                domain: domain.clone(),
+                timestamp: *timestamp,
+                load: *load,
+            }));
+        }
+        self.samples.retain(|_, samples| !samples.is_empty());
+        jsonl::rewrite(&self.path, &kept)?;
+
+        Ok(())
+    }
+
+    /// Returns the average load of the server with the given domain over the given period of
+    /// time, counting back from now. Returns `None` if there are no samples in that period.
+    pub fn average_load(&self, domain: &str, window: Duration) -> Option<f64> {
+        let oldest = unix_now().saturating_sub(window.as_secs());
+        let (sum, count) = self
+            .samples
+            .get(domain)?
+            .iter()
+            .filter(|(timestamp, _)| *timestamp >= oldest)
+            .fold((0u64, 0u64), |(sum, count), (_, load)| {
+                (sum + u64::from(*load), count + 1)
+            });
+
+        if count == 0 {
+            None
+        } else {
+            Some(sum as f64 / count as f64)
+        }
+    }
+}
+
+impl Server {
+    /// Returns the average load of this server over the given period of time, according to the
+    /// given History. Returns `None` if there are no samples of this server in that period.
+    pub fn average_load(&self, history: &History, window: Duration) -> Option<f64> {
+        history.average_load(&self.domain, window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn average_ignores_old_samples() {
+        let path = std::env::temp_dir().join("nordselect-test-history-average.jsonl");
+        let now = unix_now();
+        let samples = [(now - 7200, 90), (now - 60, 20), (now, 40)]
+            .iter()
+            .map(|(timestamp, load)| {
+                serde_json::to_string(&LoadSample {
+                    domain: Domain::from("be1.nordvpn.com"),
+                    timestamp: *timestamp,
+                    load: *load,
+                })
+                .unwrap()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        fs::write(&path, samples).unwrap();
+
+        let history = History::open(&path).unwrap();
+        assert_eq!(
+            history.average_load("be1.nordvpn.com", Duration::from_secs(3600)),
+            Some(30f64)
+        );
+        assert_eq!(
+            history.average_load("be1.nordvpn.com", Duration::from_secs(3 * 3600)),
+            Some(50f64)
+        );
+        assert_eq!(
+            history.average_load("be2.nordvpn.com", Duration::from_secs(3600)),
+            None
+        );
+    }
+}
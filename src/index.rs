@@ -0,0 +1,182 @@
+//! Looking up servers by country or category without going through the whole list, e.g. when
+//! the same list is asked for servers over and over again.
+use crate::filters::Filter;
+use crate::servers::{Country, Server, ServerCategory, Servers};
+use std::collections::HashMap;
+
+/// The positions of the servers of a list, by country and by category. It is built once, after
+/// which every lookup only visits the servers it returns.
+///
+/// The index does not keep the list itself. Methods taking a list expect the one the index was
+/// built from, and may panic when given another one.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::filters::{CountryFilter, Filter, LoadFilter};
+/// use nordselect::{Servers, ServersIndex};
+///
+/// let data = Servers::dummy_data();
+/// let index = ServersIndex::new(&data);
+///
+/// let belgium = "BE".parse().unwrap();
+/// assert!(index.country(belgium).iter().all(|&i| data.servers[i].flag == "BE"));
+///
+/// // Only the Belgian servers are checked against the filters.
+/// let country = CountryFilter::from("BE");
+/// let load = LoadFilter::from(50);
+/// let filters: Vec<&dyn Filter> = vec![&country, &load];
+/// let selection = index.filter(&data, &filters);
+/// assert!(selection.servers.iter().all(|server| server.flag == "BE" && server.load <= 50));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ServersIndex {
+    /// The positions of the servers of every country.
+    countries: HashMap<Country, Vec<usize>>,
+    /// The positions of the servers of every category.
+    categories: HashMap<ServerCategory, Vec<usize>>,
+    /// The amount of servers in the list.
+    len: usize,
+}
+
+impl ServersIndex {
+    /// Indexes the given list.
+    pub fn new(servers: &Servers) -> ServersIndex {
+        let mut index = ServersIndex {
+            len: servers.servers.len(),
+            ..ServersIndex::default()
+        };
+        for (position, server) in servers.servers.iter().enumerate() {
+            index
+                .countries
+                .entry(server.flag)
+                .or_default()
+                .push(position);
+            for category in server.categories.iter() {
+                index.categories.entry(category).or_default().push(position);
+            }
+        }
+
+        index
+    }
+
+    /// Returns the amount of servers in the indexed list.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the indexed list has no servers.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the positions of the servers in the given country, in the order of the list.
+    pub fn country(&self, country: Country) -> &[usize] {
+        self.countries
+            .get(&country)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Returns the positions of the servers of the given category, in the order of the list.
+    pub fn category(&self, category: ServerCategory) -> &[usize] {
+        self.categories
+            .get(&category)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Returns the countries that have servers, in no particular order.
+    pub fn countries(&self) -> impl Iterator<Item = Country> + '_ {
+        self.countries.keys().copied()
+    }
+
+    /// Returns the least loaded server of every country. Ties are broken on domain. See
+    /// [Servers::best_per_country](../struct.Servers.html#method.best_per_country).
+    pub fn best_per_country(&self, servers: &Servers) -> HashMap<Country, Server> {
+        debug_assert_eq!(servers.servers.len(), self.len);
+        self.countries
+            .iter()
+            .filter_map(|(country, positions)| {
+                positions
+                    .iter()
+                    .map(|&position| &servers.servers[position])
+                    .min_by(|a, b| a.load.cmp(&b.load).then_with(|| a.domain.cmp(&b.domain)))
+                    .map(|server| (*country, server.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns copies of the servers passing all filters, in the order of the list. Countries
+    /// in which a filter keeps no servers, according to
+    /// [may_pass_country](../filters/trait.Filter.html#method.may_pass_country), are skipped
+    /// as a whole.
+    pub fn filter(&self, servers: &Servers, filters: &[&dyn Filter]) -> Servers {
+        debug_assert_eq!(servers.servers.len(), self.len);
+        let mut positions: Vec<usize> = self
+            .countries
+            .iter()
+            .filter(|(country, _)| {
+                filters
+                    .iter()
+                    .all(|filter| filter.may_pass_country(**country))
+            })
+            .flat_map(|(_, positions)| positions.iter().copied())
+            .collect();
+        positions.sort_unstable();
+
+        Servers {
+            servers: positions
+                .into_iter()
+                .map(|position| &servers.servers[position])
+                .filter(|server| filters.iter().all(|filter| filter.filter(server)))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::{CountriesFilter, LoadFilter};
+    use crate::test_util::arb_servers;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn lookups_agree_with_list(data in arb_servers(0..50), load in 0u8..=100) {
+            let index = ServersIndex::new(&data);
+            prop_assert_eq!(index.len(), data.servers.len());
+            for country in index.countries() {
+                let expected: Vec<usize> = (0..data.servers.len())
+                    .filter(|&i| data.servers[i].flag == country)
+                    .collect();
+                prop_assert_eq!(index.country(country), expected.as_slice());
+            }
+            for &category in ServerCategory::ALL.iter() {
+                let expected: Vec<usize> = (0..data.servers.len())
+                    .filter(|&i| data.servers[i].categories.contains(&category))
+                    .collect();
+                prop_assert_eq!(index.category(category), expected.as_slice());
+            }
+            let best = index.best_per_country(&data);
+            prop_assert_eq!(best.len(), index.countries().count());
+            for server in &data.servers {
+                prop_assert!(best[&server.flag].load <= server.load);
+            }
+
+            let countries: CountriesFilter =
+                data.servers.iter().take(2).map(|server| server.flag).collect();
+            let load = LoadFilter::from(load);
+            let filters: Vec<&dyn Filter> = vec![&countries, &load];
+            let expected: Vec<&Server> = data
+                .servers
+                .iter()
+                .filter(|server| filters.iter().all(|filter| filter.filter(server)))
+                .collect();
+            let selection = index.filter(&data, &filters);
+            prop_assert_eq!(selection.servers.iter().collect::<Vec<_>>(), expected);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Files with one JSON entry per line, in which the load history, the failure log, the automatic
+//! blacklist and the selection log are kept.
+//!
+//! New entries are appended to the end of the file, so the file only has to be rewritten as a
+//! whole when old entries are removed.
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Returns the entries stored in the file at the given path, oldest first. A missing file holds
+/// no entries, and empty lines are skipped.
+pub(crate) fn read<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let text = fs::read_to_string(path)?;
+    let mut entries = Vec::new();
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        entries.push(serde_json::from_str(line)?);
+    }
+    Ok(entries)
+}
+
+/// Appends the entries to the file at the given path, which is created together with its
+/// directory when it does not exist yet.
+pub(crate) fn append<T: Serialize>(
+    path: &Path,
+    entries: &[T],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(lines(entries)?.as_bytes())?;
+
+    Ok(())
+}
+
+/// Replaces the contents of the file at the given path by the entries.
+pub(crate) fn rewrite<T: Serialize>(
+    path: &Path,
+    entries: &[T],
+) -> Result<(), Box<dyn std::error::Error>> {
+    fs::write(path, lines(entries)?)?;
+
+    Ok(())
+}
+
+/// Returns the entries as JSON, one per line.
+fn lines<T: Serialize>(entries: &[T]) -> Result<String, serde_json::Error> {
+    let mut lines = String::new();
+    for entry in entries {
+        lines.push_str(&serde_json::to_string(entry)?);
+        lines.push('\n');
+    }
+    Ok(lines)
+}
+
+/// Returns the current moment, in seconds since the Unix epoch, as the entries store it.
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn append_and_rewrite() {
+        let dir = std::env::temp_dir().join("nordselect-test-jsonl");
+        let path = dir.join("entries.jsonl");
+        let _ = fs::remove_dir_all(&dir);
+        assert!(read::<u32>(&path).unwrap().is_empty());
+
+        append(&path, &[1, 2]).unwrap();
+        append(&path, &[3]).unwrap();
+        assert_eq!(read::<u32>(&path).unwrap(), [1, 2, 3]);
+
+        rewrite(&path, &[4]).unwrap();
+        assert_eq!(read::<u32>(&path).unwrap(), [4]);
+        fs::write(&path, "4\n\nfive\n").unwrap();
+        assert!(read::<u32>(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
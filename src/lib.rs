@@ -4,34 +4,95 @@
 //! can be found [here](https://editicalu.github.io/nordselect) or in the README.
 //!
 //! # Example
+//!
+//! A [SelectionBuilder](selection/struct.SelectionBuilder.html) is the easiest way to select the
+//! best servers.
+//!
 //! ```
 //! use nordselect::{ServerCategory, Protocol, Servers};
+//! use nordselect::bench::LoadBenchmarker;
 //! use nordselect::filters;
-//! use nordselect::sorters;
+//! use nordselect::selection::SelectionBuilder;
 //!
 //! fn main() {
 //!     // Get data
-//!     let mut servers = Servers::from_api().unwrap();
-//!
-//!     // Filter: only servers in Canada
-//!     servers.filter(&filters::CountryFilter::from_code("CA".to_string()));
-//!     // Filter: only TCP compatible servers
-//!     servers.filter(&filters::ProtocolFilter::from(Protocol::Tcp));
-//!     // Filter: only standard servers
-//!     servers.filter(&filters::CategoryFilter::from(ServerCategory::Standard));
+//!     let servers = Servers::from_api().unwrap();
 //!
-//!     // Sort the servers on load.
-//!     servers.sort(&sorters::LoadSorter);
+//!     let selection = SelectionBuilder::new()
+//!         // Filter: only servers in Canada
+//!         .filter(filters::CountryFilter::from("CA"))
+//!         // Filter: only TCP compatible servers
+//!         .filter(filters::ProtocolFilter::from(Protocol::Tcp))
+//!         // Filter: only standard servers
+//!         .filter(filters::CategoryFilter::from(ServerCategory::Standard))
+//!         // Order the servers on load.
+//!         .benchmark(LoadBenchmarker)
+//!         // Keep the best five.
+//!         .take(5)
+//!         .run(&servers)
+//!         .unwrap();
 //!
-//!     assert!(servers.perfect_server().is_some());
+//!     assert!(selection.perfect_server().is_some());
 //! }
 //! ```
 
+#[cfg(feature = "native")]
+pub mod api;
+pub mod audit;
+pub mod bench;
+pub mod blacklist;
+#[cfg(feature = "native")]
+pub mod cache;
+pub mod cli_help;
+#[cfg(feature = "native")]
+pub mod cli_options;
+pub mod countries;
+#[cfg(feature = "daemon")]
+pub mod daemon;
+#[cfg(feature = "native")]
+pub mod detect;
+#[cfg(feature = "native")]
+pub mod doctor;
+pub mod failures;
+#[cfg(feature = "capi")]
+pub mod ffi;
+pub mod filter_spec;
 pub mod filters;
+pub mod history;
+pub mod index;
+mod jsonl;
+pub mod lists;
+#[cfg(feature = "geo-db")]
+pub mod locations;
+#[cfg(all(test, feature = "native"))]
+mod mock_api;
+pub mod output;
+pub mod profiles;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod run;
+pub mod selection;
 pub mod servers;
+pub mod settings;
+pub mod shared;
 pub mod sorters;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
+pub use crate::filter_spec::FilterSpec;
+pub use crate::filters::matches_all;
+pub use crate::index::ServersIndex;
+pub use crate::servers::Categories;
+pub use crate::servers::Country;
+pub use crate::servers::Domain;
+pub use crate::servers::DoubleChain;
+pub use crate::servers::ParseReport;
 pub use crate::servers::Protocol;
+pub use crate::servers::SampleStrategy;
 pub use crate::servers::Server;
+pub use crate::servers::ServerDiff;
 pub use crate::servers::ServerCategory;
 pub use crate::servers::Servers;
+pub use crate::shared::SharedServers;
@@ -0,0 +1,452 @@
+//! Lists of servers kept by the user, e.g. to never select some servers or to only select a few.
+//!
+//! A list file has one entry per line. An entry is either the domain of a server, or one of the
+//! following prefixed patterns:
+//!
+//! - `country:ru`: all servers in the country with the given code.
+//! - `category:obfuscated`: all servers in the given category, named like the category filters.
+//! - `glob:us-ny*`: all servers with a domain matching the pattern, where `*` matches any amount
+//!   of characters and `?` matches a single one.
+//!
+//! Empty lines and lines starting with `#` are ignored.
+//!
+//! A blacklist in the configuration directory is applied on every selection of the CLI. It can be
+//! changed with [ListFile](struct.ListFile.html).
+use crate::cli_help;
+use crate::filters::Filter;
+use crate::{Country, Server, ServerCategory};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A single line of a list file.
+pub enum ListEntry {
+    /// The server with this domain.
+    Domain(String),
+    /// All servers in this country.
+    Country(Country),
+    /// All servers in this category.
+    Category(ServerCategory),
+    /// All servers with a domain matching this pattern.
+    Glob(String),
+}
+
+impl ListEntry {
+    /// Returns whether the server matches this entry.
+    pub fn matches(&self, server: &Server) -> bool {
+        match self {
+            ListEntry::Domain(domain) => server.domain == *domain,
+            ListEntry::Country(country) => server.flag == *country,
+            ListEntry::Category(category) => server.categories.contains(category),
+            ListEntry::Glob(pattern) => glob_matches(pattern.as_bytes(), server.domain.as_bytes()),
+        }
+    }
+}
+
+impl fmt::Display for ListEntry {
+    /// Writes the entry the way it is written in a list file.
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ListEntry::Domain(domain) => formatter.write_str(domain),
+            ListEntry::Country(country) => {
+                write!(formatter, "country:{}", country.as_str().to_lowercase())
+            }
+            ListEntry::Category(category) => {
+                write!(formatter, "category:{}", cli_help::category_name(category))
+            }
+            ListEntry::Glob(pattern) => write!(formatter, "glob:{}", pattern),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The error returned when a line of a list file cannot be read.
+pub struct ParseEntryError(String);
+
+impl fmt::Display for ParseEntryError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseEntryError {}
+
+impl FromStr for ListEntry {
+    type Err = ParseEntryError;
+
+    /// Parses an entry. Domains, patterns and the names of categories are not case sensitive.
+    fn from_str(entry: &str) -> Result<ListEntry, ParseEntryError> {
+        let entry = entry.trim();
+        let (kind, value) = match entry.split_once(':') {
+            Some((kind, value)) => (kind.trim(), value.trim()),
+            None if entry.is_empty() => return Err(ParseEntryError(String::from("empty entry"))),
+            None => return Ok(ListEntry::Domain(entry.to_lowercase())),
+        };
+
+        match kind {
+            "country" => value
+                .parse()
+                .map(ListEntry::Country)
+                .map_err(|err| ParseEntryError(format!("{}", err))),
+            "category" => cli_help::parse_category(&value.to_lowercase())
+                .map(ListEntry::Category)
+                .ok_or_else(|| ParseEntryError(format!("unknown category: \"{}\"", value))),
+            "glob" if !value.is_empty() => Ok(ListEntry::Glob(value.to_lowercase())),
+            "glob" => Err(ParseEntryError(String::from("empty pattern"))),
+            _ => Err(ParseEntryError(format!(
+                "unknown kind of entry: \"{}\"",
+                kind
+            ))),
+        }
+    }
+}
+
+/// Returns whether the text matches the glob pattern.
+fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    // Where to continue after the last `*`, when the characters after it stop matching.
+    let mut backtrack: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c.eq_ignore_ascii_case(&text[t]) => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    backtrack = Some((star_p, star_t + 1));
+                    p = star_p;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// The entries of a list file. As a filter, it keeps the servers matching any of the entries,
+/// which makes it a whitelist. Wrap it in a
+/// [NegatingFilter](../filters/struct.NegatingFilter.html) to use it as a blacklist.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::filters::NegatingFilter;
+/// use nordselect::lists::ServerList;
+///
+/// let blacklist: ServerList = "# Too far away\ncountry:au\ncategory:p2p\nglob:us*"
+///     .parse()
+///     .unwrap();
+///
+/// let mut data = Servers::dummy_data();
+/// data.filter(&NegatingFilter::new(blacklist));
+/// let best = data.perfect_server().unwrap();
+/// assert!(best.flag != "AU" && !best.domain.starts_with("us"));
+/// ```
+pub struct ServerList {
+    /// The domains of single servers, which are looked up instead of compared one by one.
+    domains: HashSet<String>,
+    /// All other entries.
+    patterns: Vec<ListEntry>,
+}
+
+impl ServerList {
+    /// Reads the list file at the given path.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<ServerList, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let text =
+            std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        text.parse()
+            .map_err(|err| format!("{}: {}", path.display(), err).into())
+    }
+
+    /// Adds an entry to the list.
+    pub fn insert(&mut self, entry: ListEntry) {
+        match entry {
+            ListEntry::Domain(domain) => {
+                self.domains.insert(domain);
+            }
+            entry => self.patterns.push(entry),
+        }
+    }
+
+    /// Returns the amount of entries in the list.
+    pub fn len(&self) -> usize {
+        self.domains.len() + self.patterns.len()
+    }
+
+    /// Returns whether the list has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns whether the server matches any of the entries.
+    pub fn contains(&self, server: &Server) -> bool {
+        self.domains.contains(server.domain.as_str())
+            || self.patterns.iter().any(|entry| entry.matches(server))
+    }
+}
+
+impl FromStr for ServerList {
+    type Err = ParseEntryError;
+
+    /// Parses the contents of a list file. Errors mention the line that could not be read.
+    fn from_str(text: &str) -> Result<ServerList, ParseEntryError> {
+        let mut list = ServerList::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let entry = line
+                .parse()
+                .map_err(|err| ParseEntryError(format!("line {}: {}", number + 1, err)))?;
+            list.insert(entry);
+        }
+
+        Ok(list)
+    }
+}
+
+impl Filter for ServerList {
+    fn filter(&self, server: &Server) -> bool {
+        self.contains(server)
+    }
+
+    fn cost(&self) -> u32 {
+        2 + self.patterns.len() as u32
+    }
+
+    fn describe(&self) -> String {
+        format!("one of {} listed servers or patterns", self.len())
+    }
+
+    fn retain(&self, servers: &mut Vec<Server>) {
+        if self.is_empty() {
+            servers.clear();
+        } else if self.patterns.is_empty() {
+            servers.retain(|server| self.domains.contains(server.domain.as_str()))
+        } else {
+            servers.retain(|server| self.contains(server))
+        }
+    }
+}
+
+/// A list file that can be changed, keeping the comments and the order of its lines.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::lists::{ListEntry, ListFile};
+///
+/// let path = std::env::temp_dir().join("nordselect-doc-list");
+/// # let _ = std::fs::remove_file(&path);
+/// let mut list = ListFile::open(&path).unwrap();
+/// let entry: ListEntry = "country:ru".parse().unwrap();
+/// assert!(list.add(&entry));
+/// assert!(!list.add(&entry));
+/// list.save().unwrap();
+///
+/// let list = ListFile::open(&path).unwrap();
+/// assert_eq!(list.entries().unwrap(), vec![entry]);
+/// ```
+pub struct ListFile {
+    /// The file the list is stored in.
+    path: PathBuf,
+    /// The lines of the file, including comments.
+    lines: Vec<String>,
+}
+
+impl ListFile {
+    /// Opens the list file at the given path. A missing file is treated as an empty list.
+    pub fn open(path: impl Into<PathBuf>) -> Result<ListFile, Box<dyn std::error::Error>> {
+        let path = path.into();
+        let lines = if path.is_file() {
+            fs::read_to_string(&path)?
+                .lines()
+                .map(String::from)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Ok(ListFile { path, lines })
+    }
+
+    /// Returns the location of the blacklist in the configuration directory of the current user
+    /// (e.g. `~/.config/nordselect/blacklist` on Linux).
+    pub fn default_blacklist_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("nordselect").join("blacklist"))
+    }
+
+    /// Returns the path of the file backing this list.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the position of the line holding the entry, if any.
+    fn position(&self, entry: &ListEntry) -> Option<usize> {
+        self.lines
+            .iter()
+            .position(|line| line.parse::<ListEntry>().as_ref() == Ok(entry))
+    }
+
+    /// Adds the entry at the end of the list. Returns `false` if the list already had it.
+    pub fn add(&mut self, entry: &ListEntry) -> bool {
+        if self.position(entry).is_some() {
+            return false;
+        }
+
+        self.lines.push(entry.to_string());
+        true
+    }
+
+    /// Removes the entry from the list. Returns `false` if the list did not have it.
+    pub fn remove(&mut self, entry: &ListEntry) -> bool {
+        match self.position(entry) {
+            Some(position) => {
+                self.lines.remove(position);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns all entries, in the order of the file.
+    pub fn entries(&self) -> Result<Vec<ListEntry>, ParseEntryError> {
+        self.lines
+            .iter()
+            .map(|line| line.trim())
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(number, line)| {
+                line.parse()
+                    .map_err(|err| ParseEntryError(format!("line {}: {}", number + 1, err)))
+            })
+            .collect()
+    }
+
+    /// Turns the file into a list, to filter servers with.
+    pub fn to_list(&self) -> Result<ServerList, ParseEntryError> {
+        let mut list = ServerList::default();
+        for entry in self.entries()? {
+            list.insert(entry);
+        }
+
+        Ok(list)
+    }
+
+    /// Writes the list back to its file, creating its directory if needed.
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut text = self.lines.join("\n");
+        text.push('\n');
+        fs::write(&self.path, text)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Servers;
+
+    #[test]
+    fn parse_entries() {
+        assert_eq!(
+            "BE1.nordvpn.com".parse(),
+            Ok(ListEntry::Domain(String::from("be1.nordvpn.com")))
+        );
+        assert_eq!(
+            "country: ru".parse(),
+            Ok(ListEntry::Country("RU".parse().unwrap()))
+        );
+        assert_eq!(
+            "category:Obfuscated".parse(),
+            Ok(ListEntry::Category(ServerCategory::Obfuscated))
+        );
+        assert_eq!(
+            "glob:us-ny*".parse(),
+            Ok(ListEntry::Glob(String::from("us-ny*")))
+        );
+        assert!("country:xx".parse::<ListEntry>().is_err());
+        assert!("category:fast".parse::<ListEntry>().is_err());
+        assert!("glob:".parse::<ListEntry>().is_err());
+        assert!("regex:us.*".parse::<ListEntry>().is_err());
+
+        let error = "be1.nordvpn.com\n\ncountry:xx"
+            .parse::<ServerList>()
+            .unwrap_err();
+        assert_eq!(error.to_string(), "line 3: unknown country code: \"xx\"");
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_matches(b"us*", b"us1234.nordvpn.com"));
+        assert!(glob_matches(b"*.nordvpn.com", b"be1.nordvpn.com"));
+        assert!(glob_matches(b"be?.nordvpn.com", b"be1.nordvpn.com"));
+        assert!(glob_matches(b"*1*2*", b"us1234.nordvpn.com"));
+        assert!(!glob_matches(b"be?.nordvpn.com", b"be12.nordvpn.com"));
+        assert!(!glob_matches(b"us*", b"be1.nordvpn.com"));
+        assert!(!glob_matches(b"*.com.", b"be1.nordvpn.com"));
+    }
+
+    #[test]
+    fn list_filter() {
+        let mut data = Servers::dummy_data();
+        let first = data.servers[0].domain.clone();
+        let list: ServerList = format!("{}\ncountry:be\ncategory:p2p", first)
+            .parse()
+            .unwrap();
+        assert_eq!(list.len(), 3);
+
+        data.filter(&list);
+        assert!(!data.servers.is_empty());
+        assert!(data.servers.iter().all(|server| server.domain == first
+            || server.flag == "BE"
+            || server.categories.contains(&ServerCategory::P2P)));
+
+        // Lists of only domains, or none at all, skip the patterns.
+        let list: ServerList = first.parse().unwrap();
+        data.filter(&list);
+        assert_eq!(data.servers.len(), 1);
+        data.filter(&ServerList::default());
+        assert!(data.servers.is_empty());
+    }
+    #[test]
+    fn edit_list_file() {
+        let path = std::env::temp_dir().join("nordselect-test-list");
+        fs::write(&path, "# Blocked by my provider\nBE1.nordvpn.com\n").unwrap();
+
+        let mut list = ListFile::open(&path).unwrap();
+        let domain: ListEntry = "be1.nordvpn.com".parse().unwrap();
+        let country: ListEntry = "country:UK".parse().unwrap();
+        assert!(!list.add(&domain));
+        assert!(list.add(&country));
+        assert!(list.remove(&domain));
+        assert!(!list.remove(&domain));
+        list.save().unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert_eq!(text, "# Blocked by my provider\ncountry:gb\n");
+        assert_eq!(
+            ListFile::open(&path).unwrap().entries().unwrap(),
+            vec![country]
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
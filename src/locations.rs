@@ -0,0 +1,324 @@
+//! The cities NordVPN has servers in, with their coordinates.
+//!
+//! Not every API response tells where a server is located, e.g. the legacy API only mentions the
+//! country. This table is used instead in that case. It is only available with the `geo-db`
+//! feature.
+use crate::cli_help::normalize;
+use crate::servers::Server;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A city with NordVPN servers.
+pub struct City {
+    /// The English name of the city.
+    pub name: &'static str,
+    /// The latitude of the city, in degrees.
+    pub latitude: f64,
+    /// The longitude of the city, in degrees.
+    pub longitude: f64,
+}
+
+/// The radius of the earth, in kilometres.
+const EARTH_RADIUS: f64 = 6371f64;
+
+impl City {
+    /// Returns the distance between this city and the given coordinates, in kilometres, over the
+    /// surface of the earth.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::locations;
+    ///
+    /// let brussels = locations::for_country("BE")[0];
+    /// let amsterdam = locations::for_country("NL")[0];
+    /// let distance = brussels.distance(amsterdam.latitude, amsterdam.longitude);
+    /// assert!(170f64 < distance && distance < 190f64);
+    /// ```
+    pub fn distance(&self, latitude: f64, longitude: f64) -> f64 {
+        let (latitude1, latitude2) = (self.latitude.to_radians(), latitude.to_radians());
+        let half_latitude = (latitude2 - latitude1) / 2f64;
+        let half_longitude = (longitude - self.longitude).to_radians() / 2f64;
+
+        // The haversine formula.
+        let a = half_latitude.sin().powi(2)
+            + latitude1.cos() * latitude2.cos() * half_longitude.sin().powi(2);
+        2f64 * EARTH_RADIUS * a.sqrt().asin()
+    }
+}
+
+const fn city(name: &'static str, latitude: f64, longitude: f64) -> City {
+    City {
+        name,
+        latitude,
+        longitude,
+    }
+}
+
+/// The cities with NordVPN servers, mapped by the
+/// [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2) code of their country.
+/// Sorted on code, the cities of a country are sorted on name.
+const CITIES: &[(&str, &[City])] = &[
+    ("AE", &[city("Dubai", 25.2048, 55.2708)]),
+    ("AL", &[city("Tirana", 41.3275, 19.8187)]),
+    ("AR", &[city("Buenos Aires", -34.6037, -58.3816)]),
+    ("AT", &[city("Vienna", 48.2082, 16.3738)]),
+    (
+        "AU",
+        &[
+            city("Adelaide", -34.9285, 138.6007),
+            city("Brisbane", -27.4698, 153.0251),
+            city("Melbourne", -37.8136, 144.9631),
+            city("Perth", -31.9505, 115.8605),
+            city("Sydney", -33.8688, 151.2093),
+        ],
+    ),
+    ("BA", &[city("Sarajevo", 43.8563, 18.4131)]),
+    ("BE", &[city("Brussels", 50.8503, 4.3517)]),
+    ("BG", &[city("Sofia", 42.6977, 23.3219)]),
+    ("BR", &[city("Sao Paulo", -23.5505, -46.6333)]),
+    (
+        "CA",
+        &[
+            city("Montreal", 45.5017, -73.5673),
+            city("Toronto", 43.6532, -79.3832),
+            city("Vancouver", 49.2827, -123.1207),
+        ],
+    ),
+    ("CH", &[city("Zurich", 47.3769, 8.5417)]),
+    ("CL", &[city("Santiago", -33.4489, -70.6693)]),
+    ("CO", &[city("Bogota", 4.7110, -74.0721)]),
+    ("CR", &[city("San Jose", 9.9281, -84.0907)]),
+    ("CY", &[city("Nicosia", 35.1856, 33.3823)]),
+    ("CZ", &[city("Prague", 50.0755, 14.4378)]),
+    (
+        "DE",
+        &[
+            city("Berlin", 52.5200, 13.4050),
+            city("Frankfurt", 50.1109, 8.6821),
+        ],
+    ),
+    ("DK", &[city("Copenhagen", 55.6761, 12.5683)]),
+    ("EE", &[city("Tallinn", 59.4370, 24.7536)]),
+    (
+        "ES",
+        &[
+            city("Barcelona", 41.3874, 2.1686),
+            city("Madrid", 40.4168, -3.7038),
+        ],
+    ),
+    ("FI", &[city("Helsinki", 60.1699, 24.9384)]),
+    (
+        "FR",
+        &[
+            city("Marseille", 43.2965, 5.3698),
+            city("Paris", 48.8566, 2.3522),
+        ],
+    ),
+    (
+        "GB",
+        &[
+            city("Edinburgh", 55.9533, -3.1883),
+            city("London", 51.5074, -0.1278),
+            city("Manchester", 53.4808, -2.2426),
+        ],
+    ),
+    ("GE", &[city("Tbilisi", 41.7151, 44.8271)]),
+    ("GR", &[city("Athens", 37.9838, 23.7275)]),
+    ("HK", &[city("Hong Kong", 22.3193, 114.1694)]),
+    ("HR", &[city("Zagreb", 45.8150, 15.9819)]),
+    ("HU", &[city("Budapest", 47.4979, 19.0402)]),
+    ("ID", &[city("Jakarta", -6.2088, 106.8456)]),
+    ("IE", &[city("Dublin", 53.3498, -6.2603)]),
+    ("IL", &[city("Tel Aviv", 32.0853, 34.7818)]),
+    ("IN", &[city("Mumbai", 19.0760, 72.8777)]),
+    ("IS", &[city("Reykjavik", 64.1466, -21.9426)]),
+    (
+        "IT",
+        &[
+            city("Milan", 45.4642, 9.1900),
+            city("Rome", 41.9028, 12.4964),
+        ],
+    ),
+    (
+        "JP",
+        &[
+            city("Osaka", 34.6937, 135.5023),
+            city("Tokyo", 35.6762, 139.6503),
+        ],
+    ),
+    ("KR", &[city("Seoul", 37.5665, 126.9780)]),
+    ("LT", &[city("Vilnius", 54.6872, 25.2797)]),
+    ("LU", &[city("Luxembourg", 49.6116, 6.1319)]),
+    ("LV", &[city("Riga", 56.9496, 24.1052)]),
+    ("MD", &[city("Chisinau", 47.0105, 28.8638)]),
+    ("MK", &[city("Skopje", 41.9981, 21.4254)]),
+    ("MX", &[city("Mexico City", 19.4326, -99.1332)]),
+    ("MY", &[city("Kuala Lumpur", 3.1390, 101.6869)]),
+    ("NL", &[city("Amsterdam", 52.3676, 4.9041)]),
+    ("NO", &[city("Oslo", 59.9139, 10.7522)]),
+    ("NZ", &[city("Auckland", -36.8485, 174.7633)]),
+    ("PL", &[city("Warsaw", 52.2297, 21.0122)]),
+    ("PT", &[city("Lisbon", 38.7223, -9.1393)]),
+    ("RO", &[city("Bucharest", 44.4268, 26.1025)]),
+    ("RS", &[city("Belgrade", 44.7866, 20.4489)]),
+    ("SE", &[city("Stockholm", 59.3293, 18.0686)]),
+    ("SG", &[city("Singapore", 1.3521, 103.8198)]),
+    ("SI", &[city("Ljubljana", 46.0569, 14.5058)]),
+    ("SK", &[city("Bratislava", 48.1486, 17.1077)]),
+    ("TH", &[city("Bangkok", 13.7563, 100.5018)]),
+    ("TR", &[city("Istanbul", 41.0082, 28.9784)]),
+    ("TW", &[city("Taipei", 25.0330, 121.5654)]),
+    ("UA", &[city("Kyiv", 50.4501, 30.5234)]),
+    (
+        "US",
+        &[
+            city("Atlanta", 33.7490, -84.3880),
+            city("Buffalo", 42.8864, -78.8784),
+            city("Charlotte", 35.2271, -80.8431),
+            city("Chicago", 41.8781, -87.6298),
+            city("Dallas", 32.7767, -96.7970),
+            city("Denver", 39.7392, -104.9903),
+            city("Kansas City", 39.0997, -94.5786),
+            city("Los Angeles", 34.0522, -118.2437),
+            city("Miami", 25.7617, -80.1918),
+            city("New York", 40.7128, -74.0060),
+            city("Phoenix", 33.4484, -112.0740),
+            city("Salt Lake City", 40.7608, -111.8910),
+            city("San Francisco", 37.7749, -122.4194),
+            city("Seattle", 47.6062, -122.3321),
+            city("St. Louis", 38.6270, -90.1994),
+        ],
+    ),
+    ("VN", &[city("Hanoi", 21.0285, 105.8542)]),
+    ("ZA", &[city("Johannesburg", -26.2041, 28.0473)]),
+];
+
+/// Returns the cities NordVPN has servers in for the country with the given
+/// [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2) code, which should be
+/// uppercase. Returns an empty slice for unknown countries.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::locations;
+///
+/// assert!(locations::for_country("US").iter().any(|city| city.name == "New York"));
+/// assert!(locations::for_country("XX").is_empty());
+/// ```
+pub fn for_country(alpha2: &str) -> &'static [City] {
+    match CITIES.binary_search_by_key(&alpha2, |(code, _)| *code) {
+        Ok(index) => CITIES[index].1,
+        Err(_) => &[],
+    }
+}
+
+/// The local hours considered off-peak: from 1:00 until 7:00, when few people use their
+/// connection.
+pub const OFF_PEAK_HOURS: (u8, u8) = (1, 7);
+
+/// Returns the longitude of the server, in degrees: the one of its city if it is in the table,
+/// otherwise the average over the cities of its country. `None` if its country is not in the
+/// table.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::{locations, Servers};
+///
+/// let mut server = Servers::dummy_data().servers.remove(0);
+/// server.flag = "US".parse().unwrap();
+/// server.city = Some("Seattle".to_string());
+/// assert_eq!(locations::longitude(&server), Some(-122.3321));
+/// ```
+pub fn longitude(server: &Server) -> Option<f64> {
+    let cities = for_country(server.flag.as_str());
+    if let Some(name) = &server.city {
+        let name = normalize(name);
+        if let Some(city) = cities.iter().find(|city| normalize(city.name) == name) {
+            return Some(city.longitude);
+        }
+    }
+
+    if cities.is_empty() {
+        None
+    } else {
+        Some(cities.iter().map(|city| city.longitude).sum::<f64>() / cities.len() as f64)
+    }
+}
+
+/// Returns the local time at the given longitude at the given moment, in hours since midnight.
+///
+/// This is the solar time, which follows from the longitude alone: it can be an hour or two off
+/// from the time on the clock, as time zones follow borders and some observe daylight saving
+/// time. That is close enough to tell night from day.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::locations;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let noon = UNIX_EPOCH + Duration::from_secs(12 * 3600);
+/// assert_eq!(locations::local_hour(0f64, noon), 12f64);
+/// assert_eq!(locations::local_hour(-90f64, noon), 6f64);
+/// assert_eq!(locations::local_hour(180f64, noon), 0f64);
+/// ```
+pub fn local_hour(longitude: f64, now: SystemTime) -> f64 {
+    let seconds = now
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    let utc = (seconds / 3600f64).rem_euclid(24f64);
+    (utc + longitude / 15f64).rem_euclid(24f64)
+}
+
+/// Returns whether the given hour since midnight is between `start` and `end`. The period can
+/// span midnight, e.g. from 22 until 6.
+pub(crate) fn hour_between(hour: f64, (start, end): (u8, u8)) -> bool {
+    let (start, end) = (f64::from(start), f64::from(end));
+    if start <= end {
+        start <= hour && hour < end
+    } else {
+        start <= hour || hour < end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::countries;
+
+    #[test]
+    fn sorted_known_countries() {
+        for window in CITIES.windows(2) {
+            assert!(window[0].0 < window[1].0);
+        }
+        for (code, cities) in CITIES {
+            assert!(countries::name(code).is_some());
+            assert!(!cities.is_empty());
+            for window in cities.windows(2) {
+                assert!(window[0].name < window[1].name);
+            }
+        }
+    }
+
+    #[test]
+    fn off_peak_hours() {
+        assert!(hour_between(3.5, OFF_PEAK_HOURS));
+        assert!(!hour_between(7.0, OFF_PEAK_HOURS));
+        assert!(hour_between(23.0, (22, 6)));
+        assert!(hour_between(1.0, (22, 6)));
+        assert!(!hour_between(12.0, (22, 6)));
+
+        // Without a known city, the cities of the country are averaged.
+        let mut server = crate::Servers::dummy_data().servers.remove(0);
+        server.flag = "US".parse().unwrap();
+        server.city = Some("Atlantis".to_string());
+        let average = longitude(&server).unwrap();
+        assert!(-122.5 < average && average < -74.0);
+
+        server.flag = "XK".parse().unwrap();
+        assert_eq!(longitude(&server), None);
+    }
+}
@@ -1,10 +1,87 @@
-use nordselect::filters::{self, Filter};
-use nordselect::{Protocol, ServerCategory, Servers};
-use std::collections::HashSet;
+use nordselect::api;
+use nordselect::audit::JsonlLogger;
+use nordselect::bench::resolve::SystemResolver;
+use nordselect::bench::tcp;
+use nordselect::bench::{
+    ErrorPolicy, PingBenchmarker, PingSummary, ScoringWeights, TcpBenchmarker,
+};
+use nordselect::blacklist::AutoBlacklist;
+use nordselect::cache::Cache;
+use nordselect::cli_help::{self, Aliases, FilterError, LabelledFilter};
+use nordselect::cli_options::{self, CliOptions, OptionError};
+use nordselect::detect::ConnectedServerDetector;
+use nordselect::doctor;
+use nordselect::failures::FailureLog;
+use nordselect::filters::{self, BlackListFilter, Filter};
+use nordselect::history::History;
+use nordselect::lists::{ListEntry, ListFile, ServerList};
+use nordselect::output::{self, Cell, FormatOptions, Formatters, Report};
+use nordselect::profiles::{Profile, Profiles};
+use nordselect::run::{self, SelectOptions, SelectionOutcome};
+use nordselect::settings::{self, Settings, Source};
+use nordselect::{Country, SampleStrategy, ServerCategory, Servers};
+use serde_derive::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::iter::FromIterator;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-fn parse_cli_args<'a>() -> clap::ArgMatches<'a> {
-    use clap::{App, Arg};
-    App::new("NordSelect")
+/// Up to this amount of countries, the servers are requested per country instead of all at once.
+const MAX_COUNTRY_REQUESTS: usize = 6;
+
+/// The port used to check whether a server can be reached, which is the one of OpenVPN over TCP.
+const VERIFY_PORT: u16 = 443;
+
+/// How long to wait for a server when checking whether it can be reached.
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long servers that could not be reached stay on the blacklist.
+const BLACKLIST_DURATION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// The settings a profile or the environment can set: the long option of the command line they
+/// stand for, the name of its argument and whether it takes a value. Flags are set with `true`.
+const PROFILE_SETTINGS: &[(&str, &str, bool)] = &[
+    ("ping", "multi_ping", false),
+    ("sping", "single_ping", false),
+    ("tries", "tries", true),
+    ("ping-agg", "ping_agg", true),
+    ("ping-errors", "ping_errors", true),
+    ("amount", "amount", true),
+    ("seed", "seed", true),
+    ("sample", "sample", true),
+    ("sample-by", "sample_by", true),
+    ("deadline", "deadline", true),
+    ("history", "history", false),
+    ("history-window", "history_window", true),
+    ("prefer-bandwidth", "prefer_bandwidth", false),
+    ("prefer-established", "prefer_established", false),
+    ("prefer-recommended", "prefer_recommended", false),
+    ("off-peak", "off_peak", false),
+    ("failure-penalty", "failure_penalty", true),
+    ("nearest-region", "nearest_region", false),
+    ("verify", "verify", false),
+    ("verify-attempts", "verify_attempts", true),
+    ("alternates", "alternates", true),
+    ("better-than", "better_than", true),
+    ("margin", "margin", true),
+    ("ignore-blacklist", "ignore_blacklist", false),
+    ("dns", "dns", true),
+    ("domain", "domain", false),
+    ("output", "output", true),
+    ("cache-ttl", "cache_ttl", true),
+    ("no-proxy", "no_proxy", false),
+    ("log-selections", "log_selections", true),
+];
+
+fn parse_cli_args<'a, I>(args: I) -> clap::ArgMatches<'a>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    use clap::{App, AppSettings, Arg, SubCommand};
+    let formatters = Formatters::default();
+    let formats: Vec<&str> = formatters.names().collect();
+    let app = App::new("NordSelect")
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
@@ -31,6 +108,31 @@ fn parse_cli_args<'a>() -> clap::ArgMatches<'a> {
                 .help("Ping every server TRIES times")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("ping_agg")
+                .long("ping-agg")
+                .value_name("AGGREGATION")
+                .possible_values(&["median", "mean", "p90"])
+                .default_value("median")
+                .help("Combine the latencies of the tries of every server into their median, mean or 90th percentile")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("SEED")
+                .help("Choose between equally good servers using SEED, a number or 'random'. The same number always gives the same choice")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ping_errors")
+                .long("ping-errors")
+                .value_name("POLICY")
+                .possible_values(&["skip", "worst", "abort"])
+                .default_value("worst")
+                .help("What to do with servers that do not answer pings: leave them out, select them last, or fall back to the load of all servers")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("amount")
                 .short("a")
@@ -40,6 +142,216 @@ fn parse_cli_args<'a>() -> clap::ArgMatches<'a> {
                 .help("Ping only to the least AMOUNT ones loaded")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("sample")
+                .long("sample")
+                .value_name("SERVERS")
+                .help("Only consider a sample of SERVERS matching servers, spread over their countries, which makes benchmarking a long list quicker at the cost of maybe missing the best server. Use --seed to get the same sample every time")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sample_by")
+                .long("sample-by")
+                .value_name("STRATEGY")
+                .possible_values(&["country", "random"])
+                .default_value("country")
+                .help("Give every country its share of the sample, or pick the sampled servers at random")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("deadline")
+                .long("deadline")
+                .value_name("SECS")
+                .help("Stop pinging after SECS seconds and pick among the servers pinged so far")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("history")
+                .long("history")
+                .help("Record the current loads and prefer servers with a low average load")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("log_selections")
+                .long("log-selections")
+                .value_name("PATH")
+                .help("Append the time, the filters, the selected server and its score to PATH, one JSON line per selection")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("prefer_bandwidth")
+                .long("prefer-bandwidth")
+                .conflicts_with("history")
+                .help("Prefer servers with the highest bandwidth over the least loaded ones, when not pinging")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("prefer_established")
+                .long("prefer-established")
+                .conflicts_with_all(&["history", "prefer_bandwidth"])
+                .help("Prefer the servers that were added longest ago, which tend to have more stable routing, when not pinging")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("prefer_recommended")
+                .long("prefer-recommended")
+                .conflicts_with_all(&["history", "prefer_bandwidth", "prefer_established"])
+                .help("Prefer the servers NordVPN recommends or gives the highest hub score, when the API tells and when not pinging")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("off_peak")
+                .long("off-peak")
+                .conflicts_with_all(&["history", "prefer_bandwidth", "prefer_established", "prefer_recommended"])
+                .help("Prefer servers where it is night, which tend to be less congested, when not pinging")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("history_window")
+                .long("history-window")
+                .value_name("HOURS")
+                .default_value("24")
+                .help("Average the load over the last HOURS hours when using --history")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("report_failure")
+                .long("report-failure")
+                .value_name("SERVER")
+                .help("Report that connecting to SERVER failed, making it less likely to be selected during the next day")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("failure_penalty")
+                .long("failure-penalty")
+                .value_name("PERCENT")
+                .default_value("100")
+                .help("Make servers PERCENT worse for every reported failure")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mirror")
+                .long("mirror")
+                .value_name("URL")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Also download the server list from URL, which mirrors the NordVPN API")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("no_proxy")
+                .long("no-proxy")
+                .help("Connect to the API and mirrors directly, ignoring HTTPS_PROXY, HTTP_PROXY and ALL_PROXY")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("debug_http")
+                .long("debug-http")
+                .global(true)
+                .help("Print the URL, status code, duration and response size of every request to the API and mirrors")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("page_size")
+                .long("page-size")
+                .value_name("SERVERS")
+                .conflicts_with("mirror")
+                .help("Download the server list from the v1 API in pages of SERVERS servers, which helps when the full list times out on slow links")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dns")
+                .long("dns")
+                .value_name("IP")
+                .global(true)
+                .help("Ask the DNS server at IP for the addresses of servers to ping or verify")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("resolve")
+                .long("resolve")
+                .value_name("DOMAIN=IP")
+                .global(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Use IP as the address of DOMAIN when pinging or verifying servers")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("verify")
+                .long("verify")
+                .help("Check whether the selected server can be reached, selecting the next best one if not")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("better_than")
+                .long("better-than")
+                .value_name("SERVER")
+                .conflicts_with_all(&["want", "chain"])
+                .help("Keep SERVER, a domain or 'current' for the server you are connected to, unless it does not match the filters or another server beats its score by --margin")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("margin")
+                .long("margin")
+                .value_name("PERCENT")
+                .default_value("10")
+                .help("How many percent better than the --better-than server another server has to be")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("verify_attempts")
+                .long("verify-attempts")
+                .value_name("AMOUNT")
+                .default_value("3")
+                .help("Try up to AMOUNT servers when using --verify")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("alternates")
+                .long("alternates")
+                .value_name("AMOUNT")
+                .help("With --output json, also list the next AMOUNT best servers, best first, to fall back to when connecting to the selected server fails")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ignore_blacklist")
+                .long("ignore-blacklist")
+                .help("Also select servers that could not be reached by --verify recently")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("whitelist")
+                .long("whitelist")
+                .value_name("FILE")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only select servers listed in FILE, by domain or as country:CODE, category:NAME or glob:PATTERN")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("blacklist")
+                .long("blacklist")
+                .value_name("FILE")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Never select servers listed in FILE, in the same format as --whitelist")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("input")
+                .long("input")
+                .value_name("PATH")
+                .help("Read the server list from PATH instead of downloading it. Use - to read from stdin")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cache_ttl")
+                .long("cache-ttl")
+                .value_name("MINUTES")
+                .help("Use the stored server list instead of downloading it when it was stored less than MINUTES minutes ago")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("domain")
                 .short("d")
@@ -47,6 +359,105 @@ fn parse_cli_args<'a>() -> clap::ArgMatches<'a> {
                 .help("Print the full domain instead of the short identifier (us1.nordvpn.com instead of us1)")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FORMAT")
+                .possible_values(&formats)
+                .default_value("text")
+                .global(true)
+                .help("Print the results as plain text, as a table, as CSV or as JSON, or print the selected server as a SOCKS5 proxy URI, as a nordvpn:// link that opens it in the NordVPN app or as the JSON the API sent for it")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .value_name("WHEN")
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .global(true)
+                .help("Color the load in --output table: always, never, or only when printing to a terminal")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("proxy_user")
+                .long("proxy-user")
+                .value_name("USER")
+                .help("Include USER in the URI printed by --output proxy")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("trace_filters")
+                .long("trace-filters")
+                .global(true)
+                .help("Show how many servers are left after every filter")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .global(true)
+                .help("Check all filters before downloading the server list, and fail on countries without servers, even excluded ones")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .global(true)
+                .help("Show details about how servers are tested, such as how pings are sent")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("explain")
+                .long("explain")
+                .help("Show the filters that were applied, as they were understood")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("timings")
+                .long("timings")
+                .help("Show how long downloading, filtering and benchmarking took")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("dry_run")
+                .long("dry-run")
+                .conflicts_with_all(&["chain", "list_filters"])
+                .help("Show how a server would be selected, without downloading or measuring anything: where the server list comes from, the filters in the order they are applied, the benchmarkers and how many servers they consider")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("nearest_region")
+                .long("nearest-region")
+                .help("Only select from the country with the lowest latency, which is found by measuring one server per country")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("chain")
+                .long("chain")
+                .value_name("ENTRY:EXIT")
+                .help("Select a Double VPN server going through both countries, e.g. 'nl:ch'")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("want")
+                .long("want")
+                .value_name("FILTER=COUNT")
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(&["chain", "verify"])
+                .help("Select COUNT servers matching FILTER, e.g. 'p2p=2', next to the ones of other --want options")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .value_name("NAME")
+                .help("Add the filters and settings of a profile, e.g. 'us-streaming'. Profiles can be defined in the profiles file of the configuration directory. Environment variables such as NORDSELECT_FILTERS, NORDSELECT_OUTPUT or NORDSELECT_PROFILE override their settings, options given on the command line override both")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("list_filters")
                 .long("filters")
@@ -61,299 +472,2416 @@ fn parse_cli_args<'a>() -> clap::ArgMatches<'a> {
                 .help("Any restriction put on the server. \
                     This can be a country ('us'), a protocol ('tcp') or a type \
                     of server ('p2p'). \
-                    Any filter can be inverted by prepending '!' to it ('!us'). \
+                    Servers in any of the given countries and regions are kept \
+                    ('us ca mx'), while all other filters must be met. \
+                    Any filter can be inverted by prepending '!' to it ('!us', '!eu'). \
                     See --filters"),
         )
-        .get_matches()
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Show which servers were added or removed since the previous run")
+                .arg(
+                    Arg::with_name("loads")
+                        .short("l")
+                        .long("loads")
+                        .help("Also show servers of which the load changed")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("filter")
+                        .required(false)
+                        .multiple(true)
+                        .index(1)
+                        .help("Only compare servers matching these filters. See --filters"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("current")
+                .about("Show the server you are connected to, failing when not connected to NordVPN"),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Measure the latency of all matching servers, without selecting one")
+                .arg(
+                    Arg::with_name("tcp")
+                        .long("tcp")
+                        .help("Measure how long it takes to connect over TCP instead of pinging, which requires no special permissions")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("probe_mtu")
+                        .long("probe-mtu")
+                        .conflicts_with("tcp")
+                        .help("Also find the largest packets that reach every server unfragmented, to check it suits the MTU of WireGuard plus 60 bytes. Only on Linux")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("max_loss")
+                        .long("max-loss")
+                        .value_name("PERCENT")
+                        .help("Leave out the servers that lost more than PERCENT percent of the pings")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("filter")
+                        .required(false)
+                        .multiple(true)
+                        .index(1)
+                        .help("Only measure servers matching these filters. See --filters"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("cache")
+                .about("Manage the stored copy of the server list")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("refresh")
+                        .about("Download the server list and store it, e.g. to have a recent copy ready"),
+                )
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Show how old and how large the stored server list is"),
+                )
+                .subcommand(
+                    SubCommand::with_name("clear").about("Remove the stored server list"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("blacklist")
+                .about("Manage the servers that are never selected, e.g. because they are blocked")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Never select the given servers")
+                        .arg(
+                            Arg::with_name("entry")
+                                .multiple(true)
+                                .required(true)
+                                .index(1)
+                                .help("Domains, or country:CODE, category:NAME or glob:PATTERN"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("Select the given servers again")
+                        .arg(
+                            Arg::with_name("entry")
+                                .multiple(true)
+                                .required(true)
+                                .index(1)
+                                .help("Entries as given to `blacklist add`"),
+                        ),
+                )
+                .subcommand(SubCommand::with_name("list").about("Show the blacklisted servers")),
+        )
+        .subcommand(
+            SubCommand::with_name("doctor")
+                .about("Check the API, the stored server list, pinging, DNS and the profiles file, and explain how to fix problems"),
+        );
+
+    #[cfg(feature = "daemon")]
+    let app = app.subcommand(
+        SubCommand::with_name("daemon")
+            .about("Keep selecting the best server, announcing it over D-Bus and in a state file whenever it changes")
+            .arg(
+                Arg::with_name("interval")
+                    .long("interval")
+                    .value_name("MINUTES")
+                    .default_value("15")
+                    .help("Select the best server again every MINUTES minutes")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("state_file")
+                    .long("state-file")
+                    .value_name("PATH")
+                    .help("Write the best server as JSON to PATH instead of the default location")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("no_dbus")
+                    .long("no-dbus")
+                    .help("Do not send a D-Bus signal when the best server changes")
+                    .takes_value(false),
+            )
+            .arg(
+                Arg::with_name("filter")
+                    .required(false)
+                    .multiple(true)
+                    .index(1)
+                    .help("Only select servers matching these filters. See --filters"),
+            ),
+    );
+
+    app.get_matches_from(args)
+}
+
+/// How long every phase of a run took, in milliseconds. Downloading includes parsing the server
+/// list, as it is read while it is downloaded.
+#[derive(Serialize)]
+struct TimingsReport {
+    download_ms: f64,
+    filter_ms: f64,
+    benchmark_ms: f64,
+    total_ms: f64,
+}
+
+impl TimingsReport {
+    fn new(download: Duration, timings: nordselect::run::Timings, total: Duration) -> Self {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000f64;
+        TimingsReport {
+            download_ms: ms(download),
+            filter_ms: ms(timings.filter),
+            benchmark_ms: ms(timings.benchmark),
+            total_ms: ms(total),
+        }
+    }
+
+    /// Prints the phases to stderr.
+    fn print(&self) {
+        eprintln!("Download: {:.1} ms", self.download_ms);
+        eprintln!("Filter: {:.1} ms", self.filter_ms);
+        eprintln!("Benchmark: {:.1} ms", self.benchmark_ms);
+        eprintln!("Total: {:.1} ms", self.total_ms);
+    }
+}
+
+#[derive(Serialize)]
+struct AvailableCity {
+    name: String,
+    servers: usize,
+}
+
+#[derive(Serialize)]
+struct AvailableCountry {
+    code: String,
+    name: &'static str,
+    servers: usize,
+    cities: Vec<AvailableCity>,
+}
+
+#[derive(Serialize)]
+struct AvailableRegion {
+    code: String,
+    description: String,
 }
 
-fn show_available_filters(data: &Servers) {
+/// Returns the built-in regions, followed by the custom ones of the profiles file.
+fn available_regions() -> Vec<AvailableRegion> {
+    let built_in = nordselect::filters::Region::from_str_options();
+    let mut regions: Vec<AvailableRegion> = built_in
+        .iter()
+        .chain(&nordselect::filters::Region::language_options())
+        .map(|(code, description)| AvailableRegion {
+            code: code.to_lowercase(),
+            description: description.to_string(),
+        })
+        .collect();
+
+    let aliases = aliases();
+    let mut custom: Vec<&nordselect::filters::Region> = aliases.regions().collect();
+    custom.sort_unstable_by(|a, b| a.short().cmp(b.short()));
+    for region in custom {
+        let countries: Vec<String> = region
+            .countries()
+            .iter()
+            .map(|country| country.as_str().to_lowercase())
+            .collect();
+        regions.push(AvailableRegion {
+            code: region.short().to_string(),
+            description: format!("Custom region of {}", countries.join(", ")),
+        });
+    }
+
+    regions
+}
+
+#[derive(Serialize)]
+struct AvailableFilters {
+    protocols: &'static [&'static str],
+    features: &'static [&'static str],
+    categories: &'static [&'static str],
+    /// How many servers match every protocol, feature and category.
+    servers: BTreeMap<&'static str, usize>,
+    countries: Vec<AvailableCountry>,
+    regions: Vec<AvailableRegion>,
+}
+
+fn show_available_filters(data: &Servers, matches: &clap::ArgMatches<'_>) {
+    let mut locations = data.locations();
+    let counts = cli_help::filter_counts(data);
+    if report_format(matches, "The available filters", &["text", "json"]) == "json" {
+        let countries: Vec<AvailableCountry> = counts
+            .countries
+            .iter()
+            .map(|(country, servers)| AvailableCountry {
+                code: country.as_str().to_lowercase(),
+                name: country.name(),
+                servers: *servers,
+                cities: locations
+                    .remove(country)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(name, servers)| AvailableCity { name, servers })
+                    .collect(),
+            })
+            .collect();
+
+        let available = AvailableFilters {
+            protocols: cli_help::PROTOCOLS,
+            features: cli_help::FEATURES,
+            categories: cli_help::CATEGORIES,
+            servers: counts.filters,
+            countries,
+            regions: available_regions(),
+        };
+        println!("{}", serde_json::to_string(&available).unwrap());
+        return;
+    }
+
+    // Every filter is followed by the amount of servers matching it, e.g. p2p (2143).
+    let with_counts = |keywords: &[&str]| {
+        let keywords: Vec<String> = keywords
+            .iter()
+            .map(|keyword| format!("{} ({})", keyword, counts.filters[keyword]))
+            .collect();
+        keywords.join(", ")
+    };
+
     // Show protocols
-    println!("PROTOCOLS:\ttcp, udp, pptp, l2tp, tcp_xor, udp_xor, socks, cybersecproxy, sslproxy, cybersecsslproxy, proxy, wg_udp, nordlynx");
+    println!("PROTOCOLS:\t{}", with_counts(cli_help::PROTOCOLS));
+    // Show other features
+    println!("FEATURES:\t{}", with_counts(cli_help::FEATURES));
+    println!("BANDWIDTH:\te.g. 10gbps or 500mbps, for servers with at least that bandwidth");
     // Show server types
-    println!("SERVERS:\tstandard, dedicated, double, obfuscated, p2p, tor");
+    println!("SERVERS:\t{}", with_counts(cli_help::CATEGORIES));
 
     // Show countries
-    let mut flags: Vec<String> = data.flags().iter().map(|&x| x.to_lowercase()).collect();
-    flags.sort_unstable();
-    let flags = flags;
-
-    let mut iter = flags.iter();
-    if let Some(flag) = iter.next() {
-        print!("COUNTRIES:\t{}", flag.to_lowercase());
-        iter.for_each(|flag| print!(", {}", flag.to_lowercase()));
+    let countries: Vec<String> = counts
+        .countries
+        .iter()
+        .map(|(country, servers)| format!("{} ({})", country.as_str().to_lowercase(), servers))
+        .collect();
+    if !countries.is_empty() {
+        print!("COUNTRIES:\t{}", countries.join(", "));
     }
     println!();
+
+    // Show cities
+    locations.retain(|_, cities| !cities.is_empty());
+    if !locations.is_empty() {
+        println!();
+        println!("CITIES:");
+        for (country, cities) in &locations {
+            let cities: Vec<String> = cities
+                .iter()
+                .map(|(city, servers)| format!("{} ({})", city, servers))
+                .collect();
+            println!("{}\t{}", country.as_str().to_lowercase(), cities.join(", "));
+        }
+        println!(
+            "Prepend {} to a city to select the servers in it, e.g. {}brussels",
+            cli_help::CITY_PREFIX,
+            cli_help::CITY_PREFIX
+        );
+    }
     println!();
 
     // Show regions
     println!("REGIONS:");
-    let iter = nordselect::filters::Region::from_str_options();
-    let mut iter = iter.iter();
-    if let Some(flag) = iter.next() {
-        println!("{}\t{}", flag.0.to_lowercase(), flag.1);
-        iter.for_each(|flag| println!("{}\t{}", flag.0.to_lowercase(), flag.1));
+    let regions = available_regions();
+    for region in &regions {
+        println!("{}\t{}", region.code, region.description);
+    }
+    if !regions.is_empty() {
         println!();
     }
-    println!("Any filter can be inverted using !");
+    println!(
+        "Prepend {} to a region to select all countries outside of it",
+        cli_help::REGION_COMPLEMENT_PREFIX
+    );
+    println!(
+        "Prepend {} to a country to also select its neighbours, e.g. {}be or {}be:2",
+        cli_help::NEARBY_PREFIX,
+        cli_help::NEARBY_PREFIX,
+        cli_help::NEARBY_PREFIX
+    );
+    println!(
+        "Prepend {} to a load to select the servers with at most that load, e.g. {}40",
+        cli_help::MAX_LOAD_PREFIX,
+        cli_help::MAX_LOAD_PREFIX
+    );
+    println!(
+        "Prepend {} to an amount of days to select the servers added at least that long ago, e.g. {}30",
+        cli_help::MIN_AGE_PREFIX,
+        cli_help::MIN_AGE_PREFIX
+    );
+    println!(
+        "Prepend {} to countries to select from the first of them with servers left, e.g. {}nl,de,fr",
+        cli_help::PREFER_PREFIX,
+        cli_help::PREFER_PREFIX
+    );
+    println!("Servers in any of the given countries and regions are kept, e.g. us ca mx");
+    println!("Any filter can be inverted using !, including regions, e.g. !14e");
 }
 
-fn parse_static_filter(filter: &str) -> Option<(Box<dyn Filter>, bool)> {
-    let mut is_category_filter = false;
-    let lib_filter = {
-        let mut category_filter = |category: ServerCategory| -> Box<dyn Filter> {
-            is_category_filter = true;
-            Box::new(filters::CategoryFilter::from(category))
-        };
-        let protocol_filter = |protocol: Protocol| -> Box<dyn Filter> {
-            Box::new(filters::ProtocolFilter::from(protocol))
-        };
+fn parse_filters<'a, I: IntoIterator<Item = &'a str>>(
+    cli_filters: I,
+    data: &Servers,
+    default_to_standard: bool,
+) -> Vec<LabelledFilter> {
+    match cli_help::parse_labelled_filters(cli_filters, data, &aliases(), default_to_standard) {
+        Ok(lib_filters) => lib_filters,
+        Err(err) => exit_on_filter_error(&err),
+    }
+}
 
-        match filter {
-            "p2p" => category_filter(ServerCategory::P2P),
-            "standard" => category_filter(ServerCategory::Standard),
-            "double" => category_filter(ServerCategory::Double),
-            "dedicated" => category_filter(ServerCategory::Dedicated),
-            "tor" => category_filter(ServerCategory::Tor),
-            "obfuscated" => category_filter(ServerCategory::Obfuscated),
-            "tcp" => protocol_filter(Protocol::Tcp),
-            "udp" => protocol_filter(Protocol::Udp),
-            "pptp" => protocol_filter(Protocol::Pptp),
-            "l2tp" => protocol_filter(Protocol::L2tp),
-            "tcp_xor" => protocol_filter(Protocol::OpenVPNXTcp),
-            "udp_xor" => protocol_filter(Protocol::OpenVPNXUdp),
-            "socks" => protocol_filter(Protocol::Socks),
-            "cybersecproxy" => protocol_filter(Protocol::CyberSecProxy),
-            "sslproxy" => protocol_filter(Protocol::SslProxy),
-            "cybersecsslproxy" => protocol_filter(Protocol::CyberSecSslProxy),
-            "proxy" => protocol_filter(Protocol::Proxy),
-            "wg_udp" | "nordlynx" => protocol_filter(Protocol::WireGuardUdp),
-            _ => return None,
-        }
-    };
-    Some((lib_filter, is_category_filter))
+/// Explains why the filters could not be parsed, and exits.
+fn exit_on_filter_error(err: &FilterError) -> ! {
+    if let FilterError::NoServers(..) = err {
+        eprintln!("Error: {}.", err);
+    } else if let Ok(binary) = std::env::current_exe()
+        .unwrap()
+        .into_os_string()
+        .into_string()
+    {
+        eprintln!(
+            "Error: {}. Run `{} --filters` to list all available filters.",
+            err, binary
+        );
+    } else {
+        eprintln!(
+            "Error: {}. Use `--filters` to list all available filters.",
+            err
+        );
+    }
+    std::process::exit(1);
 }
 
-fn consider_negating_filter<'a>(filter: &'a str) -> (&'a str, bool) {
-    if filter.len() > 0 && &filter[..1] == "!" {
-        return (&filter[1..], true);
+/// With `--strict`, checks the filters before they are parsed, exiting on the first problem.
+/// Without a server list, only whether the filters are known is checked.
+fn check_strict<'a, I: IntoIterator<Item = &'a str>>(
+    matches: &clap::ArgMatches<'_>,
+    cli_filters: I,
+    data: Option<&Servers>,
+) {
+    if !matches.is_present("strict") {
+        return;
+    }
+
+    if let Err(err) = cli_help::check_filters(cli_filters, data, &aliases()) {
+        eprintln!("Error: {}.", err);
+        std::process::exit(1);
     }
-    (filter.into(), false)
 }
 
-#[test]
-fn consider_negating_filter_test() {
-    assert_eq!(consider_negating_filter("qwe"), ("qwe", false));
-    assert_eq!(consider_negating_filter("!qwe"), ("qwe", true));
-    assert_eq!(consider_negating_filter(""), ("", false));
+/// Filters on country and category that can be applied while the server list is downloaded,
+/// derived from the CLI filters without knowing which countries exist.
+struct PreFilter {
+    /// The countries all servers should be in, empty if unknown.
+    country_codes: Vec<Country>,
+    countries: Option<filters::CountriesFilter>,
+    categories: Vec<filters::CategoryFilter>,
 }
 
-fn parse_filters(cli_filters: clap::Values<'_>, data: &Servers) -> Vec<Box<dyn Filter>> {
-    // Parse which countries are in the data
-    let flags = data.flags();
+impl Filter for PreFilter {
+    fn filter(&self, server: &nordselect::Server) -> bool {
+        let in_countries = match &self.countries {
+            Some(countries) => countries.filter(server),
+            None => true,
+        };
+
+        in_countries && self.categories.iter().all(|category| category.filter(server))
+    }
+
+    fn may_pass_country(&self, country: Country) -> bool {
+        match &self.countries {
+            Some(countries) => countries.may_pass_country(country),
+            None => true,
+        }
+    }
+}
 
-    let mut lib_filters: Vec<Box<dyn Filter>> = Vec::new();
+/// Builds the filters that can already be applied during the download. These have the same
+/// outcome as the ones of `parse_filters`, but only cover the cheap and unambiguous cases.
+fn parse_prefilter<'a, I: IntoIterator<Item = &'a str>>(cli_filters: I) -> PreFilter {
+    let mut countries = HashSet::new();
+    let mut all_countries_known = true;
+    let mut categories = Vec::new();
     let mut category_filter_added = false;
-    let mut included_countries = HashSet::new();
-    let mut excluded_countries = HashSet::new();
 
-    for original_filter in cli_filters.into_iter() {
-        let (filter, is_negating) = consider_negating_filter(original_filter);
+    let aliases = aliases();
+    for original_filter in cli_filters {
+        let normalized = cli_help::normalize(original_filter);
+        let (filter, is_negating) = cli_help::split_negation(&normalized);
+        let filter = aliases.resolve(filter);
 
-        if let Some((lib_filter, is_category_filter)) = parse_static_filter(filter) {
-            lib_filters.push(if is_negating {
-                Box::new(filters::NegatingFilter::from(lib_filter))
-            } else {
-                lib_filter
-            });
-            if is_category_filter {
-                category_filter_added = true;
+        if let Some(category) = cli_help::parse_category(filter) {
+            category_filter_added = true;
+            if !is_negating {
+                categories.push(filters::CategoryFilter::from(category));
             }
             continue;
         }
 
-        let filter_upper = filter.to_uppercase();
-        let contries_to_modify = if is_negating {
-            &mut excluded_countries
-        } else {
-            &mut included_countries
-        };
+        // Servers outside the countries near the given one are never selected.
+        if let Some(nearby) = cli_help::parse_nearby(filter).filter(|_| !is_negating) {
+            countries.extend(nearby.countries().iter().copied());
+            continue;
+        }
 
-        if flags.contains(filter_upper.as_str()) {
-            contries_to_modify.insert(filter_upper);
+        if is_negating || cli_help::parse_static_filter(filter).is_some() {
             continue;
         }
 
-        if let Some(region_countries) = filters::Region::from_str(&filter_upper) {
-            region_countries.countries().into_iter().for_each(|flag| {
-                contries_to_modify.insert(flag.into());
-                ()
-            });
+        // Only servers in the preferred countries are selected.
+        if let Some(preferred) = cli_help::parse_preference(filter, &aliases) {
+            countries.extend(preferred);
             continue;
         }
 
-        if let Ok(binary) = std::env::current_exe()
-            .unwrap()
-            .into_os_string()
-            .into_string()
-        {
-            eprintln!(
-                "Error: unknown filter: \"{}\". Run `{} --filters` to list all available filters.",
-                original_filter, binary
-            );
+        if let Ok(country) = filter.parse::<Country>() {
+            countries.insert(country);
+        } else if let Some(region) = aliases.region(filter) {
+            countries.extend(region.countries());
         } else {
-            eprintln!(
-                "Error: unknown filter: \"{}\". Use `--filters` to list all available filters.",
-                original_filter
-            );
+            // Let parse_filters report the error, using the full list.
+            all_countries_known = false;
         }
-        std::process::exit(1);
     }
 
-    // Use a Standard server if no special server is requested.
     if !category_filter_added {
-        lib_filters.push(Box::new(filters::CategoryFilter::from(
-            ServerCategory::Standard,
-        )));
+        categories.push(filters::CategoryFilter::from(ServerCategory::Standard));
     }
 
-    // Add countries filters.
-    if !included_countries.is_empty() {
-        lib_filters.push(Box::new(filters::CountriesFilter::from(included_countries)));
+    if !all_countries_known || countries.is_empty() {
+        return PreFilter {
+            country_codes: Vec::new(),
+            countries: None,
+            categories,
+        };
     }
-    if !excluded_countries.is_empty() {
-        lib_filters.push(Box::new(filters::NegatingFilter::new(
-            filters::CountriesFilter::from(excluded_countries),
-        )));
+
+    PreFilter {
+        country_codes: countries.iter().copied().collect(),
+        countries: Some(filters::CountriesFilter::from_iter(countries)),
+        categories,
     }
+}
 
-    lib_filters
+/// Returns whether the servers are requested per country: when only a few countries are
+/// selected, as long as no mirror has to be used.
+fn requests_per_country(prefilter: &PreFilter, matches: &clap::ArgMatches<'_>) -> bool {
+    !prefilter.country_codes.is_empty()
+        && prefilter.country_codes.len() <= MAX_COUNTRY_REQUESTS
+        && !matches.is_present("mirror")
 }
 
-fn apply_filters(filters_to_apply: Vec<Box<dyn Filter>>, data: &mut Servers) {
-    for filter in filters_to_apply.iter() {
-        data.filter(filter.as_ref())
+/// Applies the filters in order. When tracing, prints how many servers every filter leaves and
+/// warns about the filter that removes the last servers.
+fn apply_filters(
+    filters_to_apply: Vec<LabelledFilter>,
+    data: &mut Servers,
+    trace: bool,
+) {
+    for (label, filter) in filters_to_apply.iter() {
+        let before = data.servers.len();
+        data.filter(filter.as_ref());
+        if trace {
+            eprintln!("{}: {} -> {} servers", label, before, data.servers.len());
+            if before > 0 && data.servers.is_empty() {
+                eprintln!("Warning: filter \"{}\" removed all remaining servers", label);
+            }
+        }
     }
 }
 
-fn sort(data: &mut Servers, matches: &clap::ArgMatches<'_>) {
-    let mut should_sort = true;
-
-    // Perform ping test if required
-    let s_ping = matches.is_present("single_ping");
-    let m_ping = matches.is_present("multi_ping");
-    if s_ping || m_ping {
-        let tries_opt = matches.value_of("tries").unwrap().parse();
-        if let Err(err) = tries_opt {
-            eprintln!("Could not read tries of pings: {}", err);
+/// Returns the options deciding how servers are selected, as given on the command line.
+fn cli_options(matches: &clap::ArgMatches<'_>) -> CliOptions {
+    let value = |name| matches.value_of(name).map(String::from);
+    // Options with a default value always have one.
+    let text = |name| matches.value_of(name).unwrap().to_string();
+    CliOptions {
+        multi_ping: matches.is_present("multi_ping"),
+        single_ping: matches.is_present("single_ping"),
+        tries: text("tries"),
+        ping_agg: text("ping_agg"),
+        ping_errors: text("ping_errors"),
+        amount: text("amount"),
+        seed: value("seed"),
+        sample: value("sample"),
+        sample_by: text("sample_by"),
+        deadline: value("deadline"),
+        history_window: text("history_window"),
+        prefer_bandwidth: matches.is_present("prefer_bandwidth"),
+        prefer_established: matches.is_present("prefer_established"),
+        prefer_recommended: matches.is_present("prefer_recommended"),
+        off_peak: matches.is_present("off_peak"),
+        failure_penalty: text("failure_penalty"),
+        nearest_region: matches.is_present("nearest_region"),
+        dns: value("dns"),
+        resolve: matches
+            .values_of("resolve")
+            .unwrap_or_default()
+            .map(String::from)
+            .collect(),
+        verify: matches.is_present("verify"),
+        verify_attempts: text("verify_attempts"),
+        alternates: value("alternates"),
+        margin: text("margin"),
+        want: matches.is_present("want"),
+        trace_filters: matches.is_present("trace_filters"),
+        scoring: None,
+    }
+}
 
-            std::process::exit(1);
+/// Exits with the reason an option could not be used.
+fn exit_on_option_error(err: &OptionError) -> ! {
+    match err {
+        OptionError::Invalid { option, reason } => {
+            eprintln!("Could not read {}: {}", option, reason)
         }
+        OptionError::MissingFeature { option, feature } => eprintln!(
+            "This version of nordselect was built without the {} feature, needed for --{}",
+            feature, option
+        ),
+    }
+    std::process::exit(1);
+}
 
-        let amount_opt = matches.value_of("amount").unwrap().parse();
-        if let Err(err) = amount_opt {
-            eprintln!("Could not read amount of pings: {}", err);
+/// Returns the value read from an option, exiting when it could not be read.
+fn read<T>(value: Result<T, OptionError>) -> T {
+    value.unwrap_or_else(|err| exit_on_option_error(&err))
+}
 
+fn open_history(data: &Servers, record: bool) -> History {
+    let history = History::default_path()
+        .ok_or_else(|| "no data directory found".into())
+        .and_then(History::open);
+    let mut history = match history {
+        Ok(history) => history,
+        Err(err) => {
+            eprintln!("Could not read the load history: {}", err);
             std::process::exit(1);
         }
+    };
 
-        let amount = amount_opt.unwrap();
-        let tries = tries_opt.unwrap();
+    if !record {
+        return history;
+    }
 
-        data.cut(amount);
+    // Samples older than a month are of no use to anyone.
+    if let Err(err) = history
+        .record(data)
+        .and_then(|_| history.prune(Duration::from_secs(30 * 24 * 3600)))
+    {
+        eprintln!(
+            "Could not record the loads in {}: {}",
+            history.path().display(),
+            err
+        );
+    }
 
-        match {
-            if s_ping {
-                nordselect::sorters::PingSorter::ping_single(&data, tries)
-            } else {
-                nordselect::sorters::PingSorter::ping_multi(&data, tries)
-            }
-        } {
-            Ok(sorter) => {
-                data.sort(&sorter);
-                should_sort = false;
-            }
-            Err(error) => {
-                eprintln!("An error occured when pinging: {}", error);
-                eprintln!("Results will not include ping results");
+    history
+}
 
-                match error.to_string().as_str() {
-                    "oping::PingError::LibOpingError: Operation not permitted" => {
-                        eprintln!("");
-                        eprintln!(
-                            "This error means that you did not give permission to nordselect to ping."
-                        );
-                        eprintln!(
-                            "More details can be found at https://github.com/cfallin/rust-oping"
-                        );
-                        if let Ok(exe) = std::env::current_exe() {
-                            if cfg!(unix) {
-                                eprintln!("Hint: to solve this on Linux, execute the following command (as root):");
-                                eprintln!("\tsetcap cap_net_raw+ep {:#?}", exe);
-                            } else if cfg!(windows) {
-                                eprintln!("Hint: ping has not been tested on Windows. Consider using something else.");
-                            }
-                        }
+/// Explains how to solve errors caused by not having the permission to ping.
+fn explain_ping_error(error: &dyn std::error::Error) {
+    match error.to_string().as_str() {
+        "oping::PingError::LibOpingError: Operation not permitted" => {
+            eprintln!("");
+            eprintln!("This error means that you did not give permission to nordselect to ping.");
+            eprintln!("More details can be found at https://github.com/cfallin/rust-oping");
+            if let Ok(exe) = std::env::current_exe() {
+                if cfg!(unix) {
+                    eprintln!("Hint: to solve this on Linux, execute the following command (as root):");
+                    eprintln!("\tsetcap cap_net_raw+ep {:#?}", exe);
+                    if cfg!(target_os = "linux") {
+                        eprintln!("Alternatively, allow unprivileged pings for your group (as root):");
+                        eprintln!("\tsysctl net.ipv4.ping_group_range=\"0 2147483647\"");
                     }
-                    _ => {}
+                } else if cfg!(windows) {
+                    eprintln!("Hint: ping has not been tested on Windows. Consider using something else.");
                 }
-
-                eprintln!("");
-
-                should_sort = true;
             }
         }
+        _ => {}
     }
+}
 
-    if should_sort {
-        data.sort(&nordselect::sorters::LoadSorter);
+/// Shows how pings will be sent, when asked for with `--verbose`.
+fn report_backend(matches: &clap::ArgMatches<'_>, benchmarker: &PingBenchmarker) {
+    if matches.is_present("verbose") {
+        eprintln!("Pinging using {}", benchmarker.used_backend());
     }
 }
 
-fn main() {
-    // Parse CLI args
-    let matches = parse_cli_args();
-
-    // Get API data
-    let mut data = match Servers::from_api() {
+/// Tells what the ping test leaves out, and shows how pings will be sent when asked for with
+/// `--verbose`.
+fn report_ping_test(cli: &CliOptions, matches: &clap::ArgMatches<'_>, deadline: Option<Instant>) {
+    if !cli.pings() {
+        return;
+    }
+    if let Some(deadline) = deadline {
+        if Instant::now() >= deadline {
+            eprintln!("The deadline passed before any server could be pinged");
+            eprintln!("Results will not include ping results");
+            eprintln!();
+            return;
+        }
+    }
+
+    // The distance to servers is not known.
+    if let Some(weights) = cli.scoring {
+        if weights.distance_weight > 0f64 {
+            eprintln!(
+                "Warning: distance_weight is ignored, as the distance to servers is not known."
+            );
+        }
+    }
+    if matches.is_present("verbose") {
+        if let Some((benchmarker, _)) = read(cli.ping_benchmarker(deadline)) {
+            report_backend(matches, &benchmarker);
+        }
+    }
+}
+
+/// Returns how the servers are selected, apart from the server given with `--better-than`.
+fn select_options<'a>(
+    data: &'a Servers,
+    cli_filters: Vec<&str>,
+    extra_filters: Vec<LabelledFilter>,
+    matches: &clap::ArgMatches<'_>,
+    deadline: Option<Instant>,
+    history: Option<&'a History>,
+    failures: Option<&'a FailureLog>,
+) -> SelectOptions<'a> {
+    let cli = CliOptions {
+        scoring: scoring_weights(),
+        ..cli_options(matches)
+    };
+    report_ping_test(&cli, matches, deadline);
+    let options = cli.select_options(
+        data,
+        cli_filters,
+        extra_filters,
+        deadline,
+        history,
+        failures,
+    );
+    read(options).aliases(aliases())
+}
+
+/// Selects the best servers out of the data, exiting when the filters are invalid or when the
+/// servers could not be sorted.
+fn select<'a>(
+    data: &'a Servers,
+    cli_filters: Vec<&str>,
+    extra_filters: Vec<LabelledFilter>,
+    matches: &clap::ArgMatches<'_>,
+    deadline: Option<Instant>,
+    history: Option<&'a History>,
+    failures: Option<&'a FailureLog>,
+) -> SelectionOutcome {
+    let logger = matches.value_of("log_selections").map(JsonlLogger::new);
+    let mut options = select_options(
+        data,
+        cli_filters,
+        extra_filters,
+        matches,
+        deadline,
+        history,
+        failures,
+    );
+    if let Some((server, margin)) = better_than(data, matches) {
+        options = options.better_than(server, margin);
+    }
+    if let Some(logger) = &logger {
+        options = options.logger(logger);
+    }
+
+    match run::select(options) {
+        Ok(outcome) => {
+            if let Some(err) = &outcome.log_error {
+                eprintln!("Could not log the selection: {}", err);
+            }
+            outcome
+        }
+        Err(err) => match err.downcast_ref::<FilterError>() {
+            Some(err) => exit_on_filter_error(err),
+            None => {
+                eprintln!("Could not sort the servers: {}", err);
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
+/// A filter as shown by `--dry-run`.
+#[derive(Serialize)]
+struct PlannedFilter<'a> {
+    label: &'a str,
+    description: &'a str,
+}
+
+/// How a server would be selected, as shown by `--dry-run`.
+#[derive(Serialize)]
+struct DryRunReport<'a> {
+    source: String,
+    checked_against: String,
+    filters: Vec<PlannedFilter<'a>>,
+    benchmarkers: Vec<String>,
+    limits: Vec<String>,
+}
+
+impl DryRunReport<'_> {
+    /// Prints the plan, one step per line.
+    fn print(&self) {
+        println!("Server list: {}", self.source);
+        println!("Filters checked against: {}", self.checked_against);
+        println!("Filters, in the order they are applied:");
+        for (number, filter) in self.filters.iter().enumerate() {
+            println!("  {}. {}: {}", number + 1, filter.label, filter.description);
+        }
+        println!("Benchmarkers:");
+        for benchmarker in &self.benchmarkers {
+            println!("  {}", benchmarker);
+        }
+        println!("Limits:");
+        for limit in &self.limits {
+            println!("  {}", limit);
+        }
+    }
+}
+
+/// Returns where the server list would come from.
+fn data_source(
+    matches: &clap::ArgMatches<'_>,
+    prefilter: Option<&PreFilter>,
+    page_size: Option<usize>,
+    endpoints: &[&str],
+) -> String {
+    match (matches.value_of("input"), prefilter) {
+        (Some(input), _) => format!("the file {}", input),
+        (None, _) if page_size.is_some() => {
+            format!("the API, in pages of {} servers", page_size.unwrap())
+        }
+        (None, Some(prefilter)) if requests_per_country(prefilter, matches) => {
+            let mut countries: Vec<&str> = prefilter
+                .country_codes
+                .iter()
+                .map(Country::as_str)
+                .collect();
+            countries.sort_unstable();
+            format!("the API, only the servers in {}", countries.join(", "))
+        }
+        (None, Some(_)) => format!(
+            "{}, leaving out the servers the filters remove while downloading",
+            endpoints.join(" or ")
+        ),
+        (None, None) => endpoints.join(" or "),
+    }
+}
+
+/// Shows how a server would be selected, without downloading the server list or measuring any
+/// server. The filters are checked against the `--input` file or the stored server list.
+fn dry_run(
+    matches: &clap::ArgMatches<'_>,
+    cli_filters: Vec<&str>,
+    prefilter: Option<&PreFilter>,
+    page_size: Option<usize>,
+    endpoints: &[&str],
+    deadline: Option<Instant>,
+) {
+    if matches.subcommand_name().is_some() {
+        eprintln!("Error: --dry-run only shows how a server is selected, not what a subcommand does.");
+        std::process::exit(1);
+    }
+
+    let (data, checked_against) = match (matches.value_of("input"), Cache::default_location()) {
+        (Some(input), _) => (read_input(input), format!("the file {}", input)),
+        (None, Some(cache)) if cache.exists() => (
+            cache.load(),
+            format!("the server list stored in {}", cache.path().display()),
+        ),
+        (None, _) => (
+            Err("none is stored, give one with --input or run `nordselect cache refresh`".into()),
+            String::new(),
+        ),
+    };
+    let data = match data {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("Could not read a server list to check the filters against: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut extra_filters: Vec<LabelledFilter> = Vec::new();
+    if let Some(blacklist) = blacklist_filter(matches) {
+        extra_filters.push((String::from("blacklist"), Box::new(blacklist)));
+    }
+    extra_filters.extend(list_filters(matches));
+    let failures = open_failure_log();
+    let options = select_options(
+        &data,
+        cli_filters,
+        extra_filters,
+        matches,
+        deadline,
+        None,
+        failures.as_ref(),
+    );
+    let plan = match run::plan(&options) {
+        Ok(plan) => plan,
+        Err(err) => match err.downcast_ref::<FilterError>() {
+            Some(err) => exit_on_filter_error(err),
+            None => {
+                eprintln!("Could not plan the selection: {}", err);
+                std::process::exit(1);
+            }
+        },
+    };
+
+    let tries = matches.value_of("tries").unwrap();
+    let mut benchmarkers = Vec::new();
+    if plan.nearest_country {
+        benchmarkers.push(match plan.ping {
+            Some(_) => format!(
+                "nearest country: ping one server per country {} times",
+                tries
+            ),
+            None => format!(
+                "nearest country: connect to one server per country over TCP {} times",
+                cli_options::NEAREST_COUNTRY_TRIES
+            ),
+        });
+    }
+    if let Some((candidates, policy)) = plan.ping {
+        let unanswered = match policy {
+            ErrorPolicy::SkipServer => "left out",
+            ErrorPolicy::TreatAsWorst => "selected last",
+            ErrorPolicy::Abort => "a reason to fall back",
+        };
+        benchmarkers.push(format!(
+            "ping test: the {} least loaded servers, {} times each, combined into their {}; \
+             servers without answers are {}",
+            candidates,
+            tries,
+            matches.value_of("ping_agg").unwrap(),
+            unanswered
+        ));
+        if let Some(weights) = scoring_weights() {
+            benchmarkers.push(format!(
+                "ping test scores: the latency weighs {} and the load {}",
+                weights.ping_weight, weights.load_weight
+            ));
+        }
+    }
+    let order = if matches.is_present("history") {
+        format!(
+            "order on the average load over the last {}",
+            format_age(read(cli_options(matches).history_window()))
+        )
+    } else if matches.is_present("prefer_bandwidth") {
+        String::from("order on bandwidth")
+    } else if matches.is_present("prefer_established") {
+        String::from("order on when the servers were added, oldest first")
+    } else if matches.is_present("prefer_recommended") {
+        String::from("order on the recommendations and hub scores of NordVPN, then on load")
+    } else if matches.is_present("off_peak") {
+        String::from("order on the local time of the servers, then on load")
+    } else {
+        String::from("order on the current load")
+    };
+    benchmarkers.push(match plan.ping {
+        Some(_) => format!("when the ping test fails, {}", order),
+        None => order,
+    });
+    if let Some((penalty, window)) = plan.failure_penalty {
+        benchmarkers.push(format!(
+            "reported failures: {}% worse per failure in the last {}",
+            penalty,
+            format_age(window)
+        ));
+    }
+
+    let mut limits = Vec::new();
+    if let Some((amount, strategy)) = plan.sample {
+        limits.push(match strategy {
+            SampleStrategy::PerCountry => {
+                format!("sample: {} servers, spread over countries", amount)
+            }
+            SampleStrategy::Random => format!("sample: {} servers, at random", amount),
+        });
+    }
+    limits.push(match plan.take {
+        Some(1) => String::from("select: the best server"),
+        Some(take) => format!("select: the best server and {} alternates", take - 1),
+        None => String::from("select: all servers passing the filters, best first"),
+    });
+    if let Some(deadline) = plan.deadline {
+        limits.push(format!("deadline: {:.1} s", deadline.as_secs_f64()));
+    }
+    if let Some(server) = matches.value_of("better_than") {
+        let server = match server {
+            "current" => "the server you are connected to",
+            server => server,
+        };
+        limits.push(format!(
+            "keep {} unless a server is more than {}% better",
+            server,
+            read(cli_options(matches).margin())
+        ));
+    }
+
+    let report = DryRunReport {
+        source: data_source(matches, prefilter, page_size, endpoints),
+        checked_against,
+        filters: plan
+            .filters
+            .iter()
+            .map(|(label, description)| PlannedFilter { label, description })
+            .collect(),
+        benchmarkers,
+        limits,
+    };
+    if report_format(matches, "The selection plan", &["text", "json"]) == "json" {
+        println!("{}", serde_json::to_string(&report).unwrap());
+    } else {
+        report.print();
+    }
+}
+
+fn server_name<'a>(server: &'a nordselect::Server, matches: &clap::ArgMatches<'_>) -> &'a str {
+    format_options(matches).server_name(server)
+}
+
+/// Parses one side of `--chain`, which should be a single filter.
+fn parse_chain_hop(hop: &str, data: &Servers) -> Box<dyn filters::Filter> {
+    let mut filters = parse_filters(std::iter::once(hop), data, false);
+    if filters.len() != 1 {
+        eprintln!("Error: \"{}\" should be a single filter, like a country.", hop);
+        std::process::exit(1);
+    }
+    filters.remove(0).1
+}
+
+/// Selects the Double VPN server with the lowest load that goes through the countries given as
+/// `ENTRY:EXIT`, after applying the other filters.
+fn select_chain(
+    mut data: Servers,
+    chain: &str,
+    cli_filters: &[&str],
+    matches: &clap::ArgMatches<'_>,
+) {
+    let (entry, exit) = match chain.split_once(':') {
+        Some((entry, exit)) if !entry.is_empty() && !exit.is_empty() => (entry, exit),
+        _ => {
+            eprintln!("Error: --chain should look like ENTRY:EXIT, e.g. nl:ch.");
+            std::process::exit(1);
+        }
+    };
+    let entry = parse_chain_hop(entry, &data);
+    let exit = parse_chain_hop(exit, &data);
+
+    // Standard servers are not left out by default here, Double VPN servers are all we want.
+    let mut filters_to_apply = parse_filters(cli_filters.iter().copied(), &data, false);
+    if let Some(blacklist) = blacklist_filter(matches) {
+        filters_to_apply.push((String::from("blacklist"), Box::new(blacklist)));
+    }
+    filters_to_apply.extend(list_filters(matches));
+    let applied_filters: Vec<String> = vec![
+        format!("entry {}", entry.describe()),
+        format!("exit {}", exit.describe()),
+    ]
+    .into_iter()
+    .chain(filters_to_apply.iter().map(|(_, filter)| filter.describe()))
+    .collect();
+    if matches.is_present("explain") {
+        eprintln!("Applied filters:");
+        for filter in &applied_filters {
+            eprintln!("  {}", filter);
+        }
+    }
+    apply_filters(filters_to_apply, &mut data, matches.is_present("trace_filters"));
+
+    match data.best_double_chain(&*entry, &*exit) {
+        // The hops are only shown by the text and JSON formats.
+        Some(chain) if matches.value_of("output") == Some("text") => println!(
+            "{} ({} -> {})",
+            server_name(&chain.server, matches),
+            chain.entry,
+            chain.exit
+        ),
+        Some(chain) => {
+            let mut report = Report::new(vec![&chain.server]);
+            report.applied_filters = &applied_filters;
+            for (hop, country) in &[("entry", chain.entry), ("exit", chain.exit)] {
+                let country = serde_json::Value::from(country.as_str());
+                report.details.insert(hop.to_string(), country);
+            }
+            print_report(&report, matches);
+        }
+        None => {
+            eprintln!("No server found");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn diff(
+    mut data: Servers,
+    matches: &clap::ArgMatches<'_>,
+    diff_matches: &clap::ArgMatches<'_>,
+    cache: Option<&Cache>,
+) {
+    let cache = match cache {
+        Some(cache) => cache,
+        None => {
+            eprintln!("Could not find a cache directory to store the server list in");
+            std::process::exit(1);
+        }
+    };
+
+    // A list stored by another version of nordselect that cannot be read is replaced.
+    let mut old_data = match cache.load_or_clear() {
+        Ok(Some(x)) => x,
+        Ok(None) => {
+            eprintln!("No previous server list could be read from {}.", cache.path().display());
+            eprintln!("The current list has been stored, run this command again later.");
+            let _ = cache.store(&data);
+            std::process::exit(1);
+        }
+        Err(err) => {
+            eprintln!(
+                "Could not read the previous server list from {}: {}",
+                cache.path().display(),
+                err
+            );
+            std::process::exit(1);
+        }
+    };
+    if let Err(err) = cache.store(&data) {
+        eprintln!("Could not store the server list: {}", err);
+    }
+
+    let filters_to_apply = parse_filters(
+        diff_matches.values_of("filter").unwrap_or_default(),
+        &data,
+        false,
+    );
+    for (_, filter) in filters_to_apply.iter() {
+        old_data.filter(filter.as_ref());
+        data.filter(filter.as_ref());
+    }
+
+    let diff = Servers::diff(&old_data, &data);
+    for server in &diff.added {
+        println!("+ {}\t{}\t{}%", server_name(server, matches), server.flag, server.load);
+    }
+    for server in &diff.removed {
+        println!("- {}\t{}", server_name(server, matches), server.flag);
+    }
+    if diff_matches.is_present("loads") {
+        for (old, new) in &diff.load_changed {
+            println!(
+                "~ {}\t{}\t{}% -> {}%",
+                server_name(new, matches),
+                new.flag,
+                old.load,
+                new.load
+            );
+        }
+    }
+    eprintln!(
+        "{} added, {} removed, {} with a different load",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.load_changed.len()
+    );
+}
+
+#[derive(Serialize)]
+struct CacheReport<'a> {
+    path: &'a std::path::Path,
+    age_secs: u64,
+    size: u64,
+    servers: usize,
+    refresh_hits: u64,
+    refresh_misses: u64,
+}
+
+/// Refreshes, shows or clears the stored server list.
+fn manage_cache(
+    matches: &clap::ArgMatches<'_>,
+    cache_matches: &clap::ArgMatches<'_>,
+    endpoints: &[&str],
+) {
+    let cache = match Cache::default_location() {
+        Some(cache) => cache,
+        None => {
+            eprintln!("Could not find a cache directory to store the server list in");
+            std::process::exit(1);
+        }
+    };
+
+    match cache_matches.subcommand_name() {
+        Some("refresh") => {
+            let result = match matches.value_of("input") {
+                Some(input) => read_input(input)
+                    .and_then(|data| cache.store(&data).map(|_| (data.servers.len(), true))),
+                None => cache
+                    .refresh(&api_client(matches), endpoints)
+                    .map(|refreshed| (refreshed.servers.servers.len(), refreshed.modified)),
+            };
+            match result {
+                Ok((servers, true)) => {
+                    eprintln!("Stored {} servers in {}", servers, cache.path().display())
+                }
+                Ok((servers, false)) => eprintln!(
+                    "The server list did not change, {} servers are stored in {}",
+                    servers,
+                    cache.path().display()
+                ),
+                Err(err) => {
+                    eprintln!("Could not refresh the server list: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some("show") => {
+            let metadata = match cache.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) if !cache.exists() => {
+                    eprintln!("No server list is stored in {}", cache.path().display());
+                    std::process::exit(1);
+                }
+                Err(err) => {
+                    eprintln!("Could not read {}: {}", cache.path().display(), err);
+                    std::process::exit(1);
+                }
+            };
+            let stats = cache.refresh_stats();
+            if report_format(matches, "The stored server list", &["text", "json"]) == "json" {
+                let report = CacheReport {
+                    path: cache.path(),
+                    age_secs: metadata.age().as_secs(),
+                    size: metadata.size,
+                    servers: metadata.servers,
+                    refresh_hits: stats.hits,
+                    refresh_misses: stats.misses,
+                };
+                println!("{}", serde_json::to_string(&report).unwrap());
+            } else {
+                println!("Path:\t{}", cache.path().display());
+                println!("Age:\t{}", format_age(metadata.age()));
+                println!("Size:\t{:.0} KiB", metadata.size as f64 / 1024f64);
+                println!("Servers:\t{}", metadata.servers);
+                println!(
+                    "Refreshes:\t{} unchanged, {} downloaded",
+                    stats.hits, stats.misses
+                );
+            }
+        }
+        _ => {
+            if let Err(err) = cache.clear() {
+                eprintln!("Could not remove {}: {}", cache.path().display(), err);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Checks everything nordselect depends on, printing how to fix what does not work. Exits with 1
+/// when any check failed.
+fn doctor(matches: &clap::ArgMatches<'_>) -> ! {
+    let cache = Cache::default_location();
+    let (api, sample) = doctor::check_api(&api_client(matches));
+    // Without the API, a server of the stored list can still be resolved.
+    let sample = sample.or_else(|| {
+        let data = cache.as_ref()?.load().ok()?;
+        data.servers.first().map(|server| server.domain.to_string())
+    });
+    let resolver = read(cli_options(matches).resolver());
+    let resolver = resolver.unwrap_or_else(|| Arc::new(SystemResolver));
+    let checks = vec![
+        api,
+        doctor::check_cache(cache.as_ref()),
+        doctor::check_ping(),
+        doctor::check_dns(resolver.as_ref(), sample.as_deref()),
+        doctor::check_profiles(Profiles::default_path().as_deref()),
+    ];
+
+    if report_format(matches, "The checks", &["text", "json"]) == "json" {
+        println!("{}", serde_json::to_string(&checks).unwrap());
+    } else {
+        for check in &checks {
+            println!("{:<8}{}: {}", check.status, check.name, check.detail);
+            if let Some(fix) = &check.fix {
+                println!("{:<8}{}", "", fix);
+            }
+        }
+    }
+
+    let failed = checks
+        .iter()
+        .any(|check| check.status == doctor::Status::Failed);
+    std::process::exit(if failed { 1 } else { 0 });
+}
+
+/// Returns how `--output` prints servers.
+fn format_options(matches: &clap::ArgMatches<'_>) -> FormatOptions {
+    FormatOptions {
+        domains: matches.is_present("domain"),
+        color: use_color(matches),
+        proxy_user: matches.value_of("proxy_user").map(String::from),
+    }
+}
+
+/// Prints the report in the format asked for with `--output`, exiting if it cannot be printed in
+/// that format.
+fn print_report(report: &Report<'_>, matches: &clap::ArgMatches<'_>) {
+    let formatters = Formatters::default();
+    // clap only accepts the names of the formatters.
+    let formatter = formatters
+        .get(matches.value_of("output").unwrap_or("text"))
+        .unwrap();
+    match formatter.format(report, &format_options(matches)) {
+        Ok(text) => println!("{}", text),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Returns the format asked for with `--output`, for a report that is not a list of servers and
+/// only has the given formats. Exits when another one was asked for, instead of printing text.
+fn report_format<'m>(matches: &'m clap::ArgMatches<'_>, report: &str, formats: &[&str]) -> &'m str {
+    let format = matches.value_of("output").unwrap_or("text");
+    if !formats.contains(&format) {
+        eprintln!(
+            "{} cannot be printed as {}, only as {}",
+            report,
+            format,
+            formats.join(", ")
+        );
+        std::process::exit(1);
+    }
+    format
+}
+
+/// Describes the recommendation hints the API gave for a server, if any.
+fn hints(server: &nordselect::Server) -> String {
+    let mut hints = Vec::new();
+    if let Some(score) = server.hub_score {
+        hints.push(format!("hub score {}", score));
+    }
+    match server.recommended {
+        Some(true) => hints.push(String::from("recommended")),
+        Some(false) => hints.push(String::from("not recommended")),
+        None => {}
+    }
+    if hints.is_empty() {
+        String::from("none given by the API")
+    } else {
+        hints.join(", ")
+    }
+}
+
+/// Prints the server in the format asked for with `--output`. The alternates are only part of
+/// the JSON output.
+fn print_server(
+    server: &nordselect::Server,
+    matches: &clap::ArgMatches<'_>,
+    applied_filters: &[String],
+    alternates: Option<&[nordselect::Server]>,
+    timings: Option<&TimingsReport>,
+) {
+    print_report(
+        &report(vec![server], applied_filters, alternates, timings),
+        matches,
+    );
+}
+
+/// Returns the report of the servers, with the timings as a detail.
+fn report<'a>(
+    servers: Vec<&'a nordselect::Server>,
+    applied_filters: &'a [String],
+    alternates: Option<&'a [nordselect::Server]>,
+    timings: Option<&TimingsReport>,
+) -> Report<'a> {
+    let mut report = Report::new(servers);
+    report.applied_filters = applied_filters;
+    report.alternates = alternates;
+    if let Some(timings) = timings {
+        report.details.insert(
+            String::from("timings"),
+            serde_json::to_value(timings).unwrap(),
+        );
+    }
+    report
+}
+
+/// Returns whether `--output table` should use colors.
+fn use_color(matches: &clap::ArgMatches<'_>) -> bool {
+    match matches.value_of("color") {
+        Some("always") => true,
+        Some("never") => false,
+        _ => std::env::var_os("NO_COLOR").is_none() && atty::is(atty::Stream::Stdout),
+    }
+}
+
+/// Returns the client talking to the API and mirrors, which uses the proxy set in the
+/// environment unless asked not to with `--no-proxy`, and logs its requests with `--debug-http`.
+fn api_client(matches: &clap::ArgMatches<'_>) -> api::ApiClient {
+    let client = if matches.is_present("no_proxy") {
+        api::ApiClient::without_proxy()
+    } else {
+        api::ApiClient::new()
+    };
+    if matches.is_present("debug_http") {
+        client.logger(api::StderrLogger)
+    } else {
+        client
+    }
+}
+
+/// Returns a detector for the server you are connected to, using the resolver asked for.
+fn detector(matches: &clap::ArgMatches<'_>) -> ConnectedServerDetector {
+    let detector = ConnectedServerDetector::new().client(api_client(matches));
+    match read(cli_options(matches).resolver()) {
+        Some(resolver) => detector.resolver(resolver),
+        None => detector,
+    }
+}
+
+/// Prints the server you are connected to, exiting when not connected to NordVPN.
+fn current(data: &Servers, matches: &clap::ArgMatches<'_>) {
+    match detector(matches).detect(data) {
+        Ok(Some(server)) => print_server(&server, matches, &[], None, None),
+        Ok(None) => {
+            eprintln!("Not connected to a known NordVPN server");
+            std::process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("Could not detect the current server: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Returns the server given with `--better-than` and the margin another server should beat it
+/// by, if given. Exits when the margin is invalid.
+fn better_than(data: &Servers, matches: &clap::ArgMatches<'_>) -> Option<(nordselect::Server, u8)> {
+    let server = matches.value_of("better_than")?;
+    let margin = read(cli_options(matches).margin());
+
+    let found = if server == "current" {
+        match detector(matches).detect(data) {
+            Ok(found) => found,
+            Err(err) => {
+                eprintln!("Could not detect the current server: {}", err);
+                None
+            }
+        }
+    } else {
+        data.servers
+            .iter()
+            .find(|known| known.domain == server || known.name() == Some(server))
+            .cloned()
+    };
+    if found.is_none() && matches.is_present("explain") {
+        eprintln!("{} is not a known server, selecting any server", server);
+    }
+
+    found.map(|found| (found, margin))
+}
+
+/// Prints the servers asked for with `--want`, picked from the selection in its order. Fails if
+/// not enough servers match one of the wanted filters.
+fn select_wanted(
+    data: &Servers,
+    selection: &Servers,
+    wanted: clap::Values<'_>,
+    matches: &clap::ArgMatches<'_>,
+    applied_filters: &[String],
+    timings: Option<&TimingsReport>,
+) {
+    let wanted: Vec<(&str, Box<dyn Filter>, usize)> = wanted
+        .map(|want| {
+            let parsed = want
+                .rsplit_once('=')
+                .and_then(|(filter, count)| Some((filter, count.parse().ok()?)));
+            let (filter, count) = match parsed {
+                Some((filter, count)) if !filter.is_empty() => (filter, count),
+                _ => {
+                    eprintln!("Error: --want should look like FILTER=COUNT, e.g. p2p=2.");
+                    std::process::exit(1);
+                }
+            };
+            let mut filters = parse_filters(std::iter::once(filter), data, false);
+            if filters.len() != 1 {
+                eprintln!("Error: \"{}\" should be a single filter, like a country.", filter);
+                std::process::exit(1);
+            }
+            (filter, filters.remove(0).1, count)
+        })
+        .collect();
+
+    let picked = selection.best_matching_counts(
+        &wanted
+            .iter()
+            .map(|(_, filter, count)| (filter.as_ref(), *count))
+            .collect::<Vec<_>>(),
+    );
+    let mut complete = true;
+    for ((label, _, count), servers) in wanted.iter().zip(&picked) {
+        if servers.len() < *count {
+            eprintln!("Only {} of {} servers found for {}", servers.len(), count, label);
+            complete = false;
+        }
+    }
+    if !complete {
+        std::process::exit(1);
+    }
+
+    let servers: Vec<&nordselect::Server> = picked.iter().flatten().collect();
+    print_report(&report(servers, applied_filters, None, timings), matches);
+}
+
+/// Formats the age in the largest unit that fits it, e.g. `3 hours`.
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    let (amount, unit) = match secs {
+        0..=119 => (secs, "second"),
+        120..=7199 => (secs / 60, "minute"),
+        7200..=172_799 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+
+    format!("{} {}{}", amount, unit, if amount == 1 { "" } else { "s" })
+}
+
+#[derive(Serialize)]
+struct LatencyReport<'a> {
+    domain: &'a str,
+    flag: &'a str,
+    load: u8,
+    latency_ms: Option<f64>,
+    loss: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mtu: Option<u16>,
+}
+
+fn bench(mut data: Servers, matches: &clap::ArgMatches<'_>, bench_matches: &clap::ArgMatches<'_>) {
+    let cli = cli_options(matches);
+    let tries = read(cli.tries());
+
+    let filters_to_apply = parse_filters(
+        bench_matches.values_of("filter").unwrap_or_default(),
+        &data,
+        true,
+    );
+    apply_filters(
+        filters_to_apply,
+        &mut data,
+        matches.is_present("trace_filters"),
+    );
+    if let Some((amount, strategy, seed)) = read(cli.sample()) {
+        data = data.sample(amount, strategy, seed);
+    }
+
+    let resolver = read(cli.resolver());
+    let summaries = if bench_matches.is_present("tcp") {
+        let mut benchmarker = TcpBenchmarker::new(tries);
+        if let Some(resolver) = resolver {
+            benchmarker = benchmarker.resolver(resolver);
+        }
+        benchmarker.measure(&data)
+    } else {
+        let mut benchmarker = PingBenchmarker::new(tries);
+        if let Some(resolver) = resolver {
+            benchmarker = benchmarker.resolver(resolver);
+        }
+        if bench_matches.is_present("probe_mtu") {
+            benchmarker = benchmarker.probe_mtu();
+        }
+        report_backend(matches, &benchmarker);
+        match benchmarker.measure(&data) {
+            Ok(summaries) => summaries,
+            Err(error) => {
+                eprintln!("An error occured when pinging: {}", error);
+                explain_ping_error(error.as_ref());
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let aggregation = read(cli.aggregation());
+    let probe_mtu = bench_matches.is_present("probe_mtu");
+    let max_loss = bench_matches
+        .value_of("max_loss")
+        .map(|max_loss| match max_loss.parse() {
+            Ok(max_loss) => filters::MaxLossFilter::new(&summaries, max_loss),
+            Err(err) => {
+                eprintln!("Could not read maximal loss: {}", err);
+                std::process::exit(1);
+            }
+        });
+    let mut report: Vec<(&nordselect::Server, &PingSummary)> = data
+        .servers
+        .iter()
+        .zip(summaries.iter())
+        .filter(|(server, _)| match &max_loss {
+            Some(max_loss) => max_loss.filter(server),
+            None => true,
+        })
+        .collect();
+    // Unreachable servers go last.
+    report.sort_by_key(|(_, summary)| summary.aggregate(aggregation).unwrap_or(Duration::MAX));
+
+    let format = report_format(matches, "The latencies", &["text", "json", "table"]);
+    if format == "json" {
+        let report: Vec<LatencyReport> = report
+            .iter()
+            .map(|(server, summary)| LatencyReport {
+                domain: &server.domain,
+                flag: server.flag.as_str(),
+                load: server.load,
+                latency_ms: summary
+                    .aggregate(aggregation)
+                    .map(|latency| latency.as_secs_f64() * 1000f64),
+                loss: summary.loss(),
+                mtu: summary.mtu,
+            })
+            .collect();
+        println!("{}", serde_json::to_string(&report).unwrap());
+        return;
+    }
+
+    if format == "table" {
+        let rows = report
+            .iter()
+            .map(|(server, summary)| {
+                let latency = match summary.aggregate(aggregation) {
+                    Some(latency) => format!("{:.1} ms", latency.as_secs_f64() * 1000f64),
+                    None => String::from("unreachable"),
+                };
+                let mut cells = output::server_cells(server, &format_options(matches));
+                cells.push(Cell::from(latency));
+                cells.push(Cell::from(format!("{:.0}%", summary.loss())));
+                if probe_mtu {
+                    cells.push(Cell::from(mtu_text(summary)));
+                }
+                cells
+            })
+            .collect();
+        let mut header: Vec<&str> = output::SERVER_COLUMNS
+            .iter()
+            .copied()
+            .chain(vec!["LATENCY", "LOSS"])
+            .collect();
+        if probe_mtu {
+            header.push("MTU");
+        }
+        println!(
+            "{}",
+            output::render_table(&header, rows, use_color(matches))
+        );
+        return;
+    }
+
+    for (server, summary) in report {
+        let latency = match summary.aggregate(aggregation) {
+            Some(latency) => format!("{:.1} ms", latency.as_secs_f64() * 1000f64),
+            None => String::from("unreachable"),
+        };
+        print!(
+            "{}\t{}\t{}%\t{}\t{:.0}% loss",
+            server_name(server, matches),
+            server.flag,
+            server.load,
+            latency,
+            summary.loss()
+        );
+        if probe_mtu {
+            print!("\tMTU {}", mtu_text(summary));
+        }
+        println!();
+    }
+}
+
+/// Returns the path MTU of the summary as text, which is `unknown` if it was not found.
+fn mtu_text(summary: &PingSummary) -> String {
+    match summary.mtu {
+        Some(mtu) => mtu.to_string(),
+        None => String::from("unknown"),
+    }
+}
+
+fn open_failure_log() -> Option<FailureLog> {
+    match FailureLog::open(FailureLog::default_path()?) {
+        Ok(failures) => Some(failures),
+        Err(err) => {
+            eprintln!("Could not read the reported failures: {}", err);
+            None
+        }
+    }
+}
+
+fn report_failure(server: &str) {
+    let domain = if server.contains('.') {
+        server.to_string()
+    } else {
+        format!("{}.nordvpn.com", server)
+    };
+
+    let mut failures = match open_failure_log() {
+        Some(failures) => failures,
+        None => {
+            eprintln!("Could not find a place to store the failure");
+            std::process::exit(1);
+        }
+    };
+    // Older failures are never looked at anymore.
+    if let Err(err) = failures
+        .report(&domain)
+        .and_then(|_| failures.prune(cli_options::FAILURE_WINDOW))
+    {
+        eprintln!("Could not store the failure: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn open_blacklist() -> Option<AutoBlacklist> {
+    match AutoBlacklist::open(AutoBlacklist::default_path()?) {
+        Ok(blacklist) => Some(blacklist),
+        Err(err) => {
+            eprintln!("Could not read the blacklist: {}", err);
+            None
+        }
+    }
+}
+
+/// Returns the filter leaving out the servers on the blacklist, unless it should be ignored or is
+/// empty.
+fn blacklist_filter(matches: &clap::ArgMatches<'_>) -> Option<BlackListFilter> {
+    if matches.is_present("ignore_blacklist") {
+        return None;
+    }
+
+    open_blacklist()
+        .filter(|blacklist| !blacklist.is_empty())
+        .map(|blacklist| blacklist.filter())
+}
+
+/// Opens the blacklist managed by `nordselect blacklist`.
+fn open_list_file() -> ListFile {
+    let opened = match ListFile::default_blacklist_path() {
+        Some(path) => ListFile::open(path),
+        None => Err("no configuration directory found".into()),
+    };
+    match opened {
+        Ok(list) => list,
+        Err(err) => {
+            eprintln!("Could not read the blacklist: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Adds entries to, removes them from or shows the blacklist managed by `nordselect blacklist`.
+fn manage_blacklist(blacklist_matches: &clap::ArgMatches<'_>) {
+    let mut list = open_list_file();
+    let (command, command_matches) = blacklist_matches.subcommand();
+    let entries = command_matches
+        .and_then(|command_matches| command_matches.values_of("entry"))
+        .unwrap_or_default()
+        .map(|entry| match entry.parse::<ListEntry>() {
+            Ok(entry) => entry,
+            Err(err) => {
+                eprintln!("Could not read {}: {}", entry, err);
+                std::process::exit(1);
+            }
+        });
+
+    match command {
+        "add" => entries.for_each(|entry| {
+            if !list.add(&entry) {
+                eprintln!("{} is already blacklisted", entry);
+            }
+        }),
+        "remove" => entries.for_each(|entry| {
+            if !list.remove(&entry) {
+                eprintln!("{} is not blacklisted", entry);
+            }
+        }),
+        _ => {
+            match list.entries() {
+                Ok(entries) => entries.iter().for_each(|entry| println!("{}", entry)),
+                Err(err) => {
+                    eprintln!("Could not read {}: {}", list.path().display(), err);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+    }
+
+    if let Err(err) = list.save() {
+        eprintln!("Could not write {}: {}", list.path().display(), err);
+        std::process::exit(1);
+    }
+}
+
+/// Returns the filters given by `--whitelist` and `--blacklist`, labelled with their files,
+/// followed by the blacklist managed by `nordselect blacklist`, if it has any entries.
+fn list_filters(matches: &clap::ArgMatches<'_>) -> Vec<(String, Box<dyn Filter>)> {
+    let read = |path: &str| match ServerList::from_file(path) {
+        Ok(list) => list,
+        Err(err) => {
+            eprintln!("Could not read list: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let managed = match open_list_file().to_list() {
+        Ok(list) => list,
+        Err(err) => {
+            eprintln!("Could not read the blacklist: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let whitelists = matches.values_of("whitelist").unwrap_or_default().map(|path| {
+        let list: Box<dyn Filter> = Box::new(read(path));
+        (format!("whitelist {}", path), list)
+    });
+    let blacklists = matches.values_of("blacklist").unwrap_or_default().map(|path| {
+        let list: Box<dyn Filter> = Box::new(filters::NegatingFilter::new(read(path)));
+        (format!("blacklist {}", path), list)
+    });
+    let mut filters: Vec<_> = whitelists.chain(blacklists).collect();
+    if !managed.is_empty() {
+        let list: Box<dyn Filter> = Box::new(filters::NegatingFilter::new(managed));
+        filters.push((String::from("managed blacklist"), list));
+    }
+    filters
+}
+
+/// Puts the given servers on the blacklist, so they are not selected in the next runs.
+fn blacklist(unreachable: &[&nordselect::Server]) {
+    let mut blacklist = match open_blacklist() {
+        Some(blacklist) => blacklist,
+        None => return,
+    };
+
+    let result = unreachable
+        .iter()
+        .try_for_each(|server| blacklist.add(&server.domain, BLACKLIST_DURATION))
+        .and_then(|_| blacklist.prune());
+    if let Err(err) = result {
+        eprintln!("Could not update the blacklist: {}", err);
+    }
+}
+
+/// Returns the first of the selected servers that can be reached.
+///
+/// The servers that could not be reached before it are put on the blacklist. When no server can
+/// be reached, the connection itself is likely the problem, so nothing is blacklisted.
+fn verify(selection: &Servers, matches: &clap::ArgMatches<'_>) -> Option<nordselect::Server> {
+    let cli = cli_options(matches);
+    let resolver = read(cli.resolver()).unwrap_or_else(|| Arc::new(SystemResolver));
+    let attempts = read(cli.verify_attempts()).unwrap_or(1);
+    let mut unreachable = Vec::new();
+    for server in selection.servers.iter().take(attempts) {
+        if tcp::is_reachable_via(server, VERIFY_PORT, VERIFY_TIMEOUT, &*resolver) {
+            if !unreachable.is_empty() {
+                blacklist(&unreachable);
+            }
+            return Some(server.clone());
+        }
+        eprintln!("{} could not be reached", server_name(server, matches));
+        unreachable.push(server);
+    }
+
+    if !selection.servers.is_empty() {
+        eprintln!("None of the selected servers could be reached");
+    }
+    None
+}
+
+/// Keeps selecting the best server, announcing it whenever it changes.
+#[cfg(feature = "daemon")]
+fn daemon(
+    matches: &clap::ArgMatches<'_>,
+    daemon_matches: &clap::ArgMatches<'_>,
+    endpoints: &[&str],
+) -> ! {
+    use nordselect::daemon::Announcer;
+
+    let interval = match daemon_matches.value_of("interval").unwrap().parse::<u64>() {
+        Ok(0) => {
+            eprintln!("Could not read interval: it should be at least a minute");
+            std::process::exit(1);
+        }
+        Ok(minutes) => match minutes.checked_mul(60) {
+            Some(secs) => Duration::from_secs(secs),
+            None => {
+                eprintln!("Could not read interval: it is too large");
+                std::process::exit(1);
+            }
+        },
+        Err(err) => {
+            eprintln!("Could not read interval: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut announcer = Announcer::new();
+    let state_file = match daemon_matches.value_of("state_file") {
+        Some(path) => Some(std::path::PathBuf::from(path)),
+        None => Announcer::default_state_file(),
+    };
+    if let Some(state_file) = state_file {
+        announcer = announcer.state_file(state_file);
+    }
+    if !daemon_matches.is_present("no_dbus") {
+        announcer = match announcer.dbus() {
+            Ok(announcer) => announcer,
+            Err(err) => {
+                eprintln!("Could not connect to D-Bus: {}", err);
+                eprintln!("Use --no-dbus to only write the state file");
+                std::process::exit(1);
+            }
+        };
+    }
+
+    check_strict(matches, daemon_matches.values_of("filter").unwrap_or_default(), None);
+
+    // However short the interval, the API is not asked for every list more than once a minute.
+    // Lists that did not change are not downloaded again, when they can be cached.
+    let client = api_client(matches)
+        .min_interval(Duration::from_secs(60))
+        .burst(endpoints.len() as u32);
+    let cache = Cache::default_location();
+    loop {
+        let download = match &cache {
+            Some(cache) => cache
+                .refresh(&client, endpoints)
+                .map(|refreshed| refreshed.servers)
+                .or_else(|_| Servers::from_endpoints_with_client(&client, endpoints, None)),
+            None => Servers::from_endpoints_with_client(&client, endpoints, None),
+        };
+        match download {
+            Ok(data) => {
+                let cli_filters: Vec<&str> = daemon_matches
+                    .values_of("filter")
+                    .unwrap_or_default()
+                    .collect();
+                let filters =
+                    cli_help::parse_filters(cli_filters.iter().copied(), &data, &aliases(), true);
+                let best = match filters {
+                    Ok(_) => {
+                        let mut extra_filters: Vec<LabelledFilter> = Vec::new();
+                        if let Some(blacklist) = blacklist_filter(matches) {
+                            extra_filters.push((String::from("blacklist"), Box::new(blacklist)));
+                        }
+                        extra_filters.extend(list_filters(matches));
+                        let failures = open_failure_log();
+                        select(
+                            &data,
+                            cli_filters,
+                            extra_filters,
+                            matches,
+                            None,
+                            None,
+                            failures.as_ref(),
+                        )
+                        .server
+                    }
+                    Err(err @ FilterError::Unknown(..)) => {
+                        eprintln!("Error: {}. Use `--filters` to list all available filters.", err);
+                        std::process::exit(1);
+                    }
+                    // Countries can be left without servers for a while, unless asked to be strict.
+                    Err(err) if matches.is_present("strict") => {
+                        eprintln!("Error: {}.", err);
+                        std::process::exit(1);
+                    }
+                    Err(err) => {
+                        eprintln!("Error: {}.", err);
+                        None
+                    }
+                };
+
+                match announcer.update(best) {
+                    Ok(true) => match announcer.best() {
+                        Some(server) => eprintln!("Best server: {}", server_name(server, matches)),
+                        None => eprintln!("No server found"),
+                    },
+                    Ok(false) => {}
+                    Err(err) => eprintln!("Could not announce the best server: {}", err),
+                }
+            }
+            Err(err) => eprintln!("Could not download data: {}", err),
+        }
+
+        std::thread::sleep(interval);
+    }
+}
+
+/// Returns the built-in profiles, replaced by the ones in the profiles file of the configuration
+/// directory. Exits when that file cannot be read.
+fn load_profiles() -> Profiles {
+    let mut profiles = Profiles::default();
+    if let Some(path) = Profiles::default_path() {
+        if let Err(err) = profiles.load(&path) {
+            eprintln!("Could not read the profiles in {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    }
+    profiles
+}
+
+/// Returns the default aliases, with the custom regions of the profiles file.
+fn aliases() -> Aliases {
+    let mut aliases = Aliases::default();
+    // The regions were checked when the file was loaded.
+    for region in load_profiles().regions().unwrap_or_default() {
+        aliases.add_region(region);
+    }
+    aliases
+}
+
+/// Returns the weights of the `[scoring]` section of the profiles file, if it has one.
+fn scoring_weights() -> Option<ScoringWeights> {
+    // The section was checked when the file was loaded.
+    load_profiles().scoring().ok()?
+}
+
+/// Returns the profile asked for with `--profile` or in the environment, if any. Profiles in the
+/// profiles file of the configuration directory replace the built-in ones.
+fn profile(matches: &clap::ArgMatches<'_>, env: &Settings) -> Option<Profile> {
+    let name = matches.value_of("profile").or_else(|| env.get("profile"))?;
+
+    let profiles = load_profiles();
+    match profiles.profile(name) {
+        Some(profile) => Some(profile),
+        None => {
+            let names: Vec<&str> = profiles.names().collect();
+            eprintln!(
+                "Error: unknown profile \"{}\". Known profiles: {}",
+                name,
+                names.join(", ")
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Returns the settings of the profile, below the ones in the environment.
+fn resolve_settings(profile: Option<&Profile>, env: Settings) -> Settings {
+    let mut resolved = env;
+    if let Some(profile) = profile {
+        resolved.add(Source::ConfigFile, profile.settings.clone());
+        if !profile.filters.is_empty() {
+            let filters = (String::from("filters"), profile.filters.join(" "));
+            resolved.add(Source::ConfigFile, std::iter::once(filters));
+        }
+    }
+
+    resolved
+}
+
+/// Returns how a setting is named where it comes from, for error messages.
+fn setting_name(key: &str, source: Source) -> String {
+    match source {
+        Source::Environment => format!("environment variable {}", settings::env_name(key)),
+        _ => format!("profile setting \"{}\"", key),
+    }
+}
+
+/// Parses the command line again with the settings added, as far as they were not given on it.
+/// The filters are left out, they are added to the ones on the command line later. Exits when a
+/// profile setting is unknown, while unknown environment variables are skipped with a warning, as
+/// other programs may use the same prefix.
+fn apply_settings<'a>(matches: clap::ArgMatches<'a>, settings: &Settings) -> clap::ArgMatches<'a> {
+    let mut added: Vec<OsString> = Vec::new();
+    for (key, value, source) in settings.iter() {
+        if key == "filters" || key == "profile" {
+            continue;
+        }
+        let (name, takes_value) = match PROFILE_SETTINGS.iter().find(|(long, _, _)| *long == key) {
+            Some((_, name, takes_value)) => (*name, *takes_value),
+            None if source == Source::Environment => {
+                eprintln!("Warning: ignoring unknown {}", setting_name(key, source));
+                continue;
+            }
+            None => {
+                let known: Vec<&str> = PROFILE_SETTINGS.iter().map(|(long, _, _)| *long).collect();
+                eprintln!(
+                    "Error: unknown {}. Known settings: filters, profile, {}",
+                    setting_name(key, source),
+                    known.join(", ")
+                );
+                std::process::exit(1);
+            }
+        };
+        if matches.occurrences_of(name) > 0 {
+            continue;
+        }
+
+        match (takes_value, value) {
+            (true, _) => {
+                added.push(format!("--{}", key).into());
+                added.push(value.into());
+            }
+            (false, "true") => added.push(format!("--{}", key).into()),
+            (false, "false") => {}
+            (false, _) => {
+                eprintln!(
+                    "Error: {} should be true or false",
+                    setting_name(key, source)
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+    if added.is_empty() {
+        return matches;
+    }
+
+    // The settings go before everything else, so they are never taken for filters.
+    let mut args = std::env::args_os();
+    let program = args.next();
+    parse_cli_args(program.into_iter().chain(added).chain(args))
+}
+
+/// Returns the stored server list if `--cache-ttl` is given and it was stored less than that many
+/// minutes ago.
+fn fresh_cache(matches: &clap::ArgMatches<'_>) -> Option<Servers> {
+    let ttl = match matches.value_of("cache_ttl")?.parse::<u64>() {
+        Ok(minutes) => match minutes.checked_mul(60) {
+            Some(secs) => Duration::from_secs(secs),
+            None => {
+                eprintln!("Could not read cache TTL: it is too large");
+                std::process::exit(1);
+            }
+        },
+        Err(err) => {
+            eprintln!("Could not read cache TTL: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let cache = Cache::default_location()?;
+    let stored = std::fs::metadata(cache.path())
+        .and_then(|metadata| metadata.modified())
+        .ok()?;
+    if stored.elapsed().unwrap_or_default() >= ttl {
+        return None;
+    }
+    cache.load().ok()
+}
+
+/// Reads the server list from the given file, or from stdin if the path is `-`.
+fn read_input(path: &str) -> Result<Servers, Box<dyn std::error::Error>> {
+    if path == "-" {
+        Servers::from_reader(std::io::stdin())
+    } else {
+        Servers::from_reader(std::fs::File::open(path)?)
+    }
+}
+
+fn main() {
+    let start = Instant::now();
+
+    // Parse CLI args, adding the settings of the environment and the profile.
+    let matches = parse_cli_args(std::env::args_os());
+    let mut env = Settings::new();
+    env.add(Source::Environment, settings::from_env());
+    let profile = profile(&matches, &env);
+    let settings = resolve_settings(profile.as_ref(), env);
+    let matches = apply_settings(matches, &settings);
+    let cli = cli_options(&matches);
+    let deadline = read(cli.deadline(start));
+
+    if let Some(server) = matches.value_of("report_failure") {
+        report_failure(server);
+        return;
+    }
+
+    // Get API data, unless it is given. When selecting a server, servers that will be filtered
+    // out anyway are dropped while downloading.
+    let mut endpoints = vec![api::LEGACY_ENDPOINT, api::V1_ENDPOINT];
+    endpoints.extend(matches.values_of("mirror").unwrap_or_default());
+    #[cfg(feature = "daemon")]
+    {
+        if let ("daemon", Some(daemon_matches)) = matches.subcommand() {
+            daemon(&matches, daemon_matches, &endpoints);
+        }
+    }
+    if let ("cache", Some(cache_matches)) = matches.subcommand() {
+        manage_cache(&matches, cache_matches, &endpoints);
+        return;
+    }
+    if let ("blacklist", Some(blacklist_matches)) = matches.subcommand() {
+        manage_blacklist(blacklist_matches);
+        return;
+    }
+    if let ("doctor", Some(_)) = matches.subcommand() {
+        doctor(&matches);
+    }
+    let cli_filters: Vec<&str> = settings
+        .get("filters")
+        .unwrap_or_default()
+        .split_whitespace()
+        .chain(matches.values_of("filter").unwrap_or_default())
+        .collect();
+    check_strict(&matches, cli_filters.iter().copied(), None);
+    let prefilter = if matches.subcommand_name().is_none()
+        && !matches.is_present("list_filters")
+        && !matches.is_present("chain")
+        && !matches.is_present("want")
+    {
+        Some(parse_prefilter(cli_filters.iter().copied()))
+    } else {
+        None
+    };
+    let input = matches.value_of("input");
+    let is_complete = prefilter.is_none() && input.is_none();
+    // Servers in countries other than the selected ones might be left out while downloading.
+    let has_all_countries = match &prefilter {
+        Some(prefilter) => input.is_some() || prefilter.countries.is_none(),
+        None => true,
+    };
+    let page_size = matches.value_of("page_size").map(|page_size| match page_size.parse() {
+        Ok(page_size) if page_size > 0 => page_size,
+        _ => {
+            eprintln!("Could not read page size: it should be a positive number");
+            std::process::exit(1);
+        }
+    });
+    if matches.is_present("dry_run") {
+        dry_run(
+            &matches,
+            cli_filters,
+            prefilter.as_ref(),
+            page_size,
+            &endpoints,
+            deadline,
+        );
+        return;
+    }
+    // A recent enough stored list is used instead of downloading it again.
+    let stored = match input {
+        Some(_) => None,
+        None => fresh_cache(&matches),
+    };
+    let from_cache = stored.is_some();
+    let client = api_client(&matches);
+    let download_start = Instant::now();
+    let download = match (input, prefilter) {
+        (Some(input), _) => read_input(input),
+        (None, prefilter) if from_cache => {
+            let mut data = stored.unwrap();
+            if let Some(prefilter) = prefilter {
+                data.filter(&prefilter);
+            }
+            Ok(data)
+        }
+        (None, prefilter) if page_size.is_some() => {
+            let verbose = matches.is_present("verbose");
+            let paged = Servers::from_api_paged(
+                &client,
+                page_size.unwrap(),
+                |progress| {
+                    if verbose {
+                        eprintln!(
+                            "Downloaded {} pages, {} servers",
+                            progress.pages, progress.servers
+                        );
+                    }
+                },
+            );
+            paged.map(|mut data| {
+                if let Some(prefilter) = prefilter {
+                    data.filter(&prefilter);
+                }
+                data
+            })
+        }
+        // Few countries: ask the API for only those.
+        (None, Some(prefilter)) if requests_per_country(&prefilter, &matches) => {
+            let country_codes = prefilter.country_codes.clone();
+            Servers::from_api_in_countries_with_client(&client, &country_codes, Arc::new(prefilter))
+        }
+        (None, Some(prefilter)) => {
+            Servers::from_endpoints_with_client(&client, &endpoints, Some(Arc::new(prefilter)))
+        }
+        (None, None) => Servers::from_endpoints_with_client(&client, &endpoints, None),
+    };
+    let download_time = download_start.elapsed();
+    let data = match download {
         Ok(x) => x,
+        Err(x) if input.is_some() => {
+            eprintln!("Could not read the server list: {}", x);
+            std::process::exit(1);
+        }
         Err(x) => {
             eprintln!("Could not download data: {}", x);
             std::process::exit(1);
         }
     };
 
+    let cache = Cache::default_location();
+    if let ("diff", Some(diff_matches)) = matches.subcommand() {
+        diff(data, &matches, diff_matches, cache.as_ref());
+        return;
+    }
+    if let ("current", Some(_)) = matches.subcommand() {
+        current(&data, &matches);
+        return;
+    }
+    if let ("bench", Some(bench_matches)) = matches.subcommand() {
+        bench(data, &matches, bench_matches);
+        return;
+    }
+
+    // Loads are only recorded when asked for, and only when they are current.
+    let history = if matches.is_present("history") {
+        Some(open_history(&data, input.is_none()))
+    } else {
+        None
+    };
+
+    // The cache only serves later runs, not being able to write it should not stop us. It should
+    // contain all servers though, and storing a list read from it again would keep it fresh.
+    if let (Some(cache), true) = (&cache, is_complete && !from_cache) {
+        let _ = cache.store(&data);
+    }
+
     // Should we only show the available filters?
     if matches.is_present("list_filters") {
-        show_available_filters(&data);
+        show_available_filters(&data, &matches);
         std::process::exit(0);
     }
 
-    // Detect filters
-    let filters_to_apply = parse_filters(
-        matches
-            .values_of("filter")
-            .unwrap_or(clap::Values::default()),
+    if let Some(chain) = matches.value_of("chain") {
+        select_chain(data, chain, &cli_filters, &matches);
+        return;
+    }
+
+    // Detect filters. Every --want option asks for its own category, if it wants one.
+    if has_all_countries {
+        check_strict(&matches, cli_filters.iter().copied(), Some(&data));
+    }
+    // Servers that could not be reached recently are left out.
+    let mut extra_filters: Vec<LabelledFilter> = Vec::new();
+    if let Some(blacklist) = blacklist_filter(&matches) {
+        extra_filters.push((String::from("blacklist"), Box::new(blacklist)));
+    }
+    extra_filters.extend(list_filters(&matches));
+
+    // Select the best server.
+    let failures = open_failure_log();
+    let outcome = select(
         &data,
+        cli_filters.clone(),
+        extra_filters,
+        &matches,
+        deadline,
+        history.as_ref(),
+        failures.as_ref(),
     );
+    if matches.is_present("explain") {
+        eprintln!("Applied filters:");
+        for filter in &outcome.applied_filters {
+            eprintln!("  {}", filter);
+        }
+    }
+    for step in &outcome.trace {
+        eprintln!("{}: {} -> {} servers", step.label, step.before, step.after);
+        if step.before > 0 && step.after == 0 {
+            eprintln!(
+                "Warning: filter \"{}\" removed all remaining servers",
+                step.label
+            );
+        }
+    }
+    if matches.is_present("nearest_region") {
+        match (&outcome.nearest_country, &outcome.nearest_country_error) {
+            (Some(country), _) if matches.is_present("explain") => {
+                eprintln!("Nearest country: {}", country)
+            }
+            (Some(_), _) => {}
+            (None, None) => eprintln!("No country could be reached, selecting from all of them"),
+            (None, Some(err)) => eprintln!("Could not find the nearest country: {}", err),
+        }
+    }
+    if let Some(error) = &outcome.ping_error {
+        eprintln!("An error occured when pinging: {}", error);
+        eprintln!("Results will not include ping results");
+        explain_ping_error(error.as_ref());
+        eprintln!("");
+    }
+    if outcome.kept && matches.is_present("explain") {
+        eprintln!("No server is better by the margin, keeping the --better-than server");
+    }
+    let selection = outcome.ranked();
+    let timings = TimingsReport::new(download_time, outcome.timings, start.elapsed());
+    if matches.is_present("timings") {
+        timings.print();
+    }
 
-    // Filter servers that are not required.
-    apply_filters(filters_to_apply, &mut data);
-
-    // Sort the servers
-    sort(&mut data, &matches);
+    if let Some(wanted) = matches.values_of("want") {
+        select_wanted(
+            &data,
+            &selection,
+            wanted,
+            &matches,
+            &outcome.applied_filters,
+            Some(&timings),
+        );
+        return;
+    }
 
     // Print the ideal server, if found.
-    if let Some(server) = data.perfect_server() {
-        println!(
-            "{}",
-            match matches.is_present("domain") {
-                true => &server.domain,
-                false => server.name().unwrap_or(&server.domain),
-            }
+    let server = if matches.is_present("verify") {
+        verify(&selection, &matches)
+    } else {
+        selection.perfect_server()
+    };
+    if let Some(server) = server {
+        if matches.is_present("explain") {
+            eprintln!("NordVPN hints for {}: {}", server.domain, hints(&server));
+        }
+        // The alternates are the servers ranked after the selected one, which skips the ones
+        // --verify could not reach.
+        let alternates: Option<Vec<nordselect::Server>> = read(cli.alternates()).map(|amount| {
+            selection
+                .servers
+                .iter()
+                .skip_while(|ranked| ranked.domain != server.domain)
+                .skip(1)
+                .take(amount)
+                .cloned()
+                .collect()
+        });
+        print_server(
+            &server,
+            &matches,
+            &outcome.applied_filters,
+            alternates.as_deref(),
+            Some(&timings),
         );
     } else {
         eprintln!("No server found");
+        if !matches.is_present("trace_filters") {
+            eprintln!("Use --trace-filters to see which filter removed all servers");
+        }
         std::process::exit(1);
     }
 }
@@ -0,0 +1,177 @@
+//! A local HTTP server standing in for the NordVPN API in tests, so downloading, paging and
+//! caching can be tested without a network.
+//!
+//! Every request is answered by a handler, which gets the request and returns the response.
+//! Requests are recorded, so tests can check what was asked for.
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A request the mock received.
+pub(crate) struct Request {
+    /// The path, including the query, e.g. `/v1/servers?limit=10`.
+    pub path: String,
+    /// The headers, with their names in lowercase.
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Returns the value of the header with the given lowercase name, if it was sent.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the value of the given parameter of the query, if it was sent.
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        let (_, query) = self.path.split_once('?')?;
+        query.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            if key == name {
+                Some(value)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A response of the mock.
+pub(crate) struct Response {
+    /// The status code.
+    pub status: u16,
+    /// The headers besides `Content-Length` and `Connection`.
+    pub headers: Vec<(String, String)>,
+    /// The body.
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Returns a `200 OK` response with the given body.
+    pub fn ok(body: impl Into<String>) -> Response {
+        Response {
+            status: 200,
+            headers: Vec::new(),
+            body: body.into().into_bytes(),
+        }
+    }
+
+    /// Returns a response with the given status and no body.
+    pub fn status(status: u16) -> Response {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Adds a header to the response.
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Compresses the body with gzip, like the API does when asked to.
+    pub fn gzip(mut self) -> Response {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.body).unwrap();
+        self.body = encoder.finish().unwrap();
+        self.header("Content-Encoding", "gzip")
+    }
+}
+
+/// A running mock. It keeps answering until the test ends.
+pub(crate) struct MockApi {
+    /// The URL of the mock, without a trailing slash, e.g. `http://127.0.0.1:1234`.
+    base: String,
+    /// Every request received so far, in order.
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockApi {
+    /// Starts a mock on a free local port, answering every request using the handler.
+    pub fn start<H>(handler: H) -> MockApi
+    where
+        H: Fn(&Request) -> Response + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let request = match read_request(&mut BufReader::new(&stream)) {
+                    Some(request) => request,
+                    None => continue,
+                };
+                let response = handler(&request);
+                received.lock().unwrap().push(request);
+
+                let mut head = format!("HTTP/1.1 {} Mock\r\n", response.status);
+                for (name, value) in &response.headers {
+                    head.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                // The client may already have hung up, which is its business.
+                head.push_str(&format!(
+                    "Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    response.body.len()
+                ));
+                let _ = stream
+                    .write_all(head.as_bytes())
+                    .and_then(|()| stream.write_all(&response.body));
+            }
+        });
+
+        MockApi { base, requests }
+    }
+
+    /// Returns the URL of the mock, e.g. to give to
+    /// [ApiClient::api_base](../api/struct.ApiClient.html#method.api_base).
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    /// Returns the URL of the given path on the mock.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+
+    /// Returns every request received so far, in order.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Reads the request line and headers of a request. The body, if any, is ignored.
+fn read_request(reader: &mut impl BufRead) -> Option<Request> {
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    // GET /path HTTP/1.1
+    let path = line.split(' ').nth(1)?.to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    Some(Request { path, headers })
+}
@@ -0,0 +1,521 @@
+//! Printing selected servers in the formats of `--output`.
+//!
+//! Every format is a [Formatter](trait.Formatter.html), which turns a
+//! [Report](struct.Report.html) of the selected servers into text. [Formatters](struct.Formatters.html)
+//! keeps them by the name they are asked for with. The default set holds the formats of the
+//! command line tool; programs can add their own, or replace the built-in ones.
+//!
+//! # Example
+//!
+//! ```
+//! use nordselect::output::{FormatOptions, Formatter, Formatters, Report};
+//! use nordselect::{Server, Servers};
+//!
+//! /// Prints only the countries of the servers.
+//! struct Countries;
+//!
+//! impl Formatter for Countries {
+//!     fn format(
+//!         &self,
+//!         report: &Report<'_>,
+//!         _options: &FormatOptions,
+//!     ) -> Result<String, Box<dyn std::error::Error>> {
+//!         let flags: Vec<&str> = report.servers.iter().map(|server| server.flag.as_str()).collect();
+//!         Ok(flags.join("\n"))
+//!     }
+//! }
+//!
+//! let mut formatters = Formatters::default();
+//! formatters.register("countries", Countries);
+//!
+//! let data = Servers::dummy_data();
+//! let report = Report::new(vec![&data.servers[0]]);
+//! let options = FormatOptions::default();
+//! let text = formatters.get("countries").unwrap().format(&report, &options).unwrap();
+//! assert_eq!(text, data.servers[0].flag.as_str());
+//! assert!(formatters.names().any(|name| name == "csv"));
+//! ```
+use crate::servers::Server;
+use serde_derive::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Below this load, it is colored green in tables.
+const LOW_LOAD: u8 = 30;
+/// Below this load, it is colored yellow in tables. Higher loads are red.
+const MODERATE_LOAD: u8 = 60;
+
+/// The header of the columns given by [server_cells](fn.server_cells.html).
+pub const SERVER_COLUMNS: &[&str] = &["SERVER", "COUNTRY", "CITY", "LOAD", "BANDWIDTH"];
+
+#[derive(Debug, Clone, Default)]
+/// What is printed: the selected servers, together with how they were selected.
+pub struct Report<'a> {
+    /// The servers to print, best first. Most formats print one per line.
+    pub servers: Vec<&'a Server>,
+    /// Descriptions of the filters the servers passed.
+    pub applied_filters: &'a [String],
+    /// The next best servers, if they were asked for. Only part of the JSON output.
+    pub alternates: Option<&'a [Server]>,
+    /// More fields of the JSON output, e.g. how long the selection took.
+    pub details: BTreeMap<String, Value>,
+}
+
+impl<'a> Report<'a> {
+    /// Creates a report of the given servers, without anything else.
+    pub fn new(servers: Vec<&'a Server>) -> Report<'a> {
+        Report {
+            servers,
+            ..Report::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// How the servers are printed, whatever the format.
+pub struct FormatOptions {
+    /// Whether the full domain of servers is printed instead of their short name, e.g.
+    /// `us1.nordvpn.com` instead of `us1`.
+    pub domains: bool,
+    /// Whether tables are colored using ANSI escape codes.
+    pub color: bool,
+    /// The user put in SOCKS5 proxy URIs, if any.
+    pub proxy_user: Option<String>,
+}
+
+impl FormatOptions {
+    /// Returns the name of the server as it should be printed.
+    pub fn server_name<'s>(&self, server: &'s Server) -> &'s str {
+        if self.domains {
+            &server.domain
+        } else {
+            server.name().unwrap_or(&server.domain)
+        }
+    }
+}
+
+/// A way to print a [Report](struct.Report.html).
+pub trait Formatter {
+    /// Returns the report as text, without a newline at the end. Fails when the report cannot be
+    /// shown in this format, e.g. because a server lacks what the format needs.
+    fn format(
+        &self,
+        report: &Report<'_>,
+        options: &FormatOptions,
+    ) -> Result<String, Box<dyn std::error::Error>>;
+}
+
+/// Formats the servers one per line, mapping every one of them to a line.
+fn lines(
+    report: &Report<'_>,
+    line: impl Fn(&Server) -> Result<String, Box<dyn std::error::Error>>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let lines = report
+        .servers
+        .iter()
+        .map(|server| line(server))
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok(lines.join("\n"))
+}
+
+/// Prints the name of every server.
+pub struct Text;
+
+impl Formatter for Text {
+    fn format(
+        &self,
+        report: &Report<'_>,
+        options: &FormatOptions,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        lines(report, |server| Ok(options.server_name(server).to_string()))
+    }
+}
+
+#[derive(Serialize)]
+/// A server in the JSON output.
+struct Selected<'a> {
+    #[serde(flatten)]
+    server: &'a Server,
+    applied_filters: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alternates: Option<&'a [Server]>,
+    #[serde(flatten)]
+    details: &'a BTreeMap<String, Value>,
+}
+
+/// Prints every server as a JSON object on a line of its own, together with the applied filters,
+/// the alternates and the details of the report.
+pub struct Json;
+
+impl Formatter for Json {
+    fn format(
+        &self,
+        report: &Report<'_>,
+        _options: &FormatOptions,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        lines(report, |server| {
+            let selected = Selected {
+                server,
+                applied_filters: report.applied_filters,
+                alternates: report.alternates,
+                details: &report.details,
+            };
+            Ok(serde_json::to_string(&selected)?)
+        })
+    }
+}
+
+/// Prints the servers as comma-separated values, below a header. The load is in percent, the
+/// bandwidth in Mbps.
+pub struct Csv;
+
+/// Quotes the field if it contains a separator, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl Formatter for Csv {
+    fn format(
+        &self,
+        report: &Report<'_>,
+        options: &FormatOptions,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let mut lines = vec![String::from("server,country,city,load,bandwidth")];
+        for server in &report.servers {
+            let fields = [
+                options.server_name(server).to_string(),
+                server.flag.to_string(),
+                server.city.clone().unwrap_or_default(),
+                server.load.to_string(),
+                server
+                    .bandwidth_tier
+                    .map(|bandwidth| bandwidth.to_string())
+                    .unwrap_or_default(),
+            ];
+            let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+            lines.push(fields.join(","));
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Prints the servers as a table with aligned columns, one row per server.
+pub struct Table;
+
+impl Formatter for Table {
+    fn format(
+        &self,
+        report: &Report<'_>,
+        options: &FormatOptions,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let rows = report
+            .servers
+            .iter()
+            .map(|server| server_cells(server, options))
+            .collect();
+        Ok(render_table(SERVER_COLUMNS, rows, options.color))
+    }
+}
+
+/// Prints a `nordvpn://` link for every server, which opens it in the NordVPN app.
+pub struct Uri;
+
+impl Formatter for Uri {
+    fn format(
+        &self,
+        report: &Report<'_>,
+        _options: &FormatOptions,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        lines(report, |server| Ok(server.nordvpn_uri()))
+    }
+}
+
+/// Prints the URI of the SOCKS5 proxy of every server. Fails for servers without one.
+pub struct Proxy;
+
+impl Formatter for Proxy {
+    fn format(
+        &self,
+        report: &Report<'_>,
+        options: &FormatOptions,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        lines(report, |server| {
+            let (host, port) = server.socks_endpoint().ok_or_else(|| {
+                format!(
+                    "{} does not support SOCKS, add the socks filter to select a server that does",
+                    options.server_name(server)
+                )
+            })?;
+            Ok(match &options.proxy_user {
+                Some(user) => format!("socks5://{}@{}:{}", user, host, port),
+                None => format!("socks5://{}:{}", host, port),
+            })
+        })
+    }
+}
+
+/// Prints the JSON the API sent for every server. Fails for servers that were not read from the
+/// API, and always without the `raw-json` feature.
+pub struct RawJson;
+
+impl Formatter for RawJson {
+    #[cfg(feature = "raw-json")]
+    fn format(
+        &self,
+        report: &Report<'_>,
+        _options: &FormatOptions,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        lines(report, |server| match server.raw() {
+            Some(raw) => Ok(raw.to_string()),
+            None => Err(format!(
+                "The JSON of {} is not known, as it was not read from the API",
+                server.domain
+            )
+            .into()),
+        })
+    }
+
+    #[cfg(not(feature = "raw-json"))]
+    fn format(
+        &self,
+        _report: &Report<'_>,
+        _options: &FormatOptions,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        Err("This version of nordselect was built without the raw-json feature".into())
+    }
+}
+
+/// Formatters by the name they are asked for with.
+pub struct Formatters {
+    /// The formatters, in the order they were registered.
+    formatters: Vec<(String, Box<dyn Formatter>)>,
+}
+
+/// Creates the formats of the command line tool: `text`, `table`, `json`, `csv`, `proxy`, `uri`
+/// and `raw-json`.
+impl Default for Formatters {
+    fn default() -> Formatters {
+        let mut formatters = Formatters::new();
+        formatters.register("text", Text);
+        formatters.register("table", Table);
+        formatters.register("json", Json);
+        formatters.register("csv", Csv);
+        formatters.register("proxy", Proxy);
+        formatters.register("uri", Uri);
+        formatters.register("raw-json", RawJson);
+        formatters
+    }
+}
+
+impl Formatters {
+    /// Creates a set without any formatters, not even the built-in ones.
+    pub fn new() -> Formatters {
+        Formatters {
+            formatters: Vec::new(),
+        }
+    }
+
+    /// Adds a formatter under the given name, replacing the one that had it before.
+    pub fn register(&mut self, name: impl Into<String>, formatter: impl Formatter + 'static) {
+        let name = name.into();
+        let formatter: Box<dyn Formatter> = Box::new(formatter);
+        match self.formatters.iter_mut().find(|(known, _)| *known == name) {
+            Some(entry) => entry.1 = formatter,
+            None => self.formatters.push((name, formatter)),
+        }
+    }
+
+    /// Returns the formatter with the given name.
+    pub fn get(&self, name: &str) -> Option<&dyn Formatter> {
+        self.formatters
+            .iter()
+            .find(|(known, _)| known == name)
+            .map(|(_, formatter)| formatter.as_ref())
+    }
+
+    /// Returns the names of all formatters, in the order they were registered.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.formatters.iter().map(|(name, _)| name.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A cell of a table, optionally colored using an ANSI escape code.
+pub struct Cell {
+    /// What the cell shows.
+    pub text: String,
+    /// The ANSI color code of the text, e.g. `32` for green.
+    pub color: Option<&'static str>,
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Cell {
+        Cell { text, color: None }
+    }
+}
+
+impl Cell {
+    /// Returns the load as a cell, colored green, yellow or red depending on how high it is.
+    pub fn load(load: u8) -> Cell {
+        let color = if load < LOW_LOAD {
+            "32"
+        } else if load < MODERATE_LOAD {
+            "33"
+        } else {
+            "31"
+        };
+
+        Cell {
+            text: format!("{}%", load),
+            color: Some(color),
+        }
+    }
+}
+
+/// Returns the cells describing a server in a table, below [SERVER_COLUMNS](constant.SERVER_COLUMNS.html).
+pub fn server_cells(server: &Server, options: &FormatOptions) -> Vec<Cell> {
+    let bandwidth = match server.bandwidth_tier {
+        Some(bandwidth) => match (bandwidth / 1000, bandwidth % 1000) {
+            (gbps, 0) if gbps > 0 => format!("{} Gbps", gbps),
+            _ => format!("{} Mbps", bandwidth),
+        },
+        None => String::from("-"),
+    };
+
+    vec![
+        Cell::from(options.server_name(server).to_string()),
+        Cell::from(server.flag.to_string()),
+        Cell::from(server.city.clone().unwrap_or_else(|| String::from("-"))),
+        Cell::load(server.load),
+        Cell::from(bandwidth),
+    ]
+}
+
+/// Returns the rows with their columns aligned, below a header, one line per row.
+pub fn render_table(header: &[&str], rows: Vec<Vec<Cell>>, color: bool) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|title| title.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.text.chars().count());
+        }
+    }
+
+    let header = header
+        .iter()
+        .map(|title| Cell::from(title.to_string()))
+        .collect();
+    let mut lines = Vec::with_capacity(rows.len() + 1);
+    for row in std::iter::once(header).chain(rows) {
+        let mut line = String::new();
+        for (number, (cell, width)) in row.iter().zip(&widths).enumerate() {
+            if number > 0 {
+                line.push_str("  ");
+            }
+            match cell.color {
+                Some(code) if color => {
+                    line.push_str(&format!("\x1b[{}m{}\x1b[0m", code, cell.text))
+                }
+                _ => line.push_str(&cell.text),
+            }
+            // The last column is not padded, to not leave spaces at the end of the line.
+            if number + 1 < row.len() {
+                line.push_str(&" ".repeat(width - cell.text.chars().count()));
+            }
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Servers;
+
+    fn format(name: &str, report: &Report<'_>, options: &FormatOptions) -> String {
+        Formatters::default()
+            .get(name)
+            .unwrap()
+            .format(report, options)
+            .unwrap()
+    }
+
+    #[test]
+    fn built_in_formats() {
+        let mut data = Servers::dummy_data();
+        data.servers.truncate(2);
+        data.servers[0].city = Some(String::from("Brussels, \"Centre\""));
+        data.servers[0].load = 75;
+        data.servers[0].features.socks = true;
+        data.servers[1].features.socks = false;
+        let report = Report::new(data.servers.iter().collect());
+        let options = FormatOptions::default();
+
+        let names = format("text", &report, &options);
+        assert_eq!(names.lines().count(), 2);
+        assert_eq!(
+            names.lines().next(),
+            Some(options.server_name(&data.servers[0]))
+        );
+
+        let csv = format("csv", &report, &options);
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("server,country,city,load,bandwidth"));
+        assert!(lines
+            .next()
+            .unwrap()
+            .contains(",\"Brussels, \"\"Centre\"\"\",75,"));
+
+        let table = format("table", &report, &options);
+        assert!(table.starts_with("SERVER"));
+        assert!(!table.contains('\x1b'));
+        let colored = FormatOptions {
+            color: true,
+            ..FormatOptions::default()
+        };
+        assert!(format("table", &report, &colored).contains("\x1b[31m75%\x1b[0m"));
+
+        let mut report = Report::new(vec![&data.servers[0]]);
+        report
+            .details
+            .insert(String::from("took_ms"), Value::from(3));
+        let json: Value = serde_json::from_str(&format("json", &report, &options)).unwrap();
+        assert_eq!(json["domain"], data.servers[0].domain.as_str());
+        assert_eq!(json["applied_filters"], Value::Array(Vec::new()));
+        assert_eq!(json["took_ms"], 3);
+        assert!(json.get("alternates").is_none());
+
+        let proxy = FormatOptions {
+            proxy_user: Some(String::from("me")),
+            ..FormatOptions::default()
+        };
+        assert!(format("proxy", &report, &proxy).starts_with("socks5://me@"));
+        let report = Report::new(vec![&data.servers[1]]);
+        let err = Proxy.format(&report, &options).unwrap_err();
+        assert!(err.to_string().contains("socks"), "{}", err);
+    }
+
+    #[test]
+    fn registering_replaces() {
+        let mut formatters = Formatters::default();
+        let count = formatters.names().count();
+        formatters.register("text", Uri);
+        formatters.register("uris", Uri);
+        assert_eq!(formatters.names().count(), count + 1);
+        assert_eq!(formatters.names().next(), Some("text"));
+
+        let data = Servers::dummy_data();
+        let report = Report::new(vec![&data.servers[0]]);
+        let text = formatters
+            .get("text")
+            .unwrap()
+            .format(&report, &FormatOptions::default())
+            .unwrap();
+        assert_eq!(text, data.servers[0].nordvpn_uri());
+        assert!(formatters.get("yaml").is_none());
+    }
+}
@@ -0,0 +1,464 @@
+//! Named groups of filters and settings, selected with `--profile`, e.g. `us-streaming`.
+//!
+//! A few profiles are built in. More can be defined, and the built-in ones overridden, in a
+//! profile file in the configuration directory. At the top of the file, every line defines a
+//! profile: its name, `=` and its filters separated by spaces, written the way they are given on
+//! the command line. Empty lines and lines starting with `#` are ignored.
+//!
+//! Profiles that need more than filters get a section of their own, `[profile.NAME]`, of
+//! `KEY = VALUE` lines. The `filters` key holds the filters, other keys are settings named after
+//! the options of the command line, e.g. `tries = 3` or `ping = true`. Every profile inherits
+//! the settings of the `[defaults]` section, unless it sets them itself, and its filters are
+//! added to the ones of the defaults.
+//!
+//! The `[scoring]` section sets how much the load, the latency and the distance of servers weigh
+//! when they are ranked, see [ScoringWeights](../bench/struct.ScoringWeights.html). It applies to
+//! every selection, not only to profiles.
+//!
+//! The `[regions]` section defines regions that can be used as filters, like the built-in ones.
+//! Every line holds a name, `=` and the countries and regions in it, separated by spaces. Those
+//! starting with `-` are left out again, e.g. `eea -14e`. A region can use the regions defined
+//! above it. Countries and built-in regions go before custom regions with the same name.
+//!
+//! ```text
+//! # My ISP throttles VPN traffic, so stream over obfuscated servers.
+//! us-streaming = us obfuscated maxload:40
+//!
+//! [defaults]
+//! filters = !ru
+//! tries = 3
+//!
+//! [profile.torrent]
+//! filters = p2p nl
+//! ping = true
+//!
+//! [scoring]
+//! load_weight = 0.6
+//! ping_weight = 0.4
+//!
+//! [regions]
+//! nordics = dk fi is no se
+//! private-eea = eea -14e
+//! ```
+use crate::bench::ScoringWeights;
+use crate::filters::Region;
+use crate::servers::Country;
+use serde_json::{self, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The profiles that are available without a profile file, in the format of that file.
+const BUILT_IN: &str = "\
+us-streaming = us standard maxload:40
+uk-streaming = uk standard maxload:40
+jp-streaming = jp standard maxload:40
+";
+
+/// The key of the filters in a section of a profile file.
+const FILTERS_KEY: &str = "filters";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// The filters and settings of a profile.
+pub struct Profile {
+    /// The filters, written the way they are given on the command line.
+    pub filters: Vec<String>,
+    /// The other settings, by their key in lowercase, e.g. `tries`.
+    pub settings: BTreeMap<String, String>,
+}
+
+impl Profile {
+    /// Returns this profile on top of the given one: the filters of both, and the settings of
+    /// the given one that this profile does not set.
+    fn inherit(&self, parent: &Profile) -> Profile {
+        let mut settings = parent.settings.clone();
+        settings.extend(self.settings.clone());
+        Profile {
+            filters: parent
+                .filters
+                .iter()
+                .chain(&self.filters)
+                .cloned()
+                .collect(),
+            settings,
+        }
+    }
+}
+
+/// Named groups of filters and settings.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::profiles::Profiles;
+///
+/// let mut profiles = Profiles::default();
+/// assert!(profiles.get("us-streaming").unwrap().contains(&String::from("us")));
+///
+/// profiles.read("us-streaming = us obfuscated\nbenelux-p2p = benelux p2p").unwrap();
+/// assert_eq!(profiles.get("US-Streaming").unwrap(), ["us", "obfuscated"]);
+/// assert_eq!(profiles.get("benelux-p2p").unwrap(), ["benelux", "p2p"]);
+///
+/// profiles
+///     .read("[defaults]\nfilters = !ru\ntries = 3\n[profile.torrent]\nfilters = p2p\nping = true")
+///     .unwrap();
+/// let torrent = profiles.profile("torrent").unwrap();
+/// assert_eq!(torrent.filters, ["!ru", "p2p"]);
+/// assert_eq!(torrent.settings["tries"], "3");
+/// assert_eq!(torrent.settings["ping"], "true");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profiles {
+    /// Every profile, by its name in lowercase, without the defaults.
+    profiles: BTreeMap<String, Profile>,
+    /// What every profile inherits.
+    defaults: Profile,
+    /// The values in the `[scoring]` section by their key, if there is one.
+    scoring: Option<BTreeMap<String, String>>,
+    /// The countries and regions of the custom regions by their name in lowercase, in the order
+    /// they are defined.
+    regions: Vec<(String, Vec<String>)>,
+}
+
+/// The part of a profile file a line is in.
+enum Section {
+    /// The top, defining a profile per line.
+    Top,
+    /// `[defaults]`.
+    Defaults,
+    /// `[profile.NAME]`, with the name in lowercase.
+    Profile(String),
+    /// `[scoring]`.
+    Scoring,
+    /// `[regions]`.
+    Regions,
+}
+
+/// Creates the built-in profiles.
+impl Default for Profiles {
+    fn default() -> Profiles {
+        let mut profiles = Profiles::new();
+        // The built-in profiles are known to be valid.
+        profiles.read(BUILT_IN).unwrap();
+        profiles
+    }
+}
+
+impl Profiles {
+    /// Creates a set without any profiles, not even the built-in ones.
+    pub fn new() -> Profiles {
+        Profiles {
+            profiles: BTreeMap::new(),
+            defaults: Profile::default(),
+            scoring: None,
+            regions: Vec::new(),
+        }
+    }
+
+    /// Returns the location of the profile file in the configuration directory of the current
+    /// user (e.g. `~/.config/nordselect/profiles` on Linux).
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("nordselect").join("profiles"))
+    }
+
+    /// Adds the profiles defined in the given text, in the format of a profile file. Profiles
+    /// that were already known are replaced, settings of the defaults are added.
+    pub fn read(&mut self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut section = Section::Top;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(header) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                let header = header.trim().to_lowercase();
+                section = match header.strip_prefix("profile.") {
+                    _ if header == "defaults" => Section::Defaults,
+                    _ if header == "scoring" => {
+                        self.scoring.get_or_insert_with(BTreeMap::new);
+                        Section::Scoring
+                    }
+                    _ if header == "regions" => Section::Regions,
+                    Some(name) if !name.is_empty() => {
+                        self.profiles.insert(name.to_string(), Profile::default());
+                        Section::Profile(name.to_string())
+                    }
+                    _ => {
+                        return Err(format!(
+                            "line {}: expected [defaults], [scoring], [regions] or [profile.NAME]",
+                            number + 1
+                        )
+                        .into())
+                    }
+                };
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or_default().trim().to_lowercase();
+            let value = match (parts.next(), &section) {
+                (Some(value), _) if !key.is_empty() => value.trim(),
+                (_, Section::Top) => {
+                    return Err(format!("line {}: expected NAME = FILTERS", number + 1).into())
+                }
+                _ => return Err(format!("line {}: expected KEY = VALUE", number + 1).into()),
+            };
+            let filters = || value.split_whitespace().map(String::from).collect();
+            let profile = match &section {
+                Section::Top => {
+                    self.profiles.insert(
+                        key,
+                        Profile {
+                            filters: filters(),
+                            settings: BTreeMap::new(),
+                        },
+                    );
+                    continue;
+                }
+                Section::Defaults => &mut self.defaults,
+                // The section inserted the profile.
+                Section::Profile(name) => self.profiles.get_mut(name).unwrap(),
+                Section::Scoring => {
+                    // The section inserted the values.
+                    let scoring = self.scoring.as_mut().unwrap();
+                    scoring.insert(key, value.to_string());
+                    continue;
+                }
+                Section::Regions => {
+                    // A region defined again is replaced.
+                    self.regions.retain(|(name, _)| *name != key);
+                    self.regions.push((key, filters()));
+                    continue;
+                }
+            };
+            if key == FILTERS_KEY {
+                profile.filters = filters();
+            } else {
+                profile.settings.insert(key, value.to_string());
+            }
+        }
+
+        if let Err(err) = self.scoring() {
+            return Err(format!("[scoring]: {}", err).into());
+        }
+        if let Err(err) = self.regions() {
+            return Err(format!("[regions]: {}", err).into());
+        }
+        Ok(())
+    }
+
+    /// Adds the profiles defined in the file at the given path. A missing file defines none.
+    pub fn load(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if path.is_file() {
+            self.read(&fs::read_to_string(path)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the filters of the profile with the given name, ignoring case, after the ones of
+    /// the defaults.
+    pub fn get(&self, name: &str) -> Option<Vec<String>> {
+        self.profile(name).map(|profile| profile.filters)
+    }
+
+    /// Returns the profile with the given name, ignoring case, including what it inherits from
+    /// the defaults.
+    pub fn profile(&self, name: &str) -> Option<Profile> {
+        let profile = self.profiles.get(&name.to_lowercase())?;
+        Some(profile.inherit(&self.defaults))
+    }
+
+    /// Returns the weights set in the `[scoring]` section, if there is one. Weights it does not
+    /// set keep their default.
+    pub fn scoring(&self) -> Result<Option<ScoringWeights>, Box<dyn std::error::Error>> {
+        let scoring = match &self.scoring {
+            Some(scoring) => scoring,
+            None => return Ok(None),
+        };
+
+        // Values are read as JSON where possible, so numbers are numbers.
+        let fields = scoring
+            .iter()
+            .map(|(key, value)| {
+                let value = serde_json::from_str(value).unwrap_or_else(|_| Value::from(&**value));
+                (key.clone(), value)
+            })
+            .collect();
+        Ok(Some(serde_json::from_value(Value::Object(fields))?))
+    }
+
+    /// Returns the regions defined in the `[regions]` section, in the order they are defined.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::profiles::Profiles;
+    ///
+    /// let mut profiles = Profiles::new();
+    /// profiles.read("[regions]\nprivate-eea = eea -14e\nalps = at ch li").unwrap();
+    /// let regions = profiles.regions().unwrap();
+    /// assert_eq!(regions[0].short(), "private-eea");
+    /// assert!(!regions[0].countries().contains(&"DE".parse().unwrap()));
+    /// assert_eq!(regions[1].countries(), ["AT", "CH", "LI"]);
+    /// ```
+    pub fn regions(&self) -> Result<Vec<Region>, Box<dyn std::error::Error>> {
+        let mut regions: Vec<Region> = Vec::new();
+        for (name, terms) in &self.regions {
+            let mut region = Region::custom(name.as_str(), Vec::new());
+            for term in terms {
+                let (term, left_out) = match term.strip_prefix('-') {
+                    Some(term) => (term.to_lowercase(), true),
+                    None => (term.to_lowercase(), false),
+                };
+                let part = if let Ok(country) = term.parse::<Country>() {
+                    Region::custom(term.as_str(), vec![country])
+                } else if let Some(region) = Region::from_str(&term.to_uppercase()) {
+                    region
+                } else if let Some(region) = regions.iter().find(|region| region.short() == term) {
+                    region.clone()
+                } else {
+                    return Err(format!("{}: unknown country or region \"{}\"", name, term).into());
+                };
+                region = if left_out {
+                    region.minus(&part)
+                } else {
+                    Region::union(&[region, part])
+                };
+            }
+            regions.push(Region::custom(name.as_str(), region.countries()));
+        }
+
+        Ok(regions)
+    }
+
+    /// Returns the names of all profiles, sorted.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli_help::{self, Aliases};
+    use crate::Servers;
+
+    #[test]
+    fn built_in_profiles_parse() {
+        let data = Servers::dummy_data();
+        let profiles = Profiles::default();
+        for name in profiles.names() {
+            let filters = profiles.get(name).unwrap();
+            let filters = filters.iter().map(String::as_str);
+            assert!(
+                cli_help::check_filters(filters, Some(&data), &Aliases::default()).is_ok(),
+                "{} has unknown filters",
+                name
+            );
+        }
+
+        let mut profiles = Profiles::new();
+        assert!(profiles.read("# comment\n\nempty =").is_ok());
+        assert_eq!(profiles.get("empty").unwrap().len(), 0);
+        assert!(profiles.read("us standard").is_err());
+        assert!(profiles.read(" = us").is_err());
+    }
+
+    #[test]
+    fn sections_inherit_defaults() {
+        let mut profiles = Profiles::new();
+        profiles
+            .read(
+                "plain = be\n\
+                 [profile.work]\n\
+                 filters = nl tcp\n\
+                 tries = 5\n\
+                 [Defaults]\n\
+                 filters = !ru\n\
+                 tries = 3\n\
+                 nearest-region = true\n",
+            )
+            .unwrap();
+
+        let work = profiles.profile("WORK").unwrap();
+        assert_eq!(work.filters, ["!ru", "nl", "tcp"]);
+        assert_eq!(work.settings["tries"], "5");
+        assert_eq!(work.settings["nearest-region"], "true");
+        let plain = profiles.profile("plain").unwrap();
+        assert_eq!(plain.filters, ["!ru", "be"]);
+        assert_eq!(plain.settings["tries"], "3");
+
+        // A section replaces a profile defined earlier.
+        profiles.read("[profile.plain]\nping = true").unwrap();
+        assert_eq!(profiles.get("plain").unwrap(), ["!ru"]);
+
+        assert!(profiles.read("[profile.]").is_err());
+        assert!(profiles.read("[other]").is_err());
+        assert!(profiles.read("[defaults]\ntries").is_err());
+    }
+
+    #[test]
+    fn scoring_section() {
+        let mut profiles = Profiles::default();
+        assert_eq!(profiles.scoring().unwrap(), None);
+
+        profiles
+            .read("[scoring]\nload_weight = 0.6\nping_weight = 0.4\n[profile.work]\nping = true")
+            .unwrap();
+        let weights = profiles.scoring().unwrap().unwrap();
+        assert_eq!(weights.load_weight, 0.6);
+        assert_eq!(weights.ping_weight, 0.4);
+        assert_eq!(weights.distance_weight, 0f64);
+        assert!(profiles
+            .profile("work")
+            .unwrap()
+            .settings
+            .contains_key("ping"));
+
+        assert!(Profiles::new().read("[scoring]\nping_weight = fast").is_err());
+        assert!(Profiles::new().read("[scoring]\nlatency_weight = 1").is_err());
+    }
+
+    #[test]
+    fn regions_section() {
+        let mut profiles = Profiles::default();
+        assert!(profiles.regions().unwrap().is_empty());
+
+        profiles
+            .read(
+                "[regions]\n\
+                 nordics = dk fi is no se\n\
+                 Private = eea -14e -nordics ch\n\
+                 [profile.work]\n\
+                 filters = private",
+            )
+            .unwrap();
+        let regions = profiles.regions().unwrap();
+        assert_eq!(regions[0].countries(), ["DK", "FI", "IS", "NO", "SE"]);
+        assert_eq!(regions[1].short(), "private");
+        let private = regions[1].countries();
+        assert!(private.contains(&"AT".parse().unwrap()));
+        assert!(private.contains(&"CH".parse().unwrap()));
+        assert!(!private.contains(&"BE".parse().unwrap()));
+        assert!(!private.contains(&"FI".parse().unwrap()));
+
+        let mut aliases = Aliases::default();
+        for region in regions {
+            aliases.add_region(region);
+        }
+        let data = Servers::dummy_data();
+        let filters = profiles.get("work").unwrap();
+        let filters = filters.iter().map(String::as_str);
+        assert!(cli_help::check_filters(filters, Some(&data), &aliases).is_ok());
+
+        assert!(Profiles::new().read("[regions]\nbad = be xx").is_err());
+        assert!(Profiles::new()
+            .read("[regions]\nlater = first\nfirst = be")
+            .is_err());
+    }
+}
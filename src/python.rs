@@ -0,0 +1,236 @@
+//! Python bindings, so scripts can select servers without parsing the output of the command line
+//! tool. Only available with the `python` feature.
+//!
+//! The module is built with [maturin](https://www.maturin.rs), using the `pyproject.toml` in the
+//! root of the repository:
+//!
+//! ```text
+//! maturin develop --release
+//! ```
+//!
+//! ```text
+//! import nordselect
+//!
+//! servers = nordselect.Servers.from_api()
+//! best = servers.select(["nl", "p2p"], alternates=2)
+//! print(best[0].domain, best[0].load)
+//! ```
+//!
+//! Filters are written the way they are given on the command line. Invalid filters raise a
+//! `ValueError`, failing downloads and other problems a `RuntimeError`.
+use crate::cli_help::{self, Aliases, FilterError};
+use crate::run::{self, SelectOptions};
+use crate::servers::{Server, Servers};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+/// Turns an error of this library into a Python exception.
+fn to_py_err(err: Box<dyn std::error::Error>) -> PyErr {
+    match err.downcast_ref::<FilterError>() {
+        Some(err) => PyValueError::new_err(err.to_string()),
+        None => PyRuntimeError::new_err(err.to_string()),
+    }
+}
+
+/// A server by NordVPN, as seen from Python.
+#[pyclass(name = "Server", module = "nordselect")]
+#[derive(Clone)]
+pub struct PyServer {
+    server: Server,
+}
+
+#[pymethods]
+impl PyServer {
+    /// The domain of the server, e.g. `be12.nordvpn.com`.
+    #[getter]
+    fn domain(&self) -> &str {
+        &self.server.domain
+    }
+
+    /// The name of the server, e.g. `be12`.
+    #[getter]
+    fn name(&self) -> Option<&str> {
+        self.server.name()
+    }
+
+    /// The code of the country the server is in, e.g. `BE`.
+    #[getter]
+    fn flag(&self) -> &str {
+        self.server.flag.as_str()
+    }
+
+    /// The city the server is in, if known.
+    #[getter]
+    fn city(&self) -> Option<&str> {
+        self.server.city.as_deref()
+    }
+
+    /// The current load of the server, in percent.
+    #[getter]
+    fn load(&self) -> u8 {
+        self.server.load
+    }
+
+    /// The categories of the server, named like their filters, e.g. `p2p`.
+    #[getter]
+    fn categories(&self) -> Vec<&'static str> {
+        self.server
+            .categories
+            .iter()
+            .map(|category| cli_help::category_name(&category))
+            .collect()
+    }
+
+    /// The `nordvpn://` link connecting to the server.
+    fn nordvpn_uri(&self) -> String {
+        self.server.nordvpn_uri()
+    }
+
+    /// The server as JSON, in the format of stored server lists.
+    fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string(&self.server).map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Server(domain={:?}, load={})",
+            self.server.domain, self.server.load
+        )
+    }
+}
+
+/// A list of servers, as seen from Python.
+#[pyclass(name = "Servers", module = "nordselect")]
+pub struct PyServers {
+    servers: Servers,
+}
+
+#[pymethods]
+impl PyServers {
+    /// Downloads the server list from the NordVPN API.
+    #[staticmethod]
+    fn from_api(py: Python<'_>) -> PyResult<PyServers> {
+        // Other Python threads can run during the download.
+        let servers = py.allow_threads(|| Servers::from_api().map_err(|err| err.to_string()));
+        match servers {
+            Ok(servers) => Ok(PyServers { servers }),
+            Err(err) => Err(PyRuntimeError::new_err(err)),
+        }
+    }
+
+    /// Reads a server list from JSON, such as a saved response of the API.
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<PyServers> {
+        let servers = Servers::from_reader(json.as_bytes()).map_err(to_py_err)?;
+        Ok(PyServers { servers })
+    }
+
+    /// Returns the servers passing the filters, in the order of the list. Unlike `select`,
+    /// servers of every category are kept when none is asked for.
+    fn filter(&self, filters: Vec<String>) -> PyResult<PyServers> {
+        let filters = cli_help::parse_filters(
+            filters.iter().map(String::as_str),
+            &self.servers,
+            &Aliases::default(),
+            false,
+        )
+        .map_err(|err| PyValueError::new_err(err.to_string()))?;
+
+        let mut servers = Servers {
+            servers: self.servers.servers.clone(),
+        };
+        for filter in filters {
+            servers.filter(filter.as_ref());
+        }
+        Ok(PyServers { servers })
+    }
+
+    /// Selects the best server passing the filters, like the command line tool does, followed by
+    /// up to `alternates` next best ones. Returns an empty list when no server passes them.
+    #[pyo3(signature = (filters, alternates = 0))]
+    fn select(&self, filters: Vec<String>, alternates: usize) -> PyResult<Vec<PyServer>> {
+        let options = SelectOptions::new(&self.servers)
+            .filters(filters)
+            .alternates(alternates);
+        let outcome = run::select(options).map_err(to_py_err)?;
+        Ok(outcome
+            .ranked()
+            .servers
+            .into_iter()
+            .map(|server| PyServer { server })
+            .collect())
+    }
+
+    /// Returns the codes of the countries with servers, sorted.
+    fn flags(&self) -> Vec<String> {
+        let mut flags: Vec<String> = self.servers.flags().into_iter().map(String::from).collect();
+        flags.sort();
+        flags
+    }
+
+    /// Returns every server in the list.
+    fn servers(&self) -> Vec<PyServer> {
+        self.servers
+            .servers
+            .iter()
+            .cloned()
+            .map(|server| PyServer { server })
+            .collect()
+    }
+
+    fn __len__(&self) -> usize {
+        self.servers.servers.len()
+    }
+}
+
+/// The `nordselect` Python module.
+#[pymodule]
+fn nordselect(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyServer>()?;
+    module.add_class::<PyServers>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::ffi::c_str;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn select_from_python() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "nordselect").unwrap();
+            nordselect(&module).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("nordselect", module).unwrap();
+            globals
+                .set_item("json", std::fs::read_to_string("dummydata").unwrap())
+                .unwrap();
+
+            py.run(
+                c_str!(
+                    r#"
+servers = nordselect.Servers.from_json(json)
+best = servers.select(["be", "tcp"], alternates=2)
+assert len(best) == 3, best
+assert all(server.flag == "BE" for server in best)
+assert best[0].load <= best[1].load
+assert "standard" in best[0].categories
+assert len(servers.filter(["p2p"])) < len(servers)
+
+try:
+    servers.select(["nowhere"])
+    raise AssertionError("unknown filter accepted")
+except ValueError as err:
+    assert "nowhere" in str(err)
+"#
+                ),
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+}
@@ -0,0 +1,777 @@
+//! Selecting servers the way the command line tool does, so other frontends and tests can do
+//! exactly the same without starting it.
+//!
+//! # Example
+//!
+//! ```
+//! use nordselect::run::{self, SelectOptions};
+//! use nordselect::Servers;
+//!
+//! let data = Servers::dummy_data();
+//! let options = SelectOptions::new(&data).filters(vec!["be", "udp"]).alternates(2);
+//! let outcome = run::select(options).unwrap();
+//!
+//! assert_eq!(outcome.server.unwrap().flag, "BE");
+//! assert_eq!(outcome.alternates.len(), 2);
+//! assert_eq!(outcome.applied_filters.len(), 3);
+//! ```
+use crate::audit::{SelectionLogger, SelectionRecord};
+use crate::bench::{
+    Benchmarker, ErrorPolicy, LoadBenchmarker, PenaltyBenchmarker, Score, ScoredServer,
+};
+use crate::cli_help::{self, Aliases, LabelledFilter};
+use crate::failures::FailureLog;
+use crate::filters::{apply_all, CountryFilter, Filter};
+use crate::selection::SelectionBuilder;
+use crate::servers::{Country, Domain, SampleStrategy, Server, Servers};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// How many servers a filter left, as traced by [select](fn.select.html).
+pub struct FilterTrace {
+    /// The filter as it was given, e.g. `p2p`.
+    pub label: String,
+    /// The amount of servers before the filter was applied.
+    pub before: usize,
+    /// The amount of servers that passed the filter.
+    pub after: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// How long the phases of a selection took, as measured by [select](fn.select.html).
+pub struct Timings {
+    /// Applying the filters.
+    pub filter: Duration,
+    /// Looking for the nearest country, pinging and otherwise scoring the servers.
+    pub benchmark: Duration,
+}
+
+#[derive(Debug)]
+/// What a selection found, as returned by [select](fn.select.html).
+pub struct SelectionOutcome {
+    /// The best server, if any server passed the filters.
+    pub server: Option<Server>,
+    /// The score of the best server, if it was scored.
+    pub score: Option<Score>,
+    /// The next best servers, best first, e.g. to fall back to when the best one is unreachable.
+    pub alternates: Vec<Server>,
+    /// The descriptions of the filters every server had to pass.
+    pub applied_filters: Vec<String>,
+    /// How many servers every filter left, in the order they were applied. Only filled in when
+    /// tracing.
+    pub trace: Vec<FilterTrace>,
+    /// The country the servers were selected from, if the nearest country was looked for and
+    /// found.
+    pub nearest_country: Option<Country>,
+    /// Why the nearest country could not be found, if looking for it failed.
+    pub nearest_country_error: Option<Box<dyn std::error::Error>>,
+    /// Why the ping test failed, if it did. The servers are then ordered by the fallback.
+    pub ping_error: Option<Box<dyn std::error::Error>>,
+    /// Why the selection could not be logged, if logging it failed.
+    pub log_error: Option<Box<dyn std::error::Error>>,
+    /// Whether the server given to
+    /// [SelectOptions::better_than](struct.SelectOptions.html#method.better_than) was kept,
+    /// because no server beat it by the margin.
+    pub kept: bool,
+    /// How long the selection took.
+    pub elapsed: Duration,
+    /// How long every phase of the selection took.
+    pub timings: Timings,
+}
+
+impl SelectionOutcome {
+    /// Returns the best server followed by the alternates.
+    pub fn ranked(&self) -> Servers {
+        Servers {
+            servers: self
+                .server
+                .iter()
+                .chain(&self.alternates)
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// What [select](fn.select.html) would do with some options, as returned by
+/// [plan](fn.plan.html).
+pub struct Plan {
+    /// The filters, in the order they are applied, as the label they were given with and their
+    /// description.
+    pub filters: Vec<(String, String)>,
+    /// Whether only servers in the nearest country are selected.
+    pub nearest_country: bool,
+    /// How many of the least loaded servers the ping test measures, and what happens to servers
+    /// it could not measure, if a ping test is done.
+    pub ping: Option<(usize, ErrorPolicy)>,
+    /// Whether the servers are ordered by another benchmarker than their load, when no ping test
+    /// is done or when it fails.
+    pub custom_fallback: bool,
+    /// The penalty per reported failure in percent and the period failures count in, if any
+    /// failures were reported.
+    pub failure_penalty: Option<(u32, Duration)>,
+    /// How many servers passing the filters are sampled and how, if any.
+    pub sample: Option<(usize, SampleStrategy)>,
+    /// How many servers are selected, if limited.
+    pub take: Option<usize>,
+    /// How long benchmarking may take from now on, if limited.
+    pub deadline: Option<Duration>,
+    /// The domain of the server that is kept unless another one beats it, and the margin in
+    /// percent.
+    pub better_than: Option<(Domain, u8)>,
+}
+
+/// Everything that decides how [select](fn.select.html) picks servers. By default, the least
+/// loaded standard server passing the filters is selected, without alternates.
+pub struct SelectOptions<'a> {
+    /// The servers to select from.
+    servers: &'a Servers,
+    /// The filters as given on the command line, e.g. `p2p` or `!be`.
+    filters: Vec<String>,
+    /// The aliases the filters may use.
+    aliases: Aliases,
+    /// Whether only standard servers are selected when no category is asked for.
+    default_to_standard: bool,
+    /// Filters applied on top of the parsed ones.
+    extra_filters: Vec<LabelledFilter>,
+    /// Whether the filters are applied one by one, counting the servers every one leaves.
+    trace: bool,
+    /// The benchmarker to find the nearest country with, if the servers should be in it.
+    nearest_country: Option<Box<dyn Benchmarker + 'a>>,
+    /// The ping test, how many of the least loaded servers it measures and what happens to
+    /// servers it could not measure.
+    ping: Option<(Box<dyn Benchmarker + 'a>, usize, ErrorPolicy)>,
+    /// The benchmarker used without or after a failed ping test.
+    fallback: Option<Box<dyn Benchmarker + 'a>>,
+    /// The reported failures, the penalty per failure in percent and the period they count in.
+    failures: Option<(&'a FailureLog, u32, Duration)>,
+    /// How many servers are selected, if limited.
+    take: Option<usize>,
+    /// The seed used to order equally good servers, if any.
+    seed: Option<u64>,
+    /// How many servers passing the filters are sampled, how, and using which seed, if any.
+    sample: Option<(usize, SampleStrategy, u64)>,
+    /// When benchmarking should stop, if limited.
+    deadline: Option<Instant>,
+    /// The server that is kept unless another one beats its score by the margin, in percent.
+    better_than: Option<(Server, u8)>,
+    /// Where the selection is logged, if anywhere.
+    logger: Option<&'a dyn SelectionLogger>,
+}
+
+impl<'a> SelectOptions<'a> {
+    /// Selects from the given servers, using the default options.
+    pub fn new(servers: &'a Servers) -> SelectOptions<'a> {
+        SelectOptions {
+            servers,
+            filters: Vec::new(),
+            aliases: Aliases::default(),
+            default_to_standard: true,
+            extra_filters: Vec::new(),
+            trace: false,
+            nearest_country: None,
+            ping: None,
+            fallback: None,
+            failures: None,
+            take: Some(1),
+            seed: None,
+            sample: None,
+            deadline: None,
+            better_than: None,
+            logger: None,
+        }
+    }
+
+    /// Adds filters written like on the command line. See
+    /// [cli_help::parse_filters](../cli_help/fn.parse_filters.html).
+    pub fn filters<I, S>(mut self, filters: I) -> SelectOptions<'a>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.filters.extend(filters.into_iter().map(Into::into));
+        self
+    }
+
+    /// Lets the filters use the given aliases.
+    pub fn aliases(mut self, aliases: Aliases) -> SelectOptions<'a> {
+        self.aliases = aliases;
+        self
+    }
+
+    /// Decides whether only standard servers are selected when the filters ask for no category.
+    pub fn default_to_standard(mut self, default_to_standard: bool) -> SelectOptions<'a> {
+        self.default_to_standard = default_to_standard;
+        self
+    }
+
+    /// Adds a filter on top of the ones written like on the command line, labelled for tracing.
+    pub fn filter(
+        mut self,
+        label: impl Into<String>,
+        filter: impl Filter + 'static,
+    ) -> SelectOptions<'a> {
+        self.extra_filters.push((label.into(), Box::new(filter)));
+        self
+    }
+
+    /// Applies the filters one by one, counting how many servers every one of them leaves. See
+    /// [SelectionOutcome::trace](struct.SelectionOutcome.html#structfield.trace).
+    pub fn trace(mut self, trace: bool) -> SelectOptions<'a> {
+        self.trace = trace;
+        self
+    }
+
+    /// Only selects servers in the country of which the least loaded server passing the filters
+    /// gets the best score from the benchmarker. See
+    /// [Servers::nearest_country](../servers/struct.Servers.html#method.nearest_country).
+    pub fn nearest_country(mut self, benchmarker: impl Benchmarker + 'a) -> SelectOptions<'a> {
+        self.nearest_country = Some(Box::new(benchmarker));
+        self
+    }
+
+    /// Orders the `candidates` least loaded servers using a ping test. Should the test fail, the
+    /// servers are ordered by the fallback instead.
+    pub fn ping(
+        mut self,
+        benchmarker: impl Benchmarker + 'a,
+        candidates: usize,
+        on_error: ErrorPolicy,
+    ) -> SelectOptions<'a> {
+        self.ping = Some((Box::new(benchmarker), candidates, on_error));
+        self
+    }
+
+    /// Orders the servers using this benchmarker when no ping test is done or when it fails,
+    /// instead of on their load.
+    pub fn fallback(mut self, benchmarker: impl Benchmarker + 'a) -> SelectOptions<'a> {
+        self.fallback = Some(Box::new(benchmarker));
+        self
+    }
+
+    /// Makes servers with reported failures less attractive. See
+    /// [PenaltyBenchmarker](../bench/struct.PenaltyBenchmarker.html).
+    pub fn failures(
+        mut self,
+        failures: &'a FailureLog,
+        weight: u32,
+        window: Duration,
+    ) -> SelectOptions<'a> {
+        self.failures = Some((failures, weight, window));
+        self
+    }
+
+    /// Also returns up to `amount` next best servers.
+    pub fn alternates(mut self, amount: usize) -> SelectOptions<'a> {
+        self.take = Some(amount + 1);
+        self
+    }
+
+    /// Returns all servers passing the filters, best first.
+    pub fn all_alternates(mut self) -> SelectOptions<'a> {
+        self.take = None;
+        self
+    }
+
+    /// Orders servers with the same score and load using the given seed, instead of on their
+    /// domain.
+    pub fn seed(mut self, seed: u64) -> SelectOptions<'a> {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Only considers a sample of `amount` servers passing the filters. See
+    /// [Servers::sample](../servers/struct.Servers.html#method.sample).
+    pub fn sample(
+        mut self,
+        amount: usize,
+        strategy: SampleStrategy,
+        seed: u64,
+    ) -> SelectOptions<'a> {
+        self.sample = Some((amount, strategy, seed));
+        self
+    }
+
+    /// Stops benchmarking at the given moment, selecting among the servers scored so far.
+    pub fn deadline(mut self, deadline: Instant) -> SelectOptions<'a> {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Keeps the given server, e.g. the one you are connected to, unless it does not pass the
+    /// filters or the best server beats its score by more than `margin` percent. This prevents
+    /// reconnecting for a barely better server.
+    pub fn better_than(mut self, server: Server, margin: u8) -> SelectOptions<'a> {
+        self.better_than = Some((server, margin));
+        self
+    }
+
+    /// Logs the filters, the best server and its score once selected. See
+    /// [audit](../audit/index.html).
+    pub fn logger(mut self, logger: &'a dyn SelectionLogger) -> SelectOptions<'a> {
+        self.logger = Some(logger);
+        self
+    }
+}
+
+/// Wraps the benchmarker to take reported failures into account, if there are any.
+fn penalize<'b>(
+    benchmarker: &'b dyn Benchmarker,
+    failures: Option<(&'b FailureLog, u32, Duration)>,
+) -> Box<dyn Benchmarker + 'b> {
+    match failures {
+        Some((failures, weight, window)) if !failures.is_empty() => Box::new(
+            PenaltyBenchmarker::new(benchmarker, failures, weight, window),
+        ),
+        _ => Box::new(benchmarker),
+    }
+}
+
+/// Returns what [select](fn.select.html) would do with the options, without applying the filters
+/// or benchmarking any server.
+///
+/// Returns an error if the filters are invalid, which is a
+/// [FilterError](../cli_help/enum.FilterError.html).
+///
+/// # Example
+///
+/// ```
+/// use nordselect::run::{self, SelectOptions};
+/// use nordselect::Servers;
+///
+/// let data = Servers::dummy_data();
+/// let plan = run::plan(&SelectOptions::new(&data).filters(vec!["be", "p2p"])).unwrap();
+///
+/// let labels: Vec<&str> = plan.filters.iter().map(|(label, _)| label.as_str()).collect();
+/// assert_eq!(labels, vec!["p2p", "be"]);
+/// assert_eq!(plan.ping, None);
+/// assert_eq!(plan.take, Some(1));
+/// ```
+pub fn plan(options: &SelectOptions<'_>) -> Result<Plan, Box<dyn std::error::Error>> {
+    let parsed = cli_help::parse_labelled_filters(
+        options.filters.iter().map(String::as_str),
+        options.servers,
+        &options.aliases,
+        options.default_to_standard,
+    )?;
+    let mut filters: Vec<(&str, &dyn Filter)> = parsed
+        .iter()
+        .chain(&options.extra_filters)
+        .map(|(label, filter)| (label.as_str(), filter.as_ref()))
+        .collect();
+    if !options.trace {
+        filters.sort_by_key(|(_, filter)| filter.cost());
+    }
+    let mut filters: Vec<(String, String)> = filters
+        .into_iter()
+        .map(|(label, filter)| (label.to_string(), filter.describe()))
+        .collect();
+    let preferred =
+        cli_help::preferred_countries(options.filters.iter().map(String::as_str), &options.aliases);
+    if !preferred.is_empty() {
+        filters.push((
+            preference_label(&preferred),
+            describe_preference(&preferred),
+        ));
+    }
+
+    Ok(Plan {
+        filters,
+        nearest_country: options.nearest_country.is_some(),
+        ping: options
+            .ping
+            .as_ref()
+            .map(|(_, candidates, policy)| (*candidates, *policy)),
+        custom_fallback: options.fallback.is_some(),
+        failure_penalty: options
+            .failures
+            .filter(|(failures, _, _)| !failures.is_empty())
+            .map(|(_, weight, window)| (weight, window)),
+        sample: options
+            .sample
+            .map(|(amount, strategy, _)| (amount, strategy)),
+        take: options.take,
+        deadline: options
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now())),
+        better_than: options
+            .better_than
+            .as_ref()
+            .map(|(server, margin)| (server.domain.clone(), *margin)),
+    })
+}
+
+/// Selects servers like the command line tool does.
+///
+/// Returns an error if the filters are invalid, which is a
+/// [FilterError](../cli_help/enum.FilterError.html), or if the servers could not be ordered. A
+/// failing ping test or nearest country lookup is not an error, but is reported in the outcome.
+pub fn select(options: SelectOptions<'_>) -> Result<SelectionOutcome, Box<dyn std::error::Error>> {
+    let start = Instant::now();
+
+    let mut filters = cli_help::parse_labelled_filters(
+        options.filters.iter().map(String::as_str),
+        options.servers,
+        &options.aliases,
+        options.default_to_standard,
+    )?;
+    filters.extend(options.extra_filters);
+    let mut applied_filters: Vec<String> = filters
+        .iter()
+        .map(|(_, filter)| filter.describe())
+        .collect();
+    let preferred =
+        cli_help::preferred_countries(options.filters.iter().map(String::as_str), &options.aliases);
+    if !preferred.is_empty() {
+        applied_filters.push(describe_preference(&preferred));
+    }
+    let incumbent = options
+        .better_than
+        .filter(|(server, _)| filters.iter().all(|(_, filter)| filter.filter(server)));
+
+    // The benchmarkers live longer than the selection borrowing them, as the one that ordered the
+    // servers also scores the incumbent.
+    let failures = options.failures;
+    let ping = options
+        .ping
+        .as_ref()
+        .map(|(benchmarker, candidates, policy)| {
+            (
+                penalize(benchmarker.as_ref(), failures),
+                *candidates,
+                *policy,
+            )
+        });
+    let fallback = penalize(
+        match &options.fallback {
+            Some(fallback) => fallback.as_ref(),
+            None => &LoadBenchmarker,
+        },
+        failures,
+    );
+
+    // The filters are applied before the servers are scored, to time both. When tracing, they
+    // are applied one by one, to count what every one of them does. Otherwise, the cheapest ones
+    // go first.
+    let filter_start = Instant::now();
+    let mut trace = Vec::new();
+    let mut data = if options.trace {
+        let mut data = Servers {
+            servers: options.servers.servers.clone(),
+        };
+        for (label, filter) in filters {
+            let before = data.servers.len();
+            data.filter(filter.as_ref());
+            trace.push(FilterTrace {
+                label,
+                before,
+                after: data.servers.len(),
+            });
+        }
+        data
+    } else {
+        let mut filters: Vec<&dyn Filter> =
+            filters.iter().map(|(_, filter)| filter.as_ref()).collect();
+        filters.sort_by_key(|filter| filter.cost());
+        Servers {
+            servers: apply_all(&options.servers.servers, &filters),
+        }
+    };
+
+    // Of the preferred countries, only the first one that has servers left is selected from.
+    if !preferred.is_empty() {
+        let before = data.servers.len();
+        let country = preferred
+            .iter()
+            .find(|country| data.servers.iter().any(|server| server.flag == **country))
+            .copied();
+        data.servers.retain(|server| country == Some(server.flag));
+        if options.trace {
+            trace.push(FilterTrace {
+                label: preference_label(&preferred),
+                before,
+                after: data.servers.len(),
+            });
+        }
+    }
+    let timings_filter = filter_start.elapsed();
+
+    // The nearest country is looked for among the servers that match all filters.
+    let benchmark_start = Instant::now();
+    let mut nearest_country = None;
+    let mut nearest_country_error = None;
+    if let Some(benchmarker) = &options.nearest_country {
+        match data.nearest_country(benchmarker.as_ref()) {
+            Ok(Some(country)) => {
+                data.filter(&CountryFilter::from(country));
+                nearest_country = Some(country);
+            }
+            Ok(None) => {}
+            Err(err) => nearest_country_error = Some(err),
+        }
+    }
+    let data = &data;
+
+    let mut selection = SelectionBuilder::new();
+
+    if let Some(take) = options.take {
+        selection = selection.take(take);
+    }
+    if let Some(seed) = options.seed {
+        selection = selection.seed(seed);
+    }
+    if let Some((amount, strategy, seed)) = options.sample {
+        selection = selection.sample(amount, strategy, seed);
+    }
+    if let Some(deadline) = options.deadline {
+        selection = selection.deadline(deadline.saturating_duration_since(Instant::now()));
+    }
+
+    let mut ranked = None;
+    let mut ping_error = None;
+    let mut used: &dyn Benchmarker = &fallback;
+    if let Some((benchmarker, candidates, policy)) = &ping {
+        selection = selection
+            .benchmark(benchmarker)
+            .candidates(*candidates)
+            .on_error(*policy);
+        match selection.run_scored(data) {
+            Ok(selection) => {
+                ranked = Some(selection);
+                used = benchmarker;
+            }
+            Err(err) => ping_error = Some(err),
+        }
+    }
+    let mut ranked = match ranked {
+        Some(ranked) => ranked,
+        None => selection
+            .benchmark(&fallback)
+            .on_error(ErrorPolicy::TreatAsWorst)
+            .run_scored(data)?,
+    };
+
+    let mut kept = false;
+    if let Some((incumbent, margin)) = incumbent {
+        let single = Servers {
+            servers: vec![incumbent.clone()],
+        };
+        let score = used
+            .benchmark(&single)
+            .ok()
+            .and_then(|scores| scores.get(&incumbent.domain).copied());
+        kept = match (score, ranked.first().and_then(|best| best.score)) {
+            (Some(score), Some(best)) => !beats(best, score, margin),
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if kept {
+            ranked.retain(|scored| scored.server.domain != incumbent.domain);
+            ranked.insert(
+                0,
+                ScoredServer {
+                    score,
+                    server: incumbent,
+                },
+            );
+            if let Some(take) = options.take {
+                ranked.truncate(take);
+            }
+        }
+    }
+
+    let score = ranked.first().and_then(|best| best.score);
+    let mut servers = ranked.into_iter().map(|scored| scored.server);
+    let server = servers.next();
+    let log_error = options.logger.and_then(|logger| {
+        let record = SelectionRecord::new(&applied_filters, server.as_ref(), score);
+        logger.log(&record).err()
+    });
+    Ok(SelectionOutcome {
+        server,
+        score,
+        alternates: servers.collect(),
+        applied_filters,
+        trace,
+        nearest_country,
+        nearest_country_error,
+        ping_error,
+        log_error,
+        kept,
+        elapsed: start.elapsed(),
+        timings: Timings {
+            filter: timings_filter,
+            benchmark: benchmark_start.elapsed(),
+        },
+    })
+}
+
+/// Returns the preference for the given countries the way it is given on the command line, e.g.
+/// `prefer:nl,de`.
+fn preference_label(countries: &[Country]) -> String {
+    let countries: Vec<String> = countries
+        .iter()
+        .map(|country| country.as_str().to_lowercase())
+        .collect();
+    format!("{}{}", cli_help::PREFER_PREFIX, countries.join(","))
+}
+
+/// Describes the preference for the given countries like filters describe themselves.
+fn describe_preference(countries: &[Country]) -> String {
+    let countries: Vec<&str> = countries.iter().map(Country::as_str).collect();
+    format!("first of {} with servers left", countries.join(", "))
+}
+
+/// Returns whether the score is more than `margin` percent lower than the other one. Lower scores
+/// are better. Scores in different units never beat each other.
+fn beats(score: Score, other: Score, margin: u8) -> bool {
+    score.unit() == other.unit()
+        && u64::from(score.value()) * 100
+            < u64::from(other.value()) * (100 - u64::from(margin.min(100)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::Scores;
+    use crate::cli_help::FilterError;
+
+    /// Fails like a ping test without permissions.
+    struct FailingBenchmarker;
+
+    impl Benchmarker for FailingBenchmarker {
+        fn benchmark(&self, _: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+            Err("no permission to ping".into())
+        }
+    }
+
+    #[test]
+    fn trace_and_fall_back() {
+        let data = Servers::dummy_data();
+        let outcome = select(
+            SelectOptions::new(&data)
+                .filters(vec!["us", "tcp"])
+                .trace(true)
+                .ping(FailingBenchmarker, 10, ErrorPolicy::Abort)
+                .all_alternates(),
+        )
+        .unwrap();
+
+        assert!(outcome.ping_error.is_some());
+        let labels: Vec<&str> = outcome
+            .trace
+            .iter()
+            .map(|trace| trace.label.as_str())
+            .collect();
+        assert!(labels.contains(&"us"));
+        assert!(labels.contains(&"standard (default)"));
+        assert_eq!(outcome.trace[0].before, data.servers.len());
+        let last = outcome.trace.last().unwrap();
+        assert_eq!(last.after, outcome.alternates.len() + 1);
+        assert!(outcome.timings.filter + outcome.timings.benchmark <= outcome.elapsed);
+        let ranked = outcome.ranked();
+        assert!(ranked
+            .servers
+            .windows(2)
+            .all(|pair| pair[0].load <= pair[1].load));
+    }
+
+    #[test]
+    fn keep_unless_better() {
+        let data = Servers::dummy_data();
+        let options = || SelectOptions::new(&data).filters(vec!["be"]);
+        let ranked = select(options().all_alternates()).unwrap().ranked().servers;
+        let (best, worst) = (&ranked[0], &ranked[ranked.len() - 1]);
+        assert!(worst.load > best.load);
+
+        let outcome = select(options().better_than(worst.clone(), 0)).unwrap();
+        assert!(!outcome.kept);
+        assert_eq!(outcome.server.as_ref(), Some(best));
+
+        let outcome = select(options().better_than(worst.clone(), 100)).unwrap();
+        assert!(outcome.kept);
+        assert_eq!(outcome.server.as_ref(), Some(worst));
+
+        // Servers not passing the filters are never kept.
+        let elsewhere = data
+            .servers
+            .iter()
+            .find(|server| server.flag != "BE")
+            .unwrap();
+        let outcome = select(options().better_than(elsewhere.clone(), 100)).unwrap();
+        assert!(!outcome.kept);
+        assert_eq!(outcome.server.as_ref(), Some(best));
+    }
+
+    #[test]
+    fn plan_matches_trace() {
+        let data = Servers::dummy_data();
+        let options = || {
+            SelectOptions::new(&data)
+                .filters(vec!["us", "tcp", "!p2p"])
+                .filter("blacklist", CountryFilter::from("BE"))
+                .ping(FailingBenchmarker, 10, ErrorPolicy::SkipServer)
+        };
+
+        let traced = plan(&options().trace(true)).unwrap();
+        let outcome = select(options().trace(true)).unwrap();
+        let labels: Vec<&str> = traced
+            .filters
+            .iter()
+            .map(|(label, _)| label.as_str())
+            .collect();
+        let trace: Vec<&str> = outcome
+            .trace
+            .iter()
+            .map(|step| step.label.as_str())
+            .collect();
+        assert_eq!(labels, trace);
+        assert_eq!(traced.ping, Some((10, ErrorPolicy::SkipServer)));
+
+        // Without tracing, the cheapest filters go first: a single country before a list.
+        let planned = plan(&options()).unwrap();
+        let position = |label: &str| planned.filters.iter().position(|(l, _)| l == label);
+        assert_eq!(planned.filters.len(), traced.filters.len());
+        assert_eq!(trace.last(), Some(&"blacklist"));
+        assert!(position("blacklist").unwrap() < position("us").unwrap());
+        assert!(!planned.nearest_country && planned.failure_penalty.is_none());
+    }
+
+    #[test]
+    fn preferences() {
+        let data = Servers::dummy_data();
+        let options = |filters: Vec<&str>| {
+            SelectOptions::new(&data)
+                .filters(filters)
+                .all_alternates()
+                .trace(true)
+        };
+
+        // There are no servers in Kosovo, so the ones in the Netherlands are selected.
+        let outcome = select(options(vec!["tcp", "prefer:xk,nl,be"])).unwrap();
+        let ranked = outcome.ranked();
+        assert!(!ranked.servers.is_empty());
+        assert!(ranked.servers.iter().all(|server| server.flag == "NL"));
+        assert_eq!(outcome.trace.last().unwrap().label, "prefer:xk,nl,be");
+        assert_eq!(
+            outcome.applied_filters.last().unwrap(),
+            "first of XK, NL, BE with servers left"
+        );
+
+        // The later countries are only used when the earlier ones have no servers left.
+        let outcome = select(options(vec!["!nl", "prefer:nl,be"])).unwrap();
+        assert_eq!(outcome.server.unwrap().flag, "BE");
+        assert!(select(options(vec!["prefer:xk"])).unwrap().server.is_none());
+    }
+
+    #[test]
+    fn invalid_filters() {
+        let data = Servers::dummy_data();
+        let err = select(SelectOptions::new(&data).filters(vec!["nowhere"])).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<FilterError>(),
+            Some(FilterError::Unknown(..))
+        ));
+    }
+}
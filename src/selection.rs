@@ -0,0 +1,353 @@
+//! Selecting the best servers in one go: filtering, benchmarking and keeping the best ones.
+use crate::bench::{self, Benchmarker, ErrorPolicy, LoadBenchmarker, ScoredServer};
+use crate::filters::{apply_all, Filter};
+use crate::servers::{SampleStrategy, Servers};
+use std::time::{Duration, Instant};
+
+/// Builds a selection of servers out of a list of servers.
+///
+/// The filters are applied cheapest first, after which the remaining servers are benchmarked and
+/// ordered from best to worst. Without a benchmarker, servers are ordered on their load.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::{Protocol, ServerCategory, Servers};
+/// use nordselect::bench::LoadBenchmarker;
+/// use nordselect::filters::{CategoryFilter, CountryFilter, ProtocolFilter};
+/// use nordselect::selection::SelectionBuilder;
+///
+/// let servers = Servers::dummy_data();
+/// let selection = SelectionBuilder::new()
+///     .filter(CountryFilter::from("US"))
+///     .filter(ProtocolFilter::from(Protocol::Tcp))
+///     .filter(CategoryFilter::from(ServerCategory::Standard))
+///     .benchmark(LoadBenchmarker)
+///     .take(5)
+///     .run(&servers)
+///     .unwrap();
+///
+/// assert!(selection.servers.len() <= 5);
+/// assert_eq!(selection.perfect_server().unwrap().flag, "US");
+/// ```
+#[derive(Default)]
+pub struct SelectionBuilder<'a> {
+    /// The filters every selected server has to pass.
+    filters: Vec<Box<dyn Filter + 'a>>,
+    /// The way the remaining servers are scored.
+    benchmarker: Option<Box<dyn Benchmarker + 'a>>,
+    /// How many servers passing the filters are sampled, how, and using which seed, if any.
+    sample: Option<(usize, SampleStrategy, u64)>,
+    /// How many of the least loaded servers are benchmarked, if limited.
+    candidates: Option<usize>,
+    /// How many servers are selected, if limited.
+    take: Option<usize>,
+    /// How long benchmarking may take, if limited.
+    deadline: Option<Duration>,
+    /// What happens to servers the benchmarker could not score.
+    on_error: ErrorPolicy,
+    /// The seed used to order equally good servers, if any.
+    seed: Option<u64>,
+}
+
+impl<'a> SelectionBuilder<'a> {
+    /// Creates a selection without any filters, which keeps all servers ordered on their load.
+    pub fn new() -> SelectionBuilder<'a> {
+        SelectionBuilder::default()
+    }
+
+    /// Only selects servers passing this filter, on top of the earlier ones.
+    pub fn filter(mut self, filter: impl Filter + 'a) -> SelectionBuilder<'a> {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Orders the servers using this benchmarker, replacing the previous one.
+    pub fn benchmark(mut self, benchmarker: impl Benchmarker + 'a) -> SelectionBuilder<'a> {
+        self.benchmarker = Some(Box::new(benchmarker));
+        self
+    }
+
+    /// Only benchmarks the `amount` least loaded servers passing the filters. This limits the
+    /// work done by expensive benchmarkers, such as ping tests.
+    pub fn candidates(mut self, amount: usize) -> SelectionBuilder<'a> {
+        self.candidates = Some(amount);
+        self
+    }
+
+    /// Only considers a sample of `amount` servers passing the filters, before limiting the
+    /// candidates. See [Servers::sample](../servers/struct.Servers.html#method.sample).
+    pub fn sample(
+        mut self,
+        amount: usize,
+        strategy: SampleStrategy,
+        seed: u64,
+    ) -> SelectionBuilder<'a> {
+        self.sample = Some((amount, strategy, seed));
+        self
+    }
+
+    /// Only selects the `amount` best servers.
+    pub fn take(mut self, amount: usize) -> SelectionBuilder<'a> {
+        self.take = Some(amount);
+        self
+    }
+
+    /// Stops benchmarking once the given time has passed since the selection was started,
+    /// selecting among the servers that were scored so far.
+    pub fn deadline(mut self, budget: Duration) -> SelectionBuilder<'a> {
+        self.deadline = Some(budget);
+        self
+    }
+
+    /// Decides what happens to servers the benchmarker could not score. By default, they are
+    /// selected after all servers that were scored.
+    pub fn on_error(mut self, policy: ErrorPolicy) -> SelectionBuilder<'a> {
+        self.on_error = policy;
+        self
+    }
+
+    /// Orders servers with the same score and load using the given seed, instead of on their
+    /// domain. See [ScoredServer::rank_seeded](../bench/struct.ScoredServer.html#method.rank_seeded).
+    pub fn seed(mut self, seed: u64) -> SelectionBuilder<'a> {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Selects servers from the given list, best first.
+    ///
+    /// Returns an error if the benchmarker fails, or if a server could not be scored while using
+    /// [ErrorPolicy::Abort](../bench/enum.ErrorPolicy.html#variant.Abort).
+    pub fn run(&self, servers: &Servers) -> Result<Servers, Box<dyn std::error::Error>> {
+        Ok(Servers {
+            servers: self
+                .run_scored(servers)?
+                .into_iter()
+                .map(|scored| scored.server)
+                .collect(),
+        })
+    }
+
+    /// Same as [run](#method.run), but keeps the score every server was given.
+    pub fn run_scored(
+        &self,
+        servers: &Servers,
+    ) -> Result<Vec<ScoredServer>, Box<dyn std::error::Error>> {
+        let deadline = self.deadline.map(|budget| Instant::now() + budget);
+
+        let mut filters: Vec<&dyn Filter> = self
+            .filters
+            .iter()
+            .map(|filter| filter.as_ref() as &dyn Filter)
+            .collect();
+        filters.sort_by_key(|filter| filter.cost());
+
+        let mut selection = Servers {
+            servers: apply_all(&servers.servers, &filters),
+        };
+
+        if let Some((amount, strategy, seed)) = self.sample {
+            selection = selection.sample(amount, strategy, seed);
+        }
+
+        if let Some(candidates) = self.candidates {
+            selection.servers.sort_by_key(|server| server.load);
+            selection.cut(candidates);
+        }
+
+        let benchmarker: &dyn Benchmarker = match &self.benchmarker {
+            Some(benchmarker) => benchmarker.as_ref(),
+            None => &LoadBenchmarker,
+        };
+        let scores = match deadline {
+            Some(deadline) => benchmarker.benchmark_until(&selection, deadline)?,
+            None => benchmarker.benchmark(&selection)?,
+        };
+        let mut selection = bench::rank(selection.servers, &scores, self.on_error, self.seed)?;
+
+        if let Some(take) = self.take {
+            selection.truncate(take);
+        }
+
+        Ok(selection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::{Score, ScoreUnit, Scores};
+    use crate::filters::{CountryFilter, LoadFilter};
+    use crate::test_util::arb_servers;
+    use crate::Server;
+    use proptest::prelude::*;
+    use std::cell::Cell;
+
+    /// Counts how often it is applied.
+    struct CountingFilter<'a>(&'a Cell<usize>, u32);
+
+    impl<'a> Filter for CountingFilter<'a> {
+        fn filter(&self, _: &Server) -> bool {
+            self.0.set(self.0.get() + 1);
+            true
+        }
+
+        fn cost(&self) -> u32 {
+            self.1
+        }
+    }
+
+    #[test]
+    fn cheap_filters_first() {
+        let servers = Servers::dummy_data();
+        let expensive = Cell::new(0);
+        let cheap = Cell::new(0);
+        let selection = SelectionBuilder::new()
+            .filter(CountingFilter(&expensive, 100))
+            .filter(CountryFilter::from("BE"))
+            .filter(CountingFilter(&cheap, 0))
+            .run(&servers)
+            .unwrap();
+
+        let belgian = servers
+            .servers
+            .iter()
+            .filter(|server| server.flag == "BE")
+            .count();
+        assert_eq!(selection.servers.len(), belgian);
+        assert_eq!(cheap.get(), servers.servers.len());
+        assert_eq!(expensive.get(), belgian);
+    }
+
+    struct ReverseLoadBenchmarker;
+
+    impl Benchmarker for ReverseLoadBenchmarker {
+        fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+            Ok(servers
+                .servers
+                .iter()
+                .map(|server| {
+                    let score = Score::new(100 - u32::from(server.load), ScoreUnit::Percent);
+                    (server.domain.clone(), score)
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn benchmark_candidates_then_take() {
+        let servers = Servers::dummy_data();
+        let selection = SelectionBuilder::new()
+            .filter(LoadFilter::from(50))
+            .benchmark(ReverseLoadBenchmarker)
+            .candidates(10)
+            .take(3)
+            .run(&servers)
+            .unwrap();
+
+        let mut loads: Vec<u8> = servers
+            .servers
+            .iter()
+            .map(|server| server.load)
+            .filter(|load| *load <= 50)
+            .collect();
+        loads.sort_unstable();
+        loads.truncate(10);
+        let highest_candidate = *loads.last().unwrap();
+
+        assert_eq!(selection.servers.len(), 3);
+        assert_eq!(selection.servers[0].load, highest_candidate);
+        assert!(selection
+            .servers
+            .windows(2)
+            .all(|pair| pair[0].load >= pair[1].load));
+    }
+
+    #[test]
+    fn sample_after_filters() {
+        let servers = Servers::dummy_data();
+        let selection = SelectionBuilder::new()
+            .filter(CountryFilter::from("US"))
+            .sample(5, SampleStrategy::Random, 3)
+            .run(&servers)
+            .unwrap();
+
+        assert_eq!(selection.servers.len(), 5);
+        assert!(selection.servers.iter().all(|server| server.flag == "US"));
+    }
+
+    #[test]
+    fn load_without_benchmarker() {
+        let servers = Servers::dummy_data();
+        let selection = SelectionBuilder::new().run(&servers).unwrap();
+
+        assert_eq!(selection.servers.len(), servers.servers.len());
+        assert_eq!(
+            selection.perfect_server().unwrap().load,
+            servers
+                .servers
+                .iter()
+                .map(|server| server.load)
+                .min()
+                .unwrap()
+        );
+    }
+
+    /// Only scores servers with an even load.
+    struct EvenLoadBenchmarker;
+
+    impl Benchmarker for EvenLoadBenchmarker {
+        fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+            Ok(servers
+                .servers
+                .iter()
+                .filter(|server| server.load % 2 == 0)
+                .map(|server| {
+                    let score = Score::new(u32::from(server.load), ScoreUnit::Percent);
+                    (server.domain.clone(), score)
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn error_policies() {
+        let servers = Servers::dummy_data();
+        let even = servers
+            .servers
+            .iter()
+            .filter(|server| server.load % 2 == 0)
+            .count();
+        assert!(even < servers.servers.len());
+        let selection = |policy| {
+            SelectionBuilder::new()
+                .benchmark(EvenLoadBenchmarker)
+                .on_error(policy)
+                .run(&servers)
+        };
+
+        let worst = selection(ErrorPolicy::TreatAsWorst).unwrap();
+        assert_eq!(worst.servers.len(), servers.servers.len());
+        assert!(worst.servers[..even]
+            .iter()
+            .all(|server| server.load % 2 == 0));
+
+        let skipped = selection(ErrorPolicy::SkipServer).unwrap();
+        assert_eq!(skipped.servers[..], worst.servers[..even]);
+
+        assert!(selection(ErrorPolicy::Abort).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn take_lowest_loads(servers in arb_servers(0..50), take in 1usize..10) {
+            let selection = SelectionBuilder::new().take(take).run(&servers).unwrap();
+
+            let mut loads: Vec<u8> = servers.servers.iter().map(|server| server.load).collect();
+            loads.sort_unstable();
+            loads.truncate(take);
+            let selected: Vec<u8> = selection.servers.iter().map(|server| server.load).collect();
+            prop_assert_eq!(selected, loads);
+        }
+    }
+}
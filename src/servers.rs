@@ -1,14 +1,29 @@
 //! Data structures and methods to interact with the NordVPN servers.
+#[cfg(feature = "native")]
+use crate::api;
+use crate::bench::{self, Benchmarker, ErrorPolicy};
+use crate::countries;
 use crate::filters::Filter;
+use crate::index::ServersIndex;
 use crate::sorters::Sorter;
-use reqwest;
-use serde_derive::Deserialize;
+use serde::de::{DeserializeSeed, Deserializer, Error as _, IgnoredAny, SeqAccess, Visitor};
+use serde::Serializer;
+use serde_derive::{Deserialize, Serialize};
 use serde_json;
-use std::collections::HashSet;
+use serde_json::value::RawValue;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::io::{BufReader, Read};
 use std::iter::FromIterator;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Hash)]
 /// The categories a Server can be in, as used by NordVPN.
 pub enum ServerCategory {
     /// A standard VPN server
@@ -43,6 +58,340 @@ impl From<String> for ServerCategory {
     }
 }
 
+impl ServerCategory {
+    /// Every category, in the order of their bits in [Categories](struct.Categories.html).
+    pub(crate) const ALL: [ServerCategory; 7] = [
+        ServerCategory::Standard,
+        ServerCategory::P2P,
+        ServerCategory::Obfuscated,
+        ServerCategory::Dedicated,
+        ServerCategory::Tor,
+        ServerCategory::Double,
+        ServerCategory::UnknownServer,
+    ];
+
+    /// Returns the bit standing for this category in [Categories](struct.Categories.html).
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+/// The categories a Server is in, stored as one bit per category. Servers do not allocate for
+/// their categories, and checking whether a server is in a category is a single bit test.
+///
+/// All categories unknown to this library are
+/// [UnknownServer](enum.ServerCategory.html#variant.UnknownServer). Their names are kept in
+/// [Server::unknown_categories](struct.Server.html#structfield.unknown_categories).
+///
+/// Categories are (de)serialized as a list, the way a `Vec<ServerCategory>` is.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::{Categories, ServerCategory};
+///
+/// let mut categories: Categories = vec![ServerCategory::P2P].into_iter().collect();
+/// categories.insert(ServerCategory::Standard);
+///
+/// assert!(categories.contains(&ServerCategory::P2P));
+/// assert!(!categories.contains(&ServerCategory::Tor));
+/// assert_eq!(
+///     categories.iter().collect::<Vec<_>>(),
+///     [ServerCategory::Standard, ServerCategory::P2P]
+/// );
+/// ```
+pub struct Categories(u8);
+
+impl Categories {
+    /// Creates a set without any categories.
+    pub fn new() -> Categories {
+        Categories(0)
+    }
+
+    /// Adds the category to the set.
+    pub fn insert(&mut self, category: ServerCategory) {
+        self.0 |= category.bit();
+    }
+
+    /// Removes the category from the set.
+    pub fn remove(&mut self, category: &ServerCategory) {
+        self.0 &= !category.bit();
+    }
+
+    /// Returns whether the set has the category.
+    pub fn contains(&self, category: &ServerCategory) -> bool {
+        self.0 & category.bit() != 0
+    }
+
+    /// Returns the amount of categories in the set.
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// Returns whether the set has no categories.
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the categories in the set, in the order they are declared in
+    /// [ServerCategory](enum.ServerCategory.html).
+    pub fn iter(&self) -> impl Iterator<Item = ServerCategory> {
+        let bits = self.0;
+        ServerCategory::ALL
+            .iter()
+            .copied()
+            .filter(move |category| bits & category.bit() != 0)
+    }
+}
+
+impl fmt::Debug for Categories {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl FromIterator<ServerCategory> for Categories {
+    fn from_iter<I: IntoIterator<Item = ServerCategory>>(categories: I) -> Categories {
+        let mut set = Categories::new();
+        for category in categories {
+            set.insert(category);
+        }
+        set
+    }
+}
+
+impl serde::Serialize for Categories {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Categories {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Categories, D::Error> {
+        Ok(Vec::<ServerCategory>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+/// A country, noted according to
+/// [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2).
+///
+/// Only codes that are assigned to a country can be used.
+///
+/// # Examples
+///
+/// ```
+/// use nordselect::Country;
+///
+/// let belgium: Country = "be".parse().unwrap();
+/// assert_eq!(belgium, "BE");
+/// assert_eq!(belgium.name(), "Belgium");
+/// assert_eq!(belgium.to_string(), "BE");
+///
+/// assert!("XX".parse::<Country>().is_err());
+/// assert!("BEL".parse::<Country>().is_err());
+/// ```
+pub struct Country([u8; 2]);
+
+impl Country {
+    /// Returns the uppercase code of this country.
+    pub fn as_str(&self) -> &str {
+        // Only ASCII letters are ever stored.
+        std::str::from_utf8(&self.0).unwrap()
+    }
+
+    /// Returns the English name of this country.
+    pub fn name(&self) -> &'static str {
+        countries::name(self.as_str()).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The error returned when text is not a known country code.
+pub struct ParseCountryError(String);
+
+impl fmt::Display for ParseCountryError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "unknown country code: \"{}\"", self.0)
+    }
+}
+
+impl std::error::Error for ParseCountryError {}
+
+impl FromStr for Country {
+    type Err = ParseCountryError;
+
+    /// Parses a country code, ignoring its case. `UK` is accepted for the United Kingdom, as it is
+    /// commonly used instead of `GB`.
+    fn from_str(code: &str) -> Result<Country, ParseCountryError> {
+        let upper = code.to_ascii_uppercase();
+        let upper = if upper == "UK" { String::from("GB") } else { upper };
+        match (upper.as_bytes(), countries::name(&upper)) {
+            (&[first, second], Some(_)) => Ok(Country([first, second])),
+            _ => Err(ParseCountryError(code.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for Country {
+    type Error = ParseCountryError;
+
+    fn try_from(code: String) -> Result<Country, ParseCountryError> {
+        code.parse()
+    }
+}
+
+impl From<Country> for String {
+    fn from(country: Country) -> String {
+        country.to_string()
+    }
+}
+
+impl fmt::Display for Country {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Country {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "Country({})", self.as_str())
+    }
+}
+
+impl AsRef<str> for Country {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq<str> for Country {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<'a> PartialEq<&'a str> for Country {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+/// The domain of a server, e.g. `be12.nordvpn.com`.
+///
+/// The text is shared by all clones of a Domain, so the domains kept in score maps, histories and
+/// lists are no copies of the ones of the servers. It can be used as a `&str`, and is hashed and
+/// compared like one, so maps keyed on domains can be looked up with a `&str`.
+///
+/// # Examples
+///
+/// ```
+/// use nordselect::Domain;
+/// use std::collections::HashMap;
+///
+/// let domain = Domain::from("be12.nordvpn.com");
+/// assert_eq!(domain, "be12.nordvpn.com");
+/// assert!(domain.ends_with(".nordvpn.com"));
+///
+/// let mut loads = HashMap::new();
+/// loads.insert(domain.clone(), 12);
+/// assert_eq!(loads["be12.nordvpn.com"], 12);
+/// ```
+pub struct Domain(Arc<str>);
+
+impl Domain {
+    /// Returns the text of this domain.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Domain {
+    fn from(domain: &str) -> Domain {
+        Domain(Arc::from(domain))
+    }
+}
+
+impl From<String> for Domain {
+    fn from(domain: String) -> Domain {
+        Domain(Arc::from(domain))
+    }
+}
+
+impl From<Domain> for String {
+    fn from(domain: Domain) -> String {
+        domain.as_str().to_string()
+    }
+}
+
+impl std::ops::Deref for Domain {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl std::borrow::Borrow<str> for Domain {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Domain {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for Domain {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Domain {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), formatter)
+    }
+}
+
+impl PartialEq<str> for Domain {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<'a> PartialEq<&'a str> for Domain {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Domain {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<Domain> for String {
+    fn eq(&self, other: &Domain) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Domain> for &str {
+    fn eq(&self, other: &Domain) -> bool {
+        *self == other.as_str()
+    }
+}
+
 #[derive(Debug, Deserialize, PartialEq, Clone)]
 /// The struct used to identify categories, used in the API.
 ///
@@ -52,8 +401,12 @@ struct ApiCategory {
     pub name: String,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+#[non_exhaustive]
 /// All protocols and other features a Server can have.
+///
+/// Fields are added when NordVPN adds features, so outside of this crate, Features can only be
+/// built using [builder](#method.builder).
 pub struct Features {
     /// Support for IKEv2 protocol.
     pub ikev2: bool,
@@ -93,6 +446,171 @@ pub struct Features {
     pub proxy_ssl_cybersec: bool,
     /// Support for WireGuard over UDP
     pub wireguard_udp: bool,
+    /// Support for forwarding ports to the client, which helps with P2P traffic. The API only
+    /// mentions this for some servers, others are assumed not to support it.
+    #[serde(default)]
+    pub port_forwarding: bool,
+    /// Features that are not known to this library (yet), mapped by the name used in the API.
+    #[serde(flatten, deserialize_with = "deserialize_other_features")]
+    pub other: HashMap<String, bool>,
+}
+
+/// Keeps all unknown features that are flags, ignoring other kinds of values.
+fn deserialize_other_features<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, bool>, D::Error> {
+    let values: HashMap<String, serde_json::Value> = serde::Deserialize::deserialize(deserializer)?;
+
+    Ok(values
+        .into_iter()
+        .filter_map(|(key, value)| value.as_bool().map(|value| (key, value)))
+        .collect())
+}
+
+#[cfg(any(test, feature = "test-util"))]
+/// The names the API uses for the features that are fields of Features.
+pub(crate) const KNOWN_FEATURES: &[&str] = &[
+    "ikev2",
+    "openvpn_udp",
+    "openvpn_tcp",
+    "socks",
+    "proxy",
+    "pptp",
+    "l2tp",
+    "openvpn_xor_udp",
+    "openvpn_xor_tcp",
+    "proxy_cybersec",
+    "proxy_ssl",
+    "proxy_ssl_cybersec",
+    "wireguard_udp",
+    "port_forwarding",
+];
+
+impl Features {
+    /// Returns a builder for Features, in which no feature is supported yet.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::Protocol;
+    /// use nordselect::servers::Features;
+    ///
+    /// let features = Features::builder()
+    ///     .protocol(Protocol::Tcp)
+    ///     .feature("port_forwarding", true)
+    ///     .feature("meshnet", true)
+    ///     .build();
+    /// assert!(features.openvpn_tcp && features.port_forwarding);
+    /// assert_eq!(features.get("meshnet"), Some(true));
+    /// assert_eq!(features.get("socks"), Some(false));
+    /// ```
+    pub fn builder() -> FeaturesBuilder {
+        FeaturesBuilder::default()
+    }
+
+    /// Returns the field of the feature with the given name, as used in the API, if it has one.
+    fn field_mut(&mut self, key: &str) -> Option<&mut bool> {
+        match key {
+            "ikev2" => Some(&mut self.ikev2),
+            "openvpn_udp" => Some(&mut self.openvpn_udp),
+            "openvpn_tcp" => Some(&mut self.openvpn_tcp),
+            "socks" => Some(&mut self.socks),
+            "proxy" => Some(&mut self.proxy),
+            "pptp" => Some(&mut self.pptp),
+            "l2tp" => Some(&mut self.l2tp),
+            "openvpn_xor_udp" => Some(&mut self.openvpn_xor_udp),
+            "openvpn_xor_tcp" => Some(&mut self.openvpn_xor_tcp),
+            "proxy_cybersec" => Some(&mut self.proxy_cybersec),
+            "proxy_ssl" => Some(&mut self.proxy_ssl),
+            "proxy_ssl_cybersec" => Some(&mut self.proxy_ssl_cybersec),
+            "wireguard_udp" => Some(&mut self.wireguard_udp),
+            "port_forwarding" => Some(&mut self.port_forwarding),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the feature with the given name, as used in the API, is supported. This
+    /// works for both known and [unknown](#structfield.other) features.
+    ///
+    /// Returns `None` if the feature is not mentioned at all.
+    pub fn get(&self, key: &str) -> Option<bool> {
+        match key {
+            "ikev2" => Some(self.ikev2),
+            "openvpn_udp" => Some(self.openvpn_udp),
+            "openvpn_tcp" => Some(self.openvpn_tcp),
+            "socks" => Some(self.socks),
+            "proxy" => Some(self.proxy),
+            "pptp" => Some(self.pptp),
+            "l2tp" => Some(self.l2tp),
+            "openvpn_xor_udp" => Some(self.openvpn_xor_udp),
+            "openvpn_xor_tcp" => Some(self.openvpn_xor_tcp),
+            "proxy_cybersec" => Some(self.proxy_cybersec),
+            "proxy_ssl" => Some(self.proxy_ssl),
+            "proxy_ssl_cybersec" => Some(self.proxy_ssl_cybersec),
+            "wireguard_udp" => Some(self.wireguard_udp),
+            "port_forwarding" => Some(self.port_forwarding),
+            _ => self.other.get(key).copied(),
+        }
+    }
+
+    /// Returns whether the given protocol can be used to connect to the server.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::{Protocol, Servers};
+    /// let data = Servers::dummy_data();
+    /// let features = &data.servers[0].features;
+    ///
+    /// assert_eq!(features.supports(&Protocol::OpenVPNXTcp), features.openvpn_xor_tcp);
+    /// ```
+    pub fn supports(&self, protocol: &Protocol) -> bool {
+        match protocol {
+            Protocol::Tcp => self.openvpn_tcp,
+            Protocol::Udp => self.openvpn_udp,
+            Protocol::Pptp => self.pptp,
+            Protocol::L2tp => self.l2tp,
+            Protocol::OpenVPNXTcp => self.openvpn_xor_tcp,
+            Protocol::OpenVPNXUdp => self.openvpn_xor_udp,
+            Protocol::Socks => self.socks,
+            Protocol::CyberSecProxy => self.proxy_cybersec,
+            Protocol::SslProxy => self.proxy_ssl,
+            Protocol::CyberSecSslProxy => self.proxy_ssl_cybersec,
+            Protocol::Proxy => self.proxy,
+            Protocol::WireGuardUdp => self.wireguard_udp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Builds [Features](struct.Features.html), see [Features::builder](struct.Features.html#method.builder).
+pub struct FeaturesBuilder {
+    /// The features built so far.
+    features: Features,
+}
+
+impl FeaturesBuilder {
+    /// Marks the protocol as supported.
+    pub fn protocol(self, protocol: Protocol) -> FeaturesBuilder {
+        self.feature(protocol.api_key(), true)
+    }
+
+    /// Sets whether the feature with the given name, as used in the API, is supported. Features
+    /// unknown to this library end up in [other](struct.Features.html#structfield.other).
+    pub fn feature(mut self, key: &str, supported: bool) -> FeaturesBuilder {
+        match self.features.field_mut(key) {
+            Some(field) => *field = supported,
+            None => {
+                self.features.other.insert(key.to_string(), supported);
+            }
+        }
+        self
+    }
+
+    /// Returns the Features.
+    pub fn build(self) -> Features {
+        self.features
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -108,43 +626,592 @@ struct ApiServer {
     pub categories: Vec<ApiCategory>,
     /// Features of the server
     pub features: Features,
+    /// The human readable name of this server, which can mention its bandwidth.
+    #[serde(default)]
+    pub name: String,
+    /// The score NordVPN gives this server, if the response includes it.
+    #[serde(default)]
+    pub hub_score: Option<f64>,
+    /// Whether NordVPN recommends this server, if the response includes it.
+    #[serde(default)]
+    pub recommended: Option<bool>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Deserialize)]
+/// A named item in the v1 API response, such as a group or a technology.
+struct ApiV1Item {
+    /// The human readable name of the item.
+    #[serde(default)]
+    pub title: String,
+    /// The identifier of the item, e.g. `openvpn_udp` for a technology.
+    #[serde(default)]
+    pub identifier: String,
+    /// The kind of the item. Groups can be categories or regions.
+    #[serde(rename = "type")]
+    pub kind: Option<Box<ApiV1Item>>,
+}
+
+#[derive(Debug, Deserialize)]
+/// A specification of a server in the v1 API response, such as its bandwidth.
+struct ApiV1Specification {
+    /// The identifier of the specification.
+    #[serde(default)]
+    pub identifier: String,
+    /// The human readable name of the specification, which can mention its unit.
+    #[serde(default)]
+    pub title: String,
+    /// The values of the specification. In practice, this is always one.
+    #[serde(default)]
+    pub values: Vec<ApiV1SpecificationValue>,
+}
+
+#[derive(Debug, Deserialize)]
+/// A value of a specification in the v1 API response.
+struct ApiV1SpecificationValue {
+    /// The value, which can be text or a number.
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+/// The country of a location in the v1 API response.
+struct ApiV1Country {
+    /// The country code.
+    pub code: String,
+    /// The city this location is in, if known.
+    #[serde(default)]
+    pub city: Option<ApiV1City>,
+}
+
+#[derive(Debug, Deserialize)]
+/// The city of a location in the v1 API response.
+struct ApiV1City {
+    /// The name of the city, e.g. `Brussels`.
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+/// A location in the v1 API response.
+struct ApiV1Location {
+    /// The country this location is in.
+    pub country: ApiV1Country,
+}
+
+#[derive(Debug, Deserialize)]
+/// The way servers are represented in the response of the v1 API.
+struct ApiV1Server {
+    /// The domain of this server.
+    pub hostname: String,
+    /// The current load on this server, written as a percentage (%)
+    pub load: u8,
+    /// Locations of this server. In practice, this is always one.
+    pub locations: Vec<ApiV1Location>,
+    /// Groups this server is in, which include both categories and regions.
+    pub groups: Vec<ApiV1Item>,
+    /// Protocols and other features this server supports.
+    pub technologies: Vec<ApiV1Item>,
+    /// The human readable name of this server, which can mention its bandwidth.
+    #[serde(default)]
+    pub name: String,
+    /// Specifications of the hardware of this server, if any.
+    #[serde(default)]
+    pub specifications: Vec<ApiV1Specification>,
+    /// When this server was added, e.g. `2018-10-09 14:46:13` in UTC.
+    #[serde(default)]
+    pub created_at: Option<String>,
+    /// The score NordVPN gives this server, if the response includes it.
+    #[serde(default)]
+    pub hub_score: Option<f64>,
+    /// Whether NordVPN recommends this server, if the response includes it.
+    #[serde(default)]
+    pub recommended: Option<bool>,
+}
+
+impl ApiV1Server {
+    /// Returns the country code of the first location, or an empty string if there is none.
+    fn country_code(&self) -> &str {
+        self.locations
+            .first()
+            .map(|location| location.country.code.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Returns the name of the city of the first location, if known.
+    fn city(&self) -> Option<String> {
+        let city = self.locations.first()?.country.city.as_ref()?;
+        if city.name.is_empty() {
+            None
+        } else {
+            Some(city.name.clone())
+        }
+    }
+
+    /// Returns the bandwidth of this server in Mbit/s, as given by its specifications or its
+    /// name.
+    fn bandwidth(&self) -> Option<u32> {
+        self.specifications
+            .iter()
+            .filter(|spec| {
+                let name = format!("{} {}", spec.identifier, spec.title).to_ascii_lowercase();
+                name.contains("bandwidth") || name.contains("speed")
+            })
+            .flat_map(|spec| spec.values.iter().map(move |value| (spec, &value.value)))
+            .find_map(|(spec, value)| match value {
+                serde_json::Value::String(text) => parse_bandwidth(text),
+                // Bare numbers use the unit in the title, e.g. `Bandwidth (Gbps)`.
+                value => {
+                    let title = spec.title.to_ascii_lowercase();
+                    let factor = title
+                        .split(|c: char| !c.is_ascii_alphabetic())
+                        .find_map(bandwidth_unit)?;
+                    u32::try_from(value.as_u64()?.checked_mul(u64::from(factor))?).ok()
+                }
+            })
+            .or_else(|| parse_bandwidth(&self.name))
+    }
+}
+
+/// Parses a moment as the API writes it, e.g. `2018-10-09 14:46:13` in UTC, into seconds since
+/// the Unix epoch. Fractions of seconds are ignored.
+fn parse_timestamp(text: &str) -> Option<u64> {
+    let (date, time) = text.trim().split_once([' ', 'T'])?;
+    let numbers = |text: &str, separator: char| -> Option<(u64, u64, u64)> {
+        let mut parts = text
+            .splitn(3, separator)
+            .map(|part| part.parse::<u64>().ok());
+        Some((parts.next()??, parts.next()??, parts.next()??))
+    };
+    let (year, month, day) = numbers(date, '-')?;
+    let time = time.trim_end_matches('Z').split('.').next()?;
+    let (hour, minute, second) = numbers(time, ':')?;
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // Counting years from March, so the leap day is the last day of a year.
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let days = year * 365 + year / 4 - year / 100 + year / 400 + (153 * month + 2) / 5 + day - 1;
+    // The days from 1 March of year 0 until 1 January 1970.
+    let days = days - 719_468;
+
+    Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Returns how many Mbit/s the unit at the start of the text stands for.
+fn bandwidth_unit(unit: &str) -> Option<u32> {
+    if unit.starts_with("gbps") || unit.starts_with("gbit") {
+        Some(1000)
+    } else if unit.starts_with("mbps") || unit.starts_with("mbit") {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+/// Finds a bandwidth like `10 Gbps` or `500Mbps` in the text, and returns it in Mbit/s.
+pub(crate) fn parse_bandwidth(text: &str) -> Option<u32> {
+    let text = text.to_ascii_lowercase();
+    let bytes = text.as_bytes();
+    let mut start = 0;
+    while start < bytes.len() {
+        if !bytes[start].is_ascii_digit() || (start > 0 && bytes[start - 1].is_ascii_digit()) {
+            start += 1;
+            continue;
+        }
+        let end = start + bytes[start..].iter().take_while(|b| b.is_ascii_digit()).count();
+        if let Some(factor) = bandwidth_unit(text[end..].trim_start()) {
+            let amount: u32 = text[start..end].parse().ok()?;
+            return amount.checked_mul(factor);
+        }
+        start = end;
+    }
+
+    None
+}
+
+/// Rounds a hub score given by the API, which may be a fraction, to a whole one.
+fn parse_hub_score(score: f64) -> u32 {
+    // Casting saturates, and turns NaN into 0.
+    score.round() as u32
+}
+
+/// Domain prefixes that are not the country code of the servers using them.
+const DOMAIN_PREFIX_CORRECTIONS: &[(&str, &str)] = &[("uk", "GB")];
+
+/// Guesses the country of a server from the prefix of its domain, e.g. `uk1944.nordvpn.com` is
+/// in the United Kingdom.
+fn infer_country(domain: &str) -> Option<Country> {
+    let prefix = domain
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let code = DOMAIN_PREFIX_CORRECTIONS
+        .iter()
+        .find(|(wrong, _)| *wrong == prefix)
+        .map(|(_, code)| *code)
+        .unwrap_or(&prefix);
+
+    code.parse().ok()
+}
+
+/// Parses the country given by the API, which is inferred from the domain when it is empty or
+/// unknown. Fails with the error of the given country if the domain does not help either.
+fn parse_flag(flag: &str, domain: &str) -> Result<Country, ParseCountryError> {
+    flag.parse().or_else(|err| infer_country(domain).ok_or(err))
+}
+
+impl TryFrom<ApiV1Server> for Server {
+    type Error = ParseCountryError;
+
+    fn try_from(api_server: ApiV1Server) -> Result<Server, ParseCountryError> {
+        let flag = parse_flag(api_server.country_code(), &api_server.hostname)?;
+        let bandwidth_tier = api_server.bandwidth();
+        let city = api_server.city();
+        let created_at = api_server.created_at.as_deref().and_then(parse_timestamp);
+        // Technologies that are not listed are not supported.
+        let features = api_server
+            .technologies
+            .iter()
+            .fold(Features::builder(), |builder, technology| {
+                builder.feature(&technology.identifier, true)
+            })
+            .build();
+
+        let (categories, unknown_categories) = parse_categories(
+            api_server
+                .groups
+                .into_iter()
+                // Groups are also used for regions, which are no categories.
+                .filter(|group| match &group.kind {
+                    Some(kind) => kind.identifier == "legacy_group_category",
+                    None => true,
+                })
+                .map(|group| group.title),
+        );
+
+        Ok(Server {
+            flag,
+            domain: api_server.hostname.into(),
+            load: api_server.load,
+            categories,
+            unknown_categories,
+            features,
+            bandwidth_tier,
+            city,
+            created_at,
+            hub_score: api_server.hub_score.map(parse_hub_score),
+            recommended: api_server.recommended,
+            #[cfg(feature = "raw-json")]
+            raw: None,
+        })
+    }
+}
+
+/// A server in either the legacy or the v1 API response.
+enum AnyApiServer {
+    Legacy(ApiServer),
+    V1(ApiV1Server),
+    /// A server as stored by this library, e.g. in the cache.
+    Stored(Server),
+}
+
+impl TryFrom<AnyApiServer> for Server {
+    type Error = ParseCountryError;
+
+    fn try_from(api_server: AnyApiServer) -> Result<Server, ParseCountryError> {
+        match api_server {
+            AnyApiServer::Legacy(api_server) => Server::try_from(api_server),
+            AnyApiServer::V1(api_server) => Server::try_from(api_server),
+            AnyApiServer::Stored(server) => Ok(server),
+        }
+    }
+}
+
+/// The fields of a server in any of the formats that tell which format it is in and which
+/// country it is in, borrowed from the text of the server.
+#[derive(Deserialize)]
+struct ServerProbe<'a> {
+    /// The domain, in the v1 API.
+    #[serde(borrow, default)]
+    hostname: Option<Cow<'a, str>>,
+    /// The domain, in the legacy API and in stored lists.
+    #[serde(borrow, default)]
+    domain: Option<Cow<'a, str>>,
+    /// The country, in the legacy API and in stored lists.
+    #[serde(borrow, default)]
+    flag: Option<Cow<'a, str>>,
+    /// The locations, in the v1 API.
+    #[serde(borrow, default)]
+    locations: Vec<ProbeLocation<'a>>,
+    /// The categories, which are plain names in stored lists and objects in the legacy API.
+    #[serde(borrow, default)]
+    categories: Option<&'a RawValue>,
+    /// Only stored lists have this field.
+    #[serde(default)]
+    unknown_categories: Option<IgnoredAny>,
+}
+
+#[derive(Deserialize)]
+/// A location in the v1 API, of which only the country code is probed.
+struct ProbeLocation<'a> {
+    #[serde(borrow)]
+    country: ProbeCountry<'a>,
+}
+
+#[derive(Deserialize)]
+/// The country of a location in the v1 API, of which only the code is probed.
+struct ProbeCountry<'a> {
+    #[serde(borrow, default)]
+    code: Cow<'a, str>,
+}
+
+impl ServerProbe<'_> {
+    /// Returns whether the server is in the format of the v1 API, the only one calling the
+    /// domain a hostname.
+    fn is_v1(&self) -> bool {
+        self.hostname.is_some()
+    }
+
+    /// Returns whether the server is in the format of lists stored by this library, of which the
+    /// categories are plain names instead of objects.
+    fn is_stored(&self) -> bool {
+        let names_categories = self
+            .categories
+            .and_then(|categories| categories.get().strip_prefix('['))
+            .filter(|rest| rest.trim_start().starts_with('"'))
+            .is_some();
+        self.unknown_categories.is_some() || names_categories
+    }
+
+    /// Returns the domain of the server, or an empty string if there is none.
+    fn domain(&self) -> &str {
+        self.hostname
+            .as_deref()
+            .or(self.domain.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Returns the country code as the server list gives it, which can be empty or unknown.
+    fn flag(&self) -> &str {
+        if self.is_v1() {
+            self.locations
+                .first()
+                .map(|location| location.country.code.as_ref())
+                .unwrap_or_default()
+        } else {
+            self.flag.as_deref().unwrap_or_default()
+        }
+    }
+
+    /// Parses the whole server in the format it is in.
+    fn parse(&self, raw: &RawValue) -> Result<Server, Box<dyn std::error::Error>> {
+        let text = raw.get();
+        let api_server = if self.is_v1() {
+            AnyApiServer::V1(serde_json::from_str(text)?)
+        } else if self.is_stored() {
+            AnyApiServer::Stored(serde_json::from_str(text)?)
+        } else {
+            AnyApiServer::Legacy(serde_json::from_str(text)?)
+        };
+        #[allow(unused_mut)]
+        let mut server = Server::try_from(api_server)?;
+        // Stored servers are not in the format of the API.
+        #[cfg(feature = "raw-json")]
+        if !self.is_stored() {
+            server.raw = Some(Arc::new(serde_json::from_str(text)?));
+        }
+
+        Ok(server)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// What happened while reading a server list, as returned by
+/// [Servers::from_reader_with_report](struct.Servers.html#method.from_reader_with_report).
+pub struct ParseReport {
+    /// The amount of servers that were read.
+    pub servers: usize,
+    /// The amount of servers of which the country was missing or unknown, and was inferred from
+    /// the domain instead.
+    pub corrected_flags: usize,
+    /// The amount of servers left out because their country is unknown, even from the domain.
+    pub skipped: usize,
+}
+
+/// Deserializes a list of servers, keeping only those matching the filter. Every server is kept
+/// as text until it is known that it can pass the filter.
+struct FilteredServers<'a> {
+    filter: Option<&'a dyn Filter>,
+    report: &'a mut ParseReport,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for FilteredServers<'a> {
+    type Value = Vec<Server>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Vec<Server>, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for FilteredServers<'a> {
+    type Value = Vec<Server>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of servers")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<Server>, A::Error> {
+        let mut servers = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(raw) = seq.next_element::<Box<RawValue>>()? {
+            let probe: ServerProbe = serde_json::from_str(raw.get()).map_err(A::Error::custom)?;
+            // Servers in countries unknown to this library could never be selected anyway.
+            let flag = match parse_flag(probe.flag(), probe.domain()) {
+                Ok(flag) => flag,
+                Err(_) => {
+                    self.report.skipped += 1;
+                    continue;
+                }
+            };
+            self.report.servers += 1;
+            if !probe.is_stored() && probe.flag().parse::<Country>().is_err() {
+                self.report.corrected_flags += 1;
+            }
+            // Servers in countries the filter does not keep are never parsed in full.
+            if let Some(filter) = self.filter {
+                if !filter.may_pass_country(flag) {
+                    continue;
+                }
+            }
+
+            let server = probe.parse(&raw).map_err(A::Error::custom)?;
+            let keep = match self.filter {
+                Some(filter) => filter.filter(&server),
+                None => true,
+            };
+            if keep {
+                servers.push(server);
+            }
+        }
+
+        Ok(servers)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 /// A server by NordVPN.
 pub struct Server {
     /// The country this server is located in.
-    pub flag: String,
+    pub flag: Country,
     /// The domain of this server.
-    pub domain: String,
+    pub domain: Domain,
     /// The current load on this server.
     pub load: u8,
     /// Categories this server is in.
-    pub categories: Vec<ServerCategory>,
+    pub categories: Categories,
+    /// The names of the categories this server is in that are not known to this library (yet).
+    /// These are the ones that show up as `ServerCategory::UnknownServer` in `categories`.
+    #[serde(default)]
+    pub unknown_categories: Vec<String>,
     /// Features of the server
     pub features: Features,
+    /// The bandwidth of the connection of this server in Mbit/s, e.g. `10000` for the servers
+    /// NordVPN advertises as 10 Gbps. `None` if the API does not tell.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth_tier: Option<u32>,
+    /// The city this server is located in, e.g. `Brussels`. `None` if the API does not tell.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    /// When this server was added, in seconds since the Unix epoch. `None` if the API does not
+    /// tell. See [age](#method.age).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+    /// The score NordVPN gives this server to pick the one its apps connect to, higher being
+    /// better. `None` if the API does not tell.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hub_score: Option<u32>,
+    /// Whether NordVPN recommends this server. `None` if the API does not tell.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recommended: Option<bool>,
+    /// The JSON this server was read from. See [raw](#method.raw).
+    #[cfg(feature = "raw-json")]
+    #[serde(skip)]
+    pub(crate) raw: Option<Arc<serde_json::Value>>,
+}
+
+/// Servers are equal when all their fields are, regardless of the JSON they were read from.
+impl PartialEq for Server {
+    fn eq(&self, other: &Server) -> bool {
+        self.flag == other.flag
+            && self.domain == other.domain
+            && self.load == other.load
+            && self.categories == other.categories
+            && self.unknown_categories == other.unknown_categories
+            && self.features == other.features
+            && self.bandwidth_tier == other.bandwidth_tier
+            && self.city == other.city
+            && self.created_at == other.created_at
+            && self.hub_score == other.hub_score
+            && self.recommended == other.recommended
+    }
 }
 
+impl Eq for Server {}
+
 impl Hash for Server {
     fn hash<H: Hasher>(&self, hasher: &mut H) {
         self.domain.hash(hasher);
     }
 }
 
-impl From<ApiServer> for Server {
-    fn from(api_server: ApiServer) -> Server {
-        Server {
-            flag: api_server.flag,
-            domain: api_server.domain,
+/// Converts category names into categories, also returning the names of unknown categories.
+fn parse_categories(names: impl Iterator<Item = String>) -> (Categories, Vec<String>) {
+    let mut categories = Categories::new();
+    let mut unknown_categories = Vec::new();
+    for name in names {
+        let category = ServerCategory::from(name.clone());
+        if category == ServerCategory::UnknownServer {
+            unknown_categories.push(name);
+        }
+        categories.insert(category);
+    }
+
+    (categories, unknown_categories)
+}
+
+impl TryFrom<ApiServer> for Server {
+    type Error = ParseCountryError;
+
+    fn try_from(api_server: ApiServer) -> Result<Server, ParseCountryError> {
+        let (categories, unknown_categories) = parse_categories(
+            api_server
+                .categories
+                .into_iter()
+                .map(|server_type| server_type.name),
+        );
+
+        Ok(Server {
+            flag: parse_flag(&api_server.flag, &api_server.domain)?,
+            domain: api_server.domain.into(),
             load: api_server.load,
-            categories: Vec::from_iter(
-                api_server
-                    .categories
-                    .into_iter()
-                    .map(|server_type| ServerCategory::from(server_type.name)),
-            ),
+            categories,
+            unknown_categories,
             features: api_server.features,
-        }
+            bandwidth_tier: parse_bandwidth(&api_server.name),
+            // The legacy API does not name the city, nor tell when the server was added.
+            city: None,
+            created_at: None,
+            hub_score: api_server.hub_score.map(parse_hub_score),
+            recommended: api_server.recommended,
+            #[cfg(feature = "raw-json")]
+            raw: None,
+        })
     }
 }
 
@@ -167,27 +1234,267 @@ impl Server {
             None => None,
         }
     }
+
+    /// Returns the JSON this server was read from, as sent by the API. This gives access to fields
+    /// that are not part of Server (yet).
+    ///
+    /// Returns `None` for servers that were not read from an API response, such as servers read
+    /// from the cache.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::Servers;
+    /// let data = Servers::dummy_data();
+    ///
+    /// let raw = data.servers[0].raw().unwrap();
+    /// assert_eq!(raw["domain"], data.servers[0].domain.as_str());
+    /// ```
+    #[cfg(feature = "raw-json")]
+    pub fn raw(&self) -> Option<&serde_json::Value> {
+        self.raw.as_deref()
+    }
+
+    /// Returns how long ago this server was added, if known.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::Servers;
+    /// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    ///
+    /// let mut server = Servers::dummy_data().servers.remove(0);
+    /// let week = Duration::from_secs(7 * 24 * 3600);
+    /// let added = SystemTime::now() - week;
+    /// server.created_at = Some(added.duration_since(UNIX_EPOCH).unwrap().as_secs());
+    ///
+    /// assert!(server.age().unwrap() >= week);
+    /// ```
+    pub fn age(&self) -> Option<Duration> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        Some(now.saturating_sub(Duration::from_secs(self.created_at?)))
+    }
+
+    /// Returns whether any of the given protocols can be used to connect to this server.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::{Protocol, Servers};
+    /// let data = Servers::dummy_data();
+    /// let server = &data.servers[0];
+    ///
+    /// assert!(!server.supports_any(&[]));
+    /// assert_eq!(
+    ///     server.supports_any(&[Protocol::Tcp, Protocol::Udp]),
+    ///     server.features.openvpn_tcp || server.features.openvpn_udp
+    /// );
+    /// ```
+    pub fn supports_any(&self, protocols: &[Protocol]) -> bool {
+        protocols
+            .iter()
+            .any(|protocol| self.features.supports(protocol))
+    }
+
+    /// Returns whether all of the given protocols can be used to connect to this server.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::{Protocol, Servers};
+    /// let data = Servers::dummy_data();
+    /// let server = &data.servers[0];
+    ///
+    /// assert!(server.supports_all(&[]));
+    /// assert_eq!(
+    ///     server.supports_all(&[Protocol::Tcp, Protocol::Udp]),
+    ///     server.features.openvpn_tcp && server.features.openvpn_udp
+    /// );
+    /// ```
+    pub fn supports_all(&self, protocols: &[Protocol]) -> bool {
+        protocols
+            .iter()
+            .all(|protocol| self.features.supports(protocol))
+    }
+
+    /// Returns the host and port of the SOCKS5 proxy of this server, if it has one. Connecting to
+    /// it requires the service credentials of a NordVPN account.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::{Protocol, Servers};
+    /// use nordselect::filters::ProtocolFilter;
+    /// let mut data = Servers::dummy_data();
+    /// data.filter(&ProtocolFilter::from(Protocol::Socks));
+    ///
+    /// for server in &data.servers {
+    ///     assert_eq!(server.socks_endpoint(), Some((server.domain.as_str(), 1080)));
+    /// }
+    /// ```
+    pub fn socks_endpoint(&self) -> Option<(&str, u16)> {
+        if self.features.socks {
+            Some((&self.domain, SOCKS_PORT))
+        } else {
+            None
+        }
+    }
+
+    /// Returns a `nordvpn://` link that connects to this server when opened, in the NordVPN apps
+    /// that support such links.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nordselect::Servers;
+    /// let data = Servers::dummy_data();
+    /// let server = data.perfect_server().unwrap();
+    ///
+    /// assert_eq!(
+    ///     server.nordvpn_uri(),
+    ///     format!("nordvpn://connect?server={}", server.domain)
+    /// );
+    /// ```
+    pub fn nordvpn_uri(&self) -> String {
+        format!("{}connect?server={}", NORDVPN_URI_SCHEME, self.domain)
+    }
+
+    /// Returns the countries of both hops of this Double VPN server: the country traffic enters
+    /// the VPN in, and the country it leaves the VPN in. NordVPN names these servers after both
+    /// countries, e.g. `nl-ch10.nordvpn.com`.
+    ///
+    /// Returns `None` for servers that are not Double VPN servers.
+    pub fn double_chain(&self) -> Option<(Country, Country)> {
+        if !self.categories.contains(&ServerCategory::Double) {
+            return None;
+        }
+
+        let name = self.domain.split('.').next()?;
+        let (entry, exit) = name.split_once('-')?;
+        let exit = exit.trim_end_matches(|c: char| c.is_ascii_digit());
+        Some((entry.parse().ok()?, exit.parse().ok()?))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// A Double VPN server, together with the countries of both of its hops. See
+/// [Servers::best_double_chain](struct.Servers.html#method.best_double_chain).
+pub struct DoubleChain {
+    /// The server to connect to.
+    pub server: Server,
+    /// The country traffic enters the VPN in, which is where the server is located.
+    pub entry: Country,
+    /// The country traffic leaves the VPN in.
+    pub exit: Country,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How [Servers::sample](struct.Servers.html#method.sample) picks its servers.
+pub enum SampleStrategy {
+    /// Every server is equally likely to be picked.
+    Random,
+    /// Every country gets a share of the sample matching its share of the servers, so countries
+    /// with few servers are not left out by chance.
+    PerCountry,
 }
 
-/// A list of individual servers.
+/// The port NordVPN servers accept SOCKS5 connections on.
+const SOCKS_PORT: u16 = 1080;
+
+/// The scheme of the links the NordVPN apps open.
+const NORDVPN_URI_SCHEME: &str = "nordvpn://";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+/// A list of individual servers. It is (de)serialized as the list of servers itself.
 pub struct Servers {
     /// The actual servers
     pub servers: Vec<Server>,
 }
 
-/// Functions to build and read data from the Servers.
-impl Servers {
-    /// Creates a Servers by reading the given text.
-    fn from_txt(txt: &str) -> Result<Servers, Box<dyn std::error::Error>> {
-        let api_servers: Vec<ApiServer> = serde_json::from_str(&txt)?;
+/// Functions to build and read data from the Servers.
+impl Servers {
+    /// Creates a Servers by reading the given text, which can be a response of either the legacy
+    /// or the v1 API.
+    pub(crate) fn from_txt(txt: &str) -> Result<Servers, Box<dyn std::error::Error>> {
+        Self::from_reader_filtered(txt.as_bytes(), None)
+    }
+
+    /// Creates a Servers by reading a server list, such as a saved response of the legacy or the
+    /// v1 API, or a list stored by this library. Returns an error if it can not be read or parsed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nordselect::Servers;
+    ///
+    /// let file = std::fs::File::open("dummydata").unwrap();
+    /// let data = Servers::from_reader(file).unwrap();
+    /// assert!(data.flags().contains("BE"));
+    /// ```
+    pub fn from_reader<R: Read>(reader: R) -> Result<Servers, Box<dyn std::error::Error>> {
+        Self::from_reader_filtered(reader, None)
+    }
+
+    /// Creates a Servers by parsing the API response. Servers are checked against the given
+    /// filter as soon as they are parsed, so servers that would be filtered out anyway are never
+    /// stored. Servers in countries the filter does not keep are not even parsed in full, see
+    /// [Filter::may_pass_country](../filters/trait.Filter.html#method.may_pass_country).
+    pub(crate) fn from_reader_filtered<R: Read>(
+        reader: R,
+        filter: Option<&dyn Filter>,
+    ) -> Result<Servers, Box<dyn std::error::Error>> {
+        Self::from_reader_reporting(reader, filter).map(|(servers, _)| servers)
+    }
+
+    /// Same as [from_reader](#method.from_reader), but also reports how many servers had to be
+    /// corrected or left out while reading them.
+    ///
+    /// Servers without a known country get the country their domain starts with, e.g.
+    /// `uk1944.nordvpn.com` is put in the United Kingdom. Servers of which the domain does not
+    /// tell the country either are left out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nordselect::Servers;
+    ///
+    /// let file = std::fs::File::open("dummydata").unwrap();
+    /// let (data, report) = Servers::from_reader_with_report(file).unwrap();
+    /// assert_eq!(report.servers, data.servers.len());
+    /// ```
+    pub fn from_reader_with_report<R: Read>(
+        reader: R,
+    ) -> Result<(Servers, ParseReport), Box<dyn std::error::Error>> {
+        Self::from_reader_reporting(reader, None)
+    }
+
+    pub(crate) fn from_reader_reporting<R: Read>(
+        reader: R,
+        filter: Option<&dyn Filter>,
+    ) -> Result<(Servers, ParseReport), Box<dyn std::error::Error>> {
+        // The list is parsed while it is read, so only one server is in memory as text at a time.
+        let mut report = ParseReport::default();
+        let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(reader));
+        let servers = FilteredServers {
+            filter,
+            report: &mut report,
+        }
+        .deserialize(&mut deserializer)?;
+        deserializer.end()?;
 
-        Ok(Servers {
-            servers: Vec::from_iter(api_servers.into_iter().map(Server::from)),
-        })
+        Ok((Servers { servers }, report))
     }
+}
 
+/// Ways to download the server list from the API.
+#[cfg(feature = "native")]
+impl Servers {
     /// Downloads the list of servers from the API. Returns an error on failure.
     ///
+    /// Both the legacy and the v1 API are queried at the same time, so an outage of one of them
+    /// does not matter. See [from_endpoints](#method.from_endpoints) for details.
+    ///
     /// # Examples
     ///
     /// ```
@@ -195,12 +1502,133 @@ impl Servers {
     /// assert!(data.is_ok());
     /// ```
     pub fn from_api() -> Result<Servers, Box<dyn std::error::Error>> {
-        let data = reqwest::blocking::get("https://nordvpn.com/api/server")?;
-        let text = data.text()?;
+        Self::from_endpoints(&[api::LEGACY_ENDPOINT, api::V1_ENDPOINT])
+    }
+
+    /// Same as [from_api](#method.from_api), but sends the requests using the given client. This
+    /// way, an own reqwest client can be used, with its connection pool, proxies and TLS
+    /// settings.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use nordselect::api::ApiClient;
+    ///
+    /// let client = ApiClient::from(reqwest::blocking::Client::new());
+    /// let data = nordselect::Servers::from_api_with_client(&client);
+    /// ```
+    pub fn from_api_with_client(
+        client: &api::ApiClient,
+    ) -> Result<Servers, Box<dyn std::error::Error>> {
+        Self::from_endpoints_with_client(client, &[api::LEGACY_ENDPOINT, api::V1_ENDPOINT], None)
+    }
+
+    /// Downloads the list of servers from all given URLs at the same time. These can point to the
+    /// legacy API, the v1 API or a mirror of either of them.
+    ///
+    /// The first list that is received is used. Lists that arrive shortly after are merged into
+    /// it. Only when every URL fails, an error is returned.
+    pub fn from_endpoints<S: AsRef<str>>(
+        endpoints: &[S],
+    ) -> Result<Servers, Box<dyn std::error::Error>> {
+        api::download_first(&api::ApiClient::default(), endpoints, None)
+    }
+
+    /// Downloads the list of servers from the v1 API in pages of `page_size` servers, a few pages
+    /// at the same time. This is slower than [from_api](#method.from_api), but every request is
+    /// small enough not to time out on slow links. See
+    /// [DEFAULT_PAGE_SIZE](../api/constant.DEFAULT_PAGE_SIZE.html).
+    ///
+    /// `progress` is called after every page.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use nordselect::api::{self, ApiClient};
+    ///
+    /// let data = nordselect::Servers::from_api_paged(
+    ///     &ApiClient::new(),
+    ///     api::DEFAULT_PAGE_SIZE,
+    ///     |progress| eprintln!("{} servers", progress.servers),
+    /// );
+    /// ```
+    pub fn from_api_paged(
+        client: &api::ApiClient,
+        page_size: usize,
+        mut progress: impl FnMut(api::PageProgress),
+    ) -> Result<Servers, Box<dyn std::error::Error>> {
+        api::download_paged(client, page_size, &mut progress)
+    }
+
+    /// Same as [from_endpoints](#method.from_endpoints), but only keeps the servers that match
+    /// the given filter. The filter is applied while the response is parsed, which saves a lot of
+    /// memory when only a small part of the servers is needed.
+    ///
+    /// Use this for cheap filters, like the ones on country or category.
+    pub fn from_endpoints_filtered<S: AsRef<str>>(
+        endpoints: &[S],
+        filter: Arc<dyn Filter + Send + Sync>,
+    ) -> Result<Servers, Box<dyn std::error::Error>> {
+        api::download_first(&api::ApiClient::default(), endpoints, Some(filter))
+    }
+
+    /// Same as [from_endpoints](#method.from_endpoints), but sends the requests using the given
+    /// client, e.g. to limit how often the API is queried. Only servers matching the filter are
+    /// kept, if one is given.
+    pub fn from_endpoints_with_client<S: AsRef<str>>(
+        client: &api::ApiClient,
+        endpoints: &[S],
+        filter: Option<Arc<dyn Filter + Send + Sync>>,
+    ) -> Result<Servers, Box<dyn std::error::Error>> {
+        api::download_first(client, endpoints, filter)
+    }
+
+    /// Downloads only the servers in the given countries, by asking the v1 API for one country at
+    /// a time. Only servers matching the filter are kept. Countries should be uppercase
+    /// [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2) codes.
+    ///
+    /// This is a lot faster than downloading the full list, but it depends on a single API. If a
+    /// country is not known to this library, or if the v1 API fails, this falls back to
+    /// [from_endpoints_filtered](#method.from_endpoints_filtered) with the default endpoints. The
+    /// filter should therefore filter on the countries as well.
+    pub fn from_api_in_countries<S: AsRef<str>>(
+        countries: &[S],
+        filter: Arc<dyn Filter + Send + Sync>,
+    ) -> Result<Servers, Box<dyn std::error::Error>> {
+        Self::from_api_in_countries_with_client(&api::ApiClient::default(), countries, filter)
+    }
+
+    /// Same as [from_api_in_countries](#method.from_api_in_countries), but sends the requests
+    /// using the given client, including the ones of the fallback.
+    pub fn from_api_in_countries_with_client<S: AsRef<str>>(
+        client: &api::ApiClient,
+        countries: &[S],
+        filter: Arc<dyn Filter + Send + Sync>,
+    ) -> Result<Servers, Box<dyn std::error::Error>> {
+        let country_ids: Option<Vec<u32>> = countries
+            .iter()
+            .map(|country| countries::nord_id(country.as_ref()))
+            .collect();
+
+        if let Some(country_ids) = country_ids {
+            let endpoints: Vec<String> = country_ids
+                .into_iter()
+                .map(api::v1_country_endpoint)
+                .collect();
+            if let Ok(servers) = api::download_all(client, &endpoints, Some(filter.clone())) {
+                return Ok(servers);
+            }
+        }
 
-        Self::from_txt(&text)
+        Self::from_endpoints_with_client(
+            client,
+            &[api::LEGACY_ENDPOINT, api::V1_ENDPOINT],
+            Some(filter),
+        )
     }
+}
 
+impl Servers {
     /// Returns the data, fetched out of the `dummydata` file, generated using `dummydata.sh`.
     ///
     /// Use this only for debugging, testing and benchmarking.
@@ -230,7 +1658,130 @@ impl Servers {
     /// assert!(!data.flags().contains("EU")); // The EU is not a country
     /// ```
     pub fn flags(&self) -> HashSet<&str> {
-        HashSet::from_iter(self.servers.iter().map(|server| server.flag.as_ref()))
+        HashSet::from_iter(self.servers.iter().map(|server| server.flag.as_str()))
+    }
+
+    /// Splits the servers into groups sharing the same key, e.g. their country. Servers keep their
+    /// order within every group.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nordselect::{Country, Servers};
+    /// let data = Servers::dummy_data();
+    ///
+    /// let per_country = data.group_by(|server| server.flag);
+    /// let belgium: Country = "BE".parse().unwrap();
+    /// assert!(per_country[&belgium].servers.iter().all(|server| server.flag == "BE"));
+    /// assert_eq!(per_country.len(), data.flags().len());
+    /// ```
+    pub fn group_by<K, F>(&self, mut key: F) -> HashMap<K, Servers>
+    where
+        K: Eq + Hash,
+        F: FnMut(&Server) -> K,
+    {
+        let mut groups: HashMap<K, Servers> = HashMap::new();
+        for server in &self.servers {
+            groups
+                .entry(key(server))
+                .or_insert_with(|| Servers {
+                    servers: Vec::new(),
+                })
+                .servers
+                .push(server.clone());
+        }
+
+        groups
+    }
+
+    /// Returns the cities with servers in every country, together with the amount of servers in
+    /// each of them. Both are sorted: countries on their code and cities on their name.
+    ///
+    /// Servers of which the city is not known are not counted, but their country is still in
+    /// the tree, without cities if need be.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nordselect::{Country, Servers};
+    /// let mut data = Servers::dummy_data();
+    /// for server in data.servers.iter_mut().filter(|server| server.flag == "BE") {
+    ///     server.city = Some("Brussels".to_string());
+    /// }
+    ///
+    /// let locations = data.locations();
+    /// let belgium: Country = "BE".parse().unwrap();
+    /// let belgian = data.servers.iter().filter(|server| server.flag == "BE").count();
+    /// assert_eq!(locations[&belgium]["Brussels"], belgian);
+    /// assert_eq!(locations.len(), data.flags().len());
+    /// ```
+    pub fn locations(&self) -> BTreeMap<Country, BTreeMap<String, usize>> {
+        let mut locations: BTreeMap<Country, BTreeMap<String, usize>> = BTreeMap::new();
+        for server in &self.servers {
+            let cities = locations.entry(server.flag).or_default();
+            if let Some(city) = &server.city {
+                *cities.entry(city.clone()).or_insert(0) += 1;
+            }
+        }
+
+        locations
+    }
+
+    /// Returns the least loaded server of every country. Ties are broken on domain. Lists that
+    /// are asked this more than once can be indexed once instead, see
+    /// [ServersIndex](../index/struct.ServersIndex.html).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nordselect::Servers;
+    /// let data = Servers::dummy_data();
+    ///
+    /// let best = data.best_per_country();
+    /// for (country, server) in &best {
+    ///     assert_eq!(server.flag, *country);
+    /// }
+    /// assert_eq!(best.len(), data.flags().len());
+    /// ```
+    pub fn best_per_country(&self) -> HashMap<Country, Server> {
+        ServersIndex::new(self).best_per_country(self)
+    }
+
+    /// Returns the country of which the least loaded server gets the best score from the given
+    /// Benchmarker. Only one server per country is measured, which takes far less probes than
+    /// measuring all servers, after which the servers of that country can be measured.
+    ///
+    /// Returns `None` if no server could be scored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nordselect::Servers;
+    /// use nordselect::bench::LoadBenchmarker;
+    /// let data = Servers::dummy_data();
+    ///
+    /// let country = data.nearest_country(&LoadBenchmarker).unwrap();
+    /// assert!(country.is_some());
+    /// ```
+    pub fn nearest_country(
+        &self,
+        benchmarker: &dyn Benchmarker,
+    ) -> Result<Option<Country>, Box<dyn std::error::Error>> {
+        let representatives = Servers {
+            servers: self.best_per_country().into_values().collect(),
+        };
+        let scores = benchmarker.benchmark(&representatives)?;
+
+        Ok(representatives
+            .servers
+            .iter()
+            .filter_map(|server| scores.get(&server.domain).map(|score| (score, server)))
+            .min_by(|(a, a_server), (b, b_server)| {
+                a.partial_cmp(b)
+                    .unwrap_or(Ordering::Equal)
+                    .then_with(|| a_server.domain.cmp(&b_server.domain))
+            })
+            .map(|(_, server)| server.flag))
     }
 
     /// Returns the best server, according to the given values. This should be called after all the
@@ -257,14 +1808,271 @@ impl Servers {
             None => None,
         }
     }
+
+    /// Picks a number of servers for every wanted filter, e.g. two P2P servers and one Tor server,
+    /// in a single pass over the servers. Like [perfect_server](#method.perfect_server), this
+    /// follows the current order of the servers, so it should be called after sorting them.
+    ///
+    /// Every server is picked at most once, for the first wanted filter that it matches and that
+    /// still needs servers. Returns the picked servers for every wanted filter, in the order they
+    /// were given. A list is shorter than wanted when not enough servers match.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nordselect::{Servers, ServerCategory};
+    /// use nordselect::filters::CategoryFilter;
+    /// let data = Servers::dummy_data();
+    ///
+    /// let p2p = CategoryFilter::from(ServerCategory::P2P);
+    /// let obfuscated = CategoryFilter::from(ServerCategory::Obfuscated);
+    /// let picked = data.best_matching_counts(&[(&p2p, 2), (&obfuscated, 1)]);
+    /// assert_eq!(picked[0].len(), 2);
+    /// assert_eq!(picked[1].len(), 1);
+    /// assert!(picked[0]
+    ///     .iter()
+    ///     .all(|server| server.categories.contains(&ServerCategory::P2P)));
+    /// ```
+    pub fn best_matching_counts(&self, wanted: &[(&dyn Filter, usize)]) -> Vec<Vec<Server>> {
+        let mut picked: Vec<Vec<Server>> = wanted
+            .iter()
+            .map(|(_, count)| Vec::with_capacity(*count))
+            .collect();
+        let mut missing: usize = wanted.iter().map(|(_, count)| count).sum();
+
+        for server in &self.servers {
+            if missing == 0 {
+                break;
+            }
+            let slot = wanted
+                .iter()
+                .zip(picked.iter_mut())
+                .find(|((filter, count), picked)| picked.len() < *count && filter.filter(server));
+            if let Some((_, picked)) = slot {
+                picked.push(server.clone());
+                missing -= 1;
+            }
+        }
+
+        picked
+    }
+
+    /// Returns the least loaded Double VPN server of which the entry hop matches `entry_filter`
+    /// and the exit hop matches `exit_filter`.
+    ///
+    /// The entry filter is applied to the server itself. The exit filter is applied to the server
+    /// as if it were located in the exit country, so country and region filters work for both.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nordselect::Servers;
+    /// use nordselect::filters::{CountriesFilter, CountryFilter, Region};
+    /// let data = Servers::dummy_data();
+    ///
+    /// let chain = data.best_double_chain(
+    ///     &CountryFilter::from("NL"),
+    ///     &CountriesFilter::from(Region::from_str("EU").unwrap()),
+    /// );
+    /// if let Some(chain) = chain {
+    ///     assert_eq!(chain.entry, "NL");
+    ///     assert_eq!(chain.server.flag, "NL");
+    ///     assert_ne!(chain.exit, "NL");
+    /// }
+    /// ```
+    pub fn best_double_chain(
+        &self,
+        entry_filter: &dyn Filter,
+        exit_filter: &dyn Filter,
+    ) -> Option<DoubleChain> {
+        self.servers
+            .iter()
+            .filter(|server| entry_filter.filter(server))
+            .filter_map(|server| {
+                let (entry, exit) = server.double_chain()?;
+                let mut exit_hop = server.clone();
+                exit_hop.flag = exit;
+                if exit_filter.filter(&exit_hop) {
+                    Some(DoubleChain {
+                        server: server.clone(),
+                        entry,
+                        exit,
+                    })
+                } else {
+                    None
+                }
+            })
+            .min_by(|a, b| {
+                a.server
+                    .load
+                    .cmp(&b.server.load)
+                    .then_with(|| a.server.domain.cmp(&b.server.domain))
+            })
+    }
+
+    /// Picks `amount` servers, e.g. to benchmark a representative part of a long list instead of
+    /// all of it. The same seed always picks the same servers. The picked servers keep their
+    /// order, and all servers are returned if there are no more than `amount`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nordselect::Servers;
+    /// use nordselect::servers::SampleStrategy;
+    /// let data = Servers::dummy_data();
+    ///
+    /// let sample = data.sample(20, SampleStrategy::PerCountry, 42);
+    /// assert_eq!(sample.servers.len(), 20);
+    /// assert_eq!(sample.servers, data.sample(20, SampleStrategy::PerCountry, 42).servers);
+    /// ```
+    pub fn sample(&self, amount: usize, strategy: SampleStrategy, seed: u64) -> Servers {
+        if amount >= self.servers.len() {
+            return Servers {
+                servers: self.servers.clone(),
+            };
+        }
+
+        let mut shuffled: Vec<(usize, &Server)> = self.servers.iter().enumerate().collect();
+        shuffled.sort_by_key(|(_, server)| bench::tie_breaker(&server.domain, seed));
+
+        let mut picked: Vec<usize> = match strategy {
+            SampleStrategy::Random => shuffled
+                .into_iter()
+                .take(amount)
+                .map(|(index, _)| index)
+                .collect(),
+            SampleStrategy::PerCountry => {
+                // Every country gets its proportional share rounded down, after which the servers
+                // left over go to the countries that were rounded down the most.
+                let total = self.servers.len();
+                let mut sizes: HashMap<Country, usize> = HashMap::new();
+                for server in &self.servers {
+                    *sizes.entry(server.flag).or_insert(0) += 1;
+                }
+                let mut countries: Vec<(Country, usize)> = sizes.into_iter().collect();
+                countries.sort_by(|(a, a_size), (b, b_size)| {
+                    (b_size * amount % total)
+                        .cmp(&(a_size * amount % total))
+                        .then_with(|| a.cmp(b))
+                });
+                let mut left = amount
+                    - countries
+                        .iter()
+                        .map(|(_, size)| size * amount / total)
+                        .sum::<usize>();
+                let shares: HashMap<Country, usize> = countries
+                    .into_iter()
+                    .map(|(country, size)| {
+                        let extra = if left > 0 { 1 } else { 0 };
+                        left -= extra;
+                        (country, size * amount / total + extra)
+                    })
+                    .collect();
+
+                let mut taken: HashMap<Country, usize> = HashMap::new();
+                shuffled
+                    .into_iter()
+                    .filter(|(_, server)| {
+                        let taken = taken.entry(server.flag).or_insert(0);
+                        *taken += 1;
+                        *taken <= shares[&server.flag]
+                    })
+                    .map(|(index, _)| index)
+                    .collect()
+            }
+        };
+        picked.sort_unstable();
+
+        Servers {
+            servers: picked
+                .into_iter()
+                .map(|index| self.servers[index].clone())
+                .collect(),
+        }
+    }
+}
+
+/// The differences between two snapshots of the server list, as returned by
+/// [Servers::diff](struct.Servers.html#method.diff).
+///
+/// Servers are matched on their domain. All lists are sorted on domain.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ServerDiff {
+    /// Servers that only appear in the new snapshot.
+    pub added: Vec<Server>,
+    /// Servers that only appear in the old snapshot.
+    pub removed: Vec<Server>,
+    /// Servers that appear in both snapshots, but with a different load. Stored as `(old, new)`.
+    pub load_changed: Vec<(Server, Server)>,
+}
+
+impl ServerDiff {
+    /// Returns whether both snapshots contain the same servers with the same load.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.load_changed.is_empty()
+    }
+}
+
+/// Comparing different sets of servers.
+impl Servers {
+    /// Compares two snapshots of the server list, e.g. a cached one and a freshly downloaded one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nordselect::{Servers, filters};
+    /// let old = Servers::dummy_data();
+    /// let mut new = Servers::dummy_data();
+    /// new.filter(&filters::NegatingFilter::new(filters::CountryFilter::from("BE")));
+    ///
+    /// let diff = Servers::diff(&old, &new);
+    /// assert!(diff.added.is_empty());
+    /// assert!(diff.load_changed.is_empty());
+    /// assert!(diff.removed.iter().all(|server| server.flag == "BE"));
+    /// ```
+    pub fn diff(old: &Servers, new: &Servers) -> ServerDiff {
+        let old_servers: HashMap<&str, &Server> = HashMap::from_iter(
+            old.servers
+                .iter()
+                .map(|server| (server.domain.as_str(), server)),
+        );
+        let new_domains: HashSet<&str> =
+            HashSet::from_iter(new.servers.iter().map(|server| server.domain.as_str()));
+
+        let mut diff = ServerDiff::default();
+        for server in &new.servers {
+            match old_servers.get(server.domain.as_str()) {
+                None => diff.added.push(server.clone()),
+                Some(old_server) if old_server.load != server.load => diff
+                    .load_changed
+                    .push(((*old_server).clone(), server.clone())),
+                Some(_) => {}
+            }
+        }
+        diff.removed.extend(
+            old.servers
+                .iter()
+                .filter(|server| !new_domains.contains(server.domain.as_str()))
+                .cloned(),
+        );
+
+        diff.added.sort_unstable_by(|a, b| a.domain.cmp(&b.domain));
+        diff.removed.sort_unstable_by(|a, b| a.domain.cmp(&b.domain));
+        diff.load_changed
+            .sort_unstable_by(|(a, _), (b, _)| a.domain.cmp(&b.domain));
+        diff
+    }
 }
 
-#[derive(PartialEq)]
-/// A protocol to connect to the VPN server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// A protocol to connect to the VPN server. It is (de)serialized as the name the API uses, e.g.
+/// `openvpn_udp`.
 pub enum Protocol {
     /// OpenVPN over the [User Datagram Protocol](https://en.wikipedia.org/wiki/User_Datagram_Protocol)
+    #[serde(rename = "openvpn_udp")]
     Udp,
     /// OpenVPN over the [Transmission Control Protocol](https://en.wikipedia.org/wiki/Transmission_Control_Protocol)
+    #[serde(rename = "openvpn_tcp")]
     Tcp,
     /// The older Point-to-Point Tunneling Protocol
     ///
@@ -273,6 +2081,7 @@ pub enum Protocol {
     /// From the NordVPN site:
     /// > Although technically you can use the L2TP/PPTP protocol, it has serious security flaws.
     /// > Whenever possible, we recommend choosing OpenVPN or IKEv2/IPSec instead.
+    #[serde(rename = "pptp")]
     Pptp,
     /// The Layer 2 Tunneling Protocol
     ///
@@ -281,30 +2090,59 @@ pub enum Protocol {
     /// From the NordVPN site:
     /// > Although technically you can use the L2TP/PPTP protocol, it has serious security flaws.
     /// > Whenever possible, we recommend choosing OpenVPN or IKEv2/IPSec instead.
+    #[serde(rename = "l2tp")]
     L2tp,
     /// OpenVPN over TCP with xor obfuscation
+    #[serde(rename = "openvpn_xor_tcp")]
     OpenVPNXTcp,
     /// OpenVPN over UDP with xor obfuscation
+    #[serde(rename = "openvpn_xor_udp")]
     OpenVPNXUdp,
     /// Support for the SOCKS protocol.
+    #[serde(rename = "socks")]
     Socks,
     /// Support for a proxy with CyberSec
+    #[serde(rename = "proxy_cybersec")]
     CyberSecProxy,
     /// Support for a proxy with SSL
+    #[serde(rename = "proxy_ssl")]
     SslProxy,
     /// Support for a proxy with CyberSec and SSL
+    #[serde(rename = "proxy_ssl_cybersec")]
     CyberSecSslProxy,
     /// Use the server as a proxy
+    #[serde(rename = "proxy")]
     Proxy,
     /// WireGuard over UDP
+    #[serde(rename = "wireguard_udp")]
     WireGuardUdp,
 }
 
+impl Protocol {
+    /// Returns the name the API uses for this protocol.
+    pub(crate) fn api_key(&self) -> &'static str {
+        match self {
+            Protocol::Udp => "openvpn_udp",
+            Protocol::Tcp => "openvpn_tcp",
+            Protocol::Pptp => "pptp",
+            Protocol::L2tp => "l2tp",
+            Protocol::OpenVPNXTcp => "openvpn_xor_tcp",
+            Protocol::OpenVPNXUdp => "openvpn_xor_udp",
+            Protocol::Socks => "socks",
+            Protocol::CyberSecProxy => "proxy_cybersec",
+            Protocol::SslProxy => "proxy_ssl",
+            Protocol::CyberSecSslProxy => "proxy_ssl_cybersec",
+            Protocol::Proxy => "proxy",
+            Protocol::WireGuardUdp => "wireguard_udp",
+        }
+    }
+}
+
 /// All manipulations that will alter the servers.
 impl Servers {
     /// Applies the given filter on this serverlist.
     pub fn filter(&mut self, filter: &dyn Filter) {
-        (&mut self.servers).retain(|server| filter.filter(&server))
+        filter.retain(&mut self.servers)
     }
 
     /// Sorts the servers using a Sorter. The sort is unstable.
@@ -312,6 +2150,68 @@ impl Servers {
         (&mut self.servers).sort_unstable_by(|x, y| sorter.sort(x, y));
     }
 
+    /// Scores the servers using a Benchmarker and sorts them on their score, best first. Servers
+    /// the Benchmarker could not score are placed last. Servers with the same score are ordered on
+    /// their load, and then on their domain.
+    pub fn benchmark(
+        &mut self,
+        benchmarker: &dyn Benchmarker,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let scores = benchmarker.benchmark(self)?;
+        let servers = std::mem::take(&mut self.servers);
+        self.servers = bench::rank(servers, &scores, ErrorPolicy::TreatAsWorst, None)?
+            .into_iter()
+            .map(|scored| scored.server)
+            .collect();
+
+        Ok(())
+    }
+
+    /// Adds the servers of `other` to this list. Servers that are in both lists are kept once, with
+    /// the load and other details of `other`, which is assumed to be the newest list.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nordselect::Servers;
+    /// let mut cached = Servers::dummy_data();
+    /// let mut fresh = Servers::dummy_data();
+    /// fresh.servers[0].load = 100;
+    /// let domain = fresh.servers[0].domain.clone();
+    ///
+    /// cached.merge(fresh);
+    /// assert_eq!(cached.servers.len(), Servers::dummy_data().servers.len());
+    /// assert!(cached
+    ///     .servers
+    ///     .iter()
+    ///     .any(|server| server.domain == domain && server.load == 100));
+    /// ```
+    pub fn merge(&mut self, other: Servers) {
+        self.dedup();
+        let mut positions: HashMap<Domain, usize> = HashMap::from_iter(
+            self.servers
+                .iter()
+                .enumerate()
+                .map(|(position, server)| (server.domain.clone(), position)),
+        );
+
+        for server in other.servers {
+            match positions.get(&server.domain) {
+                Some(&position) => self.servers[position] = server,
+                None => {
+                    positions.insert(server.domain.clone(), self.servers.len());
+                    self.servers.push(server);
+                }
+            }
+        }
+    }
+
+    /// Removes servers with the same domain as a server before them.
+    pub fn dedup(&mut self) {
+        let mut seen = HashSet::with_capacity(self.servers.len());
+        self.servers.retain(|server| seen.insert(server.domain.clone()));
+    }
+
     /// Removes all but the `max` best servers at the moment. Does nothing if there are less
     /// servers.
     ///
@@ -321,3 +2221,468 @@ impl Servers {
         self.servers.truncate(max);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::CountryFilter;
+    use crate::test_util::arb_servers;
+    use proptest::prelude::*;
+
+    #[test]
+    fn parse_v1_response() {
+        let text = r#"[{
+            "hostname": "be148.nordvpn.com",
+            "load": 12,
+            "status": "online",
+            "locations": [{"country": {"id": 21, "name": "Belgium", "code": "BE"}}],
+            "groups": [
+                {"title": "P2P", "type": {"identifier": "legacy_group_category"}},
+                {"title": "Europe", "type": {"identifier": "regions"}}
+            ],
+            "technologies": [{"identifier": "openvpn_tcp"}, {"identifier": "wireguard_udp"}],
+            "created_at": "2018-10-09 14:46:13"
+        }]"#;
+
+        let servers = Servers::from_txt(text).unwrap().servers;
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].flag, "BE");
+        assert_eq!(servers[0].domain, "be148.nordvpn.com");
+        assert_eq!(
+            servers[0].categories.iter().collect::<Vec<_>>(),
+            vec![ServerCategory::P2P]
+        );
+        assert!(servers[0].features.openvpn_tcp);
+        assert!(servers[0].features.wireguard_udp);
+        assert!(!servers[0].features.openvpn_udp);
+        assert_eq!(servers[0].created_at, Some(1_539_096_373));
+    }
+
+    #[test]
+    fn recommendation_hints() {
+        let text = r#"[
+            {"hostname": "nl1.nordvpn.com", "load": 12, "locations": [], "groups": [],
+             "technologies": [], "hub_score": 87.6, "recommended": true},
+            {"hostname": "nl2.nordvpn.com", "load": 12, "locations": [], "groups": [],
+             "technologies": []}
+        ]"#;
+
+        let data = Servers::from_txt(text).unwrap();
+        assert_eq!(data.servers[0].hub_score, Some(88));
+        assert_eq!(data.servers[0].recommended, Some(true));
+        assert_eq!(data.servers[1].hub_score, None);
+        assert_eq!(data.servers[1].recommended, None);
+
+        // The hints are kept in stored lists, and left out when unknown.
+        let stored = serde_json::to_string(&data).unwrap();
+        assert_eq!(stored.matches("hub_score").count(), 1);
+        assert_eq!(Servers::from_txt(&stored).unwrap(), data);
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(parse_timestamp("1970-01-01 00:00:00"), Some(0));
+        assert_eq!(parse_timestamp("2000-02-29T12:00:00Z"), Some(951_825_600));
+        assert_eq!(
+            parse_timestamp("2024-03-01 00:00:00.5"),
+            Some(1_709_251_200)
+        );
+        assert_eq!(parse_timestamp("2024-13-01 00:00:00"), None);
+        assert_eq!(parse_timestamp("1969-12-31 23:59:59"), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+
+    #[test]
+    fn bandwidth_tiers() {
+        let text = r#"[
+            {"hostname": "us1.nordvpn.com", "name": "United States #1 (10 Gbps)", "load": 12,
+             "locations": [], "groups": [], "technologies": []},
+            {"hostname": "us2.nordvpn.com", "load": 12, "locations": [], "groups": [],
+             "technologies": [], "specifications": [
+                {"identifier": "version", "title": "Version", "values": [{"value": "2.1"}]},
+                {"identifier": "port_speed", "title": "Port speed (Gbps)",
+                 "values": [{"value": 10}]}
+             ]},
+            {"hostname": "us3.nordvpn.com", "load": 12, "locations": [], "groups": [],
+             "technologies": []}
+        ]"#;
+
+        let tiers: Vec<Option<u32>> = Servers::from_txt(text)
+            .unwrap()
+            .servers
+            .iter()
+            .map(|server| server.bandwidth_tier)
+            .collect();
+        assert_eq!(tiers, vec![Some(10_000), Some(10_000), None]);
+
+        assert_eq!(parse_bandwidth("Belgium #3 - 500Mbps"), Some(500));
+        assert_eq!(parse_bandwidth("1 Gbit/s"), Some(1000));
+        assert_eq!(parse_bandwidth("Belgium #10"), None);
+    }
+
+    #[test]
+    fn city_locations() {
+        let text = r#"[
+            {"hostname": "be1.nordvpn.com", "load": 12, "groups": [], "technologies": [],
+             "locations": [{"country": {"code": "BE", "city": {"name": "Brussels"}}}]},
+            {"hostname": "be2.nordvpn.com", "load": 12, "groups": [], "technologies": [],
+             "locations": [{"country": {"code": "BE", "city": {"name": "Brussels"}}}]},
+            {"hostname": "us1.nordvpn.com", "load": 12, "groups": [], "technologies": [],
+             "locations": [{"country": {"code": "US", "city": {"name": "New York"}}}]},
+            {"hostname": "us2.nordvpn.com", "load": 12, "groups": [], "technologies": [],
+             "locations": [{"country": {"code": "US", "city": {"name": "Dallas"}}}]},
+            {"hostname": "nl1.nordvpn.com", "load": 12, "groups": [], "technologies": [],
+             "locations": [{"country": {"code": "NL"}}]}
+        ]"#;
+        let data = Servers::from_txt(text).unwrap();
+        assert_eq!(data.servers[0].city.as_deref(), Some("Brussels"));
+        assert_eq!(data.servers[4].city, None);
+
+        let tree = data.locations();
+        let locations: Vec<(&str, Vec<(&str, usize)>)> = tree
+            .iter()
+            .map(|(country, cities)| {
+                let cities = cities
+                    .iter()
+                    .map(|(city, count)| (city.as_str(), *count))
+                    .collect();
+                (country.as_str(), cities)
+            })
+            .collect();
+        assert_eq!(
+            locations,
+            vec![
+                ("BE", vec![("Brussels", 2)]),
+                ("NL", vec![]),
+                ("US", vec![("Dallas", 1), ("New York", 1)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn infer_missing_flags() {
+        let text = r#"[
+            {"hostname": "uk1944.nordvpn.com", "load": 12, "locations": [], "groups": [],
+             "technologies": []},
+            {"hostname": "be148.nordvpn.com", "load": 12,
+             "locations": [{"country": {"code": "XX"}}], "groups": [], "technologies": []},
+            {"hostname": "nl1.nordvpn.com", "load": 12,
+             "locations": [{"country": {"code": "NL"}}], "groups": [], "technologies": []},
+            {"hostname": "qq1.nordvpn.com", "load": 12, "locations": [], "groups": [],
+             "technologies": []}
+        ]"#;
+
+        let (data, report) = Servers::from_reader_with_report(text.as_bytes()).unwrap();
+        let flags: Vec<&str> = data.servers.iter().map(|server| server.flag.as_str()).collect();
+        assert_eq!(flags, vec!["GB", "BE", "NL"]);
+        assert_eq!(
+            report,
+            ParseReport {
+                servers: 3,
+                corrected_flags: 2,
+                skipped: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn skip_servers_in_other_countries() {
+        // The Dutch server has a load that cannot be read.
+        let text = r#"[{
+            "hostname": "be148.nordvpn.com",
+            "load": 12,
+            "locations": [{"country": {"code": "BE"}}],
+            "groups": [],
+            "technologies": []
+        }, {
+            "hostname": "nl12.nordvpn.com",
+            "load": "high",
+            "locations": [{"country": {"code": "NL"}}],
+            "groups": [],
+            "technologies": []
+        }]"#;
+
+        assert!(Servers::from_txt(text).is_err());
+        let belgium = crate::filters::CountryFilter::from("BE");
+        let data = Servers::from_reader_filtered(text.as_bytes(), Some(&belgium)).unwrap();
+        assert_eq!(data.servers.len(), 1);
+        assert_eq!(data.servers[0].domain, "be148.nordvpn.com");
+    }
+
+    #[test]
+    fn categories_serialize_as_list() {
+        let categories: Categories = vec![ServerCategory::Tor, ServerCategory::P2P]
+            .into_iter()
+            .collect();
+        let json = serde_json::to_string(&categories).unwrap();
+        assert_eq!(json, r#"["P2P","Tor"]"#);
+        assert_eq!(
+            serde_json::from_str::<Categories>(&json).unwrap(),
+            categories
+        );
+
+        // Lists stored by older versions could name a category twice.
+        let stored: Categories = serde_json::from_str(r#"["Tor","Tor"]"#).unwrap();
+        assert_eq!(stored.len(), 1);
+    }
+
+    #[test]
+    fn keep_unknown_features_and_categories() {
+        let text = r#"[{
+            "flag": "BE",
+            "domain": "be148.nordvpn.com",
+            "load": 12,
+            "categories": [{"name": "Standard VPN servers"}, {"name": "Quantum"}],
+            "features": {
+                "ikev2": true, "openvpn_udp": true, "openvpn_tcp": true, "socks": false,
+                "proxy": false, "pptp": false, "l2tp": false, "openvpn_xor_udp": false,
+                "openvpn_xor_tcp": false, "proxy_cybersec": false, "proxy_ssl": false,
+                "proxy_ssl_cybersec": false, "wireguard_udp": true,
+                "new_tech": true, "old_tech": false, "not_a_flag": 3
+            }
+        }]"#;
+
+        let server = &Servers::from_txt(text).unwrap().servers[0];
+        assert_eq!(
+            server.categories.iter().collect::<Vec<_>>(),
+            vec![ServerCategory::Standard, ServerCategory::UnknownServer]
+        );
+        assert_eq!(server.unknown_categories, vec!["Quantum".to_string()]);
+        assert_eq!(server.features.get("new_tech"), Some(true));
+        assert_eq!(server.features.get("old_tech"), Some(false));
+        assert_eq!(server.features.get("not_a_flag"), None);
+        assert_eq!(server.features.get("wireguard_udp"), Some(true));
+        assert!(!server.features.port_forwarding);
+    }
+
+    #[test]
+    fn port_forwarding() {
+        let text = r#"[{
+            "hostname": "nl1.nordvpn.com",
+            "load": 12,
+            "locations": [{"country": {"code": "NL"}}],
+            "groups": [{"title": "P2P", "type": {"identifier": "legacy_group_category"}}],
+            "technologies": [{"identifier": "openvpn_udp"}, {"identifier": "port_forwarding"}]
+        }]"#;
+
+        let server = &Servers::from_txt(text).unwrap().servers[0];
+        assert!(server.features.port_forwarding);
+        assert_eq!(server.features.get("port_forwarding"), Some(true));
+        assert!(server.features.other.is_empty());
+    }
+
+    #[test]
+    fn countries() {
+        assert_eq!("uk".parse::<Country>().unwrap(), "GB");
+        assert_eq!("Gb".parse::<Country>().unwrap().name(), "United Kingdom");
+        assert!("".parse::<Country>().is_err());
+        assert!("éé".parse::<Country>().is_err());
+
+        let belgium: Country = "BE".parse().unwrap();
+        assert_eq!(serde_json::to_string(&belgium).unwrap(), "\"BE\"");
+        assert_eq!(serde_json::from_str::<Country>("\"BE\"").unwrap(), belgium);
+        assert!(serde_json::from_str::<Country>("\"XX\"").is_err());
+
+        // Servers in unknown countries are left out.
+        let text = r#"[
+            {"hostname": "xx1.nordvpn.com", "load": 1, "locations": [{"country": {"code": "XX"}}],
+             "groups": [], "technologies": []},
+            {"hostname": "be1.nordvpn.com", "load": 1, "locations": [{"country": {"code": "be"}}],
+             "groups": [], "technologies": []}
+        ]"#;
+        let servers = Servers::from_txt(text).unwrap().servers;
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].flag, belgium);
+    }
+
+    #[test]
+    fn merge_and_dedup() {
+        let mut cached = Servers::dummy_data();
+        cached.servers.truncate(3);
+        let mut fresh = Servers::dummy_data();
+        fresh.servers.drain(..2);
+        fresh.servers.truncate(3);
+        fresh.servers[0].load = 100;
+        let domains: Vec<Domain> = Servers::dummy_data().servers[..5]
+            .iter()
+            .map(|server| server.domain.clone())
+            .collect();
+
+        cached.merge(fresh);
+        assert_eq!(
+            cached
+                .servers
+                .iter()
+                .map(|server| server.domain.clone())
+                .collect::<Vec<_>>(),
+            domains
+        );
+        assert_eq!(cached.servers[2].load, 100);
+
+        let mut doubled = Servers::dummy_data();
+        doubled.servers.extend(Servers::dummy_data().servers);
+        doubled.dedup();
+        assert_eq!(doubled.servers, Servers::dummy_data().servers);
+    }
+
+    proptest! {
+        #[test]
+        fn merge_keeps_servers_once(cached in arb_servers(0..30), fresh in arb_servers(0..30)) {
+            let fresh_loads: HashMap<Domain, u8> = fresh
+                .servers
+                .iter()
+                .map(|server| (server.domain.clone(), server.load))
+                .collect();
+            let mut merged = cached;
+            merged.merge(fresh);
+
+            let domains: HashSet<&str> =
+                merged.servers.iter().map(|server| server.domain.as_str()).collect();
+            prop_assert_eq!(domains.len(), merged.servers.len());
+            for (domain, load) in &fresh_loads {
+                let server = merged.servers.iter().find(|server| &server.domain == domain);
+                prop_assert_eq!(server.map(|server| server.load), Some(*load));
+            }
+        }
+
+        #[test]
+        fn group_by_partitions(servers in arb_servers(0..30)) {
+            let groups = servers.group_by(|server| server.load / 10);
+
+            prop_assert_eq!(
+                groups.values().map(|group| group.servers.len()).sum::<usize>(),
+                servers.servers.len()
+            );
+            for (key, group) in &groups {
+                prop_assert!(group.servers.iter().all(|server| server.load / 10 == *key));
+            }
+        }
+    }
+
+    #[test]
+    fn features_builder() {
+        for key in KNOWN_FEATURES {
+            let features = Features::builder().feature(key, true).build();
+            assert_eq!(features.get(key), Some(true));
+            assert!(features.other.is_empty());
+        }
+
+        let server = Server {
+            features: Features::builder()
+                .protocol(Protocol::WireGuardUdp)
+                .protocol(Protocol::OpenVPNXTcp)
+                .build(),
+            ..Servers::dummy_data().servers.remove(0)
+        };
+        assert!(server.supports_all(&[Protocol::WireGuardUdp, Protocol::OpenVPNXTcp]));
+        assert!(server.supports_any(&[Protocol::Tcp, Protocol::OpenVPNXTcp]));
+        assert!(!server.supports_any(&[Protocol::Tcp, Protocol::Udp]));
+    }
+
+    #[test]
+    fn nearest_country() {
+        let data = Servers::dummy_data();
+        let lowest = data.servers.iter().map(|server| server.load).min().unwrap();
+        let country = data
+            .nearest_country(&bench::LoadBenchmarker)
+            .unwrap()
+            .unwrap();
+        assert!(data
+            .servers
+            .iter()
+            .any(|server| server.flag == country && server.load == lowest));
+
+        let empty = Servers {
+            servers: Vec::new(),
+        };
+        assert_eq!(empty.nearest_country(&bench::LoadBenchmarker).unwrap(), None);
+    }
+
+    #[test]
+    fn sample_per_country() {
+        let data = Servers::dummy_data();
+        let amount = data.servers.len() / 2;
+        let sizes = data.group_by(|server| server.flag);
+        let sample = data.sample(amount, SampleStrategy::PerCountry, 7);
+        assert_eq!(sample.servers.len(), amount);
+
+        for (country, picked) in sample.group_by(|server| server.flag) {
+            let share = sizes[&country].servers.len() * amount / data.servers.len();
+            assert!(picked.servers.len() == share || picked.servers.len() == share + 1);
+        }
+
+        let random = data.sample(amount, SampleStrategy::Random, 7);
+        assert_eq!(random.servers.len(), amount);
+        let domains: HashSet<&str> = random
+            .servers
+            .iter()
+            .map(|server| server.domain.as_str())
+            .collect();
+        assert_eq!(domains.len(), amount);
+        assert!(data
+            .servers
+            .iter()
+            .filter(|server| domains.contains(server.domain.as_str()))
+            .map(|server| &server.domain)
+            .eq(random.servers.iter().map(|server| &server.domain)));
+
+        assert_eq!(
+            data.sample(usize::MAX, SampleStrategy::Random, 7).servers,
+            data.servers
+        );
+    }
+
+    #[test]
+    fn matching_counts() {
+        let data = Servers::dummy_data();
+        let belgium = CountryFilter::from("BE");
+        let everything = crate::filters::NegatingFilter::new(CountryFilter::from(""));
+        let picked = data.best_matching_counts(&[(&belgium, 1), (&everything, 2), (&belgium, 0)]);
+
+        // The first Belgian server goes to the first filter, never to the second.
+        let first_belgian = data.servers.iter().position(|server| server.flag == "BE").unwrap();
+        assert_eq!(picked[0], vec![data.servers[first_belgian].clone()]);
+        assert_eq!(picked[1].len(), 2);
+        assert!(!picked[1].contains(&picked[0][0]));
+        assert!(picked[2].is_empty());
+
+        let picked = data.best_matching_counts(&[(&belgium, data.servers.len())]);
+        assert!(picked[0].len() < data.servers.len());
+    }
+
+    #[test]
+    fn double_chains() {
+        let data = Servers::dummy_data();
+        let server = data
+            .servers
+            .iter()
+            .find(|server| server.domain == "uk-fr10.nordvpn.com")
+            .unwrap();
+        assert_eq!(
+            server.double_chain(),
+            Some(("GB".parse().unwrap(), "FR".parse().unwrap()))
+        );
+        assert!(data
+            .servers
+            .iter()
+            .filter(|server| !server.categories.contains(&ServerCategory::Double))
+            .all(|server| server.double_chain().is_none()));
+
+        let chain = data
+            .best_double_chain(&CountryFilter::from("GB"), &CountryFilter::from("FR"))
+            .unwrap();
+        assert_eq!((chain.entry, chain.exit), server.double_chain().unwrap());
+        assert!(data
+            .best_double_chain(&CountryFilter::from("FR"), &CountryFilter::from("GB"))
+            .is_none());
+    }
+
+    #[test]
+    fn read_stored_servers() {
+        let data = Servers::dummy_data();
+        let stored = serde_json::to_string(&data.servers).unwrap();
+
+        let read = Servers::from_reader(stored.as_bytes()).unwrap();
+        assert_eq!(read.servers, data.servers);
+    }
+}
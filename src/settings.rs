@@ -0,0 +1,176 @@
+//! Settings of the command line tool, resolved from several layers: the profile file, the
+//! environment and the command line.
+//!
+//! Every setting is named after the long option of the command line it stands for, e.g. `tries`
+//! or `output`, and its value is written the way it is given there. Flags are set with `true`.
+//! The filters go by the name `filters`, separated by spaces.
+//!
+//! In the environment, settings are set with variables starting with
+//! [ENV_PREFIX](constant.ENV_PREFIX.html), followed by the name in uppercase with `_` instead of
+//! `-`, e.g. `NORDSELECT_TRIES=3` or `NORDSELECT_CACHE_TTL=30`.
+//!
+//! # Example
+//!
+//! ```
+//! use nordselect::settings::{self, Settings, Source};
+//!
+//! let env = settings::env_values(vec![
+//!     (String::from("NORDSELECT_OUTPUT"), String::from("json")),
+//!     (String::from("NORDSELECT_CACHE_TTL"), String::from("30")),
+//!     (String::from("HOME"), String::from("/home/me")),
+//! ]);
+//!
+//! let mut resolved = Settings::new();
+//! resolved.add(Source::Environment, env);
+//! resolved.add(
+//!     Source::ConfigFile,
+//!     vec![(String::from("output"), String::from("table"))],
+//! );
+//!
+//! // The environment goes above the profile file, whatever the order the layers were added in.
+//! assert_eq!(resolved.get("output"), Some("json"));
+//! assert_eq!(resolved.source("cache-ttl"), Some(Source::Environment));
+//! assert_eq!(resolved.get("home"), None);
+//! ```
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// What the names of the environment variables holding settings start with.
+pub const ENV_PREFIX: &str = "NORDSELECT_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Where a setting comes from. Later sources override earlier ones.
+pub enum Source {
+    /// A profile of the [profile file](../profiles/index.html).
+    ConfigFile,
+    /// A `NORDSELECT_` environment variable.
+    Environment,
+    /// An option given on the command line.
+    CommandLine,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Source::ConfigFile => "the profile file",
+            Source::Environment => "the environment",
+            Source::CommandLine => "the command line",
+        })
+    }
+}
+
+/// Returns the name of the environment variable holding the setting, e.g. `NORDSELECT_CACHE_TTL`
+/// for `cache-ttl`.
+pub fn env_name(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.to_uppercase().replace('-', "_"))
+}
+
+/// Returns the settings among the given environment variables, by their name. Variables without
+/// the [prefix](constant.ENV_PREFIX.html) and empty ones are left out.
+pub fn env_values(vars: impl IntoIterator<Item = (String, String)>) -> BTreeMap<String, String> {
+    vars.into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .filter_map(|(name, value)| {
+            let key = name.strip_prefix(ENV_PREFIX)?;
+            Some((key.to_lowercase().replace('_', "-"), value))
+        })
+        .collect()
+}
+
+/// Returns the settings in the environment of the current process.
+pub fn from_env() -> BTreeMap<String, String> {
+    env_values(std::env::vars())
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Settings from several sources, of which the highest one wins.
+pub struct Settings {
+    /// Every setting, by its name, with the source it came from.
+    values: BTreeMap<String, (String, Source)>,
+}
+
+impl Settings {
+    /// Creates a set without any settings.
+    pub fn new() -> Settings {
+        Settings {
+            values: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the settings of a source. They replace the settings of lower sources, and the ones
+    /// of the same source added earlier.
+    pub fn add(&mut self, source: Source, values: impl IntoIterator<Item = (String, String)>) {
+        for (key, value) in values {
+            let replaces = match self.values.get(&key) {
+                Some((_, known)) => *known <= source,
+                None => true,
+            };
+            if replaces {
+                self.values.insert(key, (value, source));
+            }
+        }
+    }
+
+    /// Returns the value of the setting, if any source sets it.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(|(value, _)| value.as_str())
+    }
+
+    /// Returns where the value of the setting comes from, if any source sets it.
+    pub fn source(&self, key: &str) -> Option<Source> {
+        self.values.get(key).map(|(_, source)| *source)
+    }
+
+    /// Returns every setting with its value and source, sorted on name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, Source)> {
+        self.values
+            .iter()
+            .map(|(key, (value, source))| (key.as_str(), value.as_str(), *source))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn layers() {
+        let mut settings = Settings::new();
+        settings.add(Source::CommandLine, values(&[("tries", "5")]));
+        settings.add(
+            Source::ConfigFile,
+            values(&[("tries", "3"), ("filters", "nl p2p")]),
+        );
+        settings.add(
+            Source::Environment,
+            values(&[("filters", "be"), ("tries", "4")]),
+        );
+        settings.add(Source::Environment, values(&[("filters", "de")]));
+
+        assert_eq!(settings.get("tries"), Some("5"));
+        assert_eq!(settings.source("tries"), Some(Source::CommandLine));
+        assert_eq!(settings.get("filters"), Some("de"));
+        assert_eq!(settings.get("ping"), None);
+        assert_eq!(settings.iter().count(), 2);
+    }
+
+    #[test]
+    fn environment_names() {
+        assert_eq!(env_name("cache-ttl"), "NORDSELECT_CACHE_TTL");
+        let env = env_values(values(&[
+            ("NORDSELECT_CACHE_TTL", "30"),
+            ("NORDSELECT_PING", ""),
+            ("NORDSELECTOR", "x"),
+            ("PATH", "/bin"),
+        ]));
+        assert_eq!(env.len(), 1);
+        assert_eq!(env["cache-ttl"], "30");
+    }
+}
@@ -0,0 +1,120 @@
+//! Sharing a server list between threads, e.g. to keep answering queries while a new list is
+//! downloaded.
+use crate::servers::Servers;
+use std::sync::{Arc, RwLock};
+
+/// A server list shared between threads, which can be refreshed while others keep using it.
+///
+/// Readers take a [snapshot](#method.snapshot): a reference to the list at that moment, which is
+/// not changed by later refreshes. A refresh gets the new list first and only then replaces the
+/// old one, so readers are never held up by a download.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::{Servers, SharedServers};
+///
+/// let shared = SharedServers::new(Servers::dummy_data());
+/// let before = shared.snapshot();
+///
+/// let refresher = shared.clone();
+/// let refresh = std::thread::spawn(move || {
+///     refresher
+///         .refresh(|| {
+///             let mut data = Servers::dummy_data();
+///             data.servers.truncate(1);
+///             Ok(data)
+///         })
+///         .is_ok()
+/// });
+/// assert!(refresh.join().unwrap());
+///
+/// assert_eq!(shared.snapshot().servers.len(), 1);
+/// assert!(before.servers.len() > 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SharedServers {
+    /// The current list. Only the reference is replaced, the lists themselves never change.
+    servers: Arc<RwLock<Arc<Servers>>>,
+}
+
+impl SharedServers {
+    /// Shares the given list.
+    pub fn new(servers: Servers) -> SharedServers {
+        SharedServers {
+            servers: Arc::new(RwLock::new(Arc::new(servers))),
+        }
+    }
+
+    /// Returns the current list. The lock is only held to copy a reference, not while the list
+    /// is used or refreshed.
+    pub fn snapshot(&self) -> Arc<Servers> {
+        // Replacing a reference cannot be interrupted halfway, so a poisoned lock is fine.
+        match self.servers.read() {
+            Ok(servers) => servers.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Replaces the list by the given one.
+    pub fn replace(&self, servers: Servers) {
+        let servers = Arc::new(servers);
+        match self.servers.write() {
+            Ok(mut current) => *current = servers,
+            Err(poisoned) => *poisoned.into_inner() = servers,
+        }
+    }
+
+    /// Replaces the list by the one returned by `download`, e.g.
+    /// [Servers::from_api](../struct.Servers.html#method.from_api). Snapshots can be taken
+    /// while it runs. When it fails, the current list is kept and the error is returned.
+    pub fn refresh<F>(&self, download: F) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Result<Servers, Box<dyn std::error::Error>>,
+    {
+        self.replace(download()?);
+        Ok(())
+    }
+}
+
+impl From<Servers> for SharedServers {
+    fn from(servers: Servers) -> SharedServers {
+        SharedServers::new(servers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn snapshot_during_refresh() {
+        let shared = SharedServers::new(Servers::dummy_data());
+        let amount = shared.snapshot().servers.len();
+
+        // The refresh waits until the snapshot below is taken.
+        let (sender, receiver) = mpsc::channel::<()>();
+        let refresher = shared.clone();
+        let refresh = thread::spawn(move || {
+            refresher
+                .refresh(|| {
+                    receiver.recv().unwrap();
+                    Ok(Servers {
+                        servers: Vec::new(),
+                    })
+                })
+                .is_ok()
+        });
+        assert_eq!(shared.snapshot().servers.len(), amount);
+        sender.send(()).unwrap();
+        assert!(refresh.join().unwrap());
+        assert!(shared.snapshot().servers.is_empty());
+
+        // A failed refresh keeps the list.
+        shared.replace(Servers::dummy_data());
+        assert!(shared.refresh(|| Err("offline".into())).is_err());
+        assert_eq!(shared.snapshot().servers.len(), amount);
+    }
+}
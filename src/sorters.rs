@@ -1,13 +1,19 @@
 //! Sorters are ways to sort Servers, whereas the first one is the most likely to be selected for usage.
 
-use super::servers::{Server, Servers};
+#[cfg(feature = "native")]
+use super::bench::icmp::{self, PingBackend};
+use super::bench::{Benchmarker, Score, ScoreUnit, Scores};
+#[cfg(feature = "native")]
+use super::servers::Domain;
+use super::servers::{Country, Server, Servers};
 
 use std;
 use std::cmp::Ordering;
+#[cfg(feature = "native")]
 use std::collections::HashMap;
 use std::iter::FromIterator;
-
-use oping::Ping;
+#[cfg(feature = "native")]
+use std::time::{Duration, Instant};
 
 /// A Sorter is a way to order servers. It should be noted that these are sorted from best server
 /// (according to the way of sorting) to worst one.
@@ -38,6 +44,52 @@ pub trait Sorter {
     fn sort(&self, _: &Server, _: &Server) -> Ordering;
 }
 
+/// Sorter that orders servers on the scores a [Benchmarker](../bench/trait.Benchmarker.html) gave
+/// them, so new ways to measure servers only have to be written as a Benchmarker. Servers that
+/// could not be scored are placed after the ones that were.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::Servers;
+/// use nordselect::bench::LoadBenchmarker;
+/// use nordselect::sorters;
+///
+/// let mut data = Servers::dummy_data();
+/// let sorter = sorters::from_benchmarker(&LoadBenchmarker, &data).unwrap();
+/// data.sort(&sorter);
+/// let lowest = data.servers.iter().map(|server| server.load).min();
+/// assert_eq!(lowest, Some(data.perfect_server().unwrap().load));
+/// ```
+pub struct BenchmarkSorter {
+    /// The scores of the servers that were benchmarked.
+    scores: Scores,
+}
+
+/// Benchmarks the given servers once, and returns a Sorter ordering servers on the scores. See
+/// [BenchmarkSorter](struct.BenchmarkSorter.html).
+///
+/// Returns an Error if the Benchmarker fails.
+pub fn from_benchmarker(
+    benchmarker: &dyn Benchmarker,
+    servers: &Servers,
+) -> Result<BenchmarkSorter, Box<dyn std::error::Error>> {
+    Ok(BenchmarkSorter {
+        scores: benchmarker.benchmark(servers)?,
+    })
+}
+
+impl Sorter for BenchmarkSorter {
+    fn sort(&self, a: &Server, b: &Server) -> Ordering {
+        match (self.scores.get(&a.domain), self.scores.get(&b.domain)) {
+            (Some(a), Some(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+}
+
 /// Sorter that sorts servers based on their load, favouring the least loaded one.
 pub struct LoadSorter;
 
@@ -47,6 +99,186 @@ impl Sorter for LoadSorter {
     }
 }
 
+/// Sorter that favours servers with the highest bandwidth, e.g. for large downloads. Servers of
+/// which the bandwidth is not known go last. Servers with the same bandwidth are sorted on load.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::sorters::BandwidthSorter;
+/// use nordselect::Servers;
+///
+/// let mut data = Servers::dummy_data();
+/// data.servers[3].bandwidth_tier = Some(10_000);
+/// let fast = data.servers[3].clone();
+///
+/// data.sort(&BandwidthSorter);
+/// assert_eq!(data.perfect_server(), Some(fast));
+/// ```
+pub struct BandwidthSorter;
+
+impl Sorter for BandwidthSorter {
+    fn sort(&self, a: &Server, b: &Server) -> Ordering {
+        // None is less than any bandwidth, so reversing puts it last.
+        b.bandwidth_tier
+            .cmp(&a.bandwidth_tier)
+            .then_with(|| a.load.cmp(&b.load))
+    }
+}
+
+/// Sorter that favours established servers: the ones that were added longest ago come first,
+/// followed by the ones of which it is not known when they were added. Servers of the same age
+/// are sorted on their load.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::sorters::AgeSorter;
+/// use nordselect::Servers;
+///
+/// let mut data = Servers::dummy_data();
+/// data.servers[3].created_at = Some(1_500_000_000);
+/// let established = data.servers[3].clone();
+///
+/// data.sort(&AgeSorter);
+/// assert_eq!(data.perfect_server(), Some(established));
+/// ```
+pub struct AgeSorter;
+
+impl Sorter for AgeSorter {
+    fn sort(&self, a: &Server, b: &Server) -> Ordering {
+        let added = match (a.created_at, b.created_at) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        added.then_with(|| a.load.cmp(&b.load))
+    }
+}
+
+/// Sorter that follows the hints of the NordVPN API, to pick the server its apps would pick.
+/// Recommended servers come first, then the servers the API did not say anything about, then the
+/// ones that are not recommended. Within each of these, servers with a higher hub score come
+/// first, and servers without a hub score last. Ties are sorted on load.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::sorters::HubScoreSorter;
+/// use nordselect::Servers;
+///
+/// let mut data = Servers::dummy_data();
+/// data.servers[3].hub_score = Some(90);
+/// data.servers[4].hub_score = Some(95);
+/// data.servers[4].recommended = Some(false);
+/// let hub = data.servers[3].clone();
+///
+/// data.sort(&HubScoreSorter);
+/// assert_eq!(data.perfect_server(), Some(hub));
+/// ```
+pub struct HubScoreSorter;
+
+impl Sorter for HubScoreSorter {
+    fn sort(&self, a: &Server, b: &Server) -> Ordering {
+        // Unknown is better than not recommended, worse than recommended.
+        let rank = |recommended: Option<bool>| match recommended {
+            Some(true) => 0,
+            None => 1,
+            Some(false) => 2,
+        };
+        let score = match (a.hub_score, b.hub_score) {
+            (Some(a), Some(b)) => b.cmp(&a),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        rank(a.recommended)
+            .cmp(&rank(b.recommended))
+            .then(score)
+            .then_with(|| a.load.cmp(&b.load))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Sorter that favours the countries earlier in a list of preferred ones: servers in the first
+/// country come first, followed by the ones in the second, and so on. Servers in countries that are
+/// not listed come last. Servers in the same country are sorted on their load.
+/// Codes that are not a country are ignored.
+///
+/// # Example
+///
+/// ```
+/// use nordselect::sorters::CountryPreferenceSorter;
+/// use nordselect::Servers;
+///
+/// let mut data = Servers::dummy_data();
+/// data.sort(&CountryPreferenceSorter::from(["NL", "DE", "FR"]));
+/// assert_eq!(data.perfect_server().unwrap().flag, "NL");
+/// ```
+pub struct CountryPreferenceSorter {
+    /// The preferred countries, most preferred first.
+    countries: Vec<Country>,
+}
+
+impl CountryPreferenceSorter {
+    /// Returns the preferred countries, most preferred first.
+    pub fn countries(&self) -> &[Country] {
+        &self.countries
+    }
+
+    /// Returns the position of the country of the server in the list, or the length of the list
+    /// if it is not listed.
+    fn position(&self, server: &Server) -> usize {
+        self.countries
+            .iter()
+            .position(|country| *country == server.flag)
+            .unwrap_or(self.countries.len())
+    }
+}
+
+impl<'a, const N: usize> From<[&'a str; N]> for CountryPreferenceSorter {
+    fn from(codes: [&'a str; N]) -> CountryPreferenceSorter {
+        codes.iter().filter_map(|code| code.parse().ok()).collect()
+    }
+}
+
+impl From<Vec<Country>> for CountryPreferenceSorter {
+    fn from(countries: Vec<Country>) -> CountryPreferenceSorter {
+        CountryPreferenceSorter { countries }
+    }
+}
+
+impl FromIterator<Country> for CountryPreferenceSorter {
+    fn from_iter<I: IntoIterator<Item = Country>>(countries: I) -> CountryPreferenceSorter {
+        CountryPreferenceSorter {
+            countries: countries.into_iter().collect(),
+        }
+    }
+}
+
+impl Sorter for CountryPreferenceSorter {
+    fn sort(&self, a: &Server, b: &Server) -> Ordering {
+        self.position(a)
+            .cmp(&self.position(b))
+            .then_with(|| a.load.cmp(&b.load))
+    }
+}
+
+impl Benchmarker for CountryPreferenceSorter {
+    /// Scores servers on the position of their country in the list.
+    fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+        Ok(servers
+            .servers
+            .iter()
+            .map(|server| {
+                let position = Score::new(self.position(server) as u32, ScoreUnit::Unitless);
+                (server.domain.clone(), position)
+            })
+            .collect())
+    }
+}
+
 /// Sorter that sorts based on a ping-test.
 ///
 /// Please note that ping tests enhance the complexity of your program, whereas the `LoadSorter`
@@ -58,12 +290,16 @@ impl Sorter for LoadSorter {
 /// ```
 ///
 /// More details about this (and why you have to do it) can be found at the [oping crate](https://github.com/cfallin/rust-oping).
+/// Where the system allows unprivileged ICMP datagram sockets, like on macOS, those are used
+/// instead. See [PingBackend::detect](../bench/enum.PingBackend.html#method.detect).
+#[cfg(feature = "native")]
 pub struct PingSorter {
     /// The results of the ping test.
-    ping_results: HashMap<String, usize>,
+    ping_results: HashMap<Domain, usize>,
 }
 
 /// Ways to set up a PingSorter.
+#[cfg(feature = "native")]
 impl PingSorter {
     /// Creates a new PingSorter using one ping instance, doing tests simultaneously. This is less precise, but is faster to run.
     ///
@@ -74,29 +310,46 @@ impl PingSorter {
         servers: &Servers,
         tries: usize,
     ) -> Result<PingSorter, Box<dyn std::error::Error>> {
-        let mut ping_results = HashMap::new();
-        for _ in 0..tries {
-            let mut pingr = Ping::new();
-            for server in &servers.servers {
-                pingr.add_host(server.domain.as_str())?;
-            }
+        Self::ping_single_impl(servers, tries, None)
+    }
 
-            let results = pingr.send()?;
+    /// Same as [ping_single](#method.ping_single), but stops starting new tries once the given
+    /// deadline has passed. Only completed tries are taken into account.
+    pub fn ping_single_until(
+        servers: &Servers,
+        tries: usize,
+        deadline: Instant,
+    ) -> Result<PingSorter, Box<dyn std::error::Error>> {
+        Self::ping_single_impl(servers, tries, Some(deadline))
+    }
 
-            for result in results {
-                let old_value: usize = *ping_results.get(&result.hostname).unwrap_or(&0usize);
-                ping_results.insert(
-                    result.hostname,
-                    old_value + (result.latency_ms * 1000f64) as usize,
-                );
+    fn ping_single_impl(
+        servers: &Servers,
+        tries: usize,
+        deadline: Option<Instant>,
+    ) -> Result<PingSorter, Box<dyn std::error::Error>> {
+        let backend = PingBackend::detect();
+        let mut ping_results = HashMap::new();
+        let mut tries_done = 0;
+        while tries_done < tries && !deadline_passed(deadline) {
+            let hosts: Vec<&str> = servers
+                .servers
+                .iter()
+                .map(|server| server.domain.as_str())
+                .collect();
+
+            for reply in icmp::ping_once(backend, &hosts)? {
+                let old_value: usize = *ping_results.get(&reply.host).unwrap_or(&0usize);
+                ping_results.insert(reply.host, old_value + micros(reply.latency));
             }
+            tries_done += 1;
         }
 
         Ok(PingSorter {
             ping_results: HashMap::from_iter(
                 ping_results
                     .into_iter()
-                    .map(|(host, results)| (host, results / tries)),
+                    .map(|(host, results)| (Domain::from(host), results / tries_done)),
             ),
         })
     }
@@ -110,30 +363,94 @@ impl PingSorter {
         servers: &Servers,
         tries: usize,
     ) -> Result<PingSorter, Box<dyn std::error::Error>> {
+        Self::ping_multi_impl(servers, tries, None)
+    }
+
+    /// Same as [ping_multi](#method.ping_multi), but stops pinging once the given deadline has
+    /// passed. Servers that were not pinged in time will be sorted after the ones that were.
+    pub fn ping_multi_until(
+        servers: &Servers,
+        tries: usize,
+        deadline: Instant,
+    ) -> Result<PingSorter, Box<dyn std::error::Error>> {
+        Self::ping_multi_impl(servers, tries, Some(deadline))
+    }
+
+    fn ping_multi_impl(
+        servers: &Servers,
+        tries: usize,
+        deadline: Option<Instant>,
+    ) -> Result<PingSorter, Box<dyn std::error::Error>> {
+        let backend = PingBackend::detect();
         let mut ping_results = HashMap::new();
-        for server in &servers.servers {
-            let mut sum = 0;
-            for _ in 0..tries {
-                let mut pingr = Ping::new();
-                pingr.add_host(server.domain.as_str())?;
-                sum += (pingr.send()?.next().unwrap().latency_ms * 1000f64) as usize;
+        'servers: for server in &servers.servers {
+            let mut sum: usize = 0;
+            for tries_done in 0..tries {
+                if deadline_passed(deadline) {
+                    if let Some(average) = sum.checked_div(tries_done) {
+                        ping_results.insert(server.domain.clone(), average);
+                    }
+                    break 'servers;
+                }
+                let reply = icmp::ping_once(backend, &[server.domain.as_str()])?.pop();
+                sum += micros(reply.and_then(|reply| reply.latency));
             }
             ping_results.insert(server.domain.clone(), sum / tries);
         }
 
         Ok(PingSorter { ping_results })
     }
+
+    /// Returns whether no server was pinged at all, e.g. because the deadline was already over.
+    pub fn is_empty(&self) -> bool {
+        self.ping_results.is_empty()
+    }
+}
+
+/// Returns the latency in microseconds, counting unanswered pings as zero.
+#[cfg(feature = "native")]
+fn micros(latency: Option<Duration>) -> usize {
+    latency.map_or(0, |latency| latency.as_micros() as usize)
 }
 
+#[cfg(feature = "native")]
+fn deadline_passed(deadline: Option<Instant>) -> bool {
+    match deadline {
+        Some(deadline) => Instant::now() >= deadline,
+        None => false,
+    }
+}
+
+#[cfg(feature = "native")]
 impl Sorter for PingSorter {
+    /// Servers that have not been pinged (e.g. because a deadline passed) are placed after the
+    /// ones that have.
     fn sort(&self, a: &Server, b: &Server) -> Ordering {
-        self.ping_results
-            .get(a.domain.as_str())
-            .expect("Server not found in ping result")
-            .cmp(
-                self.ping_results
-                    .get(b.domain.as_str())
-                    .expect("Other server not found in ping result"),
-            )
+        match (
+            self.ping_results.get(a.domain.as_str()),
+            self.ping_results.get(b.domain.as_str()),
+        ) {
+            (Some(a), Some(b)) => a.cmp(b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+}
+
+#[cfg(feature = "native")]
+impl Benchmarker for PingSorter {
+    /// Scores servers on their ping, in microseconds.
+    fn benchmark(&self, servers: &Servers) -> Result<Scores, Box<dyn std::error::Error>> {
+        Ok(servers
+            .servers
+            .iter()
+            .filter_map(|server| {
+                self.ping_results.get(&server.domain).map(|ping| {
+                    let score = Score::new(*ping as u32, ScoreUnit::Microseconds);
+                    (server.domain.clone(), score)
+                })
+            })
+            .collect())
     }
 }
@@ -0,0 +1,97 @@
+//! Generating random servers for property-based tests, using
+//! [proptest](https://docs.rs/proptest).
+//!
+//! The generated servers are coherent: their domain matches their country and is unique within a
+//! list, their load is a percentage and they are in at least one known category. This is only
+//! available with the `test-util` feature.
+//!
+//! # Example
+//!
+//! ```
+//! use nordselect::filters::{Filter, LoadFilter};
+//! use nordselect::test_util::arb_servers;
+//! use proptest::prelude::*;
+//!
+//! proptest!(|(mut data in arb_servers(0..20))| {
+//!     data.filter(&LoadFilter::from(50));
+//!     prop_assert!(data.servers.iter().all(|server| server.load <= 50));
+//! });
+//! ```
+use crate::countries;
+use crate::servers::{Country, Features, Server, ServerCategory, Servers, KNOWN_FEATURES};
+use proptest::collection::{self, SizeRange};
+use proptest::prelude::*;
+use proptest::sample;
+
+/// Generates countries NordVPN has servers in.
+pub fn arb_country() -> impl Strategy<Value = Country> {
+    let countries: Vec<Country> = countries::nord_countries()
+        .map(|code| code.parse().unwrap())
+        .collect();
+    sample::select(countries)
+}
+
+/// Generates categories known to this library.
+pub fn arb_category() -> impl Strategy<Value = ServerCategory> {
+    sample::select(vec![
+        ServerCategory::Standard,
+        ServerCategory::P2P,
+        ServerCategory::Obfuscated,
+        ServerCategory::Dedicated,
+        ServerCategory::Tor,
+        ServerCategory::Double,
+    ])
+}
+
+/// Generates features, without unknown ones.
+pub fn arb_features() -> impl Strategy<Value = Features> {
+    collection::vec(any::<bool>(), KNOWN_FEATURES.len()).prop_map(|flags| {
+        KNOWN_FEATURES
+            .iter()
+            .zip(flags)
+            .fold(Features::builder(), |builder, (key, supported)| {
+                builder.feature(key, supported)
+            })
+            .build()
+    })
+}
+
+/// Generates a server, of which the domain is numbered after its country.
+pub fn arb_server() -> impl Strategy<Value = Server> {
+    (
+        arb_country(),
+        1u32..10000,
+        0u8..=100,
+        collection::vec(arb_category(), 1..3),
+        arb_features(),
+    )
+        .prop_map(|(flag, number, load, categories, features)| Server {
+            domain: domain(flag, number).into(),
+            flag,
+            load,
+            categories: categories.into_iter().collect(),
+            unknown_categories: Vec::new(),
+            features,
+            bandwidth_tier: None,
+            city: None,
+            created_at: None,
+            hub_score: None,
+            recommended: None,
+            #[cfg(feature = "raw-json")]
+            raw: None,
+        })
+}
+
+/// Generates lists of servers of the given size, in which every domain is unique.
+pub fn arb_servers(size: impl Into<SizeRange>) -> impl Strategy<Value = Servers> {
+    collection::vec(arb_server(), size).prop_map(|mut servers| {
+        for (number, server) in servers.iter_mut().enumerate() {
+            server.domain = domain(server.flag, number as u32 + 1).into();
+        }
+        Servers { servers }
+    })
+}
+
+fn domain(flag: Country, number: u32) -> String {
+    format!("{}{}.nordvpn.com", flag.as_str().to_lowercase(), number)
+}
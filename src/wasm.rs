@@ -0,0 +1,106 @@
+//! Selecting servers from JavaScript, e.g. in a browser extension. Only available with the
+//! `wasm` feature.
+//!
+//! Browsers cannot send pings and block downloads the way the API client does them, so this
+//! module is built without the default `native` feature, which leaves those out:
+//!
+//! ```text
+//! cargo build --release --lib --target wasm32-unknown-unknown --no-default-features --features wasm
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/nordselect.wasm
+//! ```
+//!
+//! The server list is downloaded by the JavaScript side and handed over as JSON. Filters are
+//! passed as a JSON array of the filters of the command line, and servers are returned as a JSON
+//! array, in the format of stored server lists, best first.
+//!
+//! ```text
+//! import init, { select } from "./pkg/nordselect.js";
+//!
+//! await init();
+//! const list = await fetch("https://api.nordvpn.com/v1/servers?limit=16384");
+//! const best = JSON.parse(select(await list.text(), JSON.stringify(["nl", "p2p"]), 2));
+//! console.log(best[0].domain);
+//! ```
+//!
+//! Servers are ordered on their load, like the command line tool does without a ping test.
+//! Filters on the age of servers read the clock of the system, which browsers do not offer to
+//! WebAssembly, so those cannot be used there.
+use crate::cli_help::{self, Aliases};
+use crate::selection::SelectionBuilder;
+use crate::servers::Servers;
+use wasm_bindgen::prelude::*;
+
+/// Selects the best server passing the filters out of the server list, followed by up to
+/// `alternates` next best ones. Returns them as a JSON array, which is empty when no server
+/// passes the filters. Throws an error describing the problem when the list or the filters
+/// cannot be read.
+#[wasm_bindgen]
+pub fn select(
+    servers_json: &str,
+    filters_json: &str,
+    alternates: usize,
+) -> Result<String, JsValue> {
+    select_json(servers_json, filters_json, alternates)
+        .map_err(|err| JsValue::from(err.to_string()))
+}
+
+/// Does what [select](fn.select.html) does, with errors that can be handled outside of a
+/// browser.
+fn select_json(
+    servers_json: &str,
+    filters_json: &str,
+    alternates: usize,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut servers = Servers::from_reader(servers_json.as_bytes())?;
+    let filters: Vec<String> = serde_json::from_str(filters_json)?;
+    let filters = filters.iter().map(String::as_str);
+    let aliases = Aliases::default();
+
+    for filter in cli_help::parse_filters(filters.clone(), &servers, &aliases, true)? {
+        servers.filter(filter.as_ref());
+    }
+
+    // Of the preferred countries, only the first one that has servers left is selected from.
+    let preferred = cli_help::preferred_countries(filters, &aliases);
+    if !preferred.is_empty() {
+        let country = preferred
+            .iter()
+            .find(|country| {
+                servers
+                    .servers
+                    .iter()
+                    .any(|server| server.flag == **country)
+            })
+            .copied();
+        servers
+            .servers
+            .retain(|server| country == Some(server.flag));
+    }
+
+    let selected = SelectionBuilder::new().take(alternates + 1).run(&servers)?;
+    Ok(serde_json::to_string(&selected.servers)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::Server;
+
+    #[test]
+    fn select_from_json() {
+        let json = std::fs::read_to_string("dummydata").unwrap();
+
+        let best = select_json(&json, r#"["be", "tcp"]"#, 2).unwrap();
+        let best: Vec<Server> = serde_json::from_str(&best).unwrap();
+        assert_eq!(best.len(), 3);
+        assert!(best.iter().all(|server| server.flag == "BE"));
+        assert!(best[0].load <= best[1].load);
+
+        let best = select_json(&json, r#"["tcp", "prefer:xk,be,nl"]"#, 0).unwrap();
+        let best: Vec<Server> = serde_json::from_str(&best).unwrap();
+        assert_eq!(best[0].flag, "BE");
+
+        assert!(select_json(&json, r#"["nowhere"]"#, 0).is_err());
+        assert!(select_json("[", "[]", 0).is_err());
+    }
+}